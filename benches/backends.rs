@@ -0,0 +1,77 @@
+//! Compare the inner collections of the `DynQueue` on a matrix of workloads
+//!
+//! ```text
+//! cargo bench --features bench,crossbeam-queue
+//! ```
+
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
+
+#[cfg(feature = "crossbeam-queue")]
+use dynqueue::bench::SegQueueBackend;
+use dynqueue::bench::{Backend, VecBackend, VecDequeBackend, Workload};
+
+fn run<B: Backend>(group: &mut BenchmarkGroup<'_, WallTime>, name: &str, workload: &Workload) {
+    group.bench_with_input(BenchmarkId::new(B::NAME, name), workload, |b, workload| {
+        b.iter(|| B::run(workload))
+    });
+}
+
+fn bench_all(group: &mut BenchmarkGroup<'_, WallTime>, workloads: &[(String, Workload)]) {
+    for (name, workload) in workloads {
+        group.throughput(Throughput::Elements(workload.len() as u64));
+        run::<VecBackend>(group, name, workload);
+        run::<VecDequeBackend>(group, name, workload);
+        #[cfg(feature = "crossbeam-queue")]
+        run::<SegQueueBackend>(group, name, workload);
+    }
+}
+
+fn fan_out(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fan_out");
+    let workloads = [1, 2, 8]
+        .iter()
+        .map(|&fan_out| {
+            let depth = match fan_out {
+                1 => 4096,
+                2 => 12,
+                _ => 4,
+            };
+            let workload = Workload::new(1).with_fan_out(fan_out).with_depth(depth);
+            (format!("{}x{}", fan_out, depth), workload)
+        })
+        .collect::<Vec<_>>();
+    bench_all(&mut group, &workloads);
+    group.finish();
+}
+
+fn item_cost(c: &mut Criterion) {
+    let mut group = c.benchmark_group("item_cost");
+    let workloads = [0, 100, 10_000]
+        .iter()
+        .map(|&cost| {
+            let workload = Workload::new(16)
+                .with_fan_out(2)
+                .with_depth(6)
+                .with_item_cost(cost);
+            (cost.to_string(), workload)
+        })
+        .collect::<Vec<_>>();
+    bench_all(&mut group, &workloads);
+    group.finish();
+}
+
+fn seeds(c: &mut Criterion) {
+    let mut group = c.benchmark_group("seeds");
+    let workloads = [1_000, 100_000]
+        .iter()
+        .map(|&seeds| (seeds.to_string(), Workload::new(seeds).with_item_cost(10)))
+        .collect::<Vec<_>>();
+    bench_all(&mut group, &workloads);
+    group.finish();
+}
+
+criterion_group!(benches, fan_out, item_cost, seeds);
+criterion_main!(benches);
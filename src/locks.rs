@@ -0,0 +1,241 @@
+//! Queues based on the locks of the `lock_api` crate (with `feature = "lock_api"`)
+//!
+//! Every lock implementing `lock_api::RawMutex` or `lock_api::RawRwLock`
+//! (e.g. from `parking_lot` or `spin`) can protect a `Vec<T>`, `VecDeque<T>`
+//! or `BinaryHeap<T>`.
+//!
+//! The `spin` locks (with `feature = "spin"`) do not depend on the OS, so
+//! their queues are also built without `feature = "std"`. The `DynQueue`
+//! itself requires `std`, because `rayon` does.
+
+use crate::{DoubleEndedQueue, Order, Queue};
+#[cfg(feature = "std")]
+use crate::{DynQueue, IntoDynQueue};
+use alloc::collections::{BinaryHeap, VecDeque};
+use alloc::vec::Vec;
+use lock_api::{Mutex, RawMutex, RawRwLock, RwLock};
+
+macro_rules! lock_api_queue {
+    ($lock:ident, $raw:ident, $write:ident, $read:ident) => {
+        #[cfg(feature = "std")]
+        impl<R: $raw, T> IntoDynQueue<T, $lock<R, Vec<T>>> for $lock<R, Vec<T>> {
+            #[inline(always)]
+            fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+                DynQueue::new(self)
+            }
+        }
+
+        impl<R: $raw, T> Queue<T> for $lock<R, Vec<T>> {
+            #[inline(always)]
+            fn push(&self, v: T) {
+                self.$write().push(v)
+            }
+
+            #[inline(always)]
+            fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+                self.$write().extend(iter)
+            }
+
+            #[inline(always)]
+            fn pop(&self) -> Option<T> {
+                self.$write().pop()
+            }
+
+            /// `Order::Fifo` has to shift all remaining elements on every pop,
+            /// see [`Queue::has_cheap_fifo`].
+            #[inline(always)]
+            fn pop_ordered(&self, order: Order) -> Option<T> {
+                let mut v = self.$write();
+                match order {
+                    Order::Lifo => v.pop(),
+                    Order::Fifo if v.is_empty() => None,
+                    Order::Fifo => Some(v.remove(0)),
+                }
+            }
+
+            #[inline(always)]
+            fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+                let mut v = self.$write();
+                let n = n.min(v.len());
+                match order {
+                    Some(Order::Fifo) => v.drain(..n).collect(),
+                    _ => {
+                        let at = v.len() - n;
+                        v.drain(at..).rev().collect()
+                    }
+                }
+            }
+
+            #[inline(always)]
+            fn len(&self) -> usize {
+                self.$read().len()
+            }
+
+            #[inline(always)]
+            fn reserve(&self, additional: usize) {
+                self.$write().reserve(additional)
+            }
+
+            #[inline(always)]
+            fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+                self.$write().retain(f)
+            }
+
+            #[inline(always)]
+            fn split_off(&self, size: usize) -> Self {
+                let mut v = self.$write();
+                let at = size.min(v.len());
+                $lock::new(v.split_off(at))
+            }
+
+            #[inline(always)]
+            fn is_exclusive(&self) -> bool {
+                true
+            }
+
+            #[inline(always)]
+            fn has_cheap_fifo(&self) -> bool {
+                false
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl<R: $raw, T> IntoDynQueue<T, $lock<R, VecDeque<T>>> for $lock<R, VecDeque<T>> {
+            #[inline(always)]
+            fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+                DynQueue::new(self)
+            }
+        }
+
+        impl<R: $raw, T> Queue<T> for $lock<R, VecDeque<T>> {
+            #[inline(always)]
+            fn push(&self, v: T) {
+                self.$write().push_back(v)
+            }
+
+            #[inline(always)]
+            fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+                self.$write().extend(iter)
+            }
+
+            #[inline(always)]
+            fn pop(&self) -> Option<T> {
+                self.$write().pop_front()
+            }
+
+            #[inline(always)]
+            fn pop_ordered(&self, order: Order) -> Option<T> {
+                match order {
+                    Order::Fifo => self.$write().pop_front(),
+                    Order::Lifo => self.$write().pop_back(),
+                }
+            }
+
+            #[inline(always)]
+            fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+                let mut v = self.$write();
+                let n = n.min(v.len());
+                match order {
+                    Some(Order::Lifo) => {
+                        let at = v.len() - n;
+                        v.drain(at..).rev().collect()
+                    }
+                    _ => v.drain(..n).collect(),
+                }
+            }
+
+            #[inline(always)]
+            fn len(&self) -> usize {
+                self.$read().len()
+            }
+
+            #[inline(always)]
+            fn reserve(&self, additional: usize) {
+                self.$write().reserve(additional)
+            }
+
+            #[inline(always)]
+            fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+                self.$write().retain(f)
+            }
+
+            #[inline(always)]
+            fn split_off(&self, size: usize) -> Self {
+                let mut v = self.$write();
+                let at = size.min(v.len());
+                $lock::new(v.split_off(at))
+            }
+
+            #[inline(always)]
+            fn is_exclusive(&self) -> bool {
+                true
+            }
+        }
+
+        impl<R: $raw, T> DoubleEndedQueue<T> for $lock<R, VecDeque<T>> {
+            #[inline(always)]
+            fn push_front(&self, v: T) {
+                self.$write().push_front(v)
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl<R: $raw, T: Ord> IntoDynQueue<T, $lock<R, BinaryHeap<T>>> for $lock<R, BinaryHeap<T>> {
+            #[inline(always)]
+            fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+                DynQueue::new(self)
+            }
+        }
+
+        impl<R: $raw, T: Ord> Queue<T> for $lock<R, BinaryHeap<T>> {
+            #[inline(always)]
+            fn push(&self, v: T) {
+                self.$write().push(v)
+            }
+
+            #[inline(always)]
+            fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+                self.$write().extend(iter)
+            }
+
+            #[inline(always)]
+            fn pop(&self) -> Option<T> {
+                self.$write().pop()
+            }
+
+            #[inline(always)]
+            fn pop_batch(&self, n: usize, _order: Option<Order>) -> Vec<T> {
+                let mut heap = self.$write();
+                (0..n).map_while(|_| heap.pop()).collect()
+            }
+
+            #[inline(always)]
+            fn len(&self) -> usize {
+                self.$read().len()
+            }
+
+            #[inline(always)]
+            fn reserve(&self, additional: usize) {
+                self.$write().reserve(additional)
+            }
+
+            #[inline(always)]
+            fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+                self.$write().retain(f)
+            }
+
+            #[inline(always)]
+            fn split_off(&self, size: usize) -> Self {
+                $lock::new(crate::queue::split_heap(&mut self.$write(), size))
+            }
+
+            #[inline(always)]
+            fn is_exclusive(&self) -> bool {
+                true
+            }
+        }
+    };
+}
+
+lock_api_queue!(Mutex, RawMutex, lock, lock);
+lock_api_queue!(RwLock, RawRwLock, write, read);
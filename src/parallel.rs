@@ -0,0 +1,5578 @@
+//! The rayon based `DynQueue` (with `feature = "std"`)
+
+/// Emit an event at `$level` tagged with the rayon thread index and the
+/// `key = value` fields (with `feature = "tracing"` or `feature = "log"`)
+///
+/// The target stays `"dynqueue"`, independent of the module emitting the event.
+macro_rules! trace {
+    ($level:ident, $msg:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!(
+            target: "dynqueue",
+            thread = ?rayon::current_thread_index(),
+            $($key = ?$value,)*
+            $msg
+        );
+        #[cfg(feature = "log")]
+        log::$level!(
+            target: "dynqueue",
+            concat!($msg, ": thread={:?}" $(, " ", stringify!($key), "={:?}")*),
+            rayon::current_thread_index() $(, $value)*
+        );
+    };
+}
+
+use crate::queue::split_heap;
+use crate::sync::{self, AtomicBool, AtomicUsize, Mutex, Ordering, Weak};
+use crate::{DoubleEndedQueue, Lane, Meta, Order, Queue};
+use backpressure::Backpressure;
+use context::Context;
+use counted::Counted;
+use dedup::DedupFilter;
+use delay::DelayQueue;
+use idle::Idle;
+use limit::KeyLimit;
+use progress::Progress;
+use rate::RateLimit;
+use rayon::iter::plumbing::{
+    bridge_unindexed, Consumer, Folder, Reducer, UnindexedConsumer, UnindexedProducer,
+};
+use rayon::iter::{Either, FromParallelIterator, IntoParallelIterator, ParallelExtend};
+use receiver::Receiving;
+use seeds::Seeding;
+use split::Splitting;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ops::{Range, RangeInclusive};
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, LockResult, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+mod aging;
+#[cfg(feature = "arena")]
+mod arena;
+mod backpressure;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub(crate) mod bloom;
+mod bound;
+mod btree;
+mod builder;
+#[cfg(feature = "crossbeam-channel")]
+mod channel;
+#[cfg(feature = "serde")]
+mod checkpoint;
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(feature = "concurrent-queue")]
+mod concurrent;
+mod context;
+mod control;
+pub(crate) mod counted;
+mod deadline;
+mod dedup;
+mod delay;
+#[cfg(feature = "crossbeam-deque")]
+mod deque;
+mod ext;
+mod fair;
+mod idle;
+#[cfg(feature = "dashmap")]
+mod keyed;
+mod lane;
+mod limit;
+mod progress;
+mod rate;
+mod receiver;
+mod seeds;
+mod shared_vec;
+#[cfg(feature = "spill")]
+mod spill;
+mod split;
+#[cfg(feature = "stats")]
+mod stats;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "metrics")]
+mod telemetry;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+mod watermark;
+
+pub use aging::AgingQueue;
+#[cfg(feature = "arena")]
+pub use arena::Arena;
+pub use bound::Bound;
+pub use builder::DynQueueBuilder;
+#[cfg(feature = "crossbeam-channel")]
+pub use channel::Channel;
+#[cfg(feature = "serde")]
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "compress")]
+pub use compress::{Block, Compressed};
+pub use control::Control;
+use control::{Link, Linked};
+pub use deadline::DeadlineQueue;
+#[cfg(feature = "crossbeam-deque")]
+pub use deque::Deque;
+pub use ext::DynMapExt;
+pub use fair::FairQueue;
+pub use lane::LaneQueue;
+pub use shared_vec::SharedVec;
+#[cfg(feature = "spill")]
+pub use spill::SpillQueue;
+pub use split::{QueueView, SplitPolicy, Weight, WeightedSplit};
+#[cfg(feature = "stats")]
+pub use stats::{Histogram, StatsHandle, WorkerStats};
+
+/// Trait to produce a new DynQueue
+pub trait IntoDynQueue<T, U: Queue<T>> {
+    /// new
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, U>;
+}
+
+/// What happens, when [`DynQueueHandle::enqueue`] hits a full queue
+///
+/// A queue is full, if its elements and the delayed elements of
+/// [`DynQueueHandle::enqueue_after`] reach the
+/// [`DynQueueBuilder::capacity_limit`], or a bounded queue refuses the
+/// element. The policy decides for every enqueued element, e.g. also of
+/// [`DynQueueHandle::enqueue_many`], [`DynQueueHandle::enqueue_front`] and
+/// [`DynQueueSender::send`]. A dropped or refused new element does not mark
+/// its key as seen, e.g. by [`DynQueueBuilder::dedup`].
+/// Set with [`DynQueueBuilder::overflow_policy`]. Without a policy,
+/// `enqueue` ignores the `capacity_limit` and waits for a bounded queue.
+///
+/// ```
+/// use dynqueue::{DynQueue, OverflowPolicy};
+///
+/// let result = DynQueue::builder()
+///     .capacity_limit(2)
+///     .overflow_policy(OverflowPolicy::DropOldest)
+///     .order(dynqueue::Order::Fifo)
+///     .build(vec![0])
+///     .into_iter()
+///     .map(|(handle, value)| {
+///         if value == 0 {
+///             for v in 1..=4 {
+///                 handle.enqueue(v).unwrap();
+///             }
+///         }
+///         value
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(result, vec![0, 3, 4]);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// silently drop the new element
+    DropNewest,
+    /// drop the oldest pending element of the queue to make room
+    DropOldest,
+    /// give the new element back with [`EnqueueError::Full`]
+    Reject,
+}
+
+/// What the `DynQueue` does, when a panic poisoned the lock of its queue
+///
+/// A panic while the lock of a std `RwLock` queue is held, e.g. in the
+/// iterator passed to [`DynQueueHandle::enqueue_many`] or in the `Ord` of an
+/// element of a `BinaryHeap`, poisons the queue. The element with the panic
+/// might be left in the queue or missing and the queue might be out of
+/// order. Set with [`DynQueueBuilder::poison_policy`].
+///
+/// ```
+/// use dynqueue::{DynQueue, PoisonPolicy, QueuePoisoned};
+/// use std::panic::{catch_unwind, AssertUnwindSafe};
+///
+/// let result = catch_unwind(AssertUnwindSafe(|| {
+///     DynQueue::builder()
+///         .poison_policy(PoisonPolicy::Propagate)
+///         .build(vec![1, 2])
+///         .into_iter()
+///         .map(|(handle, value)| {
+///             let _ = catch_unwind(AssertUnwindSafe(|| {
+///                 handle.enqueue_many((3..5).map(|v| if v == 4 { panic!("work item") } else { v }))
+///             }));
+///             value
+///         })
+///         .collect::<Vec<_>>()
+/// }));
+///
+/// assert!(result.unwrap_err().is::<QueuePoisoned>());
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PoisonPolicy {
+    /// go on with the elements of the poisoned queue
+    #[default]
+    Ignore,
+    /// drop the next element of the poisoned queue, clear the poison and go on
+    Skip,
+    /// cancel the iteration and panic with [`QueuePoisoned`]
+    Propagate,
+}
+
+/// Panic payload of [`PoisonPolicy::Propagate`] for a poisoned queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueuePoisoned;
+
+impl fmt::Display for QueuePoisoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the queue of the DynQueue was poisoned by a panic")
+    }
+}
+
+impl std::error::Error for QueuePoisoned {}
+
+/// Queue adapter, which stores the `Meta` data alongside every element
+///
+/// This enables e.g. [`DynQueueHandle::depth`] and [`DynQueue::with_max_depth`].
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, Tracked};
+///
+/// let mut result = Tracked::from(vec![0])
+///     .into_dyn_queue()
+///     .with_max_depth(3)
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         assert_eq!(handle.depth(), value);
+///         handle.enqueue(value + 1).unwrap();
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![0, 1, 2, 3]);
+/// ```
+#[derive(Default)]
+pub struct Tracked<U>(U);
+
+impl<U> Tracked<U> {
+    /// Wrap a queue of elements with their metadata
+    #[inline(always)]
+    pub fn new(queue: U) -> Self {
+        Tracked(queue)
+    }
+
+    /// Unwrap the inner queue
+    #[inline(always)]
+    pub fn into_inner(self) -> U {
+        self.0
+    }
+}
+
+impl<T> From<Vec<T>> for Tracked<RwLock<Vec<(Meta, T)>>> {
+    fn from(v: Vec<T>) -> Self {
+        Tracked(RwLock::new(
+            v.into_iter().map(|v| (Meta::default(), v)).collect(),
+        ))
+    }
+}
+
+impl<T> From<VecDeque<T>> for Tracked<RwLock<VecDeque<(Meta, T)>>> {
+    fn from(v: VecDeque<T>) -> Self {
+        Tracked(RwLock::new(
+            v.into_iter().map(|v| (Meta::default(), v)).collect(),
+        ))
+    }
+}
+
+impl<T, U: Queue<(Meta, T)>> IntoDynQueue<T, Tracked<U>> for Tracked<U> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T, U: Queue<(Meta, T)>> Queue<T> for Tracked<U> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.0.push((Meta::default(), v))
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.0.pop().map(|(_, v)| v)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    #[inline(always)]
+    fn split_off(&self, size: usize) -> Self {
+        Tracked(self.0.split_off(size))
+    }
+
+    #[inline(always)]
+    fn try_push(&self, v: T) -> Result<(), T> {
+        self.try_push_meta(Meta::default(), v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.push_batch_meta(Meta::default(), iter)
+    }
+
+    #[inline(always)]
+    fn reserve(&self, additional: usize) {
+        self.0.reserve(additional)
+    }
+
+    #[inline(always)]
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        self.0.retain(|(_, v)| f(v))
+    }
+
+    #[inline(always)]
+    fn retain_meta<F: FnMut(&Meta, &T) -> bool>(&self, mut f: F) {
+        self.0.retain(|(meta, v)| f(meta, v))
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        self.0.shares_queue(&other.0)
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        self.0.is_exclusive()
+    }
+
+    #[inline(always)]
+    fn push_meta(&self, meta: Meta, v: T) {
+        self.0.push((meta, v))
+    }
+
+    #[inline(always)]
+    fn try_push_meta(&self, meta: Meta, v: T) -> Result<(), T> {
+        self.0.try_push((meta, v)).map_err(|(_, v)| v)
+    }
+
+    #[inline(always)]
+    fn push_batch_meta<I: IntoIterator<Item = T>>(&self, meta: Meta, iter: I) {
+        self.0.push_batch(iter.into_iter().map(|v| (meta, v)))
+    }
+
+    #[inline(always)]
+    fn pop_meta(&self) -> Option<(Meta, T)> {
+        self.0.pop()
+    }
+
+    #[inline(always)]
+    fn pop_ordered(&self, order: Order) -> Option<T> {
+        self.0.pop_ordered(order).map(|(_, v)| v)
+    }
+
+    #[inline(always)]
+    fn pop_meta_ordered(&self, order: Order) -> Option<(Meta, T)> {
+        self.0.pop_ordered(order)
+    }
+
+    #[inline(always)]
+    fn has_cheap_fifo(&self) -> bool {
+        self.0.has_cheap_fifo()
+    }
+
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        self.0.is_poisoned()
+    }
+
+    #[inline(always)]
+    fn clear_poison(&self) {
+        self.0.clear_poison()
+    }
+
+    #[inline(always)]
+    fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+        self.0
+            .pop_batch(n, order)
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    #[inline(always)]
+    fn pop_batch_meta(&self, n: usize, order: Option<Order>) -> Vec<(Meta, T)> {
+        self.0.pop_batch(n, order)
+    }
+}
+
+impl<T, U: DoubleEndedQueue<(Meta, T)>> DoubleEndedQueue<T> for Tracked<U> {
+    #[inline(always)]
+    fn push_front(&self, v: T) {
+        self.0.push_front((Meta::default(), v))
+    }
+
+    #[inline(always)]
+    fn push_front_meta(&self, meta: Meta, v: T) {
+        self.0.push_front((meta, v))
+    }
+}
+
+/// Recover the guard of a poisoned std lock
+///
+/// A panic while holding the lock, e.g. in an iterator passed to
+/// `enqueue_many` or in a dedup key function, cannot leave the collections
+/// in an inconsistent state, so one panicking work item shall not poison the
+/// whole `DynQueue`.
+pub(crate) trait RecoverPoison<G> {
+    fn recover(self) -> G;
+}
+
+impl<G> RecoverPoison<G> for LockResult<G> {
+    #[inline(always)]
+    fn recover(self) -> G {
+        self.unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Arrays are collected into a `RwLock<Vec<T>>`
+///
+/// Any other iterator can be collected directly into a `DynQueue`, see
+/// the `FromIterator` implementation of [`DynQueue`].
+impl<T, const N: usize> IntoDynQueue<T, RwLock<Vec<T>>> for [T; N] {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<Vec<T>>> {
+        DynQueue::new(RwLock::new(Vec::from(self)))
+    }
+}
+
+/// Ranges are collected into a `RwLock<Vec<T>>`, e.g. to seed a search
+/// with `0..n`.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let mut result = (0..3)
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 2 {
+///             handle.enqueue(3).unwrap()
+///         };
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![0, 1, 2, 3]);
+/// ```
+impl<T> IntoDynQueue<T, RwLock<Vec<T>>> for Range<T>
+where
+    Range<T>: Iterator<Item = T>,
+{
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<Vec<T>>> {
+        DynQueue::new(RwLock::new(self.collect()))
+    }
+}
+
+/// Ranges are collected into a `RwLock<Vec<T>>`
+impl<T> IntoDynQueue<T, RwLock<Vec<T>>> for RangeInclusive<T>
+where
+    RangeInclusive<T>: Iterator<Item = T>,
+{
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<Vec<T>>> {
+        DynQueue::new(RwLock::new(self.collect()))
+    }
+}
+
+impl<T> IntoDynQueue<T, RwLock<Vec<T>>> for Vec<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<Vec<T>>> {
+        DynQueue::new(RwLock::new(self))
+    }
+}
+
+impl<T> IntoDynQueue<T, RwLock<Vec<T>>> for RwLock<Vec<T>> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<Vec<T>>> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<T> for RwLock<Vec<T>> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.write().recover().push(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.write().recover().extend(iter)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.write().recover().pop()
+    }
+
+    /// `Order::Fifo` has to shift all remaining elements on every pop, see
+    /// [`Queue::has_cheap_fifo`].
+    #[inline(always)]
+    fn pop_ordered(&self, order: Order) -> Option<T> {
+        let mut v = self.write().recover();
+        match order {
+            Order::Lifo => v.pop(),
+            Order::Fifo if v.is_empty() => None,
+            Order::Fifo => Some(v.remove(0)),
+        }
+    }
+
+    #[inline(always)]
+    fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+        let mut v = self.write().recover();
+        let n = n.min(v.len());
+        match order {
+            Some(Order::Fifo) => v.drain(..n).collect(),
+            _ => {
+                let at = v.len() - n;
+                v.drain(at..).rev().collect()
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.read().recover().len()
+    }
+
+    #[inline(always)]
+    fn reserve(&self, additional: usize) {
+        self.write().recover().reserve(additional)
+    }
+
+    #[inline(always)]
+    fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+        self.write().recover().retain(f)
+    }
+
+    #[inline(always)]
+    fn split_off(&self, size: usize) -> Self {
+        let mut v = self.write().recover();
+        let at = size.min(v.len());
+        RwLock::new(v.split_off(at))
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn has_cheap_fifo(&self) -> bool {
+        false
+    }
+
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        RwLock::is_poisoned(self)
+    }
+
+    #[inline(always)]
+    fn clear_poison(&self) {
+        RwLock::clear_poison(self)
+    }
+}
+
+impl<T> IntoDynQueue<T, RwLock<VecDeque<T>>> for VecDeque<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<VecDeque<T>>> {
+        DynQueue::new(RwLock::new(self))
+    }
+}
+
+impl<T> IntoDynQueue<T, RwLock<VecDeque<T>>> for RwLock<VecDeque<T>> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<VecDeque<T>>> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<T> for RwLock<VecDeque<T>> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.write().recover().push_back(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.write().recover().extend(iter)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.write().recover().pop_front()
+    }
+
+    #[inline(always)]
+    fn pop_ordered(&self, order: Order) -> Option<T> {
+        match order {
+            Order::Fifo => self.write().recover().pop_front(),
+            Order::Lifo => self.write().recover().pop_back(),
+        }
+    }
+
+    #[inline(always)]
+    fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+        let mut v = self.write().recover();
+        let n = n.min(v.len());
+        match order {
+            Some(Order::Lifo) => {
+                let at = v.len() - n;
+                v.drain(at..).rev().collect()
+            }
+            _ => v.drain(..n).collect(),
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.read().recover().len()
+    }
+
+    #[inline(always)]
+    fn reserve(&self, additional: usize) {
+        self.write().recover().reserve(additional)
+    }
+
+    #[inline(always)]
+    fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+        self.write().recover().retain(f)
+    }
+
+    #[inline(always)]
+    fn split_off(&self, size: usize) -> Self {
+        let mut v = self.write().recover();
+        let at = size.min(v.len());
+        RwLock::new(v.split_off(at))
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        RwLock::is_poisoned(self)
+    }
+
+    #[inline(always)]
+    fn clear_poison(&self) {
+        RwLock::clear_poison(self)
+    }
+}
+
+impl<T> DoubleEndedQueue<T> for RwLock<VecDeque<T>> {
+    #[inline(always)]
+    fn push_front(&self, v: T) {
+        self.write().recover().push_front(v)
+    }
+}
+
+impl<T: Ord> IntoDynQueue<T, RwLock<BinaryHeap<T>>> for BinaryHeap<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<BinaryHeap<T>>> {
+        DynQueue::new(RwLock::new(self))
+    }
+}
+
+impl<T: Ord> IntoDynQueue<T, RwLock<BinaryHeap<T>>> for RwLock<BinaryHeap<T>> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, RwLock<BinaryHeap<T>>> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T: Ord> Queue<T> for RwLock<BinaryHeap<T>> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.write().recover().push(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.write().recover().extend(iter)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.write().recover().pop()
+    }
+
+    #[inline(always)]
+    fn pop_batch(&self, n: usize, _order: Option<Order>) -> Vec<T> {
+        let mut heap = self.write().recover();
+        (0..n).map_while(|_| heap.pop()).collect()
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.read().recover().len()
+    }
+
+    #[inline(always)]
+    fn reserve(&self, additional: usize) {
+        self.write().recover().reserve(additional)
+    }
+
+    #[inline(always)]
+    fn retain<F: FnMut(&T) -> bool>(&self, f: F) {
+        self.write().recover().retain(f)
+    }
+
+    #[inline(always)]
+    fn split_off(&self, size: usize) -> Self {
+        RwLock::new(split_heap(&mut self.write().recover(), size))
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        RwLock::is_poisoned(self)
+    }
+
+    #[inline(always)]
+    fn clear_poison(&self) {
+        RwLock::clear_poison(self)
+    }
+}
+
+#[cfg(feature = "crossbeam-queue")]
+use crossbeam_queue::SegQueue;
+
+#[cfg(feature = "crossbeam-queue")]
+impl<T> IntoDynQueue<T, SegQueue<T>> for SegQueue<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+/// Turn any collection into a `DynQueue` with the lock-free
+/// `crossbeam_queue::SegQueue` (with `feature = "crossbeam-queue"`)
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::IntoLockFreeDynQueue as _;
+///
+/// let mut result = vec![1, 2, 3]
+///     .into_dyn_queue_lockfree()
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 2 {
+///             handle.enqueue(4).unwrap()
+///         };
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3, 4]);
+/// ```
+#[cfg(feature = "crossbeam-queue")]
+pub trait IntoLockFreeDynQueue<T> {
+    /// Turn `self` into a `DynQueue` with a `SegQueue`
+    fn into_dyn_queue_lockfree<'a>(self) -> DynQueue<'a, T, SegQueue<T>>;
+}
+
+#[cfg(feature = "crossbeam-queue")]
+impl<T, I: IntoIterator<Item = T>> IntoLockFreeDynQueue<T> for I {
+    #[inline(always)]
+    fn into_dyn_queue_lockfree<'a>(self) -> DynQueue<'a, T, SegQueue<T>> {
+        let queue = SegQueue::new();
+        self.into_iter().for_each(|v| queue.push(v));
+        DynQueue::new(queue)
+    }
+}
+
+#[cfg(feature = "crossbeam-queue")]
+impl<T> Queue<T> for SegQueue<T> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        SegQueue::push(self, v);
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        SegQueue::pop(self)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        SegQueue::len(self)
+    }
+
+    #[inline(always)]
+    fn split_off(&self, size: usize) -> Self {
+        let q = SegQueue::new();
+        (0..size)
+            .filter_map(|_| Queue::pop(self))
+            .for_each(|ele| q.push(ele));
+        q
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(feature = "crossbeam-queue")]
+use crossbeam_queue::ArrayQueue;
+
+#[cfg(feature = "crossbeam-queue")]
+impl<T> IntoDynQueue<T, Arc<ArrayQueue<T>>> for ArrayQueue<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Arc<ArrayQueue<T>>> {
+        DynQueue::new(Arc::new(self))
+    }
+}
+
+#[cfg(feature = "crossbeam-queue")]
+impl<T> IntoDynQueue<T, Arc<ArrayQueue<T>>> for Arc<ArrayQueue<T>> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+/// A bounded queue, which is shared by all parallel iterators.
+///
+/// Splitting is a no-op: every parallel iterator pops from the same queue,
+/// which distributes the work without moving any element.
+///
+/// The capacity is never exceeded. [`DynQueueHandle::try_enqueue`] and,
+/// with an [`OverflowPolicy`], [`DynQueueHandle::enqueue`] return
+/// [`EnqueueError::Full`] for a full queue. Without an overflow policy,
+/// `enqueue` panics on a full queue, because waiting for room could block
+/// all workers forever.
+#[cfg(feature = "crossbeam-queue")]
+impl<T> Queue<T> for Arc<ArrayQueue<T>> {
+    #[inline]
+    fn push(&self, v: T) {
+        if ArrayQueue::push(self, v).is_err() {
+            panic!(
+                "ArrayQueue with a capacity of {} is full, use `try_enqueue` or an `OverflowPolicy`",
+                self.capacity()
+            );
+        }
+    }
+
+    #[inline(always)]
+    fn try_push(&self, v: T) -> Result<(), T> {
+        ArrayQueue::push(self, v)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        ArrayQueue::pop(self)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        ArrayQueue::len(self)
+    }
+
+    /// A bounded queue cannot be split without exceeding the capacity,
+    /// so the new iterator shares the same queue and nothing is moved.
+    #[inline(always)]
+    fn split_off(&self, _size: usize) -> Self {
+        self.clone()
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        Arc::ptr_eq(self, other)
+    }
+}
+
+/// Token to cancel the iteration of a `DynQueue`
+///
+/// All clones of a token share the same state.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{CancelToken, IntoDynQueue as _};
+///
+/// let token = CancelToken::new();
+///
+/// let count = vec![0u64]
+///     .into_dyn_queue()
+///     .with_cancel(token.clone())
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 100 {
+///             handle.cancel();
+///         }
+///         handle.enqueue(value + 1).unwrap();
+///     })
+///     .count();
+///
+/// assert!(token.is_cancelled());
+/// assert!(count <= 101);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Create a new token, which is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request the termination of the iteration
+    ///
+    /// No further elements are popped or enqueued.
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    /// `true`, if the iteration was cancelled
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Handle to the elements, which were not processed, because the iteration
+/// stopped early
+///
+/// Elements remaining in the queues of the parallel iterators, e.g. after
+/// the deadline passed, the iteration was cancelled or a short-circuiting
+/// consumer like `find_any()` finished, are collected here.
+///
+/// With `.into_dyn_queue()` the leftovers can be processed later on,
+/// including their metadata, e.g. the depth.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+/// use std::time::Duration;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let queue = vec![0u64]
+///     .into_dyn_queue()
+///     .with_timeout(Duration::from_millis(10));
+/// let leftovers = queue.leftovers();
+///
+/// let processed = queue
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         handle.enqueue_many(vec![value + 1, value + 1]).unwrap();
+///     })
+///     .count();
+///
+/// assert_eq!(leftovers.take().len(), processed + 1);
+/// ```
+pub struct Leftovers<T>(Arc<Mutex<Vec<(Meta, T)>>>);
+
+impl<T> Clone for Leftovers<T> {
+    fn clone(&self) -> Self {
+        Leftovers(self.0.clone())
+    }
+}
+
+impl<T> Leftovers<T> {
+    /// Take all collected elements
+    pub fn take(&self) -> Vec<T> {
+        std::mem::take(&mut *self.0.lock().recover())
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// Number of collected elements
+    pub fn len(&self) -> usize {
+        self.0.lock().recover().len()
+    }
+
+    /// `true`, if no element was collected
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().recover().is_empty()
+    }
+}
+
+impl<T, U: Queue<T> + Default> IntoDynQueue<T, U> for Leftovers<T> {
+    /// Take all collected elements into a new `DynQueue`
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, U> {
+        let queue = U::default();
+        std::mem::take(&mut *self.0.lock().recover())
+            .into_iter()
+            .for_each(|(meta, v)| queue.push_meta(meta, v));
+        DynQueue::new(queue)
+    }
+}
+
+/// An element, which failed permanently, see [`DeadLetters`]
+pub struct DeadLetter<T> {
+    /// The failed element
+    pub item: T,
+    /// Number of times the element was requeued
+    pub attempts: usize,
+    /// The error passed to [`DynQueueHandle::reject`], or `None`, if the
+    /// element exceeded the maximum retries
+    pub error: Option<Box<dyn std::error::Error + Send + Sync>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for DeadLetter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetter")
+            .field("item", &self.item)
+            .field("attempts", &self.attempts)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Handle to the elements, which failed permanently during the iteration
+///
+/// Elements rejected with [`DynQueueHandle::reject`] and elements
+/// requeued more than [`DynQueue::with_max_retries`] times are collected
+/// here, as long as a `DeadLetters` handle exists. This separates the
+/// failed elements from the results of the iteration.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let queue = vec![1u64, 2, 3, 4].into_dyn_queue().with_max_retries(2);
+/// let dead_letters = queue.dead_letters();
+///
+/// let mut result = queue
+///     .into_par_iter()
+///     .filter_map(|(handle, value)| match value {
+///         // fails permanently
+///         1 => {
+///             handle.reject(value, "invalid").unwrap();
+///             None
+///         }
+///         // fails transiently, until the retries are exhausted
+///         2 => {
+///             handle.requeue(value).unwrap();
+///             None
+///         }
+///         _ => Some(value),
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![3, 4]);
+///
+/// let mut failed = dead_letters.take();
+/// failed.sort_by_key(|dead| dead.item);
+/// assert_eq!(failed[0].item, 1);
+/// assert_eq!(failed[0].error.as_ref().unwrap().to_string(), "invalid");
+/// assert_eq!(failed[1].item, 2);
+/// assert_eq!(failed[1].attempts, 2);
+/// assert!(failed[1].error.is_none());
+/// ```
+pub struct DeadLetters<T>(Arc<Mutex<Vec<DeadLetter<T>>>>);
+
+impl<T> Clone for DeadLetters<T> {
+    fn clone(&self) -> Self {
+        DeadLetters(self.0.clone())
+    }
+}
+
+impl<T> DeadLetters<T> {
+    /// Take all collected elements
+    pub fn take(&self) -> Vec<DeadLetter<T>> {
+        std::mem::take(&mut *self.0.lock().recover())
+    }
+
+    /// Number of collected elements
+    pub fn len(&self) -> usize {
+        self.0.lock().recover().len()
+    }
+
+    /// `true`, if no element was collected
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().recover().is_empty()
+    }
+}
+
+/// Handle to the parents of all elements of a `DynQueue`
+///
+/// Get it with [`DynQueue::lineage`] before the iteration starts. While a
+/// handle exists, every element gets a unique id, which is the index in the
+/// table of parents. The initial elements get the ids `0..n` and have no
+/// parent.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, Tracked};
+///
+/// let queue = Tracked::from(vec![1u64]).into_dyn_queue();
+/// let lineage = queue.lineage();
+///
+/// let mut result = queue
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value < 4 {
+///             handle.enqueue(value * 2).unwrap();
+///         }
+///         (handle.current_id().unwrap(), value)
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort_by_key(|(_, value)| *value);
+///
+/// let (id, _) = result[2];
+/// assert_eq!(lineage.ancestors(id), vec![result[1].0, result[0].0]);
+/// assert_eq!(lineage.parents(), vec![None, Some(0), Some(1)]);
+/// ```
+pub struct Lineage(Arc<Mutex<LineageTable>>);
+
+/// Parents and number of children of the elements indexed by their id
+#[derive(Default)]
+struct LineageTable {
+    parents: Vec<Option<usize>>,
+    children: Vec<usize>,
+}
+
+impl LineageTable {
+    #[inline]
+    fn parent_of(&self, id: usize) -> Option<usize> {
+        self.parents.get(id).copied().flatten()
+    }
+
+    /// Record a new element with `parent` and return its id
+    #[inline]
+    fn push(&mut self, parent: Option<usize>) -> usize {
+        let id = self.parents.len();
+        self.parents.push(parent);
+        self.children.push(0);
+        if let Some(parent) = parent {
+            self.children[parent] += 1;
+        }
+        id
+    }
+}
+
+impl Clone for Lineage {
+    fn clone(&self) -> Self {
+        Lineage(self.0.clone())
+    }
+}
+
+impl fmt::Debug for Lineage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lineage").field("len", &self.len()).finish()
+    }
+}
+
+impl Lineage {
+    /// The parent of the element with `id`, `None` for an initial or
+    /// unknown element
+    pub fn parent_of(&self, id: usize) -> Option<usize> {
+        self.0.lock().recover().parent_of(id)
+    }
+
+    /// Number of elements enqueued by the handle of the element with `id`
+    pub fn children_of(&self, id: usize) -> usize {
+        self.0
+            .lock()
+            .recover()
+            .children
+            .get(id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The parent, grandparent, etc. of the element with `id` up to an
+    /// initial element
+    pub fn ancestors(&self, id: usize) -> Vec<usize> {
+        let table = self.0.lock().recover();
+        let mut ancestors = Vec::new();
+        let mut current = id;
+        while let Some(parent) = table.parent_of(current) {
+            ancestors.push(parent);
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// The table of parents indexed by the id of the elements
+    pub fn parents(&self) -> Vec<Option<usize>> {
+        self.0.lock().recover().parents.clone()
+    }
+
+    /// Number of elements with an id
+    pub fn len(&self) -> usize {
+        self.0.lock().recover().parents.len()
+    }
+
+    /// `true`, if no element got an id
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().recover().parents.is_empty()
+    }
+}
+
+/// Ticket for the result of an element enqueued with
+/// [`DynQueueHandle::enqueue_ticket`]
+///
+/// The ticket is the id of the element in the [`Lineage`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ticket(usize);
+
+impl Ticket {
+    /// The id of the element
+    pub fn id(self) -> usize {
+        self.0
+    }
+}
+
+/// Results of [`DynQueue::collect_tickets`] keyed by the [`Ticket`] of
+/// their element
+///
+/// ```
+/// use dynqueue::{IntoDynQueue as _, Tracked};
+///
+/// let mut results = Tracked::from(vec![1u64, 2])
+///     .into_dyn_queue()
+///     .collect_tickets(|handle, value| {
+///         if value < 10 {
+///             let ticket = handle.enqueue_ticket(value * 10).unwrap();
+///             return (value, ticket);
+///         }
+///         (value, None)
+///     });
+///
+/// assert_eq!(results.len(), 4);
+/// let (_, ticket) = results.take_initial(0).unwrap();
+/// assert_eq!(results.take(ticket.unwrap()), Some((10, None)));
+/// assert_eq!(results.take(ticket.unwrap()), None);
+/// ```
+#[derive(Debug)]
+pub struct Tickets<R> {
+    results: HashMap<usize, R>,
+}
+
+impl<R> Tickets<R> {
+    /// The result of the element of `ticket`
+    pub fn get(&self, ticket: Ticket) -> Option<&R> {
+        self.results.get(&ticket.0)
+    }
+
+    /// Remove and return the result of the element of `ticket`
+    pub fn take(&mut self, ticket: Ticket) -> Option<R> {
+        self.results.remove(&ticket.0)
+    }
+
+    /// Remove and return the result of the initial element at `index` in
+    /// the order of the queue
+    pub fn take_initial(&mut self, index: usize) -> Option<R> {
+        self.results.remove(&index)
+    }
+
+    /// Number of results
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// `true`, if there are no results
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+impl<R> IntoIterator for Tickets<R> {
+    type Item = (Ticket, R);
+    type IntoIter = std::iter::Map<
+        std::collections::hash_map::IntoIter<usize, R>,
+        fn((usize, R)) -> (Ticket, R),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.results.into_iter().map(|(id, r)| (Ticket(id), r))
+    }
+}
+
+/// Filter of the enqueued elements, e.g. of [`DynQueue::with_dedup`]
+pub(crate) trait ElementFilter<T>: Send + Sync {
+    /// `true`, if `v` passes, which marks the key of `v` as seen
+    fn pass(&self, v: &T) -> bool;
+
+    /// `true`, if `v` would pass, without marking the key of `v` as seen
+    fn check(&self, v: &T) -> bool;
+
+    /// Forget the key of `v`, which passed, but was not enqueued after all
+    #[inline(always)]
+    fn forget(&self, _v: &T) {}
+}
+
+/// Filter of [`DynQueue::with_filter`], which only looks at the element
+pub(crate) struct Predicate<F>(pub(crate) F);
+
+impl<T, F: Fn(&T) -> bool + Send + Sync> ElementFilter<T> for Predicate<F> {
+    #[inline(always)]
+    fn pass(&self, v: &T) -> bool {
+        (self.0)(v)
+    }
+
+    #[inline(always)]
+    fn check(&self, v: &T) -> bool {
+        (self.0)(v)
+    }
+}
+
+pub(crate) type Filter<'a, T> = Box<dyn ElementFilter<T> + 'a>;
+type Prune<'a, T> = Box<dyn Fn(&T) -> bool + Send + Sync + 'a>;
+
+/// `true`, if `v` passes all `filters`, which mark its key as seen
+///
+/// If a filter refuses `v`, the filters before it forget its key again.
+#[inline]
+fn passes<T>(filters: &[Filter<'_, T>], v: &T) -> bool {
+    match filters.iter().position(|f| !f.pass(v)) {
+        Some(refused) => {
+            filters[..refused].iter().for_each(|f| f.forget(v));
+            false
+        }
+        None => true,
+    }
+}
+pub(crate) type Shards<T, U> = Mutex<Vec<Weak<Counted<T, U>>>>;
+type Retries<T> = Counted<(Meta, T), RwLock<VecDeque<(Meta, T)>>>;
+
+/// Filter, which passes every element with a key not seen before
+fn dedup_filter<'a, T, K, F>(key_fn: F) -> Filter<'a, T>
+where
+    K: Hash + Eq + Send + 'a,
+    F: Fn(&T) -> K + Send + Sync + 'a,
+{
+    dedup_filter_with_hasher(key_fn, RandomState::new())
+}
+
+/// Filter, which passes every element with a key not seen before, using
+/// `hasher` for the set of seen keys
+fn dedup_filter_with_hasher<'a, T, K, F, S>(key_fn: F, hasher: S) -> Filter<'a, T>
+where
+    K: Hash + Eq + Send + 'a,
+    F: Fn(&T) -> K + Send + Sync + 'a,
+    S: BuildHasher + Send + Sync + 'a,
+{
+    Box::new(DedupFilter::new(key_fn, hasher))
+}
+
+/// State shared by all parallel iterators split off the same `DynQueue`
+pub(crate) struct Shared<'a, T> {
+    filters: Vec<Filter<'a, T>>,
+    cancel: CancelToken,
+    max_depth: usize,
+    split: Splitting<'a, T>,
+    capacity_limit: Option<usize>,
+    overflow: Option<OverflowPolicy>,
+    poison: PoisonPolicy,
+    order: Option<Order>,
+    pop_batch_size: usize,
+    enqueue_buffer_size: usize,
+    progress: Option<Box<Progress<'a>>>,
+    deadline: Option<Instant>,
+    context: Option<Box<Context>>,
+    prune: Option<Prune<'a, T>>,
+    leftovers: Arc<Mutex<Vec<(Meta, T)>>>,
+    senders: AtomicUsize,
+    receiver: Option<Box<Receiving<T>>>,
+    seeds: Option<Box<Seeding<'a, T>>>,
+    waiter: Mutex<Option<std::thread::Thread>>,
+    backpressure: Option<Box<Backpressure<'a>>>,
+    pub(crate) in_flight: AtomicUsize,
+    pub(crate) idle: Idle,
+    rate_limit: Option<RateLimit>,
+    key_limit: Option<KeyLimit<'a, T>>,
+    sequence: Option<AtomicUsize>,
+    index: Option<AtomicUsize>,
+    max_retries: Option<usize>,
+    retries: Retries<T>,
+    dead_letters: Arc<Mutex<Vec<DeadLetter<T>>>>,
+    delayed: DelayQueue<T>,
+    lineage: Arc<Mutex<LineageTable>>,
+    paused: AtomicBool,
+    shutdown: AtomicBool,
+    #[cfg(feature = "stats")]
+    stats: Arc<stats::Counters>,
+    #[cfg(feature = "metrics")]
+    metrics: telemetry::Metrics,
+}
+
+impl<'a, T> Shared<'a, T> {
+    fn new() -> Self {
+        Shared {
+            filters: Vec::new(),
+            cancel: CancelToken::new(),
+            max_depth: usize::MAX,
+            split: Splitting::new(),
+            capacity_limit: None,
+            overflow: None,
+            poison: PoisonPolicy::Ignore,
+            order: None,
+            pop_batch_size: 1,
+            enqueue_buffer_size: 1,
+            progress: None,
+            deadline: None,
+            context: None,
+            prune: None,
+            leftovers: Default::default(),
+            senders: AtomicUsize::new(0),
+            receiver: None,
+            seeds: None,
+            waiter: Mutex::new(None),
+            backpressure: None,
+            in_flight: AtomicUsize::new(0),
+            idle: Idle::new(),
+            rate_limit: None,
+            key_limit: None,
+            sequence: None,
+            index: None,
+            max_retries: None,
+            retries: Counted::new(RwLock::new(VecDeque::new())),
+            dead_letters: Default::default(),
+            delayed: Default::default(),
+            lineage: Default::default(),
+            paused: AtomicBool::new(false),
+            shutdown: AtomicBool::new(false),
+            #[cfg(feature = "stats")]
+            stats: Default::default(),
+            #[cfg(feature = "metrics")]
+            metrics: Default::default(),
+        }
+    }
+
+    #[inline(always)]
+    fn on_start(&self, pending: usize) {
+        #[cfg(feature = "stats")]
+        self.stats.start(pending);
+        #[cfg(feature = "metrics")]
+        self.metrics.start(pending);
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.start(pending);
+        }
+    }
+
+    /// The [`Progress`] to configure
+    fn progress_mut(&mut self) -> &mut Progress<'a> {
+        self.progress
+            .get_or_insert_with(|| Box::new(Progress::new()))
+    }
+
+    /// The [`Seeding`] to configure
+    fn seeding_mut(&mut self) -> &mut Seeding<'a, T> {
+        self.seeds.get_or_insert_with(|| Box::new(Seeding::new()))
+    }
+
+    /// The [`Backpressure`] to configure
+    fn backpressure_mut(&mut self) -> &mut Backpressure<'a> {
+        self.backpressure
+            .get_or_insert_with(|| Box::new(Backpressure::new()))
+    }
+
+    #[inline(always)]
+    fn on_pop(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.pop();
+        #[cfg(feature = "metrics")]
+        self.metrics.pop();
+        self.on_drop();
+    }
+
+    /// Account for an element leaving the queue
+    #[inline(always)]
+    fn on_drop(&self) {
+        #[cfg(feature = "metrics")]
+        self.metrics.drop();
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.remove();
+        }
+    }
+
+    #[inline(always)]
+    fn on_enqueue(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.enqueue();
+        #[cfg(feature = "metrics")]
+        self.metrics.enqueue();
+        trace!(trace, "enqueue");
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.add();
+        }
+    }
+
+    /// `true`, if someone collects the failed elements
+    #[inline(always)]
+    fn has_dead_letters(&self) -> bool {
+        Arc::strong_count(&self.dead_letters) > 1
+    }
+
+    /// The next global sequence number, if enabled
+    #[inline(always)]
+    fn next_sequence(&self) -> Option<usize> {
+        self.sequence
+            .as_ref()
+            .map(|sequence| sequence.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// `true`, if someone tracks the lineage of the elements
+    #[inline(always)]
+    fn has_lineage(&self) -> bool {
+        Arc::strong_count(&self.lineage) > 1
+    }
+
+    /// `true`, if every enqueued element needs its own `Meta`
+    #[inline(always)]
+    fn stamps_each(&self) -> bool {
+        self.index.is_some() || self.has_lineage()
+    }
+
+    /// Give `meta` of an enqueued element the next index and id, if enabled
+    #[inline(always)]
+    fn stamp(&self, mut meta: Meta) -> Meta {
+        if let Some(index) = &self.index {
+            meta.index = index.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.has_lineage() {
+            meta.id = Some(self.lineage.lock().recover().push(meta.parent));
+        }
+        meta
+    }
+
+    /// Undo the count of a stamped element as a child of its parent, if it
+    /// was not enqueued after all
+    #[inline(always)]
+    fn unstamp(&self, meta: &Meta) {
+        if let (Some(_), Some(parent)) = (meta.id, meta.parent) {
+            self.lineage.lock().recover().children[parent] -= 1;
+        }
+    }
+
+    /// Block, until the rate limit allows to enqueue `n` more elements.
+    #[inline(always)]
+    fn throttle(&self, n: usize) {
+        if let Some(rate_limit) = &self.rate_limit {
+            rate_limit.acquire(n);
+        }
+    }
+
+    /// `true`, if more than `max_pending` elements are pending
+    #[inline(always)]
+    fn is_over_max_pending(&self) -> bool {
+        self.backpressure
+            .as_ref()
+            .is_some_and(|backpressure| backpressure.is_over_max_pending())
+    }
+
+    /// Block, while more than `max_pending` elements are pending.
+    ///
+    /// See [`Backpressure::wait_for_capacity`].
+    fn wait_for_capacity(&self) {
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.wait_for_capacity(|| self.is_stopped());
+        }
+    }
+
+    /// Account for the split of a queue with `len` elements
+    #[inline(always)]
+    #[cfg_attr(not(feature = "stats"), allow(unused_variables))]
+    fn on_split(&self, len: usize) {
+        #[cfg(feature = "stats")]
+        self.stats.split(len);
+        #[cfg(feature = "metrics")]
+        self.metrics.split();
+    }
+
+    /// `true`, if no further elements shall be popped
+    #[inline]
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.cancel.is_cancelled()
+            || self.is_shut_down()
+            || self.deadline.is_some_and(|d| Instant::now() >= d)
+            || self
+                .progress
+                .as_ref()
+                .is_some_and(|progress| progress.is_exhausted())
+    }
+
+    /// `true`, if the iteration was paused with `Control::pause`
+    #[inline(always)]
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// `true`, if the iteration was shut down with `Control::shutdown`
+    #[inline(always)]
+    fn is_shut_down(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    /// `true`, if a [`DynQueueSender`] is alive and might send elements
+    #[inline(always)]
+    fn has_senders(&self) -> bool {
+        self.senders.load(Ordering::Acquire) > 0
+    }
+
+    /// Park the current thread, until a [`DynQueueSender`] sent an element
+    /// or was dropped, or a short timeout passed.
+    fn wait_for_senders(&self) {
+        *self.waiter.lock().recover() = Some(std::thread::current());
+        std::thread::park_timeout(Duration::from_millis(1));
+    }
+
+    /// `true`, if the receiver of [`DynQueue::with_receiver`] is connected
+    /// and might receive elements
+    #[inline(always)]
+    fn is_receiving(&self) -> bool {
+        self.receiver
+            .as_ref()
+            .is_some_and(|receiver| receiver.is_connected())
+    }
+
+    /// Receive the next element from the receiver of
+    /// [`DynQueue::with_receiver`], waiting up to `timeout` for it.
+    ///
+    /// Without a `timeout`, another thread receiving at the same time is
+    /// not waited for either. Elements refused by the filters are skipped.
+    fn receive(&self, timeout: Duration) -> Option<(Meta, T)> {
+        let meta = Meta::default();
+        let v = self
+            .receiver
+            .as_ref()?
+            .receive(timeout, |v| self.admit(&meta, v))?;
+        self.on_enqueue();
+        Some((self.stamp(meta), v))
+    }
+
+    /// Take all elements waiting in the receiver of
+    /// [`DynQueue::with_receiver`] and stop receiving.
+    fn close_receiver(&self) -> Vec<T> {
+        self.receiver
+            .as_ref()
+            .map_or_else(Vec::new, |receiver| receiver.close())
+    }
+
+    /// Wake up the thread waiting for a [`DynQueueSender`]
+    fn wake_waiter(&self) {
+        if let Some(waiter) = &*self.waiter.lock().recover() {
+            waiter.unpark();
+        }
+    }
+
+    /// `true`, if a parallel iterator is processing an element, which might
+    /// enqueue new elements to steal
+    ///
+    /// Elements in flight further down the stack of the current thread are
+    /// not waited for, because they cannot complete before the caller
+    /// returns, e.g. if a nested rayon join stole a parallel iterator.
+    #[inline]
+    fn awaits_in_flight(&self) -> bool {
+        self.in_flight.load(Ordering::Acquire) > 0
+            && IN_FLIGHT_HERE.with(Cell::get) == 0
+            && !self.is_stopped()
+    }
+
+    /// Release a slot of the key limit and requeue the next deferred
+    /// element of the key
+    fn release_key(&self, key: u64) {
+        if let Some(ele) = self.key_limit.as_ref().and_then(|limit| limit.release(key)) {
+            self.retries.push(ele);
+        }
+    }
+
+    /// `true`, if `v` with `meta` passes all filters and shall be enqueued
+    #[inline]
+    fn admit(&self, meta: &Meta, v: &T) -> bool {
+        self.accepts(meta, v) && self.passes(v)
+    }
+
+    /// `true`, if `v` with `meta` shall be enqueued, before it runs through
+    /// the filters
+    #[inline(always)]
+    fn accepts(&self, meta: &Meta, v: &T) -> bool {
+        !self.cancel.is_cancelled() && meta.depth <= self.max_depth && !self.is_pruned(v)
+    }
+
+    /// `true`, if `v` passes all filters, which mark its key as seen
+    #[inline(always)]
+    fn passes(&self, v: &T) -> bool {
+        passes(&self.filters, v)
+    }
+
+    /// `true`, if `v` would pass all filters, without marking its key as seen
+    #[inline]
+    fn would_pass(&self, v: &T) -> bool {
+        self.filters.iter().all(|f| f.check(v))
+    }
+
+    /// Forget the key of `v`, which passed the filters, but was not enqueued
+    /// after all
+    #[inline]
+    fn forget(&self, v: &T) {
+        self.filters.iter().for_each(|f| f.forget(v));
+    }
+
+    /// The shared state of type `S`
+    fn state<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.context.as_ref().and_then(|context| context.state())
+    }
+
+    /// The [`Context`] to configure
+    fn context_mut(&mut self) -> &mut Context {
+        self.context.get_or_insert_with(|| Box::new(Context::new()))
+    }
+
+    /// `true`, if `v` cannot improve the `Bound` anymore
+    #[inline(always)]
+    fn is_pruned(&self, v: &T) -> bool {
+        self.prune.as_ref().is_some_and(|prune| prune(v))
+    }
+}
+
+#[cfg(not(loom))]
+std::thread_local! {
+    /// Number of elements of all `DynQueue`s in flight on the current thread
+    static IN_FLIGHT_HERE: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(loom)]
+loom::thread_local! {
+    static IN_FLIGHT_HERE: Cell<usize> = Cell::new(0);
+}
+
+#[cfg(not(loom))]
+std::thread_local! {
+    /// The `DynQueueInner` and its `Arc` lent by the parallel iterator on the
+    /// current thread, see [`Lend`]
+    static LENT: Cell<(*const (), *const ())> = const { Cell::new((std::ptr::null(), std::ptr::null())) };
+}
+
+#[cfg(loom)]
+loom::thread_local! {
+    static LENT: Cell<(*const (), *const ())> = Cell::new((std::ptr::null(), std::ptr::null()));
+}
+
+/// Lends the queue of a parallel iterator to the `DynQueueHandle`s on the
+/// same thread, while it processes an element.
+///
+/// The handles of the element use the queue directly instead of upgrading
+/// their `Weak` for every call. Nested parallel iterators lend their queue
+/// on top and give the previous one back, when they are done.
+struct Lend((*const (), *const ()));
+
+impl Lend {
+    fn new<T, U: Queue<T>>(inner: &sync::Arc<DynQueueInner<'_, T, U>>) -> Self {
+        let lent = (
+            sync::Arc::as_ptr(inner).cast::<()>(),
+            (inner as *const sync::Arc<DynQueueInner<'_, T, U>>).cast::<()>(),
+        );
+        Lend(LENT.with(|current| current.replace(lent)))
+    }
+}
+
+impl Drop for Lend {
+    fn drop(&mut self) {
+        LENT.with(|current| current.set(self.0));
+    }
+}
+
+/// The queue of a `DynQueueHandle`, lent by the parallel iterator or
+/// upgraded from the `Weak`
+enum Upgraded<'s, 'a, T, U: Queue<T>> {
+    Lent(&'s sync::Arc<DynQueueInner<'a, T, U>>),
+    Owned(sync::Arc<DynQueueInner<'a, T, U>>),
+}
+
+impl<'s, 'a, T, U: Queue<T>> std::ops::Deref for Upgraded<'s, 'a, T, U> {
+    type Target = sync::Arc<DynQueueInner<'a, T, U>>;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Upgraded::Lent(inner) => inner,
+            Upgraded::Owned(inner) => inner,
+        }
+    }
+}
+
+/// Counts an element, which was popped, but is not processed completely
+struct InFlight<'s, 'a, T>(&'s Shared<'a, T>);
+
+impl<'s, 'a, T> InFlight<'s, 'a, T> {
+    fn new(shared: &'s Shared<'a, T>) -> Self {
+        shared.in_flight.fetch_add(1, Ordering::AcqRel);
+        IN_FLIGHT_HERE.with(|here| here.set(here.get() + 1));
+        InFlight(shared)
+    }
+}
+
+impl<'s, 'a, T> Drop for InFlight<'s, 'a, T> {
+    fn drop(&mut self) {
+        IN_FLIGHT_HERE.with(|here| here.set(here.get() - 1));
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+        // everything the element enqueued is in the queues now
+        self.0.idle.wake(true);
+    }
+}
+
+/// Holds a slot of the key limit for an element in flight
+struct KeySlot<'s, 'a, T>(&'s Shared<'a, T>, u64);
+
+impl<'s, 'a, T> Drop for KeySlot<'s, 'a, T> {
+    fn drop(&mut self) {
+        self.0.release_key(self.1);
+    }
+}
+
+/// Counts a running parallel iterator for the backpressure of `max_pending`
+struct Active<'a, T>(sync::Arc<Shared<'a, T>>);
+
+impl<'a, T> Active<'a, T> {
+    fn new(shared: &sync::Arc<Shared<'a, T>>) -> Option<Self> {
+        let backpressure = shared.backpressure.as_ref()?;
+        backpressure.enter().then(|| Active(shared.clone()))
+    }
+}
+
+impl<'a, T> Drop for Active<'a, T> {
+    fn drop(&mut self) {
+        if let Some(backpressure) = &self.0.backpressure {
+            backpressure.leave();
+        }
+    }
+}
+
+// The lifetime should prevent `DynQueueInner` to outlive the original `DynQueue`
+// but does not always.
+pub(crate) struct DynQueueInner<'a, T, U: Queue<T>> {
+    pub(crate) queue: sync::Arc<Counted<T, U>>,
+    shards: sync::Arc<Shards<T, U>>,
+    pub(crate) shared: sync::Arc<Shared<'a, T>>,
+    /// elements collected by `DynQueueHandle::enqueue` with an
+    /// `enqueue_buffer_size`, flushed after every processed element
+    pub(crate) buffer: Mutex<Vec<(Meta, T)>>,
+    /// `true` for the original queue, which receives the elements of the
+    /// `DynQueueSender`s and stays open as long as one is alive
+    root: bool,
+    /// link of the `Control` handles and `DynQueueSender`s
+    link: sync::Arc<Link<'a, T, U>>,
+}
+
+impl<'a, T, U: Queue<T>> Drop for DynQueueInner<'a, T, U> {
+    fn drop(&mut self) {
+        // Only collect the remaining elements, if someone is interested
+        if Arc::strong_count(&self.shared.leftovers) > 1 {
+            let mut leftovers = self.shared.leftovers.lock().recover();
+            leftovers.append(self.buffer.get_mut().recover());
+            while let Some(ele) = self.queue.pop_meta() {
+                leftovers.push(ele);
+            }
+            while let Some(ele) = self.shared.retries.pop() {
+                leftovers.push(ele);
+            }
+            while let Some(ele) = self.shared.delayed.pop_any() {
+                leftovers.push(ele);
+            }
+            if let Some(limit) = &self.shared.key_limit {
+                leftovers.extend(limit.drain());
+            }
+            if self.root {
+                let received = self.shared.close_receiver();
+                leftovers.extend(received.into_iter().map(|v| (Meta::default(), v)));
+            }
+        }
+    }
+}
+
+/// Call `f` for every distinct queue of all parallel iterators
+pub(crate) fn for_each_shard<T, U: Queue<T>, F: FnMut(&U)>(shards: &Mutex<Vec<Weak<U>>>, mut f: F) {
+    let mut seen: Vec<sync::Arc<U>> = Vec::new();
+
+    for shard in shards.lock().recover().iter().filter_map(Weak::upgrade) {
+        if seen.iter().any(|other| other.shares_queue(&shard)) {
+            continue;
+        }
+        f(&shard);
+        seen.push(shard);
+    }
+}
+
+impl<'a, T, U: Queue<T>> DynQueueInner<'a, T, U> {
+    /// Identity of the queue of the parallel iterator in the log events
+    #[cfg(any(feature = "tracing", feature = "log"))]
+    fn shard_id(&self) -> *const () {
+        sync::Arc::as_ptr(&self.queue).cast()
+    }
+
+    /// Push the elements buffered by the `DynQueueHandle`s to the queue.
+    ///
+    /// Returns `true`, if there were any.
+    fn flush(&self) -> bool {
+        if self.shared.enqueue_buffer_size <= 1 {
+            return false;
+        }
+        let buffer = std::mem::take(&mut *self.buffer.lock().recover());
+        if buffer.is_empty() {
+            return false;
+        }
+        // the children of one element share their metadata
+        let mut rest = buffer.into_iter().peekable();
+        while let Some((meta, v)) = rest.next() {
+            let run = std::iter::once(v).chain(std::iter::from_fn(|| {
+                rest.next_if(|(next, _)| *next == meta).map(|(_, v)| v)
+            }));
+            self.queue.push_batch_meta(meta, run);
+        }
+        self.shared.idle.wake(true);
+        true
+    }
+
+    /// `true`, if elements are left in the queue or the buffer
+    ///
+    /// The queue itself is asked, because the count of the elements might
+    /// not include a concurrent steal yet.
+    pub(crate) fn has_queued(&self) -> bool {
+        self.queue.holds_elements() || !self.buffer.lock().recover().is_empty()
+    }
+
+    /// Account for a popped element and report the progress
+    ///
+    /// Returns `false`, if the element exceeds the configured `max_items`
+    /// and must not be processed.
+    #[inline(always)]
+    pub(crate) fn on_pop(&self) -> bool {
+        let shared = &self.shared;
+        let processed = match &shared.progress {
+            Some(progress) => match progress.count() {
+                Some(processed) => Some((progress, processed)),
+                None => return false,
+            },
+            None => None,
+        };
+
+        shared.on_pop();
+        trace!(trace, "pop");
+
+        if let Some((progress, processed)) = processed {
+            progress.report(processed, || {
+                let mut pending = 0;
+                for_each_shard(&self.shards, |shard| pending += shard.len());
+                pending
+            });
+        }
+        true
+    }
+
+    /// `true`, if the queue is exhausted, but has to wait for elements of
+    /// a `DynQueueSender` or the receiver of [`DynQueue::with_receiver`]
+    #[inline]
+    fn awaits_senders(&self) -> bool {
+        self.root
+            && (self.shared.has_senders() || self.shared.is_receiving())
+            && !self.shared.is_stopped()
+    }
+
+    /// Wait for an element of a `DynQueueSender` or the receiver of
+    /// [`DynQueue::with_receiver`].
+    fn wait_for_senders(&self) {
+        if !self.shared.is_receiving() {
+            return self.shared.wait_for_senders();
+        }
+        if let Some((meta, v)) = self.shared.receive(Duration::from_millis(1)) {
+            self.queue.push_meta(meta, v);
+        }
+    }
+
+    /// Enqueue the single `job` with the `meta` of a new child
+    ///
+    /// Every single element enqueue takes the same steps: admit, handle a
+    /// full queue with the [`OverflowPolicy`], stamp, wait for capacity,
+    /// throttle, count and `push`. Only `push` differs, e.g. to the front of
+    /// the queue, and may refuse the element of a bounded queue.
+    ///
+    /// Returns the stamped `Meta`, if `job` was enqueued.
+    fn enqueue_meta(
+        &self,
+        meta: Meta,
+        mut job: T,
+        push: impl Fn(Meta, T) -> Result<(), T>,
+    ) -> Result<Option<Meta>, EnqueueError<T>> {
+        let shared = &self.shared;
+        if !shared.accepts(&meta, &job) {
+            return Ok(None);
+        }
+        // the filters mark the key of `job` as seen, so they only run, once
+        // the overflow policy made room for it
+        if self.is_full() {
+            if !shared.would_pass(&job) {
+                return Ok(None);
+            }
+            job = match self.overflow(None, job)? {
+                Some(job) => job,
+                None => return Ok(None),
+            };
+        } else if !shared.passes(&job) {
+            return Ok(None);
+        }
+        let meta = shared.stamp(meta);
+        shared.wait_for_capacity();
+        shared.throttle(1);
+        loop {
+            shared.on_enqueue();
+            match push(meta, job) {
+                Ok(()) => {
+                    shared.idle.wake(false);
+                    return Ok(Some(meta));
+                }
+                Err(refused) => {
+                    shared.on_drop();
+                    job = match self.overflow(Some(&meta), refused)? {
+                        Some(job) => job,
+                        None => return Ok(None),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Push `job` to the back of the queue
+    ///
+    /// With an [`OverflowPolicy`], a bounded queue refuses the element
+    /// instead of waiting for room.
+    #[inline(always)]
+    fn push_back(&self, meta: Meta, job: T) -> Result<(), T> {
+        if self.shared.overflow.is_some() {
+            self.queue.try_push_meta(meta, job)
+        } else {
+            self.queue.push_meta(meta, job);
+            Ok(())
+        }
+    }
+
+    /// `true`, if an [`OverflowPolicy`] is set and the queue together with
+    /// the delayed elements reached the [`DynQueueBuilder::capacity_limit`]
+    #[inline(always)]
+    fn is_full(&self) -> bool {
+        let shared = &self.shared;
+        shared.overflow.is_some()
+            && shared
+                .capacity_limit
+                .is_some_and(|limit| self.queue.len() + shared.delayed.len() >= limit)
+    }
+
+    /// Handle the full queue for `job` with the [`OverflowPolicy`]
+    ///
+    /// `meta` is given, if `job` already passed the filters and was stamped.
+    /// Returns `job`, if the oldest element made room for it.
+    fn overflow(&self, meta: Option<&Meta>, job: T) -> Result<Option<T>, EnqueueError<T>> {
+        let shared = &self.shared;
+        let policy = match shared.overflow {
+            Some(policy) => policy,
+            None => return Ok(Some(job)),
+        };
+        trace!(
+            debug,
+            "overflow",
+            shard = self.shard_id(),
+            policy = policy,
+            len = self.queue.len(),
+        );
+
+        let refuse = |job: &T| {
+            if let Some(meta) = meta {
+                shared.unstamp(meta);
+                shared.forget(job);
+            }
+        };
+        match policy {
+            OverflowPolicy::DropNewest => {
+                refuse(&job);
+                Ok(None)
+            }
+            OverflowPolicy::Reject => {
+                refuse(&job);
+                Err(EnqueueError::Full(job))
+            }
+            OverflowPolicy::DropOldest => {
+                if meta.is_none() && !shared.passes(&job) {
+                    return Ok(None);
+                }
+                if self.queue.pop_meta_ordered(Order::Fifo).is_some() {
+                    shared.on_drop();
+                }
+                Ok(Some(job))
+            }
+        }
+    }
+
+    /// Take the next chunk of elements from the seeds of
+    /// [`DynQueue::with_seeds`] and push them to the queue.
+    ///
+    /// Returns `false`, if the seeds are exhausted.
+    fn seed(&self) -> bool {
+        let shared = &self.shared;
+        let seeds = match &shared.seeds {
+            Some(seeds) => seeds,
+            None => return false,
+        };
+        let taken = seeds.take(|v| {
+            let meta = Meta::default();
+            if !shared.admit(&meta, &v) {
+                return false;
+            }
+            shared.on_enqueue();
+            self.queue.push_meta(shared.stamp(meta), v);
+            true
+        });
+        if taken > 0 {
+            shared.idle.wake(true);
+        }
+        taken > 0
+    }
+
+    /// pop the next element in the configured order
+    #[inline(always)]
+    fn pop_queued(&self) -> Option<(Meta, T)> {
+        match self.shared.order {
+            Some(order) => self.queue.pop_meta_ordered(order),
+            None => self.queue.pop_meta(),
+        }
+    }
+
+    /// Handle a queue poisoned by a panic with the [`PoisonPolicy`]
+    #[inline(always)]
+    fn check_poison(&self) {
+        let policy = self.shared.poison;
+        if policy == PoisonPolicy::Ignore || !self.queue.is_poisoned() {
+            return;
+        }
+        trace!(warn, "poisoned", shard = self.shard_id(), policy = policy);
+        match policy {
+            PoisonPolicy::Ignore => {}
+            PoisonPolicy::Skip => {
+                if self.pop_queued().is_some() {
+                    self.shared.on_drop();
+                }
+                self.queue.clear_poison();
+            }
+            PoisonPolicy::Propagate => {
+                self.shared.cancel.cancel();
+                std::panic::panic_any(QueuePoisoned);
+            }
+        }
+    }
+
+    /// pop the next element in the configured order, or a requeued, due
+    /// delayed, received or seeded element, if the queue is empty
+    #[inline(always)]
+    fn pop(&self) -> Option<(Meta, T)> {
+        self.check_poison();
+        self.pop_queued()
+            .or_else(|| self.shared.retries.pop())
+            .or_else(|| self.shared.delayed.pop_due())
+            .or_else(|| self.shared.receive(Duration::ZERO))
+            .or_else(|| self.seed().then(|| self.pop_queued()).flatten())
+    }
+
+    /// pop the next batch of elements in the configured order, or a
+    /// requeued, due delayed, received or seeded element, if the queue is
+    /// empty
+    #[inline(always)]
+    fn pop_batch(&self) -> Vec<(Meta, T)> {
+        self.check_poison();
+        let batch = self
+            .queue
+            .pop_batch_meta(self.shared.pop_batch_size, self.shared.order);
+        if batch.is_empty() {
+            return self
+                .shared
+                .retries
+                .pop()
+                .or_else(|| self.shared.delayed.pop_due())
+                .or_else(|| self.shared.receive(Duration::ZERO))
+                .or_else(|| self.seed().then(|| self.pop_queued()).flatten())
+                .into_iter()
+                .collect();
+        }
+        batch
+    }
+}
+
+/// Error returned by the enqueue methods of the `DynQueueHandle`
+///
+/// Both variants give back the refused element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnqueueError<T> {
+    /// The iteration of the `DynQueue` already ended or was shut down with
+    /// [`Control::shutdown`].
+    Closed(T),
+    /// The queue is full, only returned by the `try_` methods.
+    Full(T),
+    /// The element was requeued too often, only returned by
+    /// [`DynQueueHandle::requeue`].
+    Exhausted(T),
+}
+
+impl<T> EnqueueError<T> {
+    /// Get back the refused element
+    #[inline]
+    pub fn into_inner(self) -> T {
+        match self {
+            EnqueueError::Closed(v) | EnqueueError::Full(v) | EnqueueError::Exhausted(v) => v,
+        }
+    }
+}
+
+impl<T> fmt::Display for EnqueueError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnqueueError::Closed(_) => f.write_str("enqueue on a closed DynQueue"),
+            EnqueueError::Full(_) => f.write_str("enqueue on a full DynQueue"),
+            EnqueueError::Exhausted(_) => f.write_str("requeue exceeded the maximum retries"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for EnqueueError<T> {}
+
+/// An element, whose processing panicked
+///
+/// Returned by [`DynQueue::collect_isolated`].
+pub struct Panicked<T> {
+    /// The element, which was processed
+    pub item: T,
+    /// The payload of the panic
+    pub payload: Box<dyn Any + Send>,
+}
+
+impl<T> Panicked<T> {
+    /// The panic message, if the payload is a string
+    pub fn message(&self) -> Option<&str> {
+        self.payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| self.payload.downcast_ref::<String>().map(String::as_str))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Panicked<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Panicked")
+            .field("item", &self.item)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+/// The `DynQueueHandle` returned by the iterator in addition to `T`
+///
+/// The handle does not keep the `DynQueue` alive. After the iteration
+/// ended or was shut down with [`Control::shutdown`], all elements are
+/// refused with [`EnqueueError::Closed`].
+pub struct DynQueueHandle<'a, T, U: Queue<T>> {
+    pub(crate) inner: Weak<DynQueueInner<'a, T, U>>,
+    pub(crate) meta: Meta,
+    pub(crate) sequence: Option<usize>,
+}
+
+/// The clone enqueues its elements with the same parent as the original.
+impl<'a, T, U: Queue<T>> Clone for DynQueueHandle<'a, T, U> {
+    fn clone(&self) -> Self {
+        DynQueueHandle {
+            inner: self.inner.clone(),
+            meta: self.meta,
+            sequence: self.sequence,
+        }
+    }
+}
+
+impl<'a, T, U: Queue<T>> fmt::Debug for DynQueueHandle<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynQueueHandle")
+            .field("queue", &std::any::type_name::<U>())
+            .field("pending", &self.pending())
+            .field("depth", &self.meta.depth)
+            .field("sequence", &self.sequence)
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl<'a, T, U: Queue<T>> DynQueueHandle<'a, T, U> {
+    /// The queue lent by the parallel iterator on this thread, or upgraded
+    /// from the `Weak`, if it is another one.
+    ///
+    /// Returns `None`, if the iteration already ended.
+    #[inline(always)]
+    fn upgrade(&self) -> Option<Upgraded<'_, 'a, T, U>> {
+        let (lent, arc) = LENT.with(Cell::get);
+        if std::ptr::eq(lent, self.inner.as_ptr().cast::<()>()) {
+            // SAFETY: the `Weak` keeps the allocation of the `DynQueueInner`
+            // alive, so it is the one the parallel iterator on this thread
+            // lent. Its `Arc` is borrowed by the iterator, until the handle
+            // returned, because the upgraded queue does not leave its call.
+            let arc = unsafe { &*arc.cast::<sync::Arc<DynQueueInner<'a, T, U>>>() };
+            return Some(Upgraded::Lent(arc));
+        }
+        self.inner.upgrade().map(Upgraded::Owned)
+    }
+
+    /// Borrow the queue as a [`DynQueueRef`], which enqueues with the
+    /// buffer of the parallel iterator
+    #[inline(always)]
+    fn scoped<'s>(
+        &'s self,
+        inner: &'s sync::Arc<DynQueueInner<'a, T, U>>,
+    ) -> DynQueueRef<'s, 'a, T, U> {
+        DynQueueRef {
+            inner,
+            meta: self.meta,
+            sequence: self.sequence,
+            buffered: true,
+        }
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// Blocks, while more than [`DynQueue::with_max_pending`] elements are
+    /// pending. A full queue is handled according to the
+    /// [`OverflowPolicy`], which disables the `enqueue_buffer_size`.
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended,
+    /// or [`EnqueueError::Full`] with [`OverflowPolicy::Reject`].
+    #[inline]
+    pub fn enqueue(&self, job: T) -> Result<(), EnqueueError<T>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).enqueue(job),
+            None => Err(EnqueueError::Closed(job)),
+        }
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>` and get a [`Ticket`] to retrieve its
+    /// result after [`DynQueue::collect_tickets`].
+    ///
+    /// Returns `Ok(None)`, if the element was dropped by a filter or an
+    /// [`OverflowPolicy`], or if the lineage is not tracked, which
+    /// `collect_tickets` does. The element is never buffered.
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended,
+    /// or [`EnqueueError::Full`] with [`OverflowPolicy::Reject`].
+    #[inline]
+    pub fn enqueue_ticket(&self, job: T) -> Result<Option<Ticket>, EnqueueError<T>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).enqueue_ticket(job),
+            None => Err(EnqueueError::Closed(job)),
+        }
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>`, so that it is popped next.
+    ///
+    /// The element bypasses the older elements in the queue, e.g. for a
+    /// depth-first bias. It is pushed to the front of the queue, or to the
+    /// back, if the queue is popped with [`Order::Lifo`].
+    ///
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended,
+    /// or [`EnqueueError::Full`] with [`OverflowPolicy::Reject`].
+    #[inline]
+    pub fn enqueue_front(&self, job: T) -> Result<(), EnqueueError<T>>
+    where
+        U: DoubleEndedQueue<T>,
+    {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).enqueue_front(job),
+            None => Err(EnqueueError::Closed(job)),
+        }
+    }
+
+    /// Enqueue `T` in `lane` of a [`LaneQueue`].
+    ///
+    /// Other queues ignore the lane and enqueue like
+    /// [`DynQueueHandle::enqueue`]. The element is never buffered.
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended,
+    /// or [`EnqueueError::Full`] with [`OverflowPolicy::Reject`].
+    #[inline]
+    pub fn enqueue_in(&self, lane: Lane, job: T) -> Result<(), EnqueueError<T>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).enqueue_in(lane, job),
+            None => Err(EnqueueError::Closed(job)),
+        }
+    }
+
+    /// Push all elements buffered by `enqueue` to the queue.
+    ///
+    /// This happens automatically after processing the current element.
+    /// See [`DynQueue::with_enqueue_buffer_size`].
+    pub fn flush(&self) {
+        if let Some(inner) = self.upgrade() {
+            inner.flush();
+        }
+    }
+
+    /// Try to enqueue `T` in the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// Returns [`EnqueueError::Full`] instead of blocking, if the underlying
+    /// queue refuses the element, e.g. because its capacity is exhausted, or
+    /// the configured [`DynQueueBuilder::capacity_limit`] or
+    /// [`DynQueueBuilder::max_pending`] is reached.
+    #[inline]
+    pub fn try_enqueue(&self, job: T) -> Result<(), EnqueueError<T>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).try_enqueue(job),
+            None => Err(EnqueueError::Closed(job)),
+        }
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>`, which becomes eligible for popping
+    /// after `delay`, e.g. to try again in 30 seconds.
+    ///
+    /// Delayed elements are kept in a time-ordered queue shared by all
+    /// parallel iterators. The iteration does not end, before all of them
+    /// were processed, and idle parallel iterators sleep until the next
+    /// one is due instead of blocking a worker.
+    ///
+    /// The delayed elements count against the
+    /// [`DynQueueBuilder::capacity_limit`] of the [`OverflowPolicy`].
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended,
+    /// or [`EnqueueError::Full`] with [`OverflowPolicy::Reject`].
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let start = Instant::now();
+    /// let mut result = vec![1, 2]
+    ///     .into_dyn_queue()
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         if value == 1 {
+    ///             handle
+    ///                 .enqueue_after(3, Duration::from_millis(20))
+    ///                 .unwrap();
+    ///         }
+    ///         value
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec![1, 2, 3]);
+    /// assert!(start.elapsed() >= Duration::from_millis(20));
+    /// ```
+    #[inline]
+    pub fn enqueue_after(&self, job: T, delay: Duration) -> Result<(), EnqueueError<T>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).enqueue_after(job, delay),
+            None => Err(EnqueueError::Closed(job)),
+        }
+    }
+
+    /// Enqueue the current element `item` again, e.g. after a transient
+    /// failure.
+    ///
+    /// The element keeps its metadata and counts the attempt, see
+    /// [`DynQueueHandle::attempt`]. It bypasses the filters and is popped,
+    /// when the queue of a parallel iterator runs empty.
+    ///
+    /// Returns [`EnqueueError::Exhausted`], if the element was already
+    /// requeued [`DynQueue::with_max_retries`] times, unless it is collected
+    /// in the [`DeadLetters`], or [`EnqueueError::Closed`], if the iteration
+    /// already ended.
+    ///
+    /// ```
+    /// use dynqueue::{EnqueueError, IntoDynQueue as _};
+    ///
+    /// let result = vec![1u64, 2]
+    ///     .into_dyn_queue()
+    ///     .with_max_retries(3)
+    ///     .try_process(|handle, value| {
+    ///         match handle.requeue(value) {
+    ///             Ok(()) => Ok(()),
+    ///             Err(EnqueueError::Exhausted(value)) => {
+    ///                 assert_eq!(handle.attempt(), 3);
+    ///                 Ok(())
+    ///             }
+    ///             Err(e) => Err(e.into_inner()),
+    ///         }
+    ///     });
+    ///
+    /// assert_eq!(result, Ok(()));
+    /// ```
+    #[inline]
+    pub fn requeue(&self, item: T) -> Result<(), EnqueueError<T>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).requeue(item),
+            None => Err(EnqueueError::Closed(item)),
+        }
+    }
+
+    /// Give up on the current element `item` because of `error`.
+    ///
+    /// The element is collected with the error in the [`DeadLetters`], or
+    /// dropped, if nobody asked for them with [`DynQueue::dead_letters`].
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended.
+    pub fn reject<E>(&self, item: T, error: E) -> Result<(), EnqueueError<T>>
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        match self.upgrade() {
+            Some(inner) => {
+                self.scoped(&inner).reject(item, error);
+                Ok(())
+            }
+            None => Err(EnqueueError::Closed(item)),
+        }
+    }
+
+    /// Enqueue all `jobs` at once in the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// For the lock based queues, the lock is only taken once. With an
+    /// [`OverflowPolicy`], the policy decides for every element instead and
+    /// [`EnqueueError::Full`] gives back the refused element together with
+    /// all elements after it.
+    /// Returns the `jobs` with [`EnqueueError::Closed`], if the iteration
+    /// already ended.
+    #[inline]
+    pub fn enqueue_many<I: IntoIterator<Item = T>>(
+        &self,
+        jobs: I,
+    ) -> Result<(), EnqueueError<Vec<T>>> {
+        match self.upgrade() {
+            Some(inner) => self.scoped(&inner).enqueue_many(jobs),
+            None => Err(EnqueueError::Closed(jobs.into_iter().collect())),
+        }
+    }
+
+    /// Enqueue all elements of `iter` in the `DynQueue<T>`, e.g. the
+    /// neighbors of a node.
+    ///
+    /// Same as [`DynQueueHandle::enqueue_many`], without collecting the
+    /// elements in a `Vec` first.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let edges = [vec![1, 2], vec![3], vec![], vec![]];
+    ///
+    /// let mut result = vec![0usize]
+    ///     .into_dyn_queue()
+    ///     .into_par_iter()
+    ///     .map(|(handle, node)| {
+    ///         handle.enqueue_iter(edges[node].iter().copied()).unwrap();
+    ///         node
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec![0, 1, 2, 3]);
+    /// ```
+    #[inline]
+    pub fn enqueue_iter<I: IntoIterator<Item = T>>(
+        &self,
+        iter: I,
+    ) -> Result<(), EnqueueError<Vec<T>>> {
+        self.enqueue_many(iter)
+    }
+
+    /// Number of elements pending in the queue of the current parallel
+    /// iterator
+    ///
+    /// Returns `0`, if the iteration already ended.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.upgrade()
+            .map_or(0, |inner| self.scoped(&inner).pending())
+    }
+
+    /// `true`, if no element is pending in the queue of the current parallel
+    /// iterator
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Estimate of the number of elements pending in the queues of all
+    /// parallel iterators
+    ///
+    /// The queues are not locked all at once, so the result is only
+    /// an estimate, while other iterators are running.
+    pub fn pending_total(&self) -> usize {
+        self.upgrade()
+            .map_or(0, |inner| self.scoped(&inner).pending_total())
+    }
+
+    /// The shared context set with [`DynQueue::with_context`]
+    ///
+    /// Returns `None`, if no context of type `C` was set, or the iteration
+    /// already ended.
+    pub fn context<C: Send + Sync + 'static>(&self) -> Option<Arc<C>> {
+        self.upgrade()
+            .and_then(|inner| self.scoped(&inner).context())
+    }
+
+    /// The shared state of type `S` set with [`DynQueue::with_shared`]
+    ///
+    /// Returns `None`, if no state of type `S` was set, or the iteration
+    /// already ended.
+    pub fn shared<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.upgrade()
+            .and_then(|inner| self.scoped(&inner).shared())
+    }
+
+    /// The best value of the [`Bound`] set with [`DynQueue::with_bound`]
+    ///
+    /// Returns `None`, if no bound of type `B` was set, it has no value yet
+    /// or the iteration already ended.
+    pub fn bound<B: Ord + Clone + Send + Sync + 'static>(&self) -> Option<B> {
+        self.upgrade().and_then(|inner| self.scoped(&inner).bound())
+    }
+
+    /// Replace the best value of the [`Bound`] set with
+    /// [`DynQueue::with_bound`] with `value`, if it is greater.
+    ///
+    /// Returns `true`, if the bound was improved, and `false` otherwise or
+    /// if no bound of type `B` was set.
+    pub fn try_improve_bound<B: Ord + Send + Sync + 'static>(&self, value: B) -> bool {
+        self.upgrade()
+            .is_some_and(|inner| self.scoped(&inner).try_improve_bound(value))
+    }
+
+    /// `true`, if the iteration of the `DynQueue<T>` ended and all elements
+    /// are refused.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.inner.strong_count() == 0
+    }
+
+    /// Depth of the current element, which is `0` for the initial elements
+    /// and the depth of the parent plus one for enqueued elements.
+    ///
+    /// Only a [`Tracked`] queue keeps track of the depth, all other
+    /// queues report `0`.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.meta.depth
+    }
+
+    /// Number of times the current element was requeued with
+    /// [`DynQueueHandle::requeue`]
+    #[inline]
+    pub fn attempt(&self) -> usize {
+        self.meta.attempt
+    }
+
+    /// Unique id of the current element.
+    ///
+    /// Returns `None`, unless the lineage of a [`Tracked`] queue is tracked
+    /// with [`DynQueue::lineage`].
+    #[inline]
+    pub fn current_id(&self) -> Option<usize> {
+        self.meta.id
+    }
+
+    /// Id of the element, whose handle enqueued the element with `id`.
+    ///
+    /// Returns `None` for the initial elements, unknown ids and if the
+    /// lineage is not tracked. See [`Lineage`].
+    pub fn parent_of(&self, id: usize) -> Option<usize> {
+        let inner = self.upgrade()?;
+        self.scoped(&inner).parent_of(id)
+    }
+
+    /// Global sequence number of the current element in the order the
+    /// elements were popped by all parallel iterators, starting with `0`.
+    ///
+    /// Returns `None`, unless enabled with [`DynQueue::with_sequence`].
+    #[inline]
+    pub fn sequence(&self) -> Option<usize> {
+        self.sequence
+    }
+
+    /// Cancel the iteration of the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// All parallel iterators stop popping elements, drop the remaining
+    /// elements and the iteration ends early.
+    #[inline]
+    pub fn cancel(&self) {
+        if let Some(inner) = self.upgrade() {
+            self.scoped(&inner).cancel()
+        }
+    }
+
+    /// `true`, if the iteration was cancelled
+    ///
+    /// Always `false`, if the iteration already ended.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.upgrade()
+            .is_some_and(|inner| self.scoped(&inner).is_cancelled())
+    }
+
+    /// Get a handle for callbacks, which might be called after the iteration
+    /// ended.
+    ///
+    /// Every `DynQueueHandle` is weak already, so this is the same as
+    /// `clone`: the handle does not keep the `DynQueue` alive and refuses
+    /// all elements with [`EnqueueError::Closed`], once the iteration ended.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::{EnqueueError, IntoDynQueue as _};
+    ///
+    /// let callbacks = vec![1, 2, 3]
+    ///     .into_dyn_queue()
+    ///     .into_par_iter()
+    ///     .map(|(handle, _)| handle.downgrade())
+    ///     .collect::<Vec<_>>();
+    ///
+    /// for callback in callbacks {
+    ///     assert_eq!(callback.enqueue(4), Err(EnqueueError::Closed(4)));
+    /// }
+    /// ```
+    #[inline]
+    pub fn downgrade(&self) -> DynQueueHandle<'a, T, U> {
+        self.clone()
+    }
+}
+
+/// The borrowed handle passed to the closure of [`DynQueue::map_scoped`]
+///
+/// A `DynQueueRef` borrows the queue of the current parallel iterator for
+/// the call of the closure, so it is `Copy` and, unlike a
+/// [`DynQueueHandle`], creating it costs no reference counting. It offers
+/// the same methods, but never buffers its elements and is only closed by
+/// [`Control::shutdown`].
+/// Use [`DynQueueRef::to_handle`] for work, which outlives the closure.
+pub struct DynQueueRef<'s, 'a, T, U: Queue<T>> {
+    inner: &'s sync::Arc<DynQueueInner<'a, T, U>>,
+    meta: Meta,
+    sequence: Option<usize>,
+    /// `true`, if `enqueue` may use the `enqueue_buffer_size`
+    buffered: bool,
+}
+
+impl<'s, 'a, T, U: Queue<T>> Clone for DynQueueRef<'s, 'a, T, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'s, 'a, T, U: Queue<T>> Copy for DynQueueRef<'s, 'a, T, U> {}
+
+impl<'s, 'a, T, U: Queue<T>> fmt::Debug for DynQueueRef<'s, 'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynQueueRef")
+            .field("queue", &std::any::type_name::<U>())
+            .field("pending", &self.pending())
+            .field("depth", &self.meta.depth)
+            .field("sequence", &self.sequence)
+            .finish()
+    }
+}
+
+impl<'s, 'a, T, U: Queue<T>> DynQueueRef<'s, 'a, T, U> {
+    /// Enqueue `T` in the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// See [`DynQueueHandle::enqueue`].
+    #[inline]
+    pub fn enqueue(self, job: T) -> Result<(), EnqueueError<T>> {
+        let inner = self.inner;
+        if inner.shared.is_shut_down() {
+            return Err(EnqueueError::Closed(job));
+        }
+        let meta = self.meta.child();
+        let size = inner.shared.enqueue_buffer_size;
+        if self.buffered
+            && size > 1
+            && inner.shared.overflow.is_none()
+            && !inner.shared.stamps_each()
+        {
+            return inner
+                .enqueue_meta(meta, job, |meta, job| {
+                    let full = {
+                        let mut buffer = inner.buffer.lock().recover();
+                        buffer.push((meta, job));
+                        buffer.len() >= size
+                    };
+                    if full {
+                        inner.flush();
+                    }
+                    Ok(())
+                })
+                .map(|_| ());
+        }
+        inner
+            .enqueue_meta(meta, job, |meta, job| inner.push_back(meta, job))
+            .map(|_| ())
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>` and get a [`Ticket`] for its result.
+    ///
+    /// See [`DynQueueHandle::enqueue_ticket`].
+    pub fn enqueue_ticket(self, job: T) -> Result<Option<Ticket>, EnqueueError<T>> {
+        let inner = self.inner;
+        if inner.shared.is_shut_down() {
+            return Err(EnqueueError::Closed(job));
+        }
+        let meta = inner.enqueue_meta(self.meta.child(), job, |meta, job| {
+            inner.push_back(meta, job)
+        })?;
+        Ok(meta.and_then(|meta| meta.id).map(Ticket))
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>`, so that it is popped next.
+    ///
+    /// See [`DynQueueHandle::enqueue_front`].
+    #[inline]
+    pub fn enqueue_front(self, job: T) -> Result<(), EnqueueError<T>>
+    where
+        U: DoubleEndedQueue<T>,
+    {
+        let inner = self.inner;
+        if inner.shared.is_shut_down() {
+            return Err(EnqueueError::Closed(job));
+        }
+        inner
+            .enqueue_meta(self.meta.child(), job, |meta, job| {
+                if inner.shared.order == Some(Order::Lifo) {
+                    inner.push_back(meta, job)
+                } else {
+                    inner.queue.push_front_meta(meta, job);
+                    Ok(())
+                }
+            })
+            .map(|_| ())
+    }
+
+    /// Enqueue `T` in `lane` of a [`LaneQueue`].
+    ///
+    /// See [`DynQueueHandle::enqueue_in`].
+    #[inline]
+    pub fn enqueue_in(self, lane: Lane, job: T) -> Result<(), EnqueueError<T>> {
+        let inner = self.inner;
+        if inner.shared.is_shut_down() {
+            return Err(EnqueueError::Closed(job));
+        }
+        let meta = Meta {
+            lane,
+            ..self.meta.child()
+        };
+        inner
+            .enqueue_meta(meta, job, |meta, job| inner.push_back(meta, job))
+            .map(|_| ())
+    }
+
+    /// Push the elements buffered by the handles to the queue
+    fn flush(self) {
+        self.inner.flush();
+    }
+
+    /// Try to enqueue `T` in the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// See [`DynQueueHandle::try_enqueue`].
+    #[inline]
+    pub fn try_enqueue(self, job: T) -> Result<(), EnqueueError<T>> {
+        let inner = self.inner;
+        if inner.shared.is_shut_down() {
+            return Err(EnqueueError::Closed(job));
+        }
+        self.flush();
+
+        if let Some(limit) = inner.shared.capacity_limit {
+            if inner.queue.len() >= limit {
+                return Err(EnqueueError::Full(job));
+            }
+        }
+
+        if inner.shared.is_over_max_pending() {
+            return Err(EnqueueError::Full(job));
+        }
+
+        // a dropped element neither uses up a token of the rate limit nor
+        // marks its key as seen by the filters
+        let meta = self.meta.child();
+        if !inner.shared.accepts(&meta, &job) || !inner.shared.would_pass(&job) {
+            return Ok(());
+        }
+        if inner
+            .shared
+            .rate_limit
+            .as_ref()
+            .is_some_and(|rate_limit| !rate_limit.try_acquire())
+        {
+            return Err(EnqueueError::Full(job));
+        }
+        if !inner.shared.passes(&job) {
+            return Ok(());
+        }
+        let meta = inner.shared.stamp(meta);
+        if let Err(job) = inner.queue.try_push_meta(meta, job) {
+            inner.shared.unstamp(&meta);
+            inner.shared.forget(&job);
+            return Err(EnqueueError::Full(job));
+        }
+        inner.shared.on_enqueue();
+        inner.shared.idle.wake(false);
+        Ok(())
+    }
+
+    /// Enqueue `T` in the `DynQueue<T>`, which becomes eligible for popping
+    /// after `delay`.
+    ///
+    /// See [`DynQueueHandle::enqueue_after`].
+    #[inline]
+    pub fn enqueue_after(self, job: T, delay: Duration) -> Result<(), EnqueueError<T>> {
+        let inner = self.inner;
+        if inner.shared.is_shut_down() {
+            return Err(EnqueueError::Closed(job));
+        }
+        let due = Instant::now() + delay;
+        inner
+            .enqueue_meta(self.meta.child(), job, |meta, job| {
+                inner.shared.delayed.push(due, meta, job);
+                Ok(())
+            })
+            .map(|_| ())
+    }
+
+    /// Enqueue the current element `item` again.
+    ///
+    /// See [`DynQueueHandle::requeue`].
+    #[inline]
+    pub fn requeue(self, item: T) -> Result<(), EnqueueError<T>> {
+        let shared = &self.inner.shared;
+        if shared.is_shut_down() {
+            return Err(EnqueueError::Closed(item));
+        }
+        if shared
+            .max_retries
+            .is_some_and(|max| self.meta.attempt >= max)
+        {
+            if !shared.has_dead_letters() {
+                return Err(EnqueueError::Exhausted(item));
+            }
+            shared.dead_letters.lock().recover().push(DeadLetter {
+                item,
+                attempts: self.meta.attempt,
+                error: None,
+            });
+            return Ok(());
+        }
+        let meta = Meta {
+            attempt: self.meta.attempt + 1,
+            ..self.meta
+        };
+        shared.on_enqueue();
+        shared.retries.push((meta, item));
+        shared.idle.wake(false);
+        Ok(())
+    }
+
+    /// Give up on the current element `item` because of `error`.
+    ///
+    /// See [`DynQueueHandle::reject`].
+    pub fn reject<E>(self, item: T, error: E)
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let shared = &self.inner.shared;
+        if shared.has_dead_letters() {
+            shared.dead_letters.lock().recover().push(DeadLetter {
+                item,
+                attempts: self.meta.attempt,
+                error: Some(error.into()),
+            });
+        }
+    }
+
+    /// Enqueue all `jobs` at once in the `DynQueue<T>`, which is currently iterated.
+    ///
+    /// See [`DynQueueHandle::enqueue_many`].
+    #[inline]
+    pub fn enqueue_many<I: IntoIterator<Item = T>>(
+        self,
+        jobs: I,
+    ) -> Result<(), EnqueueError<Vec<T>>> {
+        let inner = self.inner;
+        let meta = self.meta.child();
+        let shared = &inner.shared;
+        if shared.is_shut_down() {
+            return Err(EnqueueError::Closed(jobs.into_iter().collect()));
+        }
+        if shared.overflow.is_some() {
+            // the overflow policy decides for every single element
+            let mut jobs = jobs.into_iter();
+            while let Some(job) = jobs.next() {
+                if let Err(err) =
+                    inner.enqueue_meta(meta, job, |meta, job| inner.push_back(meta, job))
+                {
+                    let refused = std::iter::once(err.into_inner()).chain(jobs);
+                    return Err(EnqueueError::Full(refused.collect()));
+                }
+            }
+            return Ok(());
+        }
+        shared.wait_for_capacity();
+        if shared.rate_limit.is_some() || shared.stamps_each() {
+            let jobs = jobs
+                .into_iter()
+                .filter(|job| shared.admit(&meta, job))
+                .collect::<Vec<_>>();
+            shared.throttle(jobs.len());
+            jobs.iter().for_each(|_| shared.on_enqueue());
+            if shared.stamps_each() {
+                // every element needs its own index and id
+                jobs.into_iter()
+                    .for_each(|job| inner.queue.push_meta(shared.stamp(meta), job));
+            } else {
+                inner.queue.push_batch_meta(meta, jobs);
+            }
+            shared.idle.wake(true);
+            return Ok(());
+        }
+        inner.queue.push_batch_meta(
+            meta,
+            jobs.into_iter()
+                .filter(|job| shared.admit(&meta, job))
+                .inspect(|_| shared.on_enqueue()),
+        );
+        shared.idle.wake(true);
+        Ok(())
+    }
+
+    /// Enqueue all elements of `iter` in the `DynQueue<T>`.
+    ///
+    /// See [`DynQueueHandle::enqueue_iter`].
+    #[inline]
+    pub fn enqueue_iter<I: IntoIterator<Item = T>>(
+        self,
+        iter: I,
+    ) -> Result<(), EnqueueError<Vec<T>>> {
+        self.enqueue_many(iter)
+    }
+
+    /// Number of elements pending in the queue of the current parallel
+    /// iterator
+    #[inline]
+    pub fn pending(self) -> usize {
+        self.inner.queue.len()
+    }
+
+    /// `true`, if no element is pending in the queue of the current parallel
+    /// iterator
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Estimate of the number of elements pending in the queues of all
+    /// parallel iterators
+    ///
+    /// See [`DynQueueHandle::pending_total`].
+    pub fn pending_total(self) -> usize {
+        let mut pending = 0;
+        for_each_shard(&self.inner.shards, |shard| pending += shard.len());
+        pending
+    }
+
+    /// The shared context set with [`DynQueue::with_context`]
+    ///
+    /// Returns `None`, if no context of type `C` was set.
+    pub fn context<C: Send + Sync + 'static>(self) -> Option<Arc<C>> {
+        self.inner
+            .shared
+            .context
+            .as_ref()
+            .and_then(|context| context.get())
+    }
+
+    /// The shared state of type `S` set with [`DynQueue::with_shared`]
+    ///
+    /// Returns `None`, if no state of type `S` was set.
+    pub fn shared<S: Send + Sync + 'static>(self) -> Option<Arc<S>> {
+        self.inner.shared.state::<S>()
+    }
+
+    /// The [`Bound`] set with [`DynQueue::with_bound`]
+    fn bound_of<B: Send + Sync + 'static>(self) -> Option<Bound<B>> {
+        self.inner
+            .shared
+            .context
+            .as_ref()
+            .and_then(|context| context.bound())
+    }
+
+    /// The best value of the [`Bound`] set with [`DynQueue::with_bound`]
+    ///
+    /// Returns `None`, if no bound of type `B` was set or it has no value yet.
+    pub fn bound<B: Ord + Clone + Send + Sync + 'static>(self) -> Option<B> {
+        self.bound_of::<B>().and_then(|bound| bound.get())
+    }
+
+    /// Replace the best value of the [`Bound`] set with
+    /// [`DynQueue::with_bound`] with `value`, if it is greater.
+    ///
+    /// See [`DynQueueHandle::try_improve_bound`].
+    pub fn try_improve_bound<B: Ord + Send + Sync + 'static>(self, value: B) -> bool {
+        self.bound_of::<B>()
+            .is_some_and(|bound| bound.try_improve(value))
+    }
+
+    /// Depth of the current element
+    ///
+    /// See [`DynQueueHandle::depth`].
+    #[inline]
+    pub fn depth(self) -> usize {
+        self.meta.depth
+    }
+
+    /// Number of times the current element was requeued
+    #[inline]
+    pub fn attempt(self) -> usize {
+        self.meta.attempt
+    }
+
+    /// Unique id of the current element
+    ///
+    /// See [`DynQueueHandle::current_id`].
+    #[inline]
+    pub fn current_id(self) -> Option<usize> {
+        self.meta.id
+    }
+
+    /// Id of the parent of the element with `id`
+    ///
+    /// See [`DynQueueHandle::parent_of`].
+    pub fn parent_of(self, id: usize) -> Option<usize> {
+        let shared = &self.inner.shared;
+        shared.lineage.lock().recover().parent_of(id)
+    }
+
+    /// Global sequence number of the current element
+    ///
+    /// See [`DynQueueHandle::sequence`].
+    #[inline]
+    pub fn sequence(self) -> Option<usize> {
+        self.sequence
+    }
+
+    /// Cancel the iteration of the `DynQueue<T>`, which is currently iterated.
+    #[inline]
+    pub fn cancel(self) {
+        self.inner.shared.cancel.cancel()
+    }
+
+    /// `true`, if the iteration was cancelled
+    #[inline]
+    pub fn is_cancelled(self) -> bool {
+        self.inner.shared.cancel.is_cancelled()
+    }
+
+    /// An owned [`DynQueueHandle`] for the current element, e.g. to move it
+    /// into another thread
+    ///
+    /// The handle enqueues its elements with the same parent as this one.
+    pub fn to_handle(self) -> DynQueueHandle<'a, T, U> {
+        DynQueueHandle {
+            inner: sync::Arc::downgrade(self.inner),
+            meta: self.meta,
+            sequence: self.sequence,
+        }
+    }
+}
+
+/// Enqueue elements from outside of the parallel iterator
+///
+/// Created with [`DynQueue::sender`]. The senders can be cloned and moved
+/// to other threads, which are not part of the rayon thread pool, to feed
+/// the `DynQueue` while it is iterated. The iteration only ends, when all
+/// senders are dropped and the queue is empty.
+///
+/// The elements are pushed to the original queue. Combine it with
+/// [`DynQueue::with_resplit_threshold`] to spread them to other threads.
+pub struct DynQueueSender<'a, T, U: Queue<T>> {
+    link: sync::Arc<Link<'a, T, U>>,
+}
+
+impl<'a, T, U: Queue<T>> DynQueueSender<'a, T, U> {
+    /// Enqueue `T` in the `DynQueue<T>`.
+    ///
+    /// Returns [`EnqueueError::Closed`], if the iteration already ended or
+    /// was stopped, or [`EnqueueError::Full`] with [`OverflowPolicy::Reject`].
+    pub fn send(&self, v: T) -> Result<(), EnqueueError<T>> {
+        self.link.with(|linked| {
+            let inner = match linked.inner.upgrade() {
+                Some(inner) if !inner.shared.is_stopped() => inner,
+                _ => return Err(EnqueueError::Closed(v)),
+            };
+
+            let meta =
+                inner.enqueue_meta(Meta::default(), v, |meta, v| inner.push_back(meta, v))?;
+            if meta.is_some() {
+                inner.shared.wake_waiter();
+            }
+            Ok(())
+        })
+    }
+
+    /// `true`, if the iteration of the `DynQueue<T>` ended and all elements
+    /// are refused.
+    #[inline]
+    pub fn is_closed(&self) -> bool {
+        self.link.with(|linked| linked.inner.strong_count() == 0)
+    }
+}
+
+impl<'a, T, U: Queue<T>> Clone for DynQueueSender<'a, T, U> {
+    fn clone(&self) -> Self {
+        self.link
+            .with(|linked| linked.shared.senders.fetch_add(1, Ordering::AcqRel));
+        DynQueueSender {
+            link: self.link.clone(),
+        }
+    }
+}
+
+impl<'a, T, U: Queue<T>> Drop for DynQueueSender<'a, T, U> {
+    fn drop(&mut self) {
+        self.link.with(|linked| {
+            linked.shared.senders.fetch_sub(1, Ordering::AcqRel);
+            linked.shared.wake_waiter();
+        })
+    }
+}
+
+/// Create a `DynQueue` with the `initial` elements, which lives only as
+/// long as the closure `f`.
+///
+/// The lifetime of the `DynQueue` and its `DynQueueHandle`s is bound to the
+/// call of `f`, so a handle cannot escape the scope, e.g. in the result or
+/// in a `static`. This turns a stale handle into a compile error.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// let mut result = dynqueue::scope(vec![1, 2, 3], |queue| {
+///     queue
+///         .into_par_iter()
+///         .map(|(handle, value)| {
+///             if value == 2 {
+///                 handle.enqueue(4).unwrap()
+///             };
+///             value
+///         })
+///         .collect::<Vec<_>>()
+/// });
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3, 4]);
+/// ```
+///
+/// Returning a handle from the scope does not compile:
+///
+/// ```compile_fail
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// let handles = dynqueue::scope(vec![1, 2, 3], |queue| {
+///     queue
+///         .into_par_iter()
+///         .map(|(handle, _)| handle)
+///         .collect::<Vec<_>>()
+/// });
+/// ```
+pub fn scope<T, U, I, F, R>(initial: I, f: F) -> R
+where
+    U: Queue<T>,
+    I: IntoDynQueue<T, U>,
+    F: for<'s> FnOnce(DynQueue<'s, T, U>) -> R,
+{
+    f(initial.into_dyn_queue())
+}
+
+/// Call `f` for the `initial` elements and all elements enqueued with the
+/// `DynQueueHandle` in parallel.
+///
+/// Shortcut for `initial.into_dyn_queue().into_par_iter().for_each(...)`
+/// without importing the rayon traits.
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// let sum = AtomicU64::new(0);
+///
+/// dynqueue::for_each(vec![1u64, 2, 3], |handle, value| {
+///     if value == 2 {
+///         handle.enqueue(4).unwrap();
+///     }
+///     sum.fetch_add(value, Ordering::Relaxed);
+/// });
+///
+/// assert_eq!(sum.into_inner(), 10);
+/// ```
+pub fn for_each<T, U, I, F>(initial: I, f: F)
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+    I: IntoDynQueue<T, U>,
+    F: Fn(DynQueueHandle<'_, T, U>, T) + Send + Sync,
+{
+    rayon::iter::ParallelIterator::for_each(initial.into_dyn_queue(), |(handle, v)| f(handle, v))
+}
+
+/// Call `f` for the `initial` elements in parallel, enqueue the children
+/// returned by `f` and collect the results of all elements.
+///
+/// This covers the common pattern "expand a node, emit a result and
+/// enqueue the children" without the `DynQueueHandle`.
+///
+/// ```
+/// let mut result = dynqueue::process(vec![1u64], |value| {
+///     let children = if value < 8 { vec![value * 2] } else { vec![] };
+///     (value * 10, children)
+/// });
+/// result.sort();
+///
+/// assert_eq!(result, vec![10, 20, 40, 80]);
+/// ```
+pub fn process<T, U, I, F, R, C>(initial: I, f: F) -> Vec<R>
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+    I: IntoDynQueue<T, U>,
+    F: Fn(T) -> (R, C) + Send + Sync,
+    R: Send,
+    C: IntoIterator<Item = T>,
+{
+    use rayon::iter::ParallelIterator as _;
+
+    initial
+        .into_dyn_queue()
+        .map(|(handle, v)| {
+            let (result, children) = f(v);
+            // cannot fail, the iteration is still running
+            let _ = handle.enqueue_many(children);
+            result
+        })
+        .collect()
+}
+
+/// Search the tree spanned by the `seeds` with successive depth-limited
+/// passes, until `f` returns `Some` or `max_depth` is reached.
+///
+/// The pass with the depth limit `limit` calls `f` for the `seeds` and all
+/// enqueued elements up to a [`depth`](DynQueueHandle::depth) of `limit`,
+/// deeper elements are dropped. The first `Some` cancels the pass and is
+/// returned. The shallow levels are processed again in every pass, but the
+/// memory is bounded by the depth limit and a result is found at the
+/// smallest depth possible. The allocation of the root queue is reused for
+/// all passes.
+///
+/// ```
+/// // find the path from 1 to 13 in the binary tree of `n -> 2n, 2n + 1`
+/// let result = dynqueue::iterative_deepening(vec![1u64], 10, |handle, value| {
+///     if value == 13 {
+///         return Some(handle.depth());
+///     }
+///     handle.enqueue_many(vec![value * 2, value * 2 + 1]).unwrap();
+///     None
+/// });
+///
+/// assert_eq!(result, Some(3));
+/// ```
+pub fn iterative_deepening<T, I, F, R>(seeds: I, max_depth: usize, f: F) -> Option<R>
+where
+    T: Clone + Send + Sync,
+    I: IntoIterator<Item = T>,
+    F: Fn(DynQueueHandle<'_, T, Tracked<RwLock<Vec<(Meta, T)>>>>, T) -> Option<R> + Send + Sync,
+    R: Send,
+{
+    let seeds = seeds.into_iter().collect::<Vec<_>>();
+    let mut frontier = Vec::with_capacity(seeds.len());
+
+    for limit in 0..=max_depth {
+        frontier.extend(seeds.iter().cloned().map(|v| (Meta::default(), v)));
+        let queue = Tracked::new(RwLock::new(frontier))
+            .into_dyn_queue()
+            .with_max_depth(limit);
+        let root = queue.0.queue.clone();
+
+        let found = queue.try_process(|handle, v| match f(handle, v) {
+            Some(result) => Err(result),
+            None => Ok(()),
+        });
+        if let Err(result) = found {
+            return Some(result);
+        }
+
+        // all parallel iterators are done, take back the emptied root queue
+        frontier = sync::Arc::try_unwrap(root)
+            .ok()
+            .map(|queue| queue.into_inner().into_inner().into_inner().recover())
+            .unwrap_or_default();
+        frontier.clear();
+    }
+    None
+}
+
+/// The `DynQueue<T>` which can be parallel iterated over
+pub struct DynQueue<'a, T, U: Queue<T>>(pub(crate) sync::Arc<DynQueueInner<'a, T, U>>);
+
+impl<'a, T, U: Queue<T>> fmt::Debug for DynQueue<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynQueue")
+            .field("queue", &std::any::type_name::<U>())
+            .field("pending", &self.0.queue.len())
+            .finish()
+    }
+}
+
+impl<'a, T> DynQueue<'a, T, RwLock<Vec<T>>> {
+    /// Create a [`DynQueueBuilder`] to configure a new `DynQueue`.
+    ///
+    /// The builder is not restricted to the `RwLock<Vec<T>>` queue,
+    /// [`DynQueueBuilder::build`] accepts everything implementing [`IntoDynQueue`].
+    pub fn builder() -> DynQueueBuilder<'a, T> {
+        DynQueueBuilder::new()
+    }
+
+    /// Create an empty `DynQueue`, which processes the elements arriving on
+    /// `receiver`, until all senders of the channel are dropped.
+    ///
+    /// Same as `Vec::new().into_dyn_queue().with_receiver(receiver)`, see
+    /// [`DynQueue::with_receiver`].
+    pub fn from_receiver(receiver: Receiver<T>) -> Self {
+        DynQueue::new(RwLock::new(Vec::new())).with_receiver(receiver)
+    }
+
+    /// Create an empty `DynQueue`, which takes its initial elements lazily
+    /// from `seeds`, while the first elements are already processed.
+    ///
+    /// Same as `Vec::new().into_dyn_queue().with_seeds(seeds)`, see
+    /// [`DynQueue::with_seeds`].
+    pub fn from_seeds<I>(seeds: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'a,
+    {
+        DynQueue::new(RwLock::new(Vec::new())).with_seeds(seeds)
+    }
+
+    /// Create an empty `DynQueue`, which takes its initial elements lazily
+    /// from the generator `f`, until it returns `None`.
+    ///
+    /// Same as `DynQueue::from_seeds(std::iter::from_fn(f))`, see
+    /// [`DynQueue::with_seeds`].
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::DynQueue;
+    ///
+    /// let mut next = 0u64;
+    /// let sum = DynQueue::from_fn(move || {
+    ///     next += 1;
+    ///     (next <= 100).then_some(next)
+    /// })
+    /// .into_par_iter()
+    /// .map(|(_, value)| value)
+    /// .sum::<u64>();
+    ///
+    /// assert_eq!(sum, 5050);
+    /// ```
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: FnMut() -> Option<T> + Send + 'a,
+    {
+        Self::from_seeds(std::iter::from_fn(f))
+    }
+}
+
+impl<'a, T, U: Queue<T> + Default> DynQueue<'a, T, U> {
+    /// Create an empty `DynQueue`, which can hold at least `capacity`
+    /// elements without reallocating.
+    ///
+    /// Initial elements can be added with `extend()`.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::collections::VecDeque;
+    /// use std::sync::RwLock;
+    ///
+    /// use dynqueue::DynQueue;
+    ///
+    /// let mut queue = DynQueue::<_, RwLock<VecDeque<_>>>::with_capacity(1024);
+    /// queue.extend(vec![1, 2, 3]);
+    ///
+    /// let mut result = queue
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         if value == 2 {
+    ///             handle.enqueue(4).unwrap()
+    ///         };
+    ///         value
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec![1, 2, 3, 4]);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        let queue = U::default();
+        queue.reserve(capacity);
+        DynQueue::new(queue)
+    }
+}
+
+impl<'a, T, U: Queue<T>> DynQueue<'a, T, U> {
+    #[inline(always)]
+    pub(crate) fn new(queue: U) -> Self {
+        let queue = sync::Arc::new(Counted::new(queue));
+        DynQueue(sync::Arc::new(DynQueueInner {
+            shards: sync::Arc::new(Mutex::new(vec![sync::Arc::downgrade(&queue)])),
+            queue,
+            shared: sync::Arc::new(Shared::new()),
+            buffer: Mutex::new(Vec::new()),
+            root: true,
+            link: sync::Arc::new(Link::new()),
+        }))
+    }
+
+    /// Split off a new `DynQueue` with `size` elements, which shares the
+    /// state of `self`
+    fn split_off(&self, size: usize) -> Self {
+        let queue = sync::Arc::new(self.0.queue.split_off(size));
+        let mut shards = self.0.shards.lock().recover();
+        shards.retain(|shard| shard.strong_count() > 0);
+        shards.push(sync::Arc::downgrade(&queue));
+
+        DynQueue(sync::Arc::new(DynQueueInner {
+            queue,
+            shards: self.0.shards.clone(),
+            shared: self.0.shared.clone(),
+            buffer: Mutex::new(Vec::new()),
+            root: false,
+            link: self.0.link.clone(),
+        }))
+    }
+
+    /// Steal half of the elements of the fullest other queue.
+    ///
+    /// Returns one of the stolen elements to be processed right away, so
+    /// that two idle parallel iterators cannot steal a single element back
+    /// and forth, or `None`, if there was nothing to steal.
+    fn steal(&self) -> Option<(Meta, T)> {
+        // without splitting, there is no other queue
+        if self.0.shared.split.is_single_threaded() {
+            return None;
+        }
+        let victim = self
+            .0
+            .shards
+            .lock()
+            .recover()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|shard| {
+                !sync::Arc::ptr_eq(shard, &self.0.queue) && !shard.shares_queue(&self.0.queue)
+            })
+            .map(|shard| (shard.len(), shard))
+            .max_by_key(|(len, _)| *len);
+
+        match victim {
+            Some((len, shard)) if len > 0 => {
+                let stolen = shard.split_off(len / 2);
+                #[cfg_attr(
+                    not(any(
+                        feature = "tracing",
+                        feature = "log",
+                        feature = "metrics",
+                        feature = "stats"
+                    )),
+                    allow(unused_variables)
+                )]
+                let count = stolen.len();
+                let first = stolen.pop_meta();
+                while let Some((meta, v)) = stolen.pop_meta() {
+                    self.0.queue.push_meta(meta, v);
+                }
+                trace!(
+                    debug,
+                    "steal",
+                    shard = self.0.shard_id(),
+                    victim = sync::Arc::as_ptr(&shard).cast::<()>(),
+                    count = count,
+                );
+                #[cfg(feature = "metrics")]
+                self.0.shared.metrics.steal(count);
+                #[cfg(feature = "stats")]
+                self.0.shared.stats.steal(count);
+                first
+            }
+            _ => None,
+        }
+    }
+
+    /// Change the configuration of the `DynQueue` with the
+    /// [`DynQueueBuilder`] methods of `f`
+    ///
+    /// All `with_*` methods forward to the builder, so a `DynQueue` is
+    /// configured in a single place.
+    fn configure<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(DynQueueBuilder<'a, T>) -> DynQueueBuilder<'a, T>,
+    {
+        self.update(|queue| {
+            let shared = std::mem::replace(queue.shared_mut(), Shared::new());
+            f(DynQueueBuilder::from_shared(shared)).apply(queue);
+        });
+        self
+    }
+
+    /// Change the state of the `DynQueue` exclusively with `f`
+    ///
+    /// The [`Control`] handles and [`DynQueueSender`]s are unlinked
+    /// meanwhile, so they can be created before the `DynQueue` is
+    /// configured.
+    fn update(&mut self, f: impl FnOnce(&mut Self)) {
+        let link = self.0.link.clone();
+        link.unlinked(|| {
+            f(self);
+            self.linked()
+        });
+    }
+
+    /// Only call it in [`DynQueue::update`].
+    fn shared_mut(&mut self) -> &mut Shared<'a, T> {
+        sync::Arc::get_mut(&mut self.0)
+            .and_then(|inner| sync::Arc::get_mut(&mut inner.shared))
+            .expect("DynQueue is already iterated")
+    }
+
+    fn linked(&self) -> Linked<'a, T, U> {
+        Linked {
+            inner: sync::Arc::downgrade(&self.0),
+            shared: self.0.shared.clone(),
+            shards: self.0.shards.clone(),
+        }
+    }
+
+    /// Link the [`Control`] handles and [`DynQueueSender`]s to `self`
+    fn link(&self) -> sync::Arc<Link<'a, T, U>> {
+        self.0.link.link(|| self.linked());
+        self.0.link.clone()
+    }
+
+    /// Skip all elements with the same key as an element, which was already seen.
+    ///
+    /// Duplicates are removed from the initial elements and every enqueued
+    /// element, whose key was seen before, is silently dropped.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let mut result = vec![1, 1, 2]
+    ///     .into_dyn_queue()
+    ///     .with_dedup(|v| *v)
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         handle.enqueue_many(vec![1, 2, 3]).unwrap();
+    ///         value
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec![1, 2, 3]);
+    /// ```
+    pub fn with_dedup<K, F>(self, key_fn: F) -> Self
+    where
+        K: Hash + Eq + Send + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.dedup(key_fn))
+    }
+
+    /// Skip all elements with the same key as an element, which was already
+    /// seen, using `hasher` to hash the keys.
+    ///
+    /// See [`DynQueue::with_dedup`].
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let mut result = vec!["https://example.com/".to_string()]
+    ///     .into_dyn_queue()
+    ///     .with_dedup_hasher(
+    ///         |url: &String| url.to_lowercase(),
+    ///         BuildHasherDefault::<DefaultHasher>::default(),
+    ///     )
+    ///     .into_par_iter()
+    ///     .map(|(handle, url)| {
+    ///         handle
+    ///             .enqueue_many(vec![
+    ///                 "https://EXAMPLE.com/".to_string(),
+    ///                 "https://example.org/".to_string(),
+    ///             ])
+    ///             .unwrap();
+    ///         url
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec!["https://example.com/", "https://example.org/"]);
+    /// ```
+    pub fn with_dedup_hasher<K, F, S>(self, key_fn: F, hasher: S) -> Self
+    where
+        K: Hash + Eq + Send + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+        S: BuildHasher + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.dedup_with_hasher(key_fn, hasher))
+    }
+
+    /// Skip all elements with the same key as an element, which was
+    /// probably already seen, using a Bloom filter of a fixed size.
+    ///
+    /// Unlike [`DynQueue::with_dedup`], the memory does not grow with the
+    /// number of keys, e.g. for state spaces, where an exact set of the
+    /// seen keys does not fit in memory. The filter is sized for
+    /// `expected_items` keys, with which an unseen key is dropped with a
+    /// probability of about `false_positive_rate`. Beyond `expected_items`
+    /// keys, the rate increases. A key enqueued concurrently by two parallel
+    /// iterators might pass twice. A Bloom filter cannot forget a key, so an
+    /// element refused by a bounded queue stays marked as seen.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let mut result = vec![1u64, 1]
+    ///     .into_dyn_queue()
+    ///     .with_approximate_dedup(|v| *v, 1000, 0.001)
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         handle.enqueue_many(vec![1, 2]).unwrap();
+    ///         value
+    ///     })
+    ///     .collect::<Vec<_>>();
+    ///
+    /// result.sort();
+    /// assert_eq!(result.len(), 2);
+    /// assert_eq!(result, vec![1, 2]);
+    /// ```
+    pub fn with_approximate_dedup<K, F>(
+        self,
+        key_fn: F,
+        expected_items: usize,
+        false_positive_rate: f64,
+    ) -> Self
+    where
+        K: Hash + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.configure(|builder| {
+            builder.approximate_dedup(key_fn, expected_items, false_positive_rate)
+        })
+    }
+
+    /// Skip all elements, for which `predicate` returns `false`.
+    ///
+    /// The initial elements are filtered and every enqueued element, which
+    /// does not pass the `predicate`, is silently dropped, no matter which
+    /// parallel iterator enqueues it.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let budget = 10;
+    ///
+    /// // (cost, path)
+    /// let mut result = vec![(0u64, String::new()), (20, String::new())]
+    ///     .into_dyn_queue()
+    ///     .with_filter(|(cost, _)| *cost <= budget)
+    ///     .into_par_iter()
+    ///     .map(|(handle, (cost, path))| {
+    ///         handle.enqueue((cost + 4, path.clone() + "a")).unwrap();
+    ///         handle.enqueue((cost + 7, path.clone() + "b")).unwrap();
+    ///         path
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec!["", "a", "aa", "b"]);
+    /// ```
+    pub fn with_filter<F>(self, predicate: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.filter(predicate))
+    }
+
+    /// Process the elements arriving on `receiver` as well, e.g. to use the
+    /// `DynQueue` as the core of a pool of workers.
+    ///
+    /// The iteration keeps running, while the channel is connected, and
+    /// only ends, when all its senders are dropped and all elements are
+    /// processed. Received elements pass the filters like enqueued ones.
+    /// A parallel iterator, which ran out of elements, receives the next
+    /// one. Combine it with [`DynQueue::with_resplit_threshold`] to spread
+    /// the elements enqueued while processing them to other threads.
+    ///
+    /// After a stop, the elements left in the channel are collected in the
+    /// [`DynQueue::leftovers`], or returned by [`Control::shutdown`].
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::DynQueue;
+    ///
+    /// let (sender, receiver) = std::sync::mpsc::channel();
+    ///
+    /// let producer = std::thread::spawn(move || {
+    ///     for job in [10, 20, 30] {
+    ///         sender.send(job).unwrap();
+    ///         std::thread::sleep(std::time::Duration::from_millis(5));
+    ///     }
+    /// });
+    ///
+    /// let mut result = DynQueue::from_receiver(receiver)
+    ///     .into_par_iter()
+    ///     .map(|(handle, job)| {
+    ///         if job % 10 == 0 {
+    ///             handle.enqueue(job + 1).unwrap();
+    ///         }
+    ///         job
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    /// producer.join().unwrap();
+    ///
+    /// assert_eq!(result, vec![10, 11, 20, 21, 30, 31]);
+    /// ```
+    pub fn with_receiver(self, receiver: Receiver<T>) -> Self {
+        self.configure(|builder| builder.receiver(receiver))
+    }
+
+    /// Take more initial elements lazily from `seeds`, e.g. to process
+    /// millions of seeds without materializing them first.
+    ///
+    /// A parallel iterator, which ran out of elements, takes the next chunk
+    /// of [`with_seed_chunk_size`](DynQueue::with_seed_chunk_size) elements
+    /// from `seeds`, 64 by default, so the memory for pending seeds stays
+    /// bounded. The iteration does not end, before `seeds` is exhausted.
+    /// The seeds pass the filters like enqueued elements.
+    ///
+    /// Seeds not taken yet are not part of the [`DynQueue::leftovers`] or
+    /// the frontier returned by [`Control::shutdown`].
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let count = vec![0u64]
+    ///     .into_dyn_queue()
+    ///     .with_seeds(1..100_000)
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         if value % 1000 == 0 {
+    ///             handle.enqueue(value + 1).unwrap();
+    ///         }
+    ///     })
+    ///     .count();
+    ///
+    /// assert_eq!(count, 100_000 + 100);
+    /// ```
+    pub fn with_seeds<I>(self, seeds: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'a,
+    {
+        self.configure(|builder| builder.seeds(seeds))
+    }
+
+    /// Take `size` elements at once from the seeds of
+    /// [`DynQueue::with_seeds`].
+    ///
+    /// Larger chunks lock the seeds less often, smaller chunks spread them
+    /// better to the parallel iterators. A size of 0 is treated as 1.
+    pub fn with_seed_chunk_size(self, size: usize) -> Self {
+        self.configure(|builder| builder.seed_chunk_size(size))
+    }
+
+    /// Use `token` to cancel the iteration from the outside.
+    pub fn with_cancel(self, token: CancelToken) -> Self {
+        self.configure(|builder| builder.cancel(token))
+    }
+
+    /// Pop the elements in the given `order`.
+    ///
+    /// See [`Order`].
+    pub fn with_order(self, order: Order) -> Self {
+        self.configure(|builder| builder.order(order))
+    }
+
+    /// Let the parallel iterators pop up to `size` elements at once.
+    ///
+    /// For cheap work items, the locking of the queue for every single
+    /// element is the dominant cost. The batch is processed one by one,
+    /// but elements in a batch cannot be stolen by other parallel iterators.
+    /// The default is `1`.
+    pub fn with_pop_batch_size(self, size: usize) -> Self {
+        self.configure(|builder| builder.pop_batch_size(size))
+    }
+
+    /// Let `DynQueueHandle::enqueue` collect up to `size` elements, before
+    /// they are pushed to the queue at once.
+    ///
+    /// Every parallel iterator has its own buffer, which is flushed after
+    /// processing the current element, or with [`DynQueueHandle::flush`].
+    /// Elements buffered, when the iteration stops early, are part of the
+    /// [`DynQueue::leftovers`]. This amortizes the lock contention for work items enqueueing many
+    /// elements one by one. The default is `1`, which disables the buffer.
+    pub fn with_enqueue_buffer_size(self, size: usize) -> Self {
+        self.configure(|builder| builder.enqueue_buffer_size(size))
+    }
+
+    /// Call `f` with the number of processed and pending elements after
+    /// every `every` processed elements.
+    ///
+    /// `f` is called from the worker thread, which processed the element.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let calls = AtomicUsize::new(0);
+    ///
+    /// vec![1, 2, 3, 4]
+    ///     .into_dyn_queue()
+    ///     .with_progress(2, |processed, _pending| {
+    ///         assert_eq!(processed % 2, 0);
+    ///         calls.fetch_add(1, Ordering::Relaxed);
+    ///     })
+    ///     .into_par_iter()
+    ///     .for_each(|_| {});
+    ///
+    /// assert_eq!(calls.load(Ordering::Relaxed), 2);
+    /// ```
+    pub fn with_progress<F>(self, every: usize, f: F) -> Self
+    where
+        F: Fn(usize, usize) + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.on_progress(every, f))
+    }
+
+    /// Call `f` for every element in parallel, until the first error.
+    ///
+    /// Unlike `ParallelIterator::try_for_each`, the error also stops the
+    /// other parallel iterators immediately.
+    ///
+    /// The first `Err` cancels the iteration: all parallel iterators stop
+    /// popping elements, all further enqueued elements are dropped and the
+    /// error is returned. A [`CancelToken`] passed with `with_cancel` is
+    /// cancelled as well.
+    ///
+    /// ```
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let result = vec![0u64].into_dyn_queue().try_process(|handle, value| {
+    ///     if value == 100 {
+    ///         return Err(value);
+    ///     }
+    ///     handle.enqueue(value + 1).unwrap();
+    ///     Ok(())
+    /// });
+    ///
+    /// assert_eq!(result, Err(100));
+    /// ```
+    pub fn try_process<F, E>(self, f: F) -> Result<(), E>
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: Fn(DynQueueHandle<'a, T, U>, T) -> Result<(), E> + Send + Sync,
+        E: Send,
+    {
+        let cancel = self.0.shared.cancel.clone();
+        rayon::iter::ParallelIterator::try_for_each(self, |(handle, v)| {
+            f(handle, v).inspect_err(|_| cancel.cancel())
+        })
+    }
+
+    /// Call `f` for every element in parallel and collect the results,
+    /// catching the panics of `f`.
+    ///
+    /// A panic only aborts the processing of the current element. The
+    /// element and the panic payload are returned as [`Panicked`] alongside
+    /// the results of the other elements. The panic hook is still called,
+    /// so the panic messages are printed as usual.
+    ///
+    /// ```
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// # std::panic::set_hook(Box::new(|_| {}));
+    /// let (mut results, panics) = vec![1u64, 2, 3]
+    ///     .into_dyn_queue()
+    ///     .collect_isolated(|handle, value| {
+    ///         if *value == 2 {
+    ///             handle.enqueue(4).unwrap();
+    ///             panic!("bad input");
+    ///         }
+    ///         value * 10
+    ///     });
+    /// results.sort();
+    ///
+    /// assert_eq!(results, vec![10, 30, 40]);
+    /// assert_eq!(panics.len(), 1);
+    /// assert_eq!(panics[0].item, 2);
+    /// assert_eq!(panics[0].message(), Some("bad input"));
+    /// ```
+    pub fn collect_isolated<F, R>(self, f: F) -> (Vec<R>, Vec<Panicked<T>>)
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: Fn(DynQueueHandle<'a, T, U>, &T) -> R + Send + Sync,
+        R: Send,
+    {
+        rayon::iter::ParallelIterator::partition_map(self, |(handle, item)| {
+            match std::panic::catch_unwind(AssertUnwindSafe(|| f(handle, &item))) {
+                Ok(r) => Either::Left(r),
+                Err(payload) => Either::Right(Panicked { item, payload }),
+            }
+        })
+    }
+
+    /// Map all elements with `f` in parallel, passing a borrowed
+    /// [`DynQueueRef`] instead of an owned [`DynQueueHandle`].
+    ///
+    /// The `DynQueueRef` costs no reference counting per element, which
+    /// pays off for many cheap elements. It is only valid for the call of
+    /// `f`, see [`DynQueueRef::to_handle`] for an owned handle.
+    ///
+    /// ```
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let sum = vec![0u64]
+    ///     .into_dyn_queue()
+    ///     .map_scoped(|handle, value| {
+    ///         if value < 1000 {
+    ///             handle.enqueue(value + 1).unwrap();
+    ///         }
+    ///         value
+    ///     })
+    ///     .sum::<u64>();
+    ///
+    /// assert_eq!(sum, 500_500);
+    /// ```
+    pub fn map_scoped<F, R>(self, f: F) -> MapScoped<'a, T, U, F, R>
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: for<'s> Fn(DynQueueRef<'s, 'a, T, U>, T) -> R + Send + Sync,
+        R: Send,
+    {
+        MapScoped {
+            queue: self,
+            f,
+            result: PhantomData,
+        }
+    }
+
+    /// Call `f` for every element in parallel in the background and stream
+    /// the results as they complete.
+    ///
+    /// The iteration is spawned on the current rayon thread pool. The
+    /// `Receiver` is disconnected, when all elements are processed.
+    /// Dropping the `Receiver` cancels the iteration.
+    ///
+    /// ```
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let results = vec![1u64, 2, 3]
+    ///     .into_dyn_queue()
+    ///     .for_each_streamed(|handle, value| {
+    ///         if value == 2 {
+    ///             handle.enqueue(4).unwrap()
+    ///         };
+    ///         value * 10
+    ///     });
+    ///
+    /// let mut result = results.iter().collect::<Vec<_>>();
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec![10, 20, 30, 40]);
+    /// ```
+    pub fn for_each_streamed<F, R>(self, f: F) -> Receiver<R>
+    where
+        'a: 'static,
+        T: Send + Sync + 'static,
+        U: Send + Sync + 'static,
+        F: Fn(DynQueueHandle<'a, T, U>, T) -> R + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        let cancel = self.0.shared.cancel.clone();
+        rayon::spawn(move || {
+            rayon::iter::ParallelIterator::for_each_with(self, sender, |sender, (handle, v)| {
+                if sender.send(f(handle, v)).is_err() {
+                    cancel.cancel();
+                }
+            })
+        });
+        receiver
+    }
+
+    /// Run `f` with the `DynQueue` on the rayon thread `pool`.
+    ///
+    /// All parallel iterators of the `DynQueue` are executed by the threads
+    /// of `pool` instead of the global pool. The configuration of the
+    /// `DynQueue`, e.g. the cancel token or the statistics, is kept.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let pool = rayon::ThreadPoolBuilder::new()
+    ///     .num_threads(2)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let mut result = vec![1, 2, 3].into_dyn_queue().run_on(&pool, |queue| {
+    ///     queue
+    ///         .into_par_iter()
+    ///         .map(|(handle, value)| {
+    ///             assert!(pool.current_thread_index().is_some());
+    ///             if value == 2 {
+    ///                 handle.enqueue(4).unwrap()
+    ///             };
+    ///             value
+    ///         })
+    ///         .collect::<Vec<_>>()
+    /// });
+    /// result.sort();
+    ///
+    /// assert_eq!(result, vec![1, 2, 3, 4]);
+    /// ```
+    pub fn run_on<F, R>(self, pool: &rayon::ThreadPool, f: F) -> R
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: FnOnce(Self) -> R + Send,
+        R: Send,
+    {
+        pool.install(move || f(self))
+    }
+
+    /// Stop popping elements, when `deadline` has passed.
+    ///
+    /// Elements enqueued by the elements processed at the deadline are
+    /// still accepted. All remaining elements can be collected with
+    /// [`DynQueue::leftovers`].
+    pub fn with_deadline(self, deadline: Instant) -> Self {
+        self.configure(|builder| builder.deadline(deadline))
+    }
+
+    /// Stop popping elements, when `timeout` from now has passed.
+    ///
+    /// See [`DynQueue::with_deadline`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.configure(|builder| builder.timeout(timeout))
+    }
+
+    /// Stop popping elements, when `max` elements were processed by all
+    /// parallel iterators together.
+    ///
+    /// All remaining elements can be collected with [`DynQueue::leftovers`].
+    pub fn with_max_items(self, max: usize) -> Self {
+        self.configure(|builder| builder.max_items(max))
+    }
+
+    /// Let [`DynQueueHandle::requeue`] refuse an element, which was already
+    /// requeued `max` times, with [`EnqueueError::Exhausted`].
+    ///
+    /// By default, an element can be requeued indefinitely.
+    pub fn with_max_retries(self, max: usize) -> Self {
+        self.configure(|builder| builder.max_retries(max))
+    }
+
+    /// Number all popped elements with a global sequence number, which is
+    /// available with [`DynQueueHandle::sequence`].
+    ///
+    /// The results can be sorted in the order, in which the elements were
+    /// popped, e.g. to trace the processing for debugging.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let mut trace = vec![1, 2, 3]
+    ///     .into_dyn_queue()
+    ///     .with_sequence()
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| (handle.sequence().unwrap(), value))
+    ///     .collect::<Vec<_>>();
+    /// trace.sort();
+    ///
+    /// assert_eq!(trace.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), vec![0, 1, 2]);
+    /// ```
+    pub fn with_sequence(self) -> Self {
+        self.configure(|builder| builder.sequence())
+    }
+
+    /// Limit the number of elements pending in the queues of all parallel
+    /// iterators.
+    ///
+    /// When `max` elements are pending, [`DynQueueHandle::enqueue`] blocks,
+    /// until the parallel iterators drained the queues below `max / 2`, and
+    /// [`DynQueueHandle::try_enqueue`] returns [`EnqueueError::Full`].
+    /// The last running parallel iterator is never blocked, so the limit
+    /// can be exceeded to prevent a deadlock.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let count = vec![0u64]
+    ///     .into_dyn_queue()
+    ///     .with_max_pending(100)
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         if value < 10 {
+    ///             handle.enqueue_many(vec![value + 1; 2]).unwrap();
+    ///         }
+    ///     })
+    ///     .count();
+    ///
+    /// assert_eq!(count, 2047);
+    /// ```
+    pub fn with_max_pending(self, max: usize) -> Self {
+        self.configure(|builder| builder.max_pending(max))
+    }
+
+    /// Call `on_high`, when the number of pending elements of all parallel
+    /// iterators rises to `high`, and `on_low`, when it falls to `low`
+    /// afterwards, e.g. to shed load or to resume the ingestion upstream.
+    ///
+    /// The callbacks alternate, starting with `on_high`, and get the number
+    /// of pending elements. They are called by the thread, which enqueued
+    /// or popped the element crossing the watermark, and should return
+    /// quickly. Requeued, delayed and deferred elements count as pending.
+    ///
+    /// Panics, if `low` is not below `high`.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::collections::VecDeque;
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let shed = AtomicBool::new(false);
+    ///
+    /// let count = VecDeque::from(vec![0u64])
+    ///     .into_dyn_queue()
+    ///     .with_watermarks(
+    ///         100,
+    ///         10,
+    ///         |_pending| shed.store(true, Ordering::Relaxed),
+    ///         |_pending| shed.store(false, Ordering::Relaxed),
+    ///     )
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         if value < 10 && !shed.load(Ordering::Relaxed) {
+    ///             handle.enqueue_many(vec![value + 1; 2]).unwrap();
+    ///         }
+    ///     })
+    ///     .count();
+    ///
+    /// assert!(count < 2047);
+    /// assert!(!shed.load(Ordering::Relaxed));
+    /// ```
+    pub fn with_watermarks<H, L>(self, high: usize, low: usize, on_high: H, on_low: L) -> Self
+    where
+        H: Fn(usize) + Send + Sync + 'a,
+        L: Fn(usize) + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.watermarks(high, low, on_high, on_low))
+    }
+
+    /// Limit the rate of enqueued elements to `per_second` elements per
+    /// second with bursts of up to `burst` elements, e.g. to be polite to
+    /// the servers of a web crawler.
+    ///
+    /// The limit is shared by all parallel iterators. The enqueue methods of
+    /// the handles block, until the element may be enqueued, and
+    /// [`DynQueueHandle::try_enqueue`] returns [`EnqueueError::Full`].
+    /// The initial elements and the elements of a [`DynQueueSender`] are not
+    /// limited.
+    ///
+    /// # Panics
+    ///
+    /// If `per_second` is not positive.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let start = Instant::now();
+    /// let count = vec![0u64]
+    ///     .into_dyn_queue()
+    ///     .with_rate_limit(100.0, 1)
+    ///     .into_par_iter()
+    ///     .map(|(handle, value)| {
+    ///         if value < 10 {
+    ///             handle.enqueue(value + 1).unwrap();
+    ///         }
+    ///     })
+    ///     .count();
+    ///
+    /// assert_eq!(count, 11);
+    /// assert!(start.elapsed() >= Duration::from_millis(90));
+    /// ```
+    pub fn with_rate_limit(self, per_second: f64, burst: usize) -> Self {
+        self.configure(|builder| builder.rate_limit(per_second, burst))
+    }
+
+    /// Process at most `limit` elements with the same key, e.g. the same
+    /// host, at the same time.
+    ///
+    /// An element popped, while `limit` elements of its key are processed
+    /// by other parallel iterators, is deferred, until one of them is
+    /// done. Only the parallel iteration is limited, the serial
+    /// [`DynQueueIter`] processes one element at a time anyway.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let crawling = [AtomicUsize::new(0), AtomicUsize::new(0)];
+    /// let count = vec![(0usize, 0u64), (1, 0)]
+    ///     .into_dyn_queue()
+    ///     .with_key_limit(|(host, _)| *host, 1)
+    ///     .into_par_iter()
+    ///     .map(|(handle, (host, page))| {
+    ///         assert_eq!(crawling[host].fetch_add(1, Ordering::SeqCst), 0);
+    ///         if page < 10 {
+    ///             handle.enqueue((host, page * 2 + 1)).unwrap();
+    ///             handle.enqueue((host, page * 2 + 2)).unwrap();
+    ///         }
+    ///         crawling[host].fetch_sub(1, Ordering::SeqCst);
+    ///     })
+    ///     .count();
+    ///
+    /// assert_eq!(count, 2 * 21);
+    /// ```
+    pub fn with_key_limit<K, F>(self, key_fn: F, limit: usize) -> Self
+    where
+        K: Hash + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.key_limit(key_fn, limit))
+    }
+
+    /// Never split the `DynQueue` for other parallel iterators, if
+    /// `single_thread` is `true`, or always allow it, if `false`.
+    ///
+    /// By default, the `DynQueue` is not split, if the current rayon thread
+    /// pool has only one thread, e.g. on `wasm32-unknown-unknown`.
+    ///
+    /// Only the splitting and the stealing from other parallel iterators
+    /// are skipped. Every element is still pushed to and popped from the
+    /// queue `U` with its own locking, e.g. the `RwLock` of a `Vec`. The
+    /// internal locks are only taken, while a parallel iterator waits for
+    /// work, e.g. for a [`DynQueueSender`].
+    pub fn with_single_thread(self, single_thread: bool) -> Self {
+        self.configure(|builder| builder.single_thread(single_thread))
+    }
+
+    /// Let `policy` decide, if and how a queue is split for another
+    /// parallel iterator.
+    ///
+    /// This replaces the `split_threshold` and `min_shard_size` of the
+    /// builder. See [`SplitPolicy`].
+    pub fn with_split_policy<P: SplitPolicy<T> + 'a>(self, policy: P) -> Self {
+        self.configure(|builder| builder.split_policy(policy))
+    }
+
+    /// Split a parallel iterator again, when its queue grew to `threshold`
+    /// or more elements and another rayon thread might be idle.
+    ///
+    /// Normally, rayon splits the parallel iterators only at the start, so
+    /// a queue, which grows afterwards, is processed by one thread alone,
+    /// until other threads run dry and steal from it.
+    pub fn with_resplit_threshold(self, threshold: usize) -> Self {
+        self.configure(|builder| builder.resplit_threshold(threshold))
+    }
+
+    /// Share the read-only `ctx` with all elements.
+    ///
+    /// The context can be accessed with [`DynQueueHandle::context`].
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::collections::HashMap;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let graph: HashMap<u32, Vec<u32>> = vec![(1, vec![2, 3]), (2, vec![3])]
+    ///     .into_iter()
+    ///     .collect();
+    ///
+    /// let count = vec![1]
+    ///     .into_dyn_queue()
+    ///     .with_context(graph)
+    ///     .into_par_iter()
+    ///     .map(|(handle, node)| {
+    ///         let graph = handle.context::<HashMap<u32, Vec<u32>>>().unwrap();
+    ///         if let Some(edges) = graph.get(&node) {
+    ///             handle.enqueue_many(edges.clone()).unwrap();
+    ///         }
+    ///     })
+    ///     .count();
+    ///
+    /// assert_eq!(count, 4);
+    /// ```
+    pub fn with_context<C: Send + Sync + 'static>(self, ctx: C) -> Self {
+        self.configure(|builder| builder.context(ctx))
+    }
+
+    /// Share `state`, e.g. an atomic counter or flag, with all elements.
+    ///
+    /// Every type can be shared once, sharing another value of the same type
+    /// replaces it. The state can be accessed with [`DynQueueHandle::shared`]
+    /// and [`DynQueue::shared`] and lives as long as the iteration.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    /// use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let queue = vec![1u64]
+    ///     .into_dyn_queue()
+    ///     .with_shared(AtomicU64::new(0))
+    ///     .with_shared(AtomicBool::new(false));
+    /// let sum = queue.shared::<AtomicU64>().unwrap();
+    ///
+    /// queue.into_par_iter().for_each(|(handle, value)| {
+    ///     handle
+    ///         .shared::<AtomicU64>()
+    ///         .unwrap()
+    ///         .fetch_add(value, Ordering::Relaxed);
+    ///     if value == 64 {
+    ///         handle.shared::<AtomicBool>().unwrap().store(true, Ordering::Relaxed);
+    ///     } else if value < 64 {
+    ///         handle.enqueue(value * 2).unwrap();
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(sum.load(Ordering::Relaxed), 127);
+    /// ```
+    pub fn with_shared<S: Send + Sync + 'static>(self, state: S) -> Self {
+        self.configure(|builder| builder.shared(state))
+    }
+
+    /// The shared state of type `S` set with [`DynQueue::with_shared`]
+    ///
+    /// Keep it to read the state after the iteration.
+    pub fn shared<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.0.shared.state::<S>()
+    }
+
+    /// Share the best value `bound` of a branch-and-bound search with all
+    /// elements.
+    ///
+    /// See [`DynQueueHandle::bound`] and [`DynQueueHandle::try_improve_bound`].
+    pub fn with_bound<B: Ord + Send + Sync + 'static>(self, bound: Bound<B>) -> Self {
+        self.configure(|builder| builder.bound(bound))
+    }
+
+    /// Share the best value `bound` of a branch-and-bound search with all
+    /// elements and drop every element, whose optimistic `estimate` cannot
+    /// improve it.
+    ///
+    /// The elements are checked, when they are enqueued and again, when
+    /// they are popped, because the bound might have improved in between.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::{Bound, IntoDynQueue as _};
+    ///
+    /// // pick a subset of the weights with the maximum sum <= 10
+    /// let weights = [6u64, 5, 4, 3];
+    /// let bound = Bound::new();
+    ///
+    /// // (next weight, sum)
+    /// vec![(0usize, 0u64)]
+    ///     .into_dyn_queue()
+    ///     .with_bound_pruning(bound.clone(), |(next, sum)| {
+    ///         (sum + weights[*next..].iter().sum::<u64>()).min(10)
+    ///     })
+    ///     .into_par_iter()
+    ///     .for_each(|(handle, (next, sum))| {
+    ///         handle.try_improve_bound(sum);
+    ///         if let Some(weight) = weights.get(next) {
+    ///             if sum + weight <= 10 {
+    ///                 handle.enqueue((next + 1, sum + weight)).unwrap();
+    ///             }
+    ///             handle.enqueue((next + 1, sum)).unwrap();
+    ///         }
+    ///     });
+    ///
+    /// assert_eq!(bound.get(), Some(10));
+    /// ```
+    pub fn with_bound_pruning<B, F>(self, bound: Bound<B>, estimate: F) -> Self
+    where
+        B: Ord + Send + Sync + 'static,
+        F: Fn(&T) -> B + Send + Sync + 'a,
+    {
+        self.configure(|builder| builder.bound_pruning(bound, estimate))
+    }
+
+    /// Unwrap the underlying queue with all elements, which were not
+    /// processed yet.
+    ///
+    /// To get the remaining elements after an iteration stopped early, e.g.
+    /// with `find_any()`, use [`DynQueue::leftovers`].
+    ///
+    /// ```
+    /// use std::sync::RwLock;
+    ///
+    /// use dynqueue::{DynQueue, IntoDynQueue as _};
+    ///
+    /// let mut queue: DynQueue<_, RwLock<Vec<_>>> = vec![1, 2].into_dyn_queue();
+    /// queue.extend(vec![3]);
+    ///
+    /// assert_eq!(queue.into_inner().into_inner().unwrap(), vec![1, 2, 3]);
+    /// ```
+    pub fn into_inner(self) -> U
+    where
+        U: Default,
+    {
+        let queue = sync::Arc::try_unwrap(self.0)
+            .ok()
+            .map(|mut inner| std::mem::take(&mut inner.queue))
+            .expect("DynQueue is already iterated");
+        sync::Arc::try_unwrap(queue)
+            .ok()
+            .expect("DynQueue is already iterated")
+            .into_inner()
+    }
+
+    /// Get a handle to the elements, which were not processed, because the
+    /// iteration stopped early.
+    ///
+    /// See [`Leftovers`].
+    pub fn leftovers(&self) -> Leftovers<T> {
+        Leftovers(self.0.shared.leftovers.clone())
+    }
+
+    /// Get a handle to the elements, which failed permanently.
+    ///
+    /// See [`DeadLetters`].
+    pub fn dead_letters(&self) -> DeadLetters<T> {
+        DeadLetters(self.0.shared.dead_letters.clone())
+    }
+
+    /// Get a handle to control the iteration, e.g. to pause it or shut it
+    /// down.
+    ///
+    /// See [`Control`].
+    pub fn control(&self) -> Control<'a, T, U> {
+        Control { link: self.link() }
+    }
+
+    /// Get a [`DynQueueSender`] to enqueue elements from threads outside of
+    /// the parallel iterator.
+    ///
+    /// The iteration does not end, before all senders are dropped and the
+    /// queue is empty.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let queue = vec![1, 2, 3].into_dyn_queue();
+    /// let sender = queue.sender();
+    ///
+    /// let feeder = std::thread::spawn(move || {
+    ///     for i in 4..=6 {
+    ///         sender.send(i).unwrap();
+    ///     }
+    /// });
+    ///
+    /// let mut result = queue
+    ///     .into_par_iter()
+    ///     .map(|(_, value)| value)
+    ///     .collect::<Vec<_>>();
+    /// result.sort();
+    /// feeder.join().unwrap();
+    ///
+    /// assert_eq!(result, vec![1, 2, 3, 4, 5, 6]);
+    /// ```
+    pub fn sender(&self) -> DynQueueSender<'a, T, U> {
+        self.0.shared.senders.fetch_add(1, Ordering::AcqRel);
+        DynQueueSender { link: self.link() }
+    }
+
+    /// Get a handle to the statistics of the run
+    #[cfg(feature = "stats")]
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle(self.0.shared.stats.clone())
+    }
+}
+
+/// Collect the initial elements directly into the queue
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+/// use std::collections::VecDeque;
+/// use std::sync::RwLock;
+///
+/// use dynqueue::DynQueue;
+///
+/// let queue = (1..4).collect::<DynQueue<_, RwLock<VecDeque<_>>>>();
+///
+/// let mut result = queue
+///     .into_par_iter()
+///     .map(|(_, value)| value)
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3]);
+/// ```
+impl<'a, T, U: Queue<T> + Default> FromIterator<T> for DynQueue<'a, T, U> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let queue = U::default();
+        queue.push_batch(iter);
+        DynQueue::new(queue)
+    }
+}
+
+/// Collect the initial elements of a parallel iterator directly into the queue
+///
+/// Every parallel iterator collects its elements in a chunk, which is pushed
+/// with one lock acquisition.
+impl<'a, T, U> FromParallelIterator<T> for DynQueue<'a, T, U>
+where
+    T: Send,
+    U: Queue<T> + Default + Send + Sync,
+{
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(par_iter: I) -> Self {
+        use rayon::iter::ParallelIterator as _;
+
+        let queue = U::default();
+        par_iter
+            .into_par_iter()
+            .fold(Vec::new, |mut chunk, v| {
+                chunk.push(v);
+                chunk
+            })
+            .for_each(|chunk| queue.push_batch(chunk));
+        DynQueue::new(queue)
+    }
+}
+
+/// Add more initial elements, before the iteration starts
+///
+/// The elements have to pass the filters, e.g. of `with_dedup`.
+impl<'a, T, U: Queue<T>> Extend<T> for DynQueue<'a, T, U> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let shared = &self.0.shared;
+        self.0
+            .queue
+            .push_batch(iter.into_iter().filter(|v| shared.passes(v)))
+    }
+}
+
+/// Add more initial elements of a parallel iterator, before the iteration starts
+///
+/// The elements have to pass the filters, e.g. of `with_dedup`.
+impl<'a, T, U> ParallelExtend<T> for DynQueue<'a, T, U>
+where
+    T: Send,
+    U: Queue<T> + Send + Sync,
+{
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, par_iter: I) {
+        use rayon::iter::ParallelIterator as _;
+
+        let filters = &self.0.shared.filters;
+        let queue = &self.0.queue;
+        par_iter
+            .into_par_iter()
+            .filter(|v| passes(filters, v))
+            .fold(Vec::new, |mut chunk, v| {
+                chunk.push(v);
+                chunk
+            })
+            .for_each(|chunk| queue.push_batch(chunk));
+    }
+}
+
+impl<'a, T, U: Queue<(Meta, T)>> DynQueue<'a, T, Tracked<U>> {
+    /// Silently drop all enqueued elements with a depth greater than `max_depth`.
+    pub fn with_max_depth(self, max_depth: usize) -> Self {
+        self.configure(|builder| builder.max_depth(max_depth))
+    }
+
+    /// Get a handle to the parents of all elements and give every element
+    /// a unique id.
+    ///
+    /// The elements in the queue are numbered in the order of the queue.
+    /// See [`Lineage`].
+    pub fn lineage(&self) -> Lineage {
+        let shared = &self.0.shared;
+        let mut table = shared.lineage.lock().recover();
+        if table.parents.is_empty() {
+            let queue = &self.0.queue.inner().0;
+            let initial = queue.pop_batch(queue.len(), Some(Order::Fifo));
+            for (meta, v) in initial {
+                let id = Some(table.push(None));
+                queue.push((Meta { id, ..meta }, v));
+            }
+        }
+        Lineage(shared.lineage.clone())
+    }
+
+    /// Call `f` for every element in parallel and collect the results in
+    /// the order, in which the elements were enqueued.
+    ///
+    /// The initial elements come first in the order of the queue, followed
+    /// by the enqueued elements in the order of the `enqueue` calls.
+    /// The `enqueue_buffer_size` is ignored, because every element gets its
+    /// own index.
+    ///
+    /// ```
+    /// use dynqueue::{IntoDynQueue as _, Tracked};
+    ///
+    /// let result = Tracked::from(vec![1u64, 2, 3])
+    ///     .into_dyn_queue()
+    ///     .collect_ordered(|handle, value| {
+    ///         if value < 3 {
+    ///             handle.enqueue(value * 10).unwrap();
+    ///         }
+    ///         value
+    ///     });
+    ///
+    /// assert_eq!(&result[..3], &[1, 2, 3]);
+    /// assert_eq!(result.len(), 5);
+    /// ```
+    pub fn collect_ordered<F, R>(mut self, f: F) -> Vec<R>
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: Fn(DynQueueHandle<'a, T, Tracked<U>>, T) -> R + Send + Sync,
+        R: Send,
+    {
+        use rayon::iter::ParallelIterator as _;
+        use rayon::slice::ParallelSliceMut as _;
+
+        // number the initial elements
+        let queue = &self.0.queue.inner().0;
+        let initial = queue.pop_batch(queue.len(), Some(Order::Fifo));
+        let len = initial.len();
+        for (index, (meta, v)) in initial.into_iter().enumerate() {
+            queue.push((Meta { index, ..meta }, v));
+        }
+        DynQueue::update(&mut self, |queue| {
+            queue.shared_mut().index = Some(AtomicUsize::new(len))
+        });
+
+        let mut results = self
+            .map(|(handle, v)| (handle.meta.index, f(handle, v)))
+            .collect::<Vec<_>>();
+        results.par_sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+
+    /// Call `f` for every element in parallel and reduce the results of
+    /// the elements enqueued by its handle into the result of the element
+    /// with `combine`.
+    ///
+    /// An element is complete, when `f` returned for it and all of its
+    /// children are complete. Then its result is `combine`d with the
+    /// results of its children, one after the other in no particular order,
+    /// and passed on to its parent. Returns the results of the initial
+    /// elements in the order of the queue, and of elements sent with a
+    /// [`DynQueueSender`] after them.
+    ///
+    /// Elements, which are requeued, dropped by an [`OverflowPolicy`] or
+    /// not processed, because the iteration stopped early, leave their
+    /// ancestors incomplete, which are missing in the result.
+    ///
+    /// ```
+    /// use dynqueue::{IntoDynQueue as _, Tracked};
+    ///
+    /// // sum of all numbers from 1 to 15 in a binary tree
+    /// let result = Tracked::from(vec![1u64])
+    ///     .into_dyn_queue()
+    ///     .fork_join(
+    ///         |handle, value| {
+    ///             if value < 8 {
+    ///                 handle.enqueue(value * 2).unwrap();
+    ///                 handle.enqueue(value * 2 + 1).unwrap();
+    ///             }
+    ///             value
+    ///         },
+    ///         |sum, child| sum + child,
+    ///     );
+    ///
+    /// assert_eq!(result, vec![120]);
+    /// ```
+    pub fn fork_join<F, C, R>(self, f: F, combine: C) -> Vec<R>
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: Fn(DynQueueHandle<'a, T, Tracked<U>>, T) -> R + Send + Sync,
+        C: Fn(R, R) -> R + Send + Sync,
+        R: Send,
+    {
+        use rayon::iter::ParallelIterator as _;
+
+        /// An element waiting for the results of its children
+        struct Node<R> {
+            result: Option<R>,
+            expected: usize,
+            children: Vec<R>,
+        }
+
+        impl<R> Default for Node<R> {
+            fn default() -> Self {
+                Node {
+                    result: None,
+                    expected: 0,
+                    children: Vec::new(),
+                }
+            }
+        }
+
+        let lineage = self.lineage();
+        let nodes = Mutex::new(HashMap::<usize, Node<R>>::new());
+        let roots = Mutex::new(Vec::new());
+
+        self.for_each(|(handle, v)| {
+            let mut id = handle.current_id().expect("lineage is tracked");
+            let result = f(handle, v);
+
+            // store the result and pass on the completed elements
+            let mut nodes_guard = nodes.lock().recover();
+            let node = nodes_guard.entry(id).or_default();
+            node.result = Some(result);
+            node.expected = lineage.children_of(id);
+            loop {
+                let node = match nodes_guard.get(&id) {
+                    Some(node) if node.result.is_some() && node.children.len() == node.expected => {
+                        nodes_guard.remove(&id).unwrap()
+                    }
+                    _ => return,
+                };
+                drop(nodes_guard);
+                let result = node
+                    .children
+                    .into_iter()
+                    .fold(node.result.unwrap(), &combine);
+                match lineage.parent_of(id) {
+                    Some(parent) => {
+                        nodes_guard = nodes.lock().recover();
+                        nodes_guard.entry(parent).or_default().children.push(result);
+                        id = parent;
+                    }
+                    None => {
+                        roots.lock().recover().push((id, result));
+                        return;
+                    }
+                }
+            }
+        });
+
+        let mut roots = roots.into_inner().recover();
+        roots.sort_unstable_by_key(|(id, _)| *id);
+        roots.into_iter().map(|(_, r)| r).collect()
+    }
+
+    /// Call `f` for every element in parallel and collect the results keyed
+    /// by the id of their element.
+    ///
+    /// The lineage is tracked, so that [`DynQueueHandle::enqueue_ticket`]
+    /// returns the [`Ticket`] to retrieve the result of an enqueued
+    /// element. The initial elements get the ids `0..n` in the order of the
+    /// queue. See [`Tickets`].
+    pub fn collect_tickets<F, R>(self, f: F) -> Tickets<R>
+    where
+        T: Send + Sync,
+        U: Send + Sync,
+        F: Fn(DynQueueHandle<'a, T, Tracked<U>>, T) -> R + Send + Sync,
+        R: Send,
+    {
+        use rayon::iter::ParallelIterator as _;
+
+        let _lineage = self.lineage();
+        let results = self
+            .map(|(handle, v)| {
+                let id = handle.current_id().expect("lineage is tracked");
+                (id, f(handle, v))
+            })
+            .collect();
+        Tickets { results }
+    }
+}
+
+impl<'a, K, V, U> DynQueue<'a, (K, V), U>
+where
+    K: Hash + Eq + Clone + Send + 'a,
+    U: Queue<(K, V)>,
+{
+    /// Skip all elements `(K, V)` with a key `K`, which was already seen.
+    ///
+    /// Same as `with_dedup(|(key, _)| key.clone())`. The value of the first
+    /// element with a key wins. See [`DynQueue::with_dedup`].
+    pub fn with_key_dedup(self) -> Self {
+        self.with_dedup(|(k, _): &(K, V)| k.clone())
+    }
+
+    /// Call `f` for the element of every key `K` in parallel and collect
+    /// the results keyed by `K`.
+    ///
+    /// The elements are deduplicated on `K` with
+    /// [`DynQueue::with_key_dedup`], so `f` is called once per key, e.g. to
+    /// build the closure of a dependency graph, where every node is
+    /// expanded and evaluated only once.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let deps = HashMap::from([
+    ///     ("app", vec!["log", "serde"]),
+    ///     ("log", vec![]),
+    ///     ("serde", vec!["log"]),
+    /// ]);
+    ///
+    /// // the number of dependencies of every crate reachable from "app"
+    /// let closure = vec![("app", 0)]
+    ///     .into_dyn_queue()
+    ///     .collect_map(|handle, name, depth| {
+    ///         for dep in &deps[name] {
+    ///             handle.enqueue((*dep, depth + 1)).unwrap();
+    ///         }
+    ///         deps[name].len()
+    ///     });
+    ///
+    /// assert_eq!(
+    ///     closure,
+    ///     HashMap::from([("app", 2), ("log", 0), ("serde", 1)])
+    /// );
+    /// ```
+    pub fn collect_map<F, R>(self, f: F) -> HashMap<K, R>
+    where
+        K: Sync,
+        V: Send + Sync,
+        U: Send + Sync,
+        F: Fn(DynQueueHandle<'a, (K, V), U>, &K, V) -> R + Send + Sync,
+        R: Send,
+    {
+        use rayon::iter::ParallelIterator as _;
+
+        self.with_key_dedup()
+            .map(|(handle, (k, v))| {
+                let r = f(handle, &k, v);
+                (k, r)
+            })
+            .collect()
+    }
+}
+
+impl<'a, T, U> UnindexedProducer for DynQueue<'a, T, U>
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+{
+    type Item = (DynQueueHandle<'a, T, U>, T);
+
+    fn split(self) -> (Self, Option<Self>) {
+        // take more seeds, so there is something to split
+        if self.0.shared.split.is_below_threshold(self.0.queue.len()) {
+            self.0.seed();
+        }
+        match self.split_size() {
+            Some(size) => {
+                let len = self.0.queue.len();
+                let new_q = self.split_off(size);
+                self.0.shared.on_split(len);
+                trace!(
+                    debug,
+                    "split",
+                    shard = self.0.shard_id(),
+                    new = new_q.0.shard_id(),
+                    size = size,
+                    len = self.0.queue.len(),
+                );
+                (self, Some(new_q))
+            }
+            None => (self, None),
+        }
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        self.fold(&Handles, folder, false).0
+    }
+}
+
+impl<'a, T, U> DynQueue<'a, T, U>
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+{
+    /// The `size` to split off the queue, if it shall be split
+    #[inline]
+    fn split_size(&self) -> Option<usize> {
+        let shared = &self.0.shared;
+        if shared.split.is_single_threaded() || shared.is_stopped() {
+            return None;
+        }
+
+        shared.split.split_size(&*self.0.queue)
+    }
+
+    /// `true`, if the queue grew beyond the `resplit_threshold` and
+    /// another rayon thread might be idle
+    #[inline]
+    fn wants_resplit(&self) -> bool {
+        self.0
+            .shared
+            .split
+            .resplit_threshold
+            .is_some_and(|threshold| {
+                self.0.queue.len() >= threshold
+                    && rayon_core::current_thread_has_pending_tasks() == Some(false)
+                    && self.split_size().is_some()
+            })
+    }
+
+    /// Fold the elements of the queue, turned into items by `yields`,
+    /// into `folder`.
+    ///
+    /// With `resplit`, the folding stops, when the queue should be split
+    /// again, and the queue is returned alongside the folder.
+    fn fold<Y, F>(self, yields: &Y, folder: F, resplit: bool) -> (F, Option<Self>)
+    where
+        Y: Yield<'a, T, U>,
+        F: Folder<Y::Item>,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("fold", thread = ?rayon::current_thread_index()).entered();
+
+        let _active = Active::new(&self.0.shared);
+        let mut folder = folder;
+        let mut this = self;
+        // shared by the handles of all elements
+        let mut weak = sync::Arc::downgrade(&this.0);
+        let mut batch = VecDeque::new();
+        loop {
+            if this.0.shared.is_stopped() {
+                break;
+            }
+            let wakeups = this.0.shared.idle.wakeups();
+            if this.0.shared.is_paused() {
+                this.0
+                    .shared
+                    .idle
+                    .wait_for_work(wakeups, Some(control::PAUSE_POLL));
+                continue;
+            }
+
+            let ret = match batch.pop_front() {
+                Some(ret) => Some(ret),
+                None if this.0.shared.pop_batch_size > 1 => {
+                    batch.extend(this.0.pop_batch());
+                    batch.pop_front()
+                }
+                None => this.0.pop(),
+            };
+            let ret = match ret {
+                // pop the flushed elements first
+                None if this.0.flush() => continue,
+                None => this.steal(),
+                ret => ret,
+            };
+
+            if let Some((meta, v)) = ret {
+                if this.0.shared.is_pruned(&v) {
+                    trace!(trace, "prune");
+                    this.0.shared.on_drop();
+                    continue;
+                }
+                let (meta, v, key) = match &this.0.shared.key_limit {
+                    Some(limit) => match limit.acquire(meta, v) {
+                        Some((key, meta, v)) => (meta, v, Some(key)),
+                        // deferred, until an element of the key is processed
+                        None => continue,
+                    },
+                    None => (meta, v, None),
+                };
+                if !this.0.on_pop() {
+                    if let Some(key) = key {
+                        this.0.shared.release_key(key);
+                    }
+                    this.0.queue.push_meta(meta, v);
+                    break;
+                }
+                let sequence = this.0.shared.next_sequence();
+                #[cfg(feature = "stats")]
+                let start = Instant::now();
+                folder = {
+                    let _in_flight = InFlight::new(&this.0.shared);
+                    let _slot = key.map(|key| KeySlot(&this.0.shared, key));
+                    let _lend = Lend::new(&this.0);
+                    folder.consume(yields.item(&this.0, &weak, meta, sequence, v))
+                };
+                #[cfg(feature = "stats")]
+                this.0.shared.stats.busy(start.elapsed());
+                this.0.flush();
+
+                if folder.full() {
+                    break;
+                }
+
+                if resplit && this.wants_resplit() {
+                    trace!(debug, "resplit");
+                    batch
+                        .into_iter()
+                        .for_each(|(meta, v)| this.0.queue.push_meta(meta, v));
+                    return (folder, Some(this));
+                }
+            } else {
+                if this.0.awaits_senders() {
+                    this.0.wait_for_senders();
+                    continue;
+                }
+                // Elements enqueued with a delay are not due yet
+                if let Some(due) = this.0.shared.delayed.next_due() {
+                    let timeout = due.saturating_duration_since(Instant::now());
+                    this.0.shared.idle.wait_for_work(wakeups, Some(timeout));
+                    continue;
+                }
+                // A peer might be about to enqueue new elements to steal
+                if this.0.shared.awaits_in_flight() {
+                    this.0.shared.idle.wait_for_work(wakeups, None);
+                    continue;
+                }
+                // Close the queue, unless a handle is enqueueing right now
+                match sync::Arc::try_unwrap(this.0) {
+                    // a handle enqueued, before it released the queue
+                    Ok(inner) if inner.has_queued() => {
+                        this = DynQueue(sync::Arc::new(inner));
+                        weak = sync::Arc::downgrade(&this.0);
+                    }
+                    Ok(_inner) => {
+                        trace!(debug, "shard exhausted", shard = _inner.shard_id());
+                        return (folder, None);
+                    }
+                    Err(inner) => {
+                        this = DynQueue(inner);
+                        sync::yield_now();
+                    }
+                }
+            }
+        }
+
+        // give the unprocessed rest of the batch back
+        batch
+            .into_iter()
+            .for_each(|(meta, v)| this.0.queue.push_meta(meta, v));
+        (folder, None)
+    }
+}
+
+/// Like `rayon::iter::plumbing::bridge_unindexed`, but the parallel
+/// iterators are split again, when their queue grew beyond the
+/// `resplit_threshold`.
+///
+/// `splits` is the remaining number of splits, like the adaptive splitter
+/// of rayon, which is reset, when a job was stolen by another thread.
+fn bridge<'a, T, U, Y, C>(
+    producer: DynQueue<'a, T, U>,
+    yields: &Y,
+    consumer: C,
+    splits: usize,
+) -> C::Result
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+    Y: Yield<'a, T, U> + Sync,
+    C: UnindexedConsumer<Y::Item>,
+{
+    if consumer.full() {
+        return consumer.into_folder().complete();
+    }
+
+    let producer = if splits > 0 {
+        match producer.split() {
+            (left, Some(right)) => {
+                let reducer = consumer.to_reducer();
+                let left_consumer = consumer.split_off_left();
+                let (left, right) = rayon::join_context(
+                    |_| bridge(left, yields, left_consumer, splits / 2),
+                    |ctx| {
+                        let splits = if ctx.migrated() {
+                            (splits / 2).max(rayon::current_num_threads())
+                        } else {
+                            splits / 2
+                        };
+                        bridge(right, yields, consumer, splits)
+                    },
+                );
+                return reducer.reduce(left, right);
+            }
+            (producer, None) => producer,
+        }
+    } else {
+        producer
+    };
+
+    match producer.fold(yields, consumer.split_off_left().into_folder(), true) {
+        (folder, None) => folder.complete(),
+        (folder, Some(producer)) => {
+            let reducer = consumer.to_reducer();
+            let rest = bridge(producer, yields, consumer, rayon::current_num_threads());
+            reducer.reduce(folder.complete(), rest)
+        }
+    }
+}
+
+impl<'a, T, U> rayon::iter::ParallelIterator for DynQueue<'a, T, U>
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+{
+    type Item = (DynQueueHandle<'a, T, U>, T);
+
+    fn drive_unindexed<C>(self, consumer: C) -> <C as Consumer<Self::Item>>::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        self.0.shared.on_start(self.0.queue.len());
+        if self.0.shared.split.resplit_threshold.is_some() {
+            bridge(self, &Handles, consumer, rayon::current_num_threads())
+        } else {
+            bridge_unindexed(self, consumer)
+        }
+    }
+}
+
+/// Turns a popped element into an item of a parallel iterator
+trait Yield<'a, T, U: Queue<T>> {
+    type Item;
+
+    fn item(
+        &self,
+        inner: &sync::Arc<DynQueueInner<'a, T, U>>,
+        weak: &Weak<DynQueueInner<'a, T, U>>,
+        meta: Meta,
+        sequence: Option<usize>,
+        v: T,
+    ) -> Self::Item;
+}
+
+/// Yields the element with an owned `DynQueueHandle`
+struct Handles;
+
+impl<'a, T, U: Queue<T>> Yield<'a, T, U> for Handles {
+    type Item = (DynQueueHandle<'a, T, U>, T);
+
+    #[inline(always)]
+    fn item(
+        &self,
+        _inner: &sync::Arc<DynQueueInner<'a, T, U>>,
+        weak: &Weak<DynQueueInner<'a, T, U>>,
+        meta: Meta,
+        sequence: Option<usize>,
+        v: T,
+    ) -> Self::Item {
+        let handle = DynQueueHandle {
+            inner: weak.clone(),
+            meta,
+            sequence,
+        };
+        (handle, v)
+    }
+}
+
+/// Yields the result of the closure called with a borrowed `DynQueueRef`
+struct Scoped<'f, F, R>(&'f F, PhantomData<fn() -> R>);
+
+impl<'a, T, U, F, R> Yield<'a, T, U> for Scoped<'_, F, R>
+where
+    U: Queue<T>,
+    F: for<'s> Fn(DynQueueRef<'s, 'a, T, U>, T) -> R,
+{
+    type Item = R;
+
+    #[inline(always)]
+    fn item(
+        &self,
+        inner: &sync::Arc<DynQueueInner<'a, T, U>>,
+        _weak: &Weak<DynQueueInner<'a, T, U>>,
+        meta: Meta,
+        sequence: Option<usize>,
+        v: T,
+    ) -> R {
+        let handle = DynQueueRef {
+            inner,
+            meta,
+            sequence,
+            buffered: false,
+        };
+        (self.0)(handle, v)
+    }
+}
+
+/// The producer of [`MapScoped`]
+struct ScopedProducer<'f, 'a, T, U: Queue<T>, F, R> {
+    queue: DynQueue<'a, T, U>,
+    scoped: &'f Scoped<'f, F, R>,
+}
+
+impl<'f, 'a, T, U, F, R> UnindexedProducer for ScopedProducer<'f, 'a, T, U, F, R>
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+    F: for<'s> Fn(DynQueueRef<'s, 'a, T, U>, T) -> R + Sync,
+    R: Send,
+{
+    type Item = R;
+
+    fn split(self) -> (Self, Option<Self>) {
+        let scoped = self.scoped;
+        let (left, right) = self.queue.split();
+        (
+            ScopedProducer {
+                queue: left,
+                scoped,
+            },
+            right.map(|queue| ScopedProducer { queue, scoped }),
+        )
+    }
+
+    fn fold_with<G>(self, folder: G) -> G
+    where
+        G: Folder<R>,
+    {
+        self.queue.fold(self.scoped, folder, false).0
+    }
+}
+
+/// Parallel iterator returned by [`DynQueue::map_scoped`]
+pub struct MapScoped<'a, T, U: Queue<T>, F, R> {
+    queue: DynQueue<'a, T, U>,
+    f: F,
+    result: PhantomData<fn() -> R>,
+}
+
+impl<'a, T, U, F, R> rayon::iter::ParallelIterator for MapScoped<'a, T, U, F, R>
+where
+    T: Send + Sync,
+    U: Queue<T> + Send + Sync,
+    F: for<'s> Fn(DynQueueRef<'s, 'a, T, U>, T) -> R + Send + Sync,
+    R: Send,
+{
+    type Item = R;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<R>,
+    {
+        let queue = self.queue;
+        let scoped = Scoped(&self.f, PhantomData);
+        queue.0.shared.on_start(queue.0.queue.len());
+        if queue.0.shared.split.resplit_threshold.is_some() {
+            bridge(queue, &scoped, consumer, rayon::current_num_threads())
+        } else {
+            let scoped = &scoped;
+            bridge_unindexed(ScopedProducer { queue, scoped }, consumer)
+        }
+    }
+}
+
+/// Serial iterator over a `DynQueue<T>`
+///
+/// Elements are popped in the order of the underlying queue, which makes
+/// runs reproducible, e.g. for debugging.
+///
+/// ```
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let result = vec![1, 2, 3]
+///     .into_dyn_queue()
+///     .into_iter()
+///     .map(|(handle, value)| {
+///         if value == 2 {
+///             handle.enqueue(4).unwrap()
+///         };
+///         value
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(result, vec![3, 2, 4, 1]);
+/// ```
+pub struct DynQueueIter<'a, T, U: Queue<T>>(Option<sync::Arc<DynQueueInner<'a, T, U>>>);
+
+impl<'a, T, U: Queue<T>> Iterator for DynQueueIter<'a, T, U> {
+    type Item = (DynQueueHandle<'a, T, U>, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let inner = self.0.take()?;
+
+            if inner.shared.is_stopped() {
+                return None;
+            }
+            // the elements enqueued while the last element was processed
+            inner.flush();
+            if inner.shared.is_paused() {
+                inner
+                    .shared
+                    .idle
+                    .wait_for_work(inner.shared.idle.wakeups(), Some(control::PAUSE_POLL));
+                self.0 = Some(inner);
+                continue;
+            }
+
+            match inner.pop() {
+                Some((_, v)) if inner.shared.is_pruned(&v) => {
+                    trace!(trace, "prune");
+                    inner.shared.on_drop();
+                    self.0 = Some(inner);
+                }
+                Some((meta, v)) if !inner.on_pop() => {
+                    inner.queue.push_meta(meta, v);
+                    return None;
+                }
+                Some((meta, v)) => {
+                    let handle = DynQueueHandle {
+                        inner: sync::Arc::downgrade(&inner),
+                        meta,
+                        sequence: inner.shared.next_sequence(),
+                    };
+                    self.0 = Some(inner);
+                    return Some((handle, v));
+                }
+                None if inner.awaits_senders() => {
+                    inner.wait_for_senders();
+                    self.0 = Some(inner);
+                }
+                None if !inner.shared.delayed.is_empty() => {
+                    if let Some(due) = inner.shared.delayed.next_due() {
+                        std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                    }
+                    self.0 = Some(inner);
+                }
+                // Close the queue, unless a handle is enqueueing right now
+                None => match sync::Arc::try_unwrap(inner) {
+                    // a handle enqueued, before it released the queue
+                    Ok(inner) if inner.has_queued() => self.0 = Some(sync::Arc::new(inner)),
+                    Ok(_inner) => {
+                        trace!(debug, "shard exhausted", shard = _inner.shard_id());
+                        return None;
+                    }
+                    Err(inner) => {
+                        self.0 = Some(inner);
+                        sync::yield_now();
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<'a, T, U: Queue<T>> IntoIterator for DynQueue<'a, T, U> {
+    type Item = (DynQueueHandle<'a, T, U>, T);
+    type IntoIter = DynQueueIter<'a, T, U>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.shared.on_start(self.0.queue.len());
+        DynQueueIter(Some(self.0))
+    }
+}
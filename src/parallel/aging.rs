@@ -0,0 +1,234 @@
+//! Priority queue with aging of the pending elements
+
+use crate::queue::split_heap;
+use crate::{DynQueue, IntoDynQueue, Queue, RecoverPoison as _};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::iter::FromIterator;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// An element with the key of its aged priority
+struct Aged<P, T> {
+    /// `priority * step - enqueued`, in nanoseconds since the epoch of the
+    /// queue
+    key: i128,
+    sequence: usize,
+    priority: P,
+    v: T,
+}
+
+impl<P, T> PartialEq for Aged<P, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl<P, T> Eq for Aged<P, T> {}
+
+impl<P, T> PartialOrd for Aged<P, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The highest key is on top of the heap. Elements with the same key are
+/// popped in the order they were pushed.
+impl<P, T> Ord for Aged<P, T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.key
+            .cmp(&other.key)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+struct Heap<P, T> {
+    heap: BinaryHeap<Aged<P, T>>,
+    sequence: usize,
+}
+
+/// Elements `(P, T)` are popped highest priority `P` first, like a
+/// `BinaryHeap<(P, T)>`, but the priority of a pending element rises by 1
+/// for every [`step`](AgingQueue::with_aging) it waits.
+///
+/// A low priority element, e.g. a cleanup task in a best first search,
+/// therefore runs after a bounded time instead of starving until the end.
+/// Aging is linear for all elements, so it does not reorder the heap and
+/// costs nothing on push or pop. Without aging, elements of the same
+/// priority are popped in the order they were pushed.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+/// use std::time::Duration;
+///
+/// use dynqueue::{AgingQueue, IntoDynQueue as _};
+///
+/// let queue = AgingQueue::from(vec![(0u8, "cleanup")])
+///     .with_aging(Duration::from_millis(1));
+/// std::thread::sleep(Duration::from_millis(20));
+///
+/// let result = queue
+///     .into_dyn_queue()
+///     .into_iter()
+///     .map(|(handle, (priority, job))| {
+///         if job == "cleanup" {
+///             handle.enqueue((10, "search")).unwrap();
+///         }
+///         job
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(result, vec!["cleanup", "search"]);
+/// ```
+pub struct AgingQueue<P, T> {
+    heap: Mutex<Heap<P, T>>,
+    /// waiting time to rise by 1 priority, in nanoseconds
+    step: Option<u64>,
+    epoch: Instant,
+}
+
+impl<P, T> Default for AgingQueue<P, T> {
+    fn default() -> Self {
+        AgingQueue {
+            heap: Mutex::new(Heap {
+                heap: BinaryHeap::new(),
+                sequence: 0,
+            }),
+            step: None,
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl<P: Copy + Into<i64>, T> From<Vec<(P, T)>> for AgingQueue<P, T> {
+    fn from(v: Vec<(P, T)>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<P: Copy + Into<i64>, T> FromIterator<(P, T)> for AgingQueue<P, T> {
+    fn from_iter<I: IntoIterator<Item = (P, T)>>(iter: I) -> Self {
+        let queue = AgingQueue::default();
+        queue.push_batch(iter);
+        queue
+    }
+}
+
+impl<P: Copy + Into<i64>, T> AgingQueue<P, T> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Raise the priority of a pending element by 1 for every `step` it
+    /// waits.
+    ///
+    /// The elements already in the queue age from now on.
+    pub fn with_aging(mut self, step: Duration) -> Self {
+        let step = Some((step.as_nanos() as u64).max(1));
+        let now = self.elapsed();
+        self.step = step;
+        let heap = self.heap.get_mut().recover();
+        let all = std::mem::take(&mut heap.heap);
+        heap.heap = all
+            .into_iter()
+            .map(|aged| Aged {
+                key: key(step, aged.priority, now),
+                ..aged
+            })
+            .collect();
+        self
+    }
+
+    /// Nanoseconds since the epoch of the queue
+    #[inline(always)]
+    fn elapsed(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+}
+
+/// The key, which orders the elements by their aged priority at any time
+#[inline(always)]
+fn key<P: Into<i64>>(step: Option<u64>, priority: P, enqueued: u64) -> i128 {
+    match step {
+        Some(step) => i128::from(priority.into()) * i128::from(step) - i128::from(enqueued),
+        None => i128::from(priority.into()),
+    }
+}
+
+impl<P: Copy + Into<i64>, T> IntoDynQueue<(P, T), AgingQueue<P, T>> for AgingQueue<P, T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (P, T), Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<P: Copy + Into<i64>, T> Queue<(P, T)> for AgingQueue<P, T> {
+    #[inline(always)]
+    fn push(&self, v: (P, T)) {
+        self.push_batch(Some(v))
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = (P, T)>>(&self, iter: I) {
+        let now = self.step.map_or(0, |_| self.elapsed());
+        let mut heap = self.heap.lock().recover();
+        for (priority, v) in iter {
+            let sequence = heap.sequence;
+            heap.sequence += 1;
+            heap.heap.push(Aged {
+                key: key(self.step, priority, now),
+                sequence,
+                priority,
+                v,
+            });
+        }
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<(P, T)> {
+        let aged = self.heap.lock().recover().heap.pop()?;
+        Some((aged.priority, aged.v))
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.heap.lock().recover().heap.len()
+    }
+
+    /// Deals the elements out alternately, so both queues keep a fair share
+    /// of the highest priority elements.
+    fn split_off(&self, size: usize) -> Self {
+        let mut heap = self.heap.lock().recover();
+        AgingQueue {
+            heap: Mutex::new(Heap {
+                heap: split_heap(&mut heap.heap, size),
+                sequence: heap.sequence,
+            }),
+            step: self.step,
+            epoch: self.epoch,
+        }
+    }
+
+    fn retain<F: FnMut(&(P, T)) -> bool>(&self, mut f: F) {
+        let mut heap = self.heap.lock().recover();
+        let all = std::mem::take(&mut heap.heap);
+        heap.heap = all
+            .into_iter()
+            .map(|aged| ((aged.key, aged.sequence), (aged.priority, aged.v)))
+            .filter(|(_, ele)| f(ele))
+            .map(|((key, sequence), (priority, v))| Aged {
+                key,
+                sequence,
+                priority,
+                v,
+            })
+            .collect();
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+}
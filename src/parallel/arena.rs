@@ -0,0 +1,106 @@
+//! Arena to iterate over borrowed elements (with `feature = "arena"`)
+
+use crate::{DynQueue, IntoDynQueue};
+use std::fmt;
+use std::sync::RwLock;
+
+/// Append-only storage for large elements, which are processed by reference
+///
+/// A `DynQueue<&T>` created with [`Arena::dyn_queue`] only moves the
+/// references through its queues. New elements are allocated in the arena
+/// with [`Arena::alloc`] and the returned reference is enqueued. The
+/// references stay valid, as long as the arena lives, so the elements are
+/// never moved or cloned.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::Arena;
+///
+/// #[derive(Debug)]
+/// struct State {
+///     depth: usize,
+///     board: [u8; 2048],
+/// }
+///
+/// let arena = Arena::new();
+/// let count = arena
+///     .dyn_queue(vec![State { depth: 0, board: [0; 2048] }])
+///     .into_par_iter()
+///     .map(|(handle, state)| {
+///         if state.depth < 3 {
+///             let mut board = state.board;
+///             board[state.depth] = 1;
+///             let next = arena.alloc(State { depth: state.depth + 1, board });
+///             handle.enqueue(next).unwrap();
+///         }
+///     })
+///     .count();
+///
+/// assert_eq!(count, 4);
+/// assert_eq!(arena.len(), 4);
+/// ```
+pub struct Arena<T>(boxcar::Vec<T>);
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Arena<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Arena<T> {
+    /// Create an empty arena
+    pub fn new() -> Self {
+        Arena(boxcar::Vec::new())
+    }
+
+    /// Move `v` into the arena and get a reference to it, which is valid
+    /// as long as the arena lives.
+    #[inline]
+    pub fn alloc(&self, v: T) -> &T {
+        let index = self.0.push(v);
+        &self.0[index]
+    }
+
+    /// Number of elements allocated in the arena
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.count()
+    }
+
+    /// `true`, if no element was allocated in the arena
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Iterate over all elements in the order they were allocated
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.0.iter().map(|(_, v)| v)
+    }
+
+    /// Get all elements in the order they were allocated
+    pub fn into_vec(self) -> Vec<T> {
+        self.0.into_iter().collect()
+    }
+
+    /// Allocate the `initial` elements in the arena and create a `DynQueue`
+    /// of references to them.
+    pub fn dyn_queue<'s, I>(&'s self, initial: I) -> DynQueue<'s, &'s T, RwLock<Vec<&'s T>>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        initial
+            .into_iter()
+            .map(|v| self.alloc(v))
+            .collect::<Vec<_>>()
+            .into_dyn_queue()
+    }
+}
@@ -0,0 +1,110 @@
+//! Global number of pending elements for `max_pending` and the watermarks
+
+use super::watermark::Watermarks;
+use crate::sync::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Number of pending elements of all parallel iterators of a `DynQueue`
+///
+/// Only counted, if enqueueing is blocked above `max_pending` or the
+/// pending elements are watched by watermarks.
+pub(crate) struct Backpressure<'a> {
+    pub(crate) max_pending: Option<usize>,
+    pub(crate) watermarks: Option<Watermarks<'a>>,
+    pending: AtomicUsize,
+    /// running parallel iterators
+    active: AtomicUsize,
+    /// parallel iterators blocked in `wait_for_capacity`
+    blocked: AtomicUsize,
+}
+
+impl<'a> Backpressure<'a> {
+    pub(crate) fn new() -> Self {
+        Backpressure {
+            max_pending: None,
+            watermarks: None,
+            pending: AtomicUsize::new(0),
+            active: AtomicUsize::new(0),
+            blocked: AtomicUsize::new(0),
+        }
+    }
+
+    /// Start counting with the `pending` initial elements
+    pub(crate) fn start(&self, pending: usize) {
+        self.pending.store(pending, Ordering::Relaxed);
+        self.check(pending);
+    }
+
+    /// Account for an enqueued element
+    #[inline(always)]
+    pub(crate) fn add(&self) {
+        let pending = self.pending.fetch_add(1, Ordering::Relaxed) + 1;
+        self.check(pending);
+    }
+
+    /// Account for an element leaving the queue
+    #[inline(always)]
+    pub(crate) fn remove(&self) {
+        let pending = self
+            .pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |p| {
+                Some(p.saturating_sub(1))
+            })
+            .map_or(0, |p| p.saturating_sub(1));
+        self.check(pending);
+    }
+
+    #[inline(always)]
+    fn check(&self, pending: usize) {
+        if let Some(watermarks) = &self.watermarks {
+            watermarks.check(pending);
+        }
+    }
+
+    /// `true`, if more than `max_pending` elements are pending
+    #[inline(always)]
+    pub(crate) fn is_over_max_pending(&self) -> bool {
+        self.max_pending
+            .is_some_and(|max| self.pending.load(Ordering::Relaxed) >= max)
+    }
+
+    /// Block, while more than `max_pending` elements are pending, until the
+    /// parallel iterators drained the queues below half of `max_pending` or
+    /// `is_stopped` returns `true`.
+    ///
+    /// To prevent a deadlock, the last running parallel iterator is never
+    /// blocked.
+    pub(crate) fn wait_for_capacity(&self, is_stopped: impl Fn() -> bool) {
+        let low = match self.max_pending {
+            Some(max) if self.is_over_max_pending() => max / 2,
+            _ => return,
+        };
+
+        self.blocked.fetch_add(1, Ordering::AcqRel);
+        let mut backoff = Duration::from_micros(1);
+        while self.pending.load(Ordering::Relaxed) > low
+            && self.blocked.load(Ordering::Acquire) < self.active.load(Ordering::Acquire)
+            && !is_stopped()
+        {
+            std::thread::sleep(backoff);
+            backoff = (backoff * 2).min(Duration::from_millis(1));
+        }
+        self.blocked.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// Count a running parallel iterator, if enqueueing might block.
+    ///
+    /// Returns `false`, if it is not counted.
+    pub(crate) fn enter(&self) -> bool {
+        if self.max_pending.is_none() {
+            return false;
+        }
+        self.active.fetch_add(1, Ordering::AcqRel);
+        true
+    }
+
+    /// Stop counting a parallel iterator counted by [`Backpressure::enter`]
+    pub(crate) fn leave(&self) {
+        self.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
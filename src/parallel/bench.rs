@@ -0,0 +1,190 @@
+//! Parameterized workloads to compare queue backends (with `feature = "bench"`)
+//!
+//! A [`Workload`] starts with a number of seed elements. Every element
+//! burns `item_cost` rounds of work and enqueues `fan_out` children, until
+//! the tree is `depth` levels deep. Running the same workload on different
+//! [`Backend`]s shows, which inner collection suits a workload best.
+//!
+//! ```
+//! use dynqueue::bench::{Backend as _, VecBackend, VecDequeBackend, Workload};
+//!
+//! let workload = Workload::new(4).with_fan_out(2).with_depth(3);
+//!
+//! assert_eq!(VecBackend::run(&workload), workload.len());
+//! assert_eq!(VecDequeBackend::run(&workload), workload.len());
+//! ```
+//!
+//! The criterion benchmarks in `benches/` run a matrix of workloads on all
+//! backends:
+//!
+//! ```text
+//! cargo bench --features bench,crossbeam-queue
+//! ```
+
+use crate::{DynQueue, IntoDynQueue as _, Queue};
+use rayon::iter::IntoParallelIterator as _;
+use rayon::iter::ParallelIterator as _;
+use std::collections::VecDeque;
+use std::hint::black_box;
+use std::sync::RwLock;
+
+/// An element of a [`Workload`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Task {
+    /// Depth in the tree of elements, `0` for the seeds
+    pub depth: usize,
+    /// Input of the work burned for this element
+    pub value: u64,
+}
+
+/// Shape of the elements processed by a benchmark
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Workload {
+    seeds: usize,
+    fan_out: usize,
+    depth: usize,
+    item_cost: u32,
+}
+
+impl Workload {
+    /// A workload with `seeds` initial elements, which enqueue nothing and
+    /// cost nothing
+    pub fn new(seeds: usize) -> Self {
+        Workload {
+            seeds,
+            fan_out: 0,
+            depth: 0,
+            item_cost: 0,
+        }
+    }
+
+    /// Every element above the maximum depth enqueues `fan_out` children.
+    pub fn with_fan_out(mut self, fan_out: usize) -> Self {
+        self.fan_out = fan_out;
+        self
+    }
+
+    /// Elements of depth `depth` enqueue no children.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Every element burns `item_cost` rounds of work.
+    pub fn with_item_cost(mut self, item_cost: u32) -> Self {
+        self.item_cost = item_cost;
+        self
+    }
+
+    /// The seed elements
+    pub fn tasks(&self) -> Vec<Task> {
+        (0..self.seeds as u64)
+            .map(|value| Task { depth: 0, value })
+            .collect()
+    }
+
+    /// Number of elements processed by a run of the workload
+    pub fn len(&self) -> usize {
+        let mut level = self.seeds;
+        let mut len = level;
+        for _ in 0..self.depth {
+            level *= self.fan_out;
+            len += level;
+        }
+        len
+    }
+
+    /// `true`, if the workload has no seed elements
+    pub fn is_empty(&self) -> bool {
+        self.seeds == 0
+    }
+
+    /// Process the workload with `queue`, which holds the seed elements.
+    ///
+    /// Returns the number of processed elements.
+    pub fn run<U>(&self, queue: DynQueue<'_, Task, U>) -> usize
+    where
+        U: Queue<Task> + Send + Sync,
+    {
+        queue
+            .into_par_iter()
+            .map(|(handle, task)| {
+                let mut value = task.value;
+                for _ in 0..self.item_cost {
+                    value = black_box(value.wrapping_mul(6_364_136_223_846_793_005) ^ 1);
+                }
+                if task.depth < self.depth {
+                    handle
+                        .enqueue_many((0..self.fan_out).map(|i| Task {
+                            depth: task.depth + 1,
+                            value: value.wrapping_add(i as u64),
+                        }))
+                        .unwrap();
+                }
+            })
+            .count()
+    }
+}
+
+/// An inner collection of the `DynQueue` to benchmark
+///
+/// Implement it for a custom [`Queue`] to compare it with the provided
+/// backends.
+pub trait Backend {
+    /// The name of the backend in the benchmark reports
+    const NAME: &'static str;
+
+    /// The queue holding the elements
+    type Queue: Queue<Task> + Send + Sync;
+
+    /// A `DynQueue` holding the seed `tasks`
+    fn dyn_queue<'a>(tasks: Vec<Task>) -> DynQueue<'a, Task, Self::Queue>;
+
+    /// Process `workload` and return the number of processed elements.
+    fn run(workload: &Workload) -> usize {
+        workload.run(Self::dyn_queue(workload.tasks()))
+    }
+}
+
+/// Backend with a `RwLock<Vec<Task>>`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VecBackend;
+
+impl Backend for VecBackend {
+    const NAME: &'static str = "RwLock<Vec>";
+    type Queue = RwLock<Vec<Task>>;
+
+    fn dyn_queue<'a>(tasks: Vec<Task>) -> DynQueue<'a, Task, Self::Queue> {
+        tasks.into_dyn_queue()
+    }
+}
+
+/// Backend with a `RwLock<VecDeque<Task>>`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VecDequeBackend;
+
+impl Backend for VecDequeBackend {
+    const NAME: &'static str = "RwLock<VecDeque>";
+    type Queue = RwLock<VecDeque<Task>>;
+
+    fn dyn_queue<'a>(tasks: Vec<Task>) -> DynQueue<'a, Task, Self::Queue> {
+        VecDeque::from(tasks).into_dyn_queue()
+    }
+}
+
+/// Backend with a lock-free `crossbeam_queue::SegQueue<Task>` (with
+/// `feature = "crossbeam-queue"`)
+#[cfg(feature = "crossbeam-queue")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SegQueueBackend;
+
+#[cfg(feature = "crossbeam-queue")]
+impl Backend for SegQueueBackend {
+    const NAME: &'static str = "SegQueue";
+    type Queue = crossbeam_queue::SegQueue<Task>;
+
+    fn dyn_queue<'a>(tasks: Vec<Task>) -> DynQueue<'a, Task, Self::Queue> {
+        use crate::IntoLockFreeDynQueue as _;
+        tasks.into_dyn_queue_lockfree()
+    }
+}
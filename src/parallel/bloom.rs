@@ -0,0 +1,117 @@
+//! Concurrent Bloom filter for the approximate dedup
+
+use crate::parallel::ElementFilter;
+use crate::sync::{AtomicUsize, Ordering};
+use crate::Filter;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher as _, Hash};
+use std::marker::PhantomData;
+
+const WORD_BITS: u64 = usize::BITS as u64;
+
+/// A lock-free Bloom filter of a fixed size
+pub(crate) struct BloomFilter {
+    words: Box<[AtomicUsize]>,
+    /// number of bits
+    bits: u64,
+    /// number of hash functions
+    hashes: u32,
+    hasher: RandomState,
+}
+
+impl BloomFilter {
+    /// A filter for `items` elements with a false positive rate of
+    /// `false_positive_rate`
+    pub(crate) fn new(items: usize, false_positive_rate: f64) -> Self {
+        let items = items.max(1) as f64;
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-items * rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(WORD_BITS as f64) as u64;
+        let hashes = ((bits as f64 / items) * ln2).round().clamp(1.0, 32.0) as u32;
+        let words = bits.div_ceil(WORD_BITS);
+        BloomFilter {
+            words: (0..words).map(|_| AtomicUsize::new(0)).collect(),
+            bits: words * WORD_BITS,
+            hashes,
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Insert `key` and return `true`, if it was not (probably) seen before
+    pub(crate) fn insert<K: Hash>(&self, key: &K) -> bool {
+        let mut new = false;
+        for (word, mask) in self.bits(key) {
+            if word.load(Ordering::Relaxed) & mask == 0 {
+                new |= word.fetch_or(mask, Ordering::Relaxed) & mask == 0;
+            }
+        }
+        new
+    }
+
+    /// `true`, if `key` was probably seen before
+    pub(crate) fn contains<K: Hash>(&self, key: &K) -> bool {
+        self.bits(key)
+            .all(|(word, mask)| word.load(Ordering::Relaxed) & mask != 0)
+    }
+
+    /// The words and masks of the bits of `key`
+    fn bits<K: Hash>(&self, key: &K) -> impl Iterator<Item = (&AtomicUsize, usize)> {
+        let hash = self.hasher.hash_one(key);
+        // double hashing, the i-th bit is `hash + i * step`
+        let step = hash.rotate_left(32).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..u64::from(self.hashes)).map(move |i| {
+            let bit = hash.wrapping_add(i.wrapping_mul(step)) % self.bits;
+            (
+                &self.words[(bit / WORD_BITS) as usize],
+                1usize << (bit % WORD_BITS),
+            )
+        })
+    }
+}
+
+/// Filter, which passes every element with a key probably not seen before
+///
+/// A Bloom filter cannot forget a key, so an element refused by a bounded
+/// queue after passing the filter stays marked as seen.
+struct ApproximateDedup<F, K> {
+    key_fn: F,
+    seen: BloomFilter,
+    key: PhantomData<fn() -> K>,
+}
+
+impl<T, F, K> ElementFilter<T> for ApproximateDedup<F, K>
+where
+    F: Fn(&T) -> K + Send + Sync,
+    K: Hash,
+{
+    #[inline]
+    fn pass(&self, v: &T) -> bool {
+        self.seen.insert(&(self.key_fn)(v))
+    }
+
+    #[inline]
+    fn check(&self, v: &T) -> bool {
+        !self.seen.contains(&(self.key_fn)(v))
+    }
+}
+
+/// Filter, which passes every element with a key probably not seen before
+/// with a false positive rate of about `false_positive_rate` for `items`
+/// keys
+pub(crate) fn bloom_filter<'a, T, K, F>(
+    key_fn: F,
+    items: usize,
+    false_positive_rate: f64,
+) -> Filter<'a, T>
+where
+    K: Hash + 'a,
+    F: Fn(&T) -> K + Send + Sync + 'a,
+{
+    Box::new(ApproximateDedup {
+        key_fn,
+        seen: BloomFilter::new(items, false_positive_rate),
+        key: PhantomData,
+    })
+}
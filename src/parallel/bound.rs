@@ -0,0 +1,87 @@
+//! Shared best value of a branch-and-bound search
+
+use crate::sync::{Arc, RwLock};
+use crate::RecoverPoison;
+use std::fmt;
+
+/// The best value found so far, shared by all elements of a
+/// branch-and-bound search
+///
+/// Greater values are better, wrap the values in [`std::cmp::Reverse`] to
+/// search for a minimum.
+///
+/// Set with [`DynQueue::with_bound`](crate::DynQueue::with_bound) the bound
+/// is accessible from the `DynQueueHandle` with
+/// [`bound`](crate::DynQueueHandle::bound) and
+/// [`try_improve_bound`](crate::DynQueueHandle::try_improve_bound).
+/// With [`DynQueue::with_bound_pruning`](crate::DynQueue::with_bound_pruning)
+/// elements, which cannot improve the bound, are dropped.
+///
+/// `Bound` is a cheap clone of the same shared value, so the result can be
+/// read after the iteration.
+pub struct Bound<B>(Arc<RwLock<Option<B>>>);
+
+impl<B> Clone for Bound<B> {
+    fn clone(&self) -> Self {
+        Bound(self.0.clone())
+    }
+}
+
+impl<B> Default for Bound<B> {
+    fn default() -> Self {
+        Bound(Arc::new(RwLock::new(None)))
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for Bound<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Bound")
+            .field(&*self.0.read().recover())
+            .finish()
+    }
+}
+
+impl<B: Ord> Bound<B> {
+    /// Create a bound without a value
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a bound, which has to be improved on `initial`, e.g. the value
+    /// of a known solution.
+    pub fn with_initial(initial: B) -> Self {
+        Bound(Arc::new(RwLock::new(Some(initial))))
+    }
+
+    /// The best value so far
+    pub fn get(&self) -> Option<B>
+    where
+        B: Clone,
+    {
+        self.0.read().recover().clone()
+    }
+
+    /// Replace the best value with `value`, if it is greater.
+    ///
+    /// Returns `true`, if the bound was improved.
+    pub fn try_improve(&self, value: B) -> bool {
+        if self.is_dominated(&value) {
+            return false;
+        }
+        let mut best = self.0.write().recover();
+        if best.as_ref().is_some_and(|best| value <= *best) {
+            return false;
+        }
+        *best = Some(value);
+        true
+    }
+
+    /// `true`, if `value` cannot improve the best value
+    pub fn is_dominated(&self, value: &B) -> bool {
+        self.0
+            .read()
+            .recover()
+            .as_ref()
+            .is_some_and(|best| value <= best)
+    }
+}
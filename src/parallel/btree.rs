@@ -0,0 +1,129 @@
+//! Keyed priority queue based on a `BTreeMap`
+
+use crate::{DynQueue, IntoDynQueue, Queue, RecoverPoison as _};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+/// Elements `(K, T)` are stored under their key `K` and the element with the
+/// lowest key is popped first.
+///
+/// A split keeps the lower key range and splits off the higher keys.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+/// use std::collections::BTreeMap;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let mut result = BTreeMap::from([(3u32, vec!["c"]), (1, vec!["a"])])
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, (cost, node))| {
+///         if node == "a" {
+///             handle.enqueue((cost + 1, "b")).unwrap();
+///         }
+///         (cost, node)
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![(1, "a"), (2, "b"), (3, "c")]);
+/// ```
+impl<K: Ord + Clone, T> IntoDynQueue<(K, T), RwLock<BTreeMap<K, Vec<T>>>> for BTreeMap<K, Vec<T>> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (K, T), RwLock<BTreeMap<K, Vec<T>>>> {
+        DynQueue::new(RwLock::new(self))
+    }
+}
+
+impl<K: Ord + Clone, T> IntoDynQueue<(K, T), RwLock<BTreeMap<K, Vec<T>>>>
+    for RwLock<BTreeMap<K, Vec<T>>>
+{
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (K, T), RwLock<BTreeMap<K, Vec<T>>>> {
+        DynQueue::new(self)
+    }
+}
+
+impl<K: Ord + Clone, T> Queue<(K, T)> for RwLock<BTreeMap<K, Vec<T>>> {
+    #[inline(always)]
+    fn push(&self, (k, v): (K, T)) {
+        self.write().recover().entry(k).or_default().push(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = (K, T)>>(&self, iter: I) {
+        let mut map = self.write().recover();
+        iter.into_iter()
+            .for_each(|(k, v)| map.entry(k).or_default().push(v))
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<(K, T)> {
+        let mut map = self.write().recover();
+        let mut entry = map.first_entry()?;
+        let v = entry.get_mut().pop();
+        let k = if entry.get().is_empty() {
+            entry.remove_entry().0
+        } else {
+            entry.key().clone()
+        };
+        v.map(|v| (k, v))
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.read().recover().values().map(Vec::len).sum()
+    }
+
+    fn retain<F: FnMut(&(K, T)) -> bool>(&self, mut f: F) {
+        let mut map = self.write().recover();
+        let all = std::mem::take(&mut *map);
+        for (k, values) in all {
+            let values = values
+                .into_iter()
+                .map(|v| (k.clone(), v))
+                .filter(|ele| f(ele))
+                .map(|(_, v)| v)
+                .collect::<Vec<_>>();
+            if !values.is_empty() {
+                map.insert(k, values);
+            }
+        }
+    }
+
+    /// Keep the lowest keys with about `size` elements and split off the
+    /// higher keys.
+    fn split_off(&self, size: usize) -> Self {
+        let mut map = self.write().recover();
+        let mut count = 0;
+        let at = map
+            .iter()
+            .find(|(_, values)| {
+                let found = count >= size;
+                count += values.len();
+                found
+            })
+            .map(|(k, _)| k.clone());
+
+        match at {
+            Some(at) => RwLock::new(map.split_off(&at)),
+            None => RwLock::new(BTreeMap::new()),
+        }
+    }
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        RwLock::is_poisoned(self)
+    }
+
+    #[inline(always)]
+    fn clear_poison(&self) {
+        RwLock::clear_poison(self)
+    }
+}
@@ -0,0 +1,451 @@
+//! Configurable construction of a `DynQueue`
+
+use crate::parallel::limit::KeyLimit;
+use crate::parallel::rate::RateLimit;
+use crate::parallel::receiver::Receiving;
+use crate::parallel::watermark::Watermarks;
+use crate::parallel::{bloom, dedup_filter, dedup_filter_with_hasher, passes, Predicate};
+use crate::sync::AtomicUsize;
+use crate::{
+    Bound, CancelToken, DynQueue, IntoDynQueue, Order, OverflowPolicy, PoisonPolicy, Queue, Shared,
+    SplitPolicy,
+};
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, Instant};
+
+/// Builder to configure a `DynQueue` before it is created
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::DynQueue;
+///
+/// let mut result = DynQueue::builder()
+///     .split_threshold(4)
+///     .dedup(|v: &u64| *v)
+///     .build(vec![1, 2, 3, 3])
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value < 5 {
+///             handle.enqueue(value * 2).unwrap();
+///         }
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3, 4, 6, 8]);
+/// ```
+pub struct DynQueueBuilder<'a, T> {
+    shared: Shared<'a, T>,
+    timeout: Option<Duration>,
+    /// number of filters, which already filtered the initial elements
+    filtered: usize,
+    /// `true`, if the pruning already dropped the initial elements
+    pruned: bool,
+}
+
+impl<'a, T> Default for DynQueueBuilder<'a, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T> DynQueueBuilder<'a, T> {
+    /// Create a builder with the default configuration
+    pub fn new() -> Self {
+        Self::from_shared(Shared::new())
+    }
+
+    /// Create a builder with the configuration `shared` of a `DynQueue`,
+    /// whose initial elements passed its filters already
+    pub(crate) fn from_shared(shared: Shared<'a, T>) -> Self {
+        DynQueueBuilder {
+            filtered: shared.filters.len(),
+            pruned: shared.prune.is_some(),
+            shared,
+            timeout: None,
+        }
+    }
+
+    /// Only split a queue for another parallel iterator, if it holds at
+    /// least `threshold` elements.
+    ///
+    /// The default is `2`.
+    pub fn split_threshold(mut self, threshold: usize) -> Self {
+        self.shared.split.threshold = threshold;
+        self
+    }
+
+    /// Never split off a queue with less than `size` elements.
+    ///
+    /// The default is `1`.
+    pub fn min_shard_size(mut self, size: usize) -> Self {
+        self.shared.split.min_shard_size = size.max(1);
+        self
+    }
+
+    /// Limit the number of pending elements per queue shard.
+    ///
+    /// [`DynQueueHandle::try_enqueue`](crate::DynQueueHandle::try_enqueue)
+    /// returns the element, if the queue of the current shard holds `limit`
+    /// or more elements. `enqueue` ignores the limit, unless an
+    /// [`OverflowPolicy`](crate::OverflowPolicy) is set.
+    pub fn capacity_limit(mut self, limit: usize) -> Self {
+        self.shared.capacity_limit = Some(limit);
+        self
+    }
+
+    /// Handle a full queue in [`DynQueueHandle::enqueue`](crate::DynQueueHandle::enqueue)
+    /// with `policy`.
+    ///
+    /// See [`OverflowPolicy`].
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.shared.overflow = Some(policy);
+        self
+    }
+
+    /// Handle a queue poisoned by a panic with `policy`.
+    ///
+    /// See [`PoisonPolicy`].
+    pub fn poison_policy(mut self, policy: PoisonPolicy) -> Self {
+        self.shared.poison = policy;
+        self
+    }
+
+    /// Skip all elements with the same key as an element, which was already seen.
+    ///
+    /// See [`DynQueue::with_dedup`].
+    pub fn dedup<K, F>(mut self, key_fn: F) -> Self
+    where
+        K: Hash + Eq + Send + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.shared.filters.push(dedup_filter(key_fn));
+        self
+    }
+
+    /// Skip all elements with the same key as an element, which was already
+    /// seen, using `hasher` to hash the keys.
+    ///
+    /// See [`DynQueue::with_dedup_hasher`].
+    pub fn dedup_with_hasher<K, F, S>(mut self, key_fn: F, hasher: S) -> Self
+    where
+        K: Hash + Eq + Send + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+        S: BuildHasher + Send + Sync + 'a,
+    {
+        self.shared
+            .filters
+            .push(dedup_filter_with_hasher(key_fn, hasher));
+        self
+    }
+
+    /// Skip all elements with the same key as an element, which was
+    /// probably already seen, using a Bloom filter of a fixed size.
+    ///
+    /// See [`DynQueue::with_approximate_dedup`].
+    pub fn approximate_dedup<K, F>(
+        mut self,
+        key_fn: F,
+        expected_items: usize,
+        false_positive_rate: f64,
+    ) -> Self
+    where
+        K: Hash + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.shared.filters.push(bloom::bloom_filter(
+            key_fn,
+            expected_items,
+            false_positive_rate,
+        ));
+        self
+    }
+
+    /// Skip all elements, for which `predicate` returns `false`.
+    ///
+    /// See [`DynQueue::with_filter`].
+    pub fn filter<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&T) -> bool + Send + Sync + 'a,
+    {
+        self.shared.filters.push(Box::new(Predicate(predicate)));
+        self
+    }
+
+    /// Pop the elements in the given `order`.
+    ///
+    /// See [`Order`].
+    pub fn order(mut self, order: Order) -> Self {
+        self.shared.order = Some(order);
+        self
+    }
+
+    /// Let the parallel iterators pop up to `size` elements at once.
+    ///
+    /// See [`DynQueue::with_pop_batch_size`].
+    pub fn pop_batch_size(mut self, size: usize) -> Self {
+        self.shared.pop_batch_size = size.max(1);
+        self
+    }
+
+    /// Let `DynQueueHandle::enqueue` collect up to `size` elements, before
+    /// they are pushed to the queue at once.
+    ///
+    /// See [`DynQueue::with_enqueue_buffer_size`].
+    pub fn enqueue_buffer_size(mut self, size: usize) -> Self {
+        self.shared.enqueue_buffer_size = size.max(1);
+        self
+    }
+
+    /// Call `f` with the number of processed and pending elements after
+    /// every `every` processed elements.
+    ///
+    /// See [`DynQueue::with_progress`].
+    pub fn on_progress<F>(mut self, every: usize, f: F) -> Self
+    where
+        F: Fn(usize, usize) + Send + Sync + 'a,
+    {
+        self.shared.progress_mut().on_progress = Some((every.max(1), Box::new(f)));
+        self
+    }
+
+    /// Stop popping elements, when `deadline` has passed.
+    ///
+    /// See [`DynQueue::with_deadline`].
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.shared.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop popping elements, when `timeout` has passed after building
+    /// the `DynQueue`.
+    ///
+    /// See [`DynQueue::with_deadline`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop popping elements, when `max` elements were processed.
+    ///
+    /// See [`DynQueue::with_max_items`].
+    pub fn max_items(mut self, max: usize) -> Self {
+        self.shared.progress_mut().max_items = Some(max);
+        self
+    }
+
+    /// Refuse to requeue an element more than `max` times.
+    ///
+    /// See [`DynQueue::with_max_retries`].
+    pub fn max_retries(mut self, max: usize) -> Self {
+        self.shared.max_retries = Some(max);
+        self
+    }
+
+    /// Number all popped elements with a global sequence number.
+    ///
+    /// See [`DynQueue::with_sequence`].
+    pub fn sequence(mut self) -> Self {
+        self.shared.sequence = Some(AtomicUsize::new(0));
+        self
+    }
+
+    /// Block enqueueing, while `max` or more elements are pending.
+    ///
+    /// See [`DynQueue::with_max_pending`].
+    pub fn max_pending(mut self, max: usize) -> Self {
+        self.shared.backpressure_mut().max_pending = Some(max.max(1));
+        self
+    }
+
+    /// Call `on_high`, when the number of pending elements rises to `high`,
+    /// and `on_low`, when it falls to `low` afterwards.
+    ///
+    /// See [`DynQueue::with_watermarks`].
+    pub fn watermarks<H, L>(mut self, high: usize, low: usize, on_high: H, on_low: L) -> Self
+    where
+        H: Fn(usize) + Send + Sync + 'a,
+        L: Fn(usize) + Send + Sync + 'a,
+    {
+        self.shared.backpressure_mut().watermarks =
+            Some(Watermarks::new(high, low, on_high, on_low));
+        self
+    }
+
+    /// Limit the rate of enqueued elements to `per_second` elements per
+    /// second with bursts of up to `burst` elements.
+    ///
+    /// See [`DynQueue::with_rate_limit`].
+    pub fn rate_limit(mut self, per_second: f64, burst: usize) -> Self {
+        self.shared.rate_limit = Some(RateLimit::new(per_second, burst));
+        self
+    }
+
+    /// Process at most `limit` elements with the same key at the same
+    /// time.
+    ///
+    /// See [`DynQueue::with_key_limit`].
+    pub fn key_limit<K, F>(mut self, key_fn: F, limit: usize) -> Self
+    where
+        K: Hash + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        self.shared.key_limit = Some(KeyLimit::new(key_fn, limit));
+        self
+    }
+
+    /// Never split the `DynQueue` and never steal elements, if
+    /// `single_thread` is `true`, or always allow it, if `false`.
+    ///
+    /// See [`DynQueue::with_single_thread`].
+    pub fn single_thread(mut self, single_thread: bool) -> Self {
+        self.shared.split.single_thread = Some(single_thread);
+        self
+    }
+
+    /// Let `policy` decide, if and how a queue is split.
+    ///
+    /// See [`DynQueue::with_split_policy`].
+    pub fn split_policy<P: SplitPolicy<T> + 'a>(mut self, policy: P) -> Self {
+        self.shared.split.policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Split a parallel iterator again, when its queue grew to `threshold`
+    /// or more elements.
+    ///
+    /// See [`DynQueue::with_resplit_threshold`].
+    pub fn resplit_threshold(mut self, threshold: usize) -> Self {
+        self.shared.split.resplit_threshold = Some(threshold.max(2));
+        self
+    }
+
+    /// Process the elements arriving on `receiver` as well, until all
+    /// senders of the channel are dropped.
+    ///
+    /// See [`DynQueue::with_receiver`].
+    pub fn receiver(mut self, receiver: Receiver<T>) -> Self {
+        self.shared.receiver = Some(Box::new(Receiving::new(receiver)));
+        self
+    }
+
+    /// Take more initial elements lazily from `seeds`.
+    ///
+    /// See [`DynQueue::with_seeds`].
+    pub fn seeds<I>(mut self, seeds: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'a,
+    {
+        self.shared.seeding_mut().set(seeds.into_iter());
+        self
+    }
+
+    /// Take `size` elements at once from the seeds.
+    ///
+    /// See [`DynQueue::with_seed_chunk_size`].
+    pub fn seed_chunk_size(mut self, size: usize) -> Self {
+        self.shared.seeding_mut().chunk_size = size.max(1);
+        self
+    }
+
+    /// Share the read-only `ctx` with all elements.
+    ///
+    /// See [`DynQueue::with_context`].
+    pub fn context<C: Send + Sync + 'static>(mut self, ctx: C) -> Self {
+        self.shared.context_mut().set(ctx);
+        self
+    }
+
+    /// Share `state`, e.g. an atomic counter or flag, with all elements.
+    ///
+    /// See [`DynQueue::with_shared`].
+    pub fn shared<S: Send + Sync + 'static>(mut self, state: S) -> Self {
+        self.shared.context_mut().set_state(state);
+        self
+    }
+
+    /// Share the best value `bound` of a branch-and-bound search with all
+    /// elements.
+    ///
+    /// See [`DynQueue::with_bound`].
+    pub fn bound<B: Ord + Send + Sync + 'static>(mut self, bound: Bound<B>) -> Self {
+        self.shared.context_mut().set_bound(bound);
+        self
+    }
+
+    /// Share the best value `bound` of a branch-and-bound search with all
+    /// elements and drop every element, whose optimistic `estimate` cannot
+    /// improve it.
+    ///
+    /// See [`DynQueue::with_bound_pruning`].
+    pub fn bound_pruning<B, F>(mut self, bound: Bound<B>, estimate: F) -> Self
+    where
+        B: Ord + Send + Sync + 'static,
+        F: Fn(&T) -> B + Send + Sync + 'a,
+    {
+        self.shared.context_mut().set_bound(bound.clone());
+        self.shared.prune = Some(Box::new(move |v| bound.is_dominated(&estimate(v))));
+        self.pruned = false;
+        self
+    }
+
+    /// Silently drop all enqueued elements with a depth greater than
+    /// `max_depth`.
+    ///
+    /// Only a queue, which stores the [`Meta`](crate::Meta) of its elements,
+    /// e.g. a [`Tracked`](crate::Tracked) queue, knows the depth of the
+    /// elements.
+    /// See [`DynQueue::with_max_depth`].
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.shared.max_depth = max_depth;
+        self
+    }
+
+    /// Use `token` to cancel the iteration from the outside.
+    pub fn cancel(mut self, token: CancelToken) -> Self {
+        self.shared.cancel = token;
+        self
+    }
+
+    /// Create the `DynQueue` with the `initial` elements.
+    ///
+    /// Initial elements, which do not pass the filters (e.g. `dedup`),
+    /// are dropped.
+    pub fn build<U, I>(self, initial: I) -> DynQueue<'a, T, U>
+    where
+        U: Queue<T>,
+        I: IntoDynQueue<T, U>,
+    {
+        initial.into_dyn_queue().configure(|_| self)
+    }
+
+    /// Set the configuration of `queue`
+    ///
+    /// The initial elements have to pass the filters and the pruning added
+    /// by this builder.
+    pub(crate) fn apply<U: Queue<T>>(self, queue: &mut DynQueue<'a, T, U>) {
+        let DynQueueBuilder {
+            mut shared,
+            timeout,
+            filtered,
+            pruned,
+        } = self;
+        if let Some(timeout) = timeout {
+            shared.deadline = Some(Instant::now() + timeout);
+        }
+        let filters = &shared.filters[filtered..];
+        let prune = shared.prune.as_ref().filter(|_| !pruned);
+        if !filters.is_empty() || prune.is_some() {
+            queue
+                .0
+                .queue
+                .retain(|v| !prune.is_some_and(|prune| prune(v)) && passes(filters, v));
+        }
+        *queue.shared_mut() = shared;
+    }
+}
@@ -0,0 +1,113 @@
+//! MPMC channel backend (with `feature = "crossbeam-channel"`)
+
+use crate::{DynQueue, IntoDynQueue, Queue};
+use crossbeam_channel::{Receiver, Sender};
+
+/// A `crossbeam_channel` as the queue of a `DynQueue`
+///
+/// All parallel iterators share the same channel. Other threads can send
+/// elements with a [`Channel::sender`] while the `DynQueue` is iterated.
+/// The iteration ends, as soon as the channel is empty, so elements sent
+/// after that are not processed.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{Channel, IntoDynQueue as _};
+///
+/// let channel = Channel::unbounded();
+/// let sender = channel.sender();
+///
+/// std::thread::spawn(move || sender.send(1).unwrap())
+///     .join()
+///     .unwrap();
+///
+/// let mut result = channel
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 1 {
+///             handle.enqueue(2).unwrap()
+///         };
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2]);
+/// ```
+pub struct Channel<T> {
+    sender: Sender<T>,
+    receiver: Receiver<T>,
+}
+
+impl<T> Clone for Channel<T> {
+    fn clone(&self) -> Self {
+        Channel {
+            sender: self.sender.clone(),
+            receiver: self.receiver.clone(),
+        }
+    }
+}
+
+impl<T> Channel<T> {
+    /// Create a channel of unbounded capacity
+    pub fn unbounded() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Channel { sender, receiver }
+    }
+
+    /// Create a channel, which holds at most `cap` elements
+    pub fn bounded(cap: usize) -> Self {
+        let (sender, receiver) = crossbeam_channel::bounded(cap);
+        Channel { sender, receiver }
+    }
+
+    /// A sender to put elements in the channel from the outside
+    pub fn sender(&self) -> Sender<T> {
+        self.sender.clone()
+    }
+}
+
+impl<T> IntoDynQueue<T, Channel<T>> for Channel<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<T> for Channel<T> {
+    /// Blocks, while a bounded channel is full.
+    #[inline(always)]
+    fn push(&self, v: T) {
+        // `self` holds a receiver, so the channel is never disconnected
+        let _ = self.sender.send(v);
+    }
+
+    #[inline(always)]
+    fn try_push(&self, v: T) -> Result<(), T> {
+        self.sender.try_send(v).map_err(|e| e.into_inner())
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.receiver.len()
+    }
+
+    /// All parallel iterators share the same channel.
+    #[inline(always)]
+    fn split_off(&self, _size: usize) -> Self {
+        self.clone()
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        self.receiver.same_channel(&other.receiver)
+    }
+}
@@ -0,0 +1,110 @@
+//! Checkpoint and resume (with `feature = "serde"`)
+
+use crate::sync::{Mutex, Weak};
+use crate::{for_each_shard, DynQueue, DynQueueHandle, DynQueueRef, IntoDynQueue, Meta, Queue};
+use serde::{Deserialize, Serialize};
+
+/// Serializable snapshot of the pending elements of a `DynQueue`
+///
+/// A checkpoint can be turned into a new `DynQueue` with `.into_dyn_queue()`
+/// to resume the processing, e.g. after a crash.
+///
+/// Elements, which are processed while the checkpoint is taken, and their
+/// descendants are not part of the checkpoint.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+/// use std::sync::RwLock;
+///
+/// use dynqueue::{Checkpoint, DynQueue, IntoDynQueue as _};
+///
+/// let queue = vec![1, 2, 3].into_dyn_queue();
+/// let json = serde_json::to_string(&queue.checkpoint()).unwrap();
+///
+/// let checkpoint: Checkpoint<u64> = serde_json::from_str(&json).unwrap();
+/// let queue: DynQueue<_, RwLock<Vec<_>>> = checkpoint.into_dyn_queue();
+///
+/// let mut result = queue
+///     .into_par_iter()
+///     .map(|(_, value)| value)
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint<T> {
+    pending: Vec<(Meta, T)>,
+}
+
+impl<T> Checkpoint<T> {
+    /// Number of pending elements
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// `true`, if no element is pending
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// The pending elements without their metadata
+    pub fn into_vec(self) -> Vec<T> {
+        self.pending.into_iter().map(|(_, v)| v).collect()
+    }
+
+    /// Snapshot of all queues in `shards`
+    fn of_shards<U: Queue<T>>(shards: &Mutex<Vec<Weak<U>>>) -> Self
+    where
+        T: Clone,
+    {
+        let mut pending = Vec::new();
+        for_each_shard(shards, |shard| {
+            shard.retain_meta(|meta, v| {
+                pending.push((*meta, v.clone()));
+                true
+            })
+        });
+
+        Checkpoint { pending }
+    }
+}
+
+impl<T, U: Queue<T> + Default> IntoDynQueue<T, U> for Checkpoint<T> {
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, U> {
+        let queue = U::default();
+        self.pending
+            .into_iter()
+            .for_each(|(meta, v)| queue.push_meta(meta, v));
+        DynQueue::new(queue)
+    }
+}
+
+impl<'a, T: Clone, U: Queue<T>> DynQueue<'a, T, U> {
+    /// Snapshot of the elements of the `DynQueue`
+    pub fn checkpoint(&self) -> Checkpoint<T> {
+        Checkpoint::of_shards(&self.0.shards)
+    }
+}
+
+impl<'a, T: Clone, U: Queue<T>> DynQueueHandle<'a, T, U> {
+    /// Snapshot of the pending elements of all parallel iterators of the
+    /// `DynQueue<T>`, which is currently iterated.
+    ///
+    /// The elements buffered by this handle are flushed first.
+    /// Returns `None`, if the iteration already ended.
+    pub fn checkpoint(&self) -> Option<Checkpoint<T>> {
+        self.flush();
+        let inner = self.inner.upgrade()?;
+        Some(Checkpoint::of_shards(&inner.shards))
+    }
+}
+
+impl<'s, 'a, T: Clone, U: Queue<T>> DynQueueRef<'s, 'a, T, U> {
+    /// Snapshot of the pending elements of all parallel iterators of the
+    /// `DynQueue<T>`, which is currently iterated.
+    pub fn checkpoint(self) -> Checkpoint<T> {
+        Checkpoint::of_shards(&self.inner.shards)
+    }
+}
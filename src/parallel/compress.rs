@@ -0,0 +1,267 @@
+//! Queue adapter, which compresses its elements (with `feature = "compress"`)
+
+use crate::sync::{AtomicUsize, Ordering};
+use crate::{DynQueue, IntoDynQueue, Queue, RecoverPoison as _};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Default number of elements in a compressed [`Block`]
+const DEFAULT_BLOCK_SIZE: usize = 256;
+
+/// A block of elements serialized with `bincode` and compressed with LZ4,
+/// the element type of the inner queue of a [`Compressed`] queue
+pub struct Block {
+    len: usize,
+    data: Box<[u8]>,
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Block")
+            .field("len", &self.len)
+            .field("bytes", &self.data.len())
+            .finish()
+    }
+}
+
+impl Block {
+    fn encode<T: Serialize>(elements: &[T]) -> Self {
+        let bytes = bincode::serialize(elements).expect("Compressed: cannot serialize an element");
+        Block {
+            len: elements.len(),
+            data: lz4_flex::compress_prepend_size(&bytes).into_boxed_slice(),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self) -> Vec<T> {
+        let bytes =
+            lz4_flex::decompress_size_prepended(&self.data).expect("Compressed: corrupt block");
+        bincode::deserialize(&bytes).expect("Compressed: cannot deserialize a block")
+    }
+
+    /// Number of elements in the block
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// `true`, if the block has no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Size of the compressed elements in bytes
+    pub fn compressed_len(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// A `Queue` adapter, which stores the elements of any `Queue<Block>` in
+/// compressed blocks
+///
+/// Pushed elements are collected, until [`block_size`] of them are
+/// serialized with `bincode` and compressed with LZ4 as one [`Block`] in
+/// the inner queue. Popping decompresses a whole block. This trades CPU
+/// for memory, which pays off for large frontiers of similar elements,
+/// e.g. the states of a model checker.
+///
+/// The inner queue decides the order of the blocks, the elements of a
+/// block are popped in the order they were pushed. Elements, which are not
+/// yet compressed, are popped after all blocks.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{Compressed, IntoDynQueue as _};
+/// use std::collections::VecDeque;
+/// use std::sync::RwLock;
+///
+/// let mut queue = Compressed::new(RwLock::new(VecDeque::new()));
+/// queue.extend((0..10_000u64).map(|i| [i; 16]));
+///
+/// let count = queue
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, state)| {
+///         if state[0] == 0 {
+///             handle.enqueue([10_000; 16]).unwrap();
+///         }
+///     })
+///     .count();
+///
+/// assert_eq!(count, 10_001);
+/// ```
+///
+/// [`block_size`]: Compressed::with_block_size
+pub struct Compressed<T, U> {
+    inner: U,
+    block_size: usize,
+    /// number of elements in the blocks of `inner`, shared with the
+    /// queues, which share `inner`
+    compressed: Arc<AtomicUsize>,
+    /// elements of the last popped block
+    popped: Mutex<VecDeque<T>>,
+    /// pushed elements, which are not yet compressed
+    pushed: Mutex<Vec<T>>,
+}
+
+impl<T, U> Compressed<T, U> {
+    /// Store the elements in the empty queue `inner`
+    pub fn new(inner: U) -> Self {
+        Compressed {
+            inner,
+            block_size: DEFAULT_BLOCK_SIZE,
+            compressed: Arc::new(AtomicUsize::new(0)),
+            popped: Mutex::new(VecDeque::new()),
+            pushed: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Compress `block_size` elements in one block
+    ///
+    /// Larger blocks compress better, but more elements are decompressed
+    /// at once. The default is 256.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+
+    /// The queue of the compressed blocks
+    pub fn inner(&self) -> &U {
+        &self.inner
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, U: Queue<Block>> Compressed<T, U> {
+    /// Compress `elements` in one block
+    fn push_block(&self, elements: &[T]) {
+        if elements.is_empty() {
+            return;
+        }
+        self.compressed.fetch_add(elements.len(), Ordering::AcqRel);
+        self.inner.push(Block::encode(elements));
+    }
+
+    /// Compress the pushed elements, which do not fill a block
+    fn flush(&self) {
+        let pushed = std::mem::take(&mut *self.pushed.lock().recover());
+        self.push_block(&pushed);
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, U: Queue<Block>> Extend<T> for Compressed<T, U> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.push_batch(iter)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, U: Queue<Block>> IntoDynQueue<T, Compressed<T, U>>
+    for Compressed<T, U>
+{
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, U: Queue<Block>> Queue<T> for Compressed<T, U> {
+    fn push(&self, v: T) {
+        let mut pushed = self.pushed.lock().recover();
+        pushed.push(v);
+        if pushed.len() >= self.block_size {
+            let block = std::mem::take(&mut *pushed);
+            drop(pushed);
+            self.push_block(&block);
+        }
+    }
+
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        let mut pushed = self.pushed.lock().recover();
+        for v in iter {
+            pushed.push(v);
+            if pushed.len() >= self.block_size {
+                self.push_block(&pushed);
+                pushed.clear();
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut popped = self.popped.lock().recover();
+        if let Some(v) = popped.pop_front() {
+            return Some(v);
+        }
+        if let Some(block) = self.inner.pop() {
+            self.compressed.fetch_sub(block.len(), Ordering::AcqRel);
+            popped.extend(block.decode::<T>());
+            return popped.pop_front();
+        }
+        drop(popped);
+        let mut pushed = self.pushed.lock().recover();
+        if pushed.is_empty() {
+            None
+        } else {
+            Some(pushed.remove(0))
+        }
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.popped.lock().recover().len()
+            + self.compressed.load(Ordering::Acquire)
+            + self.pushed.lock().recover().len()
+    }
+
+    /// Splits off whole blocks of the inner queue.
+    fn split_off(&self, size: usize) -> Self {
+        self.flush();
+        let inner = self.inner.split_off(size.div_ceil(self.block_size));
+        let compressed = if self.inner.shares_queue(&inner) {
+            self.compressed.clone()
+        } else {
+            let mut moved = 0;
+            inner.retain(|block| {
+                moved += block.len();
+                true
+            });
+            self.compressed.fetch_sub(moved, Ordering::AcqRel);
+            Arc::new(AtomicUsize::new(moved))
+        };
+        Compressed {
+            inner,
+            block_size: self.block_size,
+            compressed,
+            popped: Mutex::new(VecDeque::new()),
+            pushed: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Decompresses and compresses all blocks again.
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        self.popped.lock().recover().retain(|v| f(v));
+        let mut blocks = Vec::new();
+        while let Some(block) = self.inner.pop() {
+            self.compressed.fetch_sub(block.len(), Ordering::AcqRel);
+            blocks.push(block);
+        }
+        for block in blocks {
+            let mut elements = block.decode::<T>();
+            elements.retain(|v| f(v));
+            self.push_block(&elements);
+        }
+        self.pushed.lock().recover().retain(|v| f(v));
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        self.inner.shares_queue(&other.inner)
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        self.inner.is_exclusive()
+    }
+}
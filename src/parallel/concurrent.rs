@@ -0,0 +1,71 @@
+//! Queue backend of the `concurrent-queue` crate (with `feature = "concurrent-queue"`)
+
+use crate::{DynQueue, IntoDynQueue, Queue};
+use concurrent_queue::{ConcurrentQueue, PushError};
+use std::sync::Arc;
+
+impl<T> IntoDynQueue<T, Arc<ConcurrentQueue<T>>> for ConcurrentQueue<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Arc<ConcurrentQueue<T>>> {
+        DynQueue::new(Arc::new(self))
+    }
+}
+
+impl<T> IntoDynQueue<T, Arc<ConcurrentQueue<T>>> for Arc<ConcurrentQueue<T>> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+/// Both the bounded and the unbounded `ConcurrentQueue`
+///
+/// A bounded queue waits for free capacity on `push`. Elements pushed to a
+/// closed queue are dropped.
+impl<T> Queue<T> for Arc<ConcurrentQueue<T>> {
+    #[inline]
+    fn push(&self, v: T) {
+        let mut v = v;
+        while let Err(PushError::Full(ele)) = ConcurrentQueue::push(self, v) {
+            v = ele;
+            std::thread::yield_now();
+        }
+    }
+
+    #[inline(always)]
+    fn try_push(&self, v: T) -> Result<(), T> {
+        ConcurrentQueue::push(self, v).map_err(PushError::into_inner)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        ConcurrentQueue::pop(self).ok()
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        ConcurrentQueue::len(self)
+    }
+
+    /// An unbounded queue moves `size` elements to a new queue. A bounded
+    /// queue cannot be split without exceeding the capacity, so the new
+    /// iterator shares the same queue.
+    fn split_off(&self, size: usize) -> Self {
+        if self.capacity().is_some() {
+            return self.clone();
+        }
+
+        let q = ConcurrentQueue::unbounded();
+        (0..size)
+            .map_while(|_| ConcurrentQueue::pop(self).ok())
+            .for_each(|ele| {
+                let _ = q.push(ele);
+            });
+        Arc::new(q)
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        Arc::ptr_eq(self, other)
+    }
+}
@@ -0,0 +1,61 @@
+//! Values shared with all elements of a `DynQueue`
+
+use crate::Bound;
+use std::any::Any;
+use std::sync::Arc;
+
+type Shared = Arc<dyn Any + Send + Sync>;
+
+/// The context, the shared states and the bound of a `DynQueue`
+pub(crate) struct Context {
+    /// set with [`DynQueue::with_context`](crate::DynQueue::with_context)
+    ctx: Option<Shared>,
+    /// set with [`DynQueue::with_shared`](crate::DynQueue::with_shared),
+    /// at most one per type
+    state: Vec<Shared>,
+    /// set with [`DynQueue::with_bound`](crate::DynQueue::with_bound)
+    bound: Option<Shared>,
+}
+
+impl Context {
+    pub(crate) fn new() -> Self {
+        Context {
+            ctx: None,
+            state: Vec::new(),
+            bound: None,
+        }
+    }
+
+    /// The context of type `C`
+    pub(crate) fn get<C: Send + Sync + 'static>(&self) -> Option<Arc<C>> {
+        self.ctx.clone().and_then(|ctx| ctx.downcast().ok())
+    }
+
+    pub(crate) fn set<C: Send + Sync + 'static>(&mut self, ctx: C) {
+        self.ctx = Some(Arc::new(ctx));
+    }
+
+    /// The shared state of type `S`
+    pub(crate) fn state<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.state
+            .iter()
+            .find_map(|state| state.clone().downcast().ok())
+    }
+
+    /// Set the shared state of type `S` to `state`
+    pub(crate) fn set_state<S: Send + Sync + 'static>(&mut self, state: S) {
+        self.state.retain(|other| !other.is::<S>());
+        self.state.push(Arc::new(state));
+    }
+
+    /// The [`Bound`] of type `B`
+    pub(crate) fn bound<B: Send + Sync + 'static>(&self) -> Option<Bound<B>> {
+        self.bound
+            .as_ref()
+            .and_then(|bound| bound.downcast_ref::<Bound<B>>().cloned())
+    }
+
+    pub(crate) fn set_bound<B: Send + Sync + 'static>(&mut self, bound: Bound<B>) {
+        self.bound = Some(Arc::new(bound));
+    }
+}
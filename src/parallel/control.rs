@@ -0,0 +1,274 @@
+//! Control of a running `DynQueue`
+
+use super::DynQueueInner;
+use crate::sync::{Arc, Ordering, RwLock, Weak};
+use crate::{for_each_shard, Queue, RecoverPoison as _, Shards, Shared};
+use std::fmt;
+use std::time::Duration;
+
+/// How long a paused parallel iterator sleeps, before it checks again,
+/// if it was not woken up by [`Control::resume`]
+pub(crate) const PAUSE_POLL: Duration = Duration::from_millis(10);
+
+/// Handle to control a `DynQueue` from outside of the iteration
+///
+/// Get it with [`DynQueue::control`](crate::DynQueue::control) before the
+/// iteration starts. All clones of a handle control the same `DynQueue`.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let queue = vec![0u64].into_dyn_queue();
+/// let control = queue.control();
+///
+/// let count = queue
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 10 {
+///             // e.g. yield the CPU to another thread, which resumes later
+///             control.pause();
+///             assert!(control.is_paused());
+///             control.resume();
+///         }
+///         if value < 20 {
+///             handle.enqueue(value + 1).unwrap();
+///         }
+///     })
+///     .count();
+///
+/// assert_eq!(count, 21);
+/// ```
+pub struct Control<'a, T, U: Queue<T>> {
+    pub(crate) link: Arc<Link<'a, T, U>>,
+}
+
+impl<'a, T, U: Queue<T>> Clone for Control<'a, T, U> {
+    fn clone(&self) -> Self {
+        Control {
+            link: self.link.clone(),
+        }
+    }
+}
+
+impl<'a, T, U: Queue<T>> fmt::Debug for Control<'a, T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Control")
+            .field("paused", &self.is_paused())
+            .field("shut_down", &self.is_shut_down())
+            .finish()
+    }
+}
+
+impl<'a, T, U: Queue<T>> Control<'a, T, U> {
+    /// Stop popping new elements, until [`resume`](Self::resume) is called.
+    ///
+    /// The parallel iterators finish the elements in flight and park their
+    /// threads without ending the iteration. Elements can still be
+    /// enqueued.
+    pub fn pause(&self) {
+        self.link
+            .with(|linked| linked.shared.paused.store(true, Ordering::Release))
+    }
+
+    /// Continue popping elements after [`pause`](Self::pause).
+    pub fn resume(&self) {
+        self.link.with(|linked| {
+            linked.shared.paused.store(false, Ordering::Release);
+            linked.shared.idle.wake(true);
+        })
+    }
+
+    /// `true`, if the `DynQueue` is paused
+    pub fn is_paused(&self) -> bool {
+        self.link
+            .with(|linked| linked.shared.paused.load(Ordering::Acquire))
+    }
+
+    /// `true`, if the `DynQueue` was shut down with
+    /// [`shutdown`](Self::shutdown)
+    pub fn is_shut_down(&self) -> bool {
+        self.link.with(|linked| linked.shared.is_shut_down())
+    }
+
+    /// Estimate of the number of elements pending in the queues of all
+    /// parallel iterators, including requeued, delayed and deferred elements
+    pub fn pending(&self) -> usize {
+        self.link.with(|Linked { shared, shards, .. }| {
+            let mut pending = shared.retries.len() + shared.delayed.len();
+            if let Some(limit) = &shared.key_limit {
+                pending += limit.deferred();
+            }
+            for_each_shard(shards, |shard| pending += shard.len());
+            pending
+        })
+    }
+
+    /// Shut the `DynQueue` down gracefully and return the elements, which
+    /// were not processed, e.g. on `SIGTERM`.
+    ///
+    /// Unlike a cancellation, no element is lost: new elements are refused
+    /// with [`EnqueueError::Closed`](crate::EnqueueError::Closed), the
+    /// parallel iterators finish the elements in flight and the iteration
+    /// ends. The pending elements of all parallel iterators, including
+    /// requeued, delayed and deferred elements, and the elements waiting in
+    /// the receiver of [`DynQueue::with_receiver`](crate::DynQueue::with_receiver)
+    /// are returned.
+    ///
+    /// Elements popped in a batch, buffered by a handle or enqueued while
+    /// shutting down are not returned, but collected in the
+    /// [`DynQueue::leftovers`](crate::DynQueue::leftovers).
+    ///
+    /// ```
+    /// use dynqueue::{EnqueueError, IntoDynQueue as _};
+    ///
+    /// let queue = vec![0u64].into_dyn_queue();
+    /// let control = queue.control();
+    /// let mut frontier = Vec::new();
+    ///
+    /// for (handle, value) in queue.into_iter() {
+    ///     handle.enqueue(value + 1).unwrap();
+    ///     handle.enqueue(value + 2).unwrap();
+    ///     if value == 4 {
+    ///         frontier = control.shutdown();
+    ///         assert_eq!(handle.enqueue(value + 3), Err(EnqueueError::Closed(7)));
+    ///     }
+    /// }
+    ///
+    /// frontier.sort();
+    /// assert_eq!(frontier, vec![1, 3, 5, 6]);
+    /// ```
+    pub fn shutdown(&self) -> Vec<T> {
+        self.link.with(|Linked { shared, shards, .. }| {
+            shared.shutdown.store(true, Ordering::Release);
+
+            let mut frontier = Vec::new();
+            for_each_shard(shards, |shard| {
+                while let Some((_, v)) = shard.pop_meta() {
+                    frontier.push(v);
+                }
+            });
+            while let Some((_, v)) = shared.retries.pop() {
+                frontier.push(v);
+            }
+            while let Some((_, v)) = shared.delayed.pop_any() {
+                frontier.push(v);
+            }
+            if let Some(limit) = &shared.key_limit {
+                frontier.extend(limit.drain().into_iter().map(|(_, v)| v));
+            }
+            frontier.iter().for_each(|_| shared.on_drop());
+            // never enqueued, so not accounted for
+            frontier.extend(shared.close_receiver());
+
+            // let the idle parallel iterators see the end
+            shared.idle.wake(true);
+            shared.wake_waiter();
+            frontier
+        })
+    }
+
+    /// Clones of all elements pending in the queues of all parallel
+    /// iterators, including requeued, delayed and deferred elements, e.g. to
+    /// debug a stuck search.
+    ///
+    /// The snapshot can be taken, while the `DynQueue` is iterated. It is
+    /// not atomic across the parallel iterators and does not include the
+    /// elements in flight, popped in a batch or buffered by a handle.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let queue = vec![1u64, 2, 3].into_dyn_queue();
+    /// let control = queue.control();
+    ///
+    /// queue.into_par_iter().for_each(|(handle, value)| {
+    ///     if value == 3 {
+    ///         handle.enqueue(4).unwrap();
+    ///         assert!(control.snapshot().contains(&4));
+    ///     }
+    /// });
+    ///
+    /// assert!(control.snapshot().is_empty());
+    /// ```
+    pub fn snapshot(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.link.with(|Linked { shared, shards, .. }| {
+            let mut pending = Vec::new();
+            for_each_shard(shards, |shard| {
+                shard.retain(|v| {
+                    pending.push(v.clone());
+                    true
+                })
+            });
+            shared.retries.retain(|(_, v)| {
+                pending.push(v.clone());
+                true
+            });
+            shared.delayed.for_each(|v| pending.push(v.clone()));
+            if let Some(limit) = &shared.key_limit {
+                limit.for_each(|v| pending.push(v.clone()));
+            }
+            pending
+        })
+    }
+}
+
+/// Link of the handles created before the iteration, e.g. a [`Control`] or
+/// a [`DynQueueSender`](crate::DynQueueSender), to the `DynQueue`
+///
+/// Configuring the `DynQueue` needs exclusive access to its state, so the
+/// handles are unlinked meanwhile and wait for it, see [`Link::unlinked`].
+pub(crate) struct Link<'a, T, U: Queue<T>>(RwLock<Option<Linked<'a, T, U>>>);
+
+/// The state of the `DynQueue` a [`Link`] refers to
+pub(crate) struct Linked<'a, T, U: Queue<T>> {
+    pub(crate) inner: Weak<DynQueueInner<'a, T, U>>,
+    pub(crate) shared: Arc<Shared<'a, T>>,
+    pub(crate) shards: Arc<Shards<T, U>>,
+}
+
+impl<'a, T, U: Queue<T>> Link<'a, T, U> {
+    pub(crate) fn new() -> Self {
+        Link(RwLock::new(None))
+    }
+
+    /// Link the handles to the state returned by `linked`, if they are not
+    /// linked yet
+    pub(crate) fn link(&self, linked: impl FnOnce() -> Linked<'a, T, U>) {
+        let mut link = self.0.write().recover();
+        if link.is_none() {
+            *link = Some(linked());
+        }
+    }
+
+    /// Call `f` with the state of the `DynQueue`
+    #[inline]
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&Linked<'a, T, U>) -> R) -> R {
+        let link = self.0.read().recover();
+        f(link
+            .as_ref()
+            .expect("a handle exists only after it was linked"))
+    }
+
+    /// Unlink the handles for `configure` and link them to the state it
+    /// returns afterwards
+    ///
+    /// The handles hold references to the state, which `configure` has to
+    /// change exclusively.
+    pub(crate) fn unlinked(&self, configure: impl FnOnce() -> Linked<'a, T, U>) {
+        let mut link = self.0.write().recover();
+        let linked = link.take().is_some();
+        let relinked = configure();
+        if linked {
+            *link = Some(relinked);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+//! Queue wrapper with a cached length
+
+use crate::sync::{AtomicUsize, Mutex, Ordering};
+use crate::{DoubleEndedQueue, Meta, Order, Queue, RecoverPoison as _};
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+
+/// Wraps the queue of every parallel iterator and counts its elements in
+/// an atomic, so `len()` and the check for an empty queue in the `pop`
+/// methods do not take the lock of the queue.
+///
+/// The count is increased before an element is pushed and decreased after
+/// it was removed, so it never drops below the real length and `0` means,
+/// that the queue is empty.
+///
+/// Only queues, which are [exclusive](Queue::is_exclusive), are counted,
+/// all others are passed through.
+///
+/// A counted queue without a [cheap FIFO pop](Queue::has_cheap_fifo) hands
+/// out its oldest elements in batches of half the queue to a `Front`, from
+/// which `Order::Fifo` pops them one by one. Elements in the front are
+/// still counted, retained and popped by all other methods.
+pub(crate) struct Counted<T, U> {
+    queue: U,
+    len: Option<AtomicUsize>,
+    front: Option<Front<T>>,
+    _element: PhantomData<fn(T) -> T>,
+}
+
+/// The oldest elements of a queue without a cheap FIFO pop
+struct Front<T> {
+    elements: Mutex<VecDeque<(Meta, T)>>,
+    /// number of elements, so the pops of other orders do not take the lock
+    len: AtomicUsize,
+}
+
+impl<T> Front<T> {
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        self.len.load(Ordering::Acquire) == 0
+    }
+
+    fn pop(&self, order: Order) -> Option<(Meta, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut elements = self.elements.lock().recover();
+        let ele = match order {
+            Order::Fifo => elements.pop_front(),
+            Order::Lifo => elements.pop_back(),
+        };
+        self.len.store(elements.len(), Ordering::Release);
+        ele
+    }
+}
+
+impl<T, U: Default> Default for Counted<T, U> {
+    fn default() -> Self {
+        Counted {
+            queue: U::default(),
+            len: None,
+            front: None,
+            _element: PhantomData,
+        }
+    }
+}
+
+impl<T, U: Queue<T>> Counted<T, U> {
+    pub(crate) fn new(queue: U) -> Self {
+        let exclusive = queue.is_exclusive();
+        Counted {
+            len: exclusive.then(|| AtomicUsize::new(queue.len())),
+            front: (exclusive && !queue.has_cheap_fifo()).then(|| Front {
+                elements: Mutex::new(VecDeque::new()),
+                len: AtomicUsize::new(0),
+            }),
+            queue,
+            _element: PhantomData,
+        }
+    }
+
+    /// The number of elements, without the lock of the queue, if counted
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        match &self.len {
+            Some(len) => len.load(Ordering::Relaxed),
+            None => self.queue.len(),
+        }
+    }
+
+    /// The wrapped queue
+    ///
+    /// It does not contain the elements taken for FIFO pops, see
+    /// [`Counted::holds_elements`].
+    #[inline(always)]
+    pub(crate) fn inner(&self) -> &U {
+        &self.queue
+    }
+
+    /// The wrapped queue with the elements taken for FIFO pops put back in
+    /// front of the remaining ones
+    pub(crate) fn into_inner(self) -> U {
+        if let Some(front) = self.front {
+            let elements = front.elements.into_inner().recover();
+            if !elements.is_empty() {
+                let rest = self
+                    .queue
+                    .pop_batch_meta(self.queue.len(), Some(Order::Fifo));
+                for (meta, v) in elements.into_iter().chain(rest) {
+                    self.queue.push_meta(meta, v);
+                }
+            }
+        }
+        self.queue
+    }
+
+    /// `true`, if the wrapped queue or the front hold elements
+    ///
+    /// Unlike `len`, this asks the wrapped queue, because the count might
+    /// not include a concurrent steal yet.
+    pub(crate) fn holds_elements(&self) -> bool {
+        self.queue.len() > 0 || self.front.as_ref().is_some_and(|front| !front.is_empty())
+    }
+
+    /// `true`, if the queue is counted and empty
+    #[inline(always)]
+    fn is_empty(&self) -> bool {
+        self.len
+            .as_ref()
+            .is_some_and(|len| len.load(Ordering::Acquire) == 0)
+    }
+
+    #[inline(always)]
+    fn add(&self, n: usize) {
+        if let Some(len) = &self.len {
+            len.fetch_add(n, Ordering::AcqRel);
+        }
+    }
+
+    #[inline(always)]
+    fn sub(&self, n: usize) {
+        if let Some(len) = &self.len {
+            len.fetch_sub(n, Ordering::AcqRel);
+        }
+    }
+
+    /// Count a popped element
+    #[inline(always)]
+    fn popped<V>(&self, v: Option<V>) -> Option<V> {
+        if v.is_some() {
+            self.sub(1);
+        }
+        v
+    }
+
+    /// pop the newest element of the front, after the queue is empty
+    #[inline(always)]
+    fn pop_front_newest(&self) -> Option<(Meta, T)> {
+        self.front.as_ref().and_then(|front| front.pop(Order::Lifo))
+    }
+
+    /// pop the oldest element of the front and take the oldest half of the
+    /// queue, if the front is empty
+    fn pop_front_oldest(&self, front: &Front<T>) -> Option<(Meta, T)> {
+        if let Some(ele) = front.pop(Order::Fifo) {
+            return Some(ele);
+        }
+        let mut elements = front.elements.lock().recover();
+        if elements.is_empty() {
+            // `Vec::drain` of half the queue costs as much as all remaining
+            // elements, so every element is shifted about twice
+            let half = self.queue.len().div_ceil(2);
+            elements.extend(self.queue.pop_batch_meta(half, Some(Order::Fifo)));
+        }
+        let ele = elements.pop_front();
+        front.len.store(elements.len(), Ordering::Release);
+        ele
+    }
+
+    /// pop in `order` from the queue and its front
+    #[inline(always)]
+    fn pop_in_order(&self, order: Order) -> Option<(Meta, T)> {
+        match (&self.front, order) {
+            (Some(front), Order::Fifo) => self.pop_front_oldest(front),
+            _ => self
+                .queue
+                .pop_meta_ordered(order)
+                .or_else(|| self.pop_front_newest()),
+        }
+    }
+
+    /// retain the elements of the front
+    fn retain_front<F: FnMut(&Meta, &T) -> bool>(&self, mut f: F) -> usize {
+        let front = match &self.front {
+            Some(front) if !front.is_empty() => front,
+            _ => return 0,
+        };
+        let mut elements = front.elements.lock().recover();
+        let len = elements.len();
+        elements.retain(|(meta, v)| f(meta, v));
+        front.len.store(elements.len(), Ordering::Release);
+        len - elements.len()
+    }
+}
+
+impl<T, U: Queue<T>> Queue<T> for Counted<T, U> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.add(1);
+        self.queue.push(v)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.popped(
+            self.queue
+                .pop()
+                .or_else(|| self.pop_front_newest().map(|(_, v)| v)),
+        )
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        Counted::len(self)
+    }
+
+    #[inline]
+    fn split_off(&self, size: usize) -> Self {
+        let other = Counted::new(self.queue.split_off(size));
+        self.sub(other.len());
+        other
+    }
+
+    #[inline(always)]
+    fn try_push(&self, v: T) -> Result<(), T> {
+        self.add(1);
+        self.queue.try_push(v).inspect_err(|_| self.sub(1))
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.queue
+            .push_batch(iter.into_iter().inspect(|_| self.add(1)))
+    }
+
+    #[inline(always)]
+    fn reserve(&self, additional: usize) {
+        self.queue.reserve(additional)
+    }
+
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        let mut removed = self.retain_front(|_, v| f(v));
+        self.queue.retain(|v| {
+            let keep = f(v);
+            removed += usize::from(!keep);
+            keep
+        });
+        self.sub(removed);
+    }
+
+    fn retain_meta<F: FnMut(&Meta, &T) -> bool>(&self, mut f: F) {
+        let mut removed = self.retain_front(&mut f);
+        self.queue.retain_meta(|meta, v| {
+            let keep = f(meta, v);
+            removed += usize::from(!keep);
+            keep
+        });
+        self.sub(removed);
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        self.queue.is_exclusive()
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        self.queue.shares_queue(&other.queue)
+    }
+
+    #[inline(always)]
+    fn has_cheap_fifo(&self) -> bool {
+        self.queue.has_cheap_fifo() || self.front.is_some()
+    }
+
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        self.queue.is_poisoned()
+    }
+
+    #[inline(always)]
+    fn clear_poison(&self) {
+        self.queue.clear_poison()
+    }
+
+    #[inline(always)]
+    fn push_meta(&self, meta: Meta, v: T) {
+        self.add(1);
+        self.queue.push_meta(meta, v)
+    }
+
+    #[inline(always)]
+    fn try_push_meta(&self, meta: Meta, v: T) -> Result<(), T> {
+        self.add(1);
+        self.queue
+            .try_push_meta(meta, v)
+            .inspect_err(|_| self.sub(1))
+    }
+
+    #[inline(always)]
+    fn push_batch_meta<I: IntoIterator<Item = T>>(&self, meta: Meta, iter: I) {
+        self.queue
+            .push_batch_meta(meta, iter.into_iter().inspect(|_| self.add(1)))
+    }
+
+    #[inline(always)]
+    fn pop_meta(&self) -> Option<(Meta, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        self.popped(self.queue.pop_meta().or_else(|| self.pop_front_newest()))
+    }
+
+    #[inline(always)]
+    fn pop_ordered(&self, order: Order) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        if self.front.is_none() {
+            return self.popped(self.queue.pop_ordered(order));
+        }
+        self.popped(self.pop_in_order(order).map(|(_, v)| v))
+    }
+
+    #[inline(always)]
+    fn pop_meta_ordered(&self, order: Order) -> Option<(Meta, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        self.popped(self.pop_in_order(order))
+    }
+
+    #[inline]
+    fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+        if self.front.is_some() {
+            return self
+                .pop_batch_meta(n, order)
+                .into_iter()
+                .map(|(_, v)| v)
+                .collect();
+        }
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let batch = self.queue.pop_batch(n, order);
+        self.sub(batch.len());
+        batch
+    }
+
+    #[inline]
+    fn pop_batch_meta(&self, n: usize, order: Option<Order>) -> Vec<(Meta, T)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let batch = match (&self.front, order) {
+            (Some(front), Some(Order::Fifo)) => {
+                let mut batch = Vec::with_capacity(n);
+                while batch.len() < n {
+                    match self.pop_front_oldest(front) {
+                        Some(ele) => batch.push(ele),
+                        None => break,
+                    }
+                }
+                batch
+            }
+            (Some(_), _) => {
+                let mut batch = self.queue.pop_batch_meta(n, order);
+                while batch.len() < n {
+                    match self.pop_front_newest() {
+                        Some(ele) => batch.push(ele),
+                        None => break,
+                    }
+                }
+                batch
+            }
+            (None, _) => self.queue.pop_batch_meta(n, order),
+        };
+        self.sub(batch.len());
+        batch
+    }
+}
+
+impl<T, U: DoubleEndedQueue<T>> DoubleEndedQueue<T> for Counted<T, U> {
+    #[inline(always)]
+    fn push_front(&self, v: T) {
+        self.push_front_meta(Meta::default(), v)
+    }
+
+    #[inline(always)]
+    fn push_front_meta(&self, meta: Meta, v: T) {
+        self.add(1);
+        match &self.front {
+            Some(front) if !front.is_empty() => {
+                let mut elements = front.elements.lock().recover();
+                elements.push_front((meta, v));
+                front.len.store(elements.len(), Ordering::Release);
+            }
+            _ => self.queue.push_front_meta(meta, v),
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Earliest deadline first queue
+
+use crate::queue::split_heap;
+use crate::sync::{AtomicUsize, Ordering};
+use crate::{DynQueue, IntoDynQueue, Queue, RecoverPoison as _};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::iter::FromIterator;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// An element with its deadline and the order it was pushed in
+struct Urgent<T> {
+    deadline: Instant,
+    sequence: usize,
+    v: T,
+}
+
+impl<T> PartialEq for Urgent<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl<T> Eq for Urgent<T> {}
+
+impl<T> PartialOrd for Urgent<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The earliest deadline is the greatest, so it is on top of the heap.
+/// Elements with the same deadline are popped in the order they were pushed.
+impl<T> Ord for Urgent<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other
+            .deadline
+            .cmp(&self.deadline)
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
+}
+
+struct Heap<T> {
+    heap: BinaryHeap<Urgent<T>>,
+    sequence: usize,
+}
+
+/// Elements `(Instant, T)` are popped earliest deadline first.
+///
+/// With [`with_drop_expired`](DeadlineQueue::with_drop_expired), elements,
+/// whose deadline already passed, are dropped instead of popped and only
+/// counted in [`expired`](DeadlineQueue::expired).
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+/// use std::time::{Duration, Instant};
+///
+/// use dynqueue::{DeadlineQueue, IntoDynQueue as _};
+///
+/// let now = Instant::now();
+/// let queue = DeadlineQueue::from(vec![
+///     (now + Duration::from_secs(60), "report"),
+///     (now + Duration::from_secs(1), "frame"),
+/// ]);
+///
+/// let result = queue
+///     .into_dyn_queue()
+///     .into_iter()
+///     .map(|(handle, (deadline, job))| {
+///         if job == "frame" {
+///             handle
+///                 .enqueue((deadline + Duration::from_secs(1), "next frame"))
+///                 .unwrap();
+///         }
+///         job
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(result, vec!["frame", "next frame", "report"]);
+/// ```
+pub struct DeadlineQueue<T> {
+    heap: Mutex<Heap<T>>,
+    drop_expired: bool,
+    /// number of dropped elements, shared with the split off queues
+    expired: Arc<AtomicUsize>,
+}
+
+impl<T> Default for DeadlineQueue<T> {
+    fn default() -> Self {
+        DeadlineQueue {
+            heap: Mutex::new(Heap {
+                heap: BinaryHeap::new(),
+                sequence: 0,
+            }),
+            drop_expired: false,
+            expired: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl<T> From<Vec<(Instant, T)>> for DeadlineQueue<T> {
+    fn from(v: Vec<(Instant, T)>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<(Instant, T)> for DeadlineQueue<T> {
+    fn from_iter<I: IntoIterator<Item = (Instant, T)>>(iter: I) -> Self {
+        let queue = DeadlineQueue::default();
+        queue.push_batch(iter);
+        queue
+    }
+}
+
+impl<T> DeadlineQueue<T> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop the elements, whose deadline already passed, instead of popping
+    /// them.
+    ///
+    /// The dropped elements are not accounted for by the `DynQueue`, e.g.
+    /// in [`DynQueue::with_max_pending`](crate::DynQueue::with_max_pending).
+    pub fn with_drop_expired(mut self) -> Self {
+        self.drop_expired = true;
+        self
+    }
+
+    /// Number of elements dropped, because their deadline passed, by this
+    /// queue and all queues split off from it
+    pub fn expired(&self) -> usize {
+        self.expired.load(Ordering::Acquire)
+    }
+
+    /// The earliest deadline of all elements
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.heap
+            .lock()
+            .recover()
+            .heap
+            .peek()
+            .map(|urgent| urgent.deadline)
+    }
+}
+
+impl<T> IntoDynQueue<(Instant, T), DeadlineQueue<T>> for DeadlineQueue<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (Instant, T), Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<(Instant, T)> for DeadlineQueue<T> {
+    #[inline(always)]
+    fn push(&self, v: (Instant, T)) {
+        self.push_batch(Some(v))
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = (Instant, T)>>(&self, iter: I) {
+        let mut heap = self.heap.lock().recover();
+        for (deadline, v) in iter {
+            let sequence = heap.sequence;
+            heap.sequence += 1;
+            heap.heap.push(Urgent {
+                deadline,
+                sequence,
+                v,
+            });
+        }
+    }
+
+    fn pop(&self) -> Option<(Instant, T)> {
+        let mut heap = self.heap.lock().recover();
+        let now = self.drop_expired.then(Instant::now);
+        loop {
+            let Urgent { deadline, v, .. } = heap.heap.pop()?;
+            if now.is_some_and(|now| deadline < now) {
+                self.expired.fetch_add(1, Ordering::AcqRel);
+                continue;
+            }
+            return Some((deadline, v));
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.heap.lock().recover().heap.len()
+    }
+
+    /// Deals the elements out alternately, so both queues keep a fair share
+    /// of the most urgent elements.
+    fn split_off(&self, size: usize) -> Self {
+        let mut heap = self.heap.lock().recover();
+        DeadlineQueue {
+            heap: Mutex::new(Heap {
+                heap: split_heap(&mut heap.heap, size),
+                sequence: heap.sequence,
+            }),
+            drop_expired: self.drop_expired,
+            expired: self.expired.clone(),
+        }
+    }
+
+    fn retain<F: FnMut(&(Instant, T)) -> bool>(&self, mut f: F) {
+        let mut heap = self.heap.lock().recover();
+        let all = std::mem::take(&mut heap.heap);
+        heap.heap = all
+            .into_iter()
+            .map(|urgent| (urgent.sequence, (urgent.deadline, urgent.v)))
+            .filter(|(_, ele)| f(ele))
+            .map(|(sequence, (deadline, v))| Urgent {
+                deadline,
+                sequence,
+                v,
+            })
+            .collect();
+    }
+
+    /// Only without [`DeadlineQueue::with_drop_expired`], because the
+    /// `DynQueue` cannot count the dropped elements.
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        !self.drop_expired
+    }
+}
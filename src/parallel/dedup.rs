@@ -0,0 +1,149 @@
+//! Concurrent set of the keys seen by the dedup filter
+
+use crate::parallel::ElementFilter;
+use crate::sync::Mutex;
+use crate::RecoverPoison as _;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+/// Number of independently locked shards of a [`SeenSet`]
+const SHARDS: usize = 64;
+
+/// A key with its hash, computed once before any shard is locked
+struct Prehashed<K> {
+    hash: u64,
+    key: K,
+}
+
+impl<K: Hash> Hash for Prehashed<K> {
+    #[inline(always)]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash)
+    }
+}
+
+impl<K: Eq> PartialEq for Prehashed<K> {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash && self.key == other.key
+    }
+}
+
+impl<K: Eq> Eq for Prehashed<K> {}
+
+/// Passes the precomputed hash of a [`Prehashed`] through
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    #[inline(always)]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    #[inline(always)]
+    fn write(&mut self, _bytes: &[u8]) {
+        unreachable!("only u64 hashes are passed through")
+    }
+
+    #[inline(always)]
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash
+    }
+}
+
+type Shard<K> = Mutex<HashSet<Prehashed<K>, BuildHasherDefault<IdentityHasher>>>;
+
+/// Set of keys, which can be inserted concurrently
+///
+/// The keys are spread over independently locked shards by their hash, so
+/// concurrent inserts of different keys rarely wait for each other.
+pub(crate) struct SeenSet<K, S> {
+    hasher: S,
+    shards: Box<[Shard<K>]>,
+}
+
+impl<K: Hash + Eq, S: BuildHasher> SeenSet<K, S> {
+    pub(crate) fn with_hasher(hasher: S) -> Self {
+        SeenSet {
+            hasher,
+            shards: (0..SHARDS)
+                .map(|_| Mutex::new(HashSet::default()))
+                .collect(),
+        }
+    }
+
+    /// Insert `key` and return `true`, if it was not seen before.
+    #[inline]
+    pub(crate) fn insert(&self, key: K) -> bool {
+        let key = self.prehash(key);
+        self.shard(&key).lock().recover().insert(key)
+    }
+
+    /// `true`, if `key` was seen before
+    #[inline]
+    pub(crate) fn contains(&self, key: K) -> bool {
+        let key = self.prehash(key);
+        self.shard(&key).lock().recover().contains(&key)
+    }
+
+    /// Forget `key`, so that it passes again
+    #[inline]
+    pub(crate) fn remove(&self, key: K) {
+        let key = self.prehash(key);
+        self.shard(&key).lock().recover().remove(&key);
+    }
+
+    #[inline(always)]
+    fn prehash(&self, key: K) -> Prehashed<K> {
+        Prehashed {
+            hash: self.hasher.hash_one(&key),
+            key,
+        }
+    }
+
+    /// The shard of `key`
+    #[inline(always)]
+    fn shard(&self, key: &Prehashed<K>) -> &Shard<K> {
+        // the low bits select the bucket and the top bits are the tag of the
+        // `HashSet` of the shard, so take the shard from the bits in between
+        &self.shards[(key.hash >> 32) as usize % SHARDS]
+    }
+}
+
+/// Filter, which passes every element with a key not seen before
+pub(crate) struct DedupFilter<F, K, S> {
+    key_fn: F,
+    seen: SeenSet<K, S>,
+}
+
+impl<F, K: Hash + Eq, S: BuildHasher> DedupFilter<F, K, S> {
+    pub(crate) fn new(key_fn: F, hasher: S) -> Self {
+        DedupFilter {
+            key_fn,
+            seen: SeenSet::with_hasher(hasher),
+        }
+    }
+}
+
+impl<T, F, K, S> ElementFilter<T> for DedupFilter<F, K, S>
+where
+    F: Fn(&T) -> K + Send + Sync,
+    K: Hash + Eq + Send,
+    S: BuildHasher + Send + Sync,
+{
+    #[inline]
+    fn pass(&self, v: &T) -> bool {
+        self.seen.insert((self.key_fn)(v))
+    }
+
+    #[inline]
+    fn check(&self, v: &T) -> bool {
+        !self.seen.contains((self.key_fn)(v))
+    }
+
+    #[inline]
+    fn forget(&self, v: &T) {
+        self.seen.remove((self.key_fn)(v))
+    }
+}
@@ -0,0 +1,110 @@
+//! Time-ordered side queue for elements enqueued with a delay
+
+use crate::sync::{AtomicUsize, Mutex, Ordering};
+use crate::{Meta, RecoverPoison as _};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::time::Instant;
+
+/// An element, which becomes eligible for popping at `due`
+struct Delayed<T> {
+    due: Instant,
+    meta: Meta,
+    v: T,
+}
+
+impl<T> PartialEq for Delayed<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.due == other.due
+    }
+}
+
+impl<T> Eq for Delayed<T> {}
+
+impl<T> PartialOrd for Delayed<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The earliest element is the greatest, so it is on top of the heap.
+impl<T> Ord for Delayed<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other.due.cmp(&self.due)
+    }
+}
+
+/// Elements enqueued with `DynQueueHandle::enqueue_after`, shared by all
+/// parallel iterators of a `DynQueue`
+pub(crate) struct DelayQueue<T> {
+    heap: Mutex<BinaryHeap<Delayed<T>>>,
+    /// number of elements, to check for an empty queue without the lock
+    len: AtomicUsize,
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        DelayQueue {
+            heap: Mutex::new(BinaryHeap::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T> DelayQueue<T> {
+    /// Add `v`, which becomes eligible for popping at `due`
+    pub(crate) fn push(&self, due: Instant, meta: Meta, v: T) {
+        let mut heap = self.heap.lock().recover();
+        heap.push(Delayed { due, meta, v });
+        self.len.fetch_add(1, Ordering::AcqRel);
+    }
+
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pop the earliest element, if it is due
+    #[inline]
+    pub(crate) fn pop_due(&self) -> Option<(Meta, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut heap = self.heap.lock().recover();
+        if heap.peek()?.due > Instant::now() {
+            return None;
+        }
+        let Delayed { meta, v, .. } = heap.pop()?;
+        self.len.fetch_sub(1, Ordering::AcqRel);
+        Some((meta, v))
+    }
+
+    /// The time, when the earliest element is due
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        if self.is_empty() {
+            return None;
+        }
+        self.heap.lock().recover().peek().map(|delayed| delayed.due)
+    }
+
+    /// Call `f` for every element, regardless of its due time
+    pub(crate) fn for_each<F: FnMut(&T)>(&self, mut f: F) {
+        self.heap
+            .lock()
+            .recover()
+            .iter()
+            .for_each(|delayed| f(&delayed.v));
+    }
+
+    /// Pop an element, regardless of its due time
+    pub(crate) fn pop_any(&self) -> Option<(Meta, T)> {
+        let Delayed { meta, v, .. } = self.heap.lock().recover().pop()?;
+        self.len.fetch_sub(1, Ordering::AcqRel);
+        Some((meta, v))
+    }
+}
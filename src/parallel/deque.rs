@@ -0,0 +1,182 @@
+//! Work-stealing deque backend (with `feature = "crossbeam-deque"`)
+
+use crate::{DynQueue, IntoDynQueue, Order, Queue, RecoverPoison as _};
+use crossbeam_deque::{Steal, Stealer, Worker};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// A `crossbeam_deque::Worker` per parallel iterator, which steals from its
+/// peers, when it runs dry
+///
+/// Every split creates a new local deque, so every rayon worker pushes to and
+/// pops from its own deque without contention. An empty deque steals a batch
+/// of elements from the other deques of the same `DynQueue`.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{Deque, IntoDynQueue as _};
+///
+/// let mut result = Deque::from(vec![1, 2, 3])
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 2 {
+///             handle.enqueue(4).unwrap()
+///         };
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3, 4]);
+/// ```
+pub struct Deque<T> {
+    id: usize,
+    lifo: bool,
+    worker: Mutex<Worker<T>>,
+    peers: Peers<T>,
+}
+
+/// The stealers of all deques of a `DynQueue` with the id of their deque
+type Peers<T> = Arc<RwLock<Vec<(usize, Stealer<T>)>>>;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl<T> Deque<T> {
+    fn with_worker(worker: Worker<T>, lifo: bool, peers: Peers<T>) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        peers.write().recover().push((id, worker.stealer()));
+        Deque {
+            id,
+            lifo,
+            worker: Mutex::new(worker),
+            peers,
+        }
+    }
+
+    /// Create an empty deque, which pops the oldest element first
+    pub fn new_fifo() -> Self {
+        Self::with_worker(Worker::new_fifo(), false, Default::default())
+    }
+
+    /// Create an empty deque, which pops the newest element first
+    pub fn new_lifo() -> Self {
+        Self::with_worker(Worker::new_lifo(), true, Default::default())
+    }
+
+    /// Steal a batch of elements from a peer and pop one of them
+    fn steal(&self, worker: &Worker<T>) -> Option<T> {
+        let peers = self.peers.read().recover();
+        loop {
+            let mut retry = false;
+            for (_, stealer) in peers.iter().filter(|(id, _)| *id != self.id) {
+                match stealer.steal_batch_and_pop(worker) {
+                    Steal::Success(v) => return Some(v),
+                    Steal::Retry => retry = true,
+                    Steal::Empty => {}
+                }
+            }
+            if !retry {
+                return None;
+            }
+        }
+    }
+}
+
+impl<T> Drop for Deque<T> {
+    fn drop(&mut self) {
+        self.peers
+            .write()
+            .recover()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
+
+impl<T> From<Vec<T>> for Deque<T> {
+    /// A LIFO deque with the elements of `v`
+    fn from(v: Vec<T>) -> Self {
+        let deque = Self::new_lifo();
+        v.into_iter().for_each(|ele| deque.push(ele));
+        deque
+    }
+}
+
+impl<T> IntoDynQueue<T, Deque<T>> for Deque<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<T> for Deque<T> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.worker.lock().recover().push(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        let worker = self.worker.lock().recover();
+        iter.into_iter().for_each(|v| worker.push(v))
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<T> {
+        let worker = self.worker.lock().recover();
+        worker.pop().or_else(|| self.steal(&worker))
+    }
+
+    /// Steals only, if the local deque is empty.
+    fn pop_batch(&self, n: usize, _order: Option<Order>) -> Vec<T> {
+        let worker = self.worker.lock().recover();
+        let mut batch = (0..n).map_while(|_| worker.pop()).collect::<Vec<_>>();
+        if batch.is_empty() && n > 0 {
+            batch.extend(self.steal(&worker));
+        }
+        batch
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.worker.lock().recover().len()
+    }
+
+    /// Only the elements of the local deque are filtered, the peers are
+    /// left untouched.
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        let worker = self.worker.lock().recover();
+        let mut all = Vec::with_capacity(worker.len());
+        while let Some(v) = worker.pop() {
+            all.push(v);
+        }
+        if self.lifo {
+            all.reverse();
+        }
+        all.into_iter()
+            .filter(|v| f(v))
+            .for_each(|v| worker.push(v))
+    }
+
+    /// Move `size` elements to a new deque, which becomes a peer of `self`.
+    fn split_off(&self, size: usize) -> Self {
+        let new = if self.lifo {
+            Worker::new_lifo()
+        } else {
+            Worker::new_fifo()
+        };
+
+        {
+            let worker = self.worker.lock().recover();
+            for _ in 0..size {
+                match worker.pop() {
+                    Some(v) => new.push(v),
+                    None => break,
+                }
+            }
+        }
+
+        Self::with_worker(new, self.lifo, self.peers.clone())
+    }
+}
@@ -0,0 +1,81 @@
+//! Extension trait for the standard collections
+
+use crate::{DynQueueHandle, IntoDynQueue, Queue};
+use rayon::iter::ParallelIterator;
+use std::collections::VecDeque;
+use std::sync::RwLock;
+
+/// Map the elements of a collection and all elements enqueued with the
+/// `DynQueueHandle` in parallel.
+///
+/// The result is a `ParallelIterator` and the collection does not have to
+/// be turned into a `DynQueue` and a parallel iterator first. Slices are
+/// cloned into a `Vec<T>`.
+///
+/// ```
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::DynMapExt as _;
+///
+/// let mut result = vec![1, 2, 3]
+///     .par_dyn_map(|handle, value| {
+///         if value == 2 {
+///             handle.enqueue(4).unwrap()
+///         };
+///         value * 10
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![10, 20, 30, 40]);
+/// ```
+pub trait DynMapExt<T>: Sized {
+    /// The queue holding the elements
+    type Queue: Queue<T> + Send + Sync;
+
+    /// Map all elements with `f` in parallel.
+    fn par_dyn_map<'a, F, R>(self, f: F) -> impl ParallelIterator<Item = R>
+    where
+        T: 'a,
+        F: Fn(DynQueueHandle<'a, T, Self::Queue>, T) -> R + Send + Sync,
+        R: Send;
+}
+
+impl<T: Send + Sync> DynMapExt<T> for Vec<T> {
+    type Queue = RwLock<Vec<T>>;
+
+    fn par_dyn_map<'a, F, R>(self, f: F) -> impl ParallelIterator<Item = R>
+    where
+        T: 'a,
+        F: Fn(DynQueueHandle<'a, T, Self::Queue>, T) -> R + Send + Sync,
+        R: Send,
+    {
+        self.into_dyn_queue().map(move |(handle, v)| f(handle, v))
+    }
+}
+
+impl<T: Send + Sync> DynMapExt<T> for VecDeque<T> {
+    type Queue = RwLock<VecDeque<T>>;
+
+    fn par_dyn_map<'a, F, R>(self, f: F) -> impl ParallelIterator<Item = R>
+    where
+        T: 'a,
+        F: Fn(DynQueueHandle<'a, T, Self::Queue>, T) -> R + Send + Sync,
+        R: Send,
+    {
+        self.into_dyn_queue().map(move |(handle, v)| f(handle, v))
+    }
+}
+
+impl<T: Clone + Send + Sync> DynMapExt<T> for &[T] {
+    type Queue = RwLock<Vec<T>>;
+
+    fn par_dyn_map<'a, F, R>(self, f: F) -> impl ParallelIterator<Item = R>
+    where
+        T: 'a,
+        F: Fn(DynQueueHandle<'a, T, Self::Queue>, T) -> R + Send + Sync,
+        R: Send,
+    {
+        self.to_vec().par_dyn_map(f)
+    }
+}
@@ -0,0 +1,230 @@
+//! Weighted fair queue of classes of elements
+
+use crate::{DynQueue, IntoDynQueue, Queue, RecoverPoison as _};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::iter::FromIterator;
+use std::sync::Mutex;
+
+/// Elements `(K, T)` are queued FIFO per class `K` and the classes are
+/// popped in proportion to their weights.
+///
+/// A class with weight 3 gets three elements popped for every element of a
+/// class with weight 1, as long as both have pending elements, so no class
+/// starves. The pops of the classes are interleaved with a smooth weighted
+/// round robin. Classes without a [`weight`](FairQueue::with_weight) have a
+/// weight of 1.
+///
+/// A split takes about the same share of every class.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{FairQueue, IntoDynQueue as _};
+///
+/// let queue = (0..4)
+///     .map(|v| ("bulk", v))
+///     .collect::<FairQueue<_, _>>()
+///     .with_weight("interactive", 3);
+///
+/// let result = queue
+///     .into_dyn_queue()
+///     .into_iter()
+///     .map(|(handle, (class, v))| {
+///         if class == "bulk" && v == 0 {
+///             for v in 0..6 {
+///                 handle.enqueue(("interactive", v)).unwrap();
+///             }
+///         }
+///         class
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(
+///     result,
+///     [
+///         "bulk",
+///         "interactive", "interactive", "bulk", "interactive",
+///         "interactive", "interactive", "bulk", "interactive",
+///         "bulk",
+///     ]
+/// );
+/// ```
+pub struct FairQueue<K, T>(Mutex<Classes<K, T>>);
+
+struct Class<K, T> {
+    key: K,
+    weight: usize,
+    /// credit of the smooth weighted round robin
+    current: isize,
+    queue: VecDeque<T>,
+}
+
+struct Classes<K, T> {
+    /// in the order the classes were seen first, which breaks ties
+    classes: Vec<Class<K, T>>,
+    index: HashMap<K, usize>,
+}
+
+impl<K: Hash + Eq + Clone, T> Classes<K, T> {
+    fn class(&mut self, key: K) -> &mut Class<K, T> {
+        let classes = &mut self.classes;
+        let i = *self.index.entry(key.clone()).or_insert_with(|| {
+            classes.push(Class {
+                key,
+                weight: 1,
+                current: 0,
+                queue: VecDeque::new(),
+            });
+            classes.len() - 1
+        });
+        &mut self.classes[i]
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.classes.iter().map(|class| class.queue.len()).sum()
+    }
+
+    fn pop(&mut self) -> Option<(K, T)> {
+        let mut total = 0;
+        // the highest credit wins, ties go to the heavier class
+        let mut best: Option<(usize, isize, usize)> = None;
+        for (i, class) in self.classes.iter_mut().enumerate() {
+            if class.queue.is_empty() {
+                continue;
+            }
+            class.current += class.weight as isize;
+            total += class.weight as isize;
+            if best.is_none_or(|(_, current, weight)| {
+                (class.current, class.weight) > (current, weight)
+            }) {
+                best = Some((i, class.current, class.weight));
+            }
+        }
+        let class = &mut self.classes[best?.0];
+        class.current -= total;
+        let v = class.queue.pop_front()?;
+        if class.queue.is_empty() {
+            // no credit is saved up, while the class has nothing to pop
+            class.current = 0;
+        }
+        Some((class.key.clone(), v))
+    }
+}
+
+impl<K, T> Default for FairQueue<K, T> {
+    fn default() -> Self {
+        FairQueue(Mutex::new(Classes {
+            classes: Vec::new(),
+            index: HashMap::new(),
+        }))
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> From<Vec<(K, T)>> for FairQueue<K, T> {
+    fn from(v: Vec<(K, T)>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> FromIterator<(K, T)> for FairQueue<K, T> {
+    fn from_iter<I: IntoIterator<Item = (K, T)>>(iter: I) -> Self {
+        let queue = FairQueue::default();
+        queue.push_batch(iter);
+        queue
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> FairQueue<K, T> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pop the elements of `class` in proportion to `weight`.
+    ///
+    /// A weight of 0 is treated as 1.
+    pub fn with_weight(self, class: K, weight: usize) -> Self {
+        self.0.lock().recover().class(class).weight = weight.max(1);
+        self
+    }
+
+    /// Number of elements of `class`
+    pub fn class_len(&self, class: &K) -> usize {
+        let classes = self.0.lock().recover();
+        classes
+            .index
+            .get(class)
+            .map_or(0, |&i| classes.classes[i].queue.len())
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> IntoDynQueue<(K, T), FairQueue<K, T>> for FairQueue<K, T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (K, T), Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> Queue<(K, T)> for FairQueue<K, T> {
+    #[inline(always)]
+    fn push(&self, (k, v): (K, T)) {
+        self.0.lock().recover().class(k).queue.push_back(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = (K, T)>>(&self, iter: I) {
+        let mut classes = self.0.lock().recover();
+        iter.into_iter()
+            .for_each(|(k, v)| classes.class(k).queue.push_back(v))
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<(K, T)> {
+        self.0.lock().recover().pop()
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.0.lock().recover().len()
+    }
+
+    /// Splits off about the same share of every class.
+    fn split_off(&self, size: usize) -> Self {
+        let mut classes = self.0.lock().recover();
+        let len = classes.len().max(1);
+        let split = FairQueue::default();
+        {
+            let mut other = split.0.lock().recover();
+            for class in classes.classes.iter_mut() {
+                let take = (class.queue.len() * size)
+                    .div_ceil(len)
+                    .min(class.queue.len());
+                let to = other.class(class.key.clone());
+                to.weight = class.weight;
+                to.queue = class.queue.split_off(class.queue.len() - take);
+            }
+        }
+        split
+    }
+
+    fn retain<F: FnMut(&(K, T)) -> bool>(&self, mut f: F) {
+        let mut classes = self.0.lock().recover();
+        for class in classes.classes.iter_mut() {
+            let key = &class.key;
+            class.queue = std::mem::take(&mut class.queue)
+                .into_iter()
+                .map(|v| (key.clone(), v))
+                .filter(|ele| f(ele))
+                .map(|(_, v)| v)
+                .collect();
+        }
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+}
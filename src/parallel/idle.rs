@@ -0,0 +1,69 @@
+//! Parallel iterators sleeping until there is work to steal
+
+use super::RecoverPoison as _;
+use crate::sync::{AtomicUsize, Condvar, Mutex, Ordering};
+use std::time::Duration;
+
+/// Idle parallel iterators of a `DynQueue` and the wake ups for them
+pub(crate) struct Idle {
+    /// threads waiting in `wait_for_work`
+    idlers: AtomicUsize,
+    wakeups: AtomicUsize,
+    lock: Mutex<()>,
+    wakeup: Condvar,
+}
+
+impl Idle {
+    pub(crate) fn new() -> Self {
+        Idle {
+            idlers: AtomicUsize::new(0),
+            wakeups: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    /// Number of wake ups so far, to be passed to [`Idle::wait_for_work`]
+    ///
+    /// Read it before looking for work, so that no wake up in between is
+    /// missed.
+    #[inline(always)]
+    pub(crate) fn wakeups(&self) -> usize {
+        self.wakeups.load(Ordering::Acquire)
+    }
+
+    /// Block the current thread, until an element was enqueued or an
+    /// element in flight was processed since `wakeups` were counted, or the
+    /// optional `timeout` passed.
+    ///
+    /// The counters of the idle threads and the wake ups are read with
+    /// read-modify-write operations on both sides, so either the waiting
+    /// thread sees the wake up or the waking thread sees the waiting one.
+    pub(crate) fn wait_for_work(&self, wakeups: usize, timeout: Option<Duration>) {
+        self.idlers.fetch_add(1, Ordering::AcqRel);
+        let idle = self.lock.lock().recover();
+        if self.wakeups.fetch_add(0, Ordering::AcqRel) == wakeups {
+            let _idle = match timeout {
+                Some(timeout) => self.wakeup.wait_timeout(idle, timeout).recover().0,
+                None => self.wakeup.wait(idle).recover(),
+            };
+        }
+        self.idlers.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// Wake up one or `all` threads waiting for work
+    #[inline(always)]
+    pub(crate) fn wake(&self, all: bool) {
+        self.wakeups.fetch_add(1, Ordering::AcqRel);
+        if self.idlers.fetch_add(0, Ordering::AcqRel) == 0 {
+            return;
+        }
+        // a thread between counting the wake ups and waiting holds the lock
+        drop(self.lock.lock().recover());
+        if all {
+            self.wakeup.notify_all();
+        } else {
+            self.wakeup.notify_one();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Keyed queue based on a `DashMap` (with `feature = "dashmap"`)
+
+use crate::{DynQueue, IntoDynQueue, Queue};
+use dashmap::DashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+impl<K, T, S> IntoDynQueue<(K, T), Arc<DashMap<K, T, S>>> for DashMap<K, T, S>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (K, T), Arc<DashMap<K, T, S>>> {
+        DynQueue::new(Arc::new(self))
+    }
+}
+
+impl<K, T, S> IntoDynQueue<(K, T), Arc<DashMap<K, T, S>>> for Arc<DashMap<K, T, S>>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, (K, T), Self> {
+        DynQueue::new(self)
+    }
+}
+
+/// Elements `(K, T)` are stored under their key `K`, which is shared by all
+/// parallel iterators.
+///
+/// Enqueueing an element with the key of a pending element replaces the
+/// pending element, so every key is processed only once, until it is
+/// enqueued again after it was popped. The elements are popped in no
+/// particular order.
+///
+/// ```
+/// use dashmap::DashMap;
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let map = DashMap::new();
+/// map.insert("a", 1);
+/// map.insert("b", 2);
+///
+/// let mut result = map
+///     .into_dyn_queue()
+///     .into_iter()
+///     .map(|(handle, (node, value))| {
+///         if node == "a" {
+///             handle.enqueue(("c", 3)).unwrap();
+///             handle.enqueue(("c", 4)).unwrap();
+///         }
+///         (node, value)
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![("a", 1), ("b", 2), ("c", 4)]);
+/// ```
+impl<K, T, S> Queue<(K, T)> for Arc<DashMap<K, T, S>>
+where
+    K: Hash + Eq + Clone,
+    S: BuildHasher + Clone,
+{
+    #[inline(always)]
+    fn push(&self, (k, v): (K, T)) {
+        self.insert(k, v);
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<(K, T)> {
+        loop {
+            let key = self.iter().next().map(|entry| entry.key().clone())?;
+            // another thread might have popped the key in the meantime
+            if let Some(ele) = self.remove(&key) {
+                return Some(ele);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        DashMap::len(self)
+    }
+
+    fn retain<F: FnMut(&(K, T)) -> bool>(&self, mut f: F) {
+        let keys = self
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect::<Vec<_>>();
+        for key in keys {
+            if let Some(ele) = self.remove(&key) {
+                if f(&ele) {
+                    self.insert(ele.0, ele.1);
+                }
+            }
+        }
+    }
+
+    /// All parallel iterators share the same map, so that a pending key is
+    /// replaced regardless of the iterator enqueueing it.
+    #[inline(always)]
+    fn split_off(&self, _size: usize) -> Self {
+        self.clone()
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        Arc::ptr_eq(self, other)
+    }
+}
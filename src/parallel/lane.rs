@@ -0,0 +1,244 @@
+//! Queue with a fixed set of priority lanes
+
+use crate::{DynQueue, IntoDynQueue, Lane, Meta, Order, Queue, RecoverPoison as _};
+use std::collections::VecDeque;
+use std::iter::FromIterator;
+use std::sync::Mutex;
+
+/// Default number of elements popped from higher lanes, before a waiting
+/// element of a lower lane is popped
+const DEFAULT_RATIO: usize = 4;
+
+/// A FIFO queue per [`Lane`], which pops the higher lanes first
+///
+/// To keep the lower lanes from starving, a waiting element of a lower lane
+/// is popped after the [`ratio`](LaneQueue::with_ratio) of elements of the
+/// higher lanes, 4 by default. Elements enqueued without a lane are put in
+/// [`Lane::Normal`].
+///
+/// A split takes about the same share of every lane.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, Lane, LaneQueue};
+///
+/// let mut result = LaneQueue::from(vec!["bulk"])
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, job)| {
+///         if job == "bulk" {
+///             handle.enqueue_in(Lane::High, "interactive").unwrap();
+///             handle.enqueue_in(Lane::Low, "cleanup").unwrap();
+///         }
+///         job
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec!["bulk", "cleanup", "interactive"]);
+/// ```
+pub struct LaneQueue<T>(Mutex<Lanes<T>>);
+
+struct Lanes<T> {
+    lanes: [VecDeque<T>; 3],
+    /// elements popped from higher lanes, before a waiting element of the
+    /// lane is popped
+    ratio: [usize; 3],
+    /// elements popped from higher lanes, while the lane was waiting
+    skipped: [usize; 3],
+}
+
+impl<T> Lanes<T> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.lanes.iter().map(VecDeque::len).sum()
+    }
+
+    fn pop(&mut self) -> Option<(Lane, T)> {
+        // a starving lower lane goes first
+        let lane = Lane::ALL
+            .iter()
+            .rev()
+            .find(|lane| {
+                let i = lane.index();
+                !self.lanes[i].is_empty() && self.skipped[i] >= self.ratio[i]
+            })
+            .or_else(|| {
+                Lane::ALL
+                    .iter()
+                    .find(|lane| !self.lanes[lane.index()].is_empty())
+            })
+            .copied()?;
+        let i = lane.index();
+        self.skipped[i] = 0;
+        for lower in i + 1..self.lanes.len() {
+            if !self.lanes[lower].is_empty() {
+                self.skipped[lower] += 1;
+            }
+        }
+        self.lanes[i].pop_front().map(|v| (lane, v))
+    }
+}
+
+impl<T> Default for LaneQueue<T> {
+    fn default() -> Self {
+        LaneQueue(Mutex::new(Lanes {
+            lanes: Default::default(),
+            ratio: [0, DEFAULT_RATIO, DEFAULT_RATIO],
+            skipped: [0; 3],
+        }))
+    }
+}
+
+impl<T> From<Vec<T>> for LaneQueue<T> {
+    /// All elements are put in [`Lane::Normal`].
+    fn from(v: Vec<T>) -> Self {
+        v.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<T> for LaneQueue<T> {
+    /// All elements are put in [`Lane::Normal`].
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let queue = LaneQueue::default();
+        queue.0.lock().recover().lanes[Lane::Normal.index()].extend(iter);
+        queue
+    }
+}
+
+impl<T> LaneQueue<T> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pop a waiting element of `lane`, after `ratio` elements of the
+    /// higher lanes were popped.
+    ///
+    /// `usize::MAX` pops the higher lanes strictly first. The ratio of
+    /// [`Lane::High`] has no effect.
+    pub fn with_ratio(self, lane: Lane, ratio: usize) -> Self {
+        self.0.lock().recover().ratio[lane.index()] = ratio;
+        self
+    }
+
+    /// Put `v` in `lane`
+    pub fn push_in(&self, lane: Lane, v: T) {
+        self.0.lock().recover().lanes[lane.index()].push_back(v)
+    }
+
+    /// Number of elements in `lane`
+    pub fn lane_len(&self, lane: Lane) -> usize {
+        self.0.lock().recover().lanes[lane.index()].len()
+    }
+}
+
+impl<T> IntoDynQueue<T, LaneQueue<T>> for LaneQueue<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<T> for LaneQueue<T> {
+    /// Puts `v` in [`Lane::Normal`].
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.push_in(Lane::Normal, v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.0.lock().recover().lanes[Lane::Normal.index()].extend(iter)
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.0.lock().recover().pop().map(|(_, v)| v)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.0.lock().recover().len()
+    }
+
+    /// Splits off about the same share of every lane.
+    fn split_off(&self, size: usize) -> Self {
+        let mut lanes = self.0.lock().recover();
+        let len = lanes.len().max(1);
+        let split = LaneQueue::default();
+        {
+            let mut other = split.0.lock().recover();
+            other.ratio = lanes.ratio;
+            for (from, to) in lanes.lanes.iter_mut().zip(other.lanes.iter_mut()) {
+                let take = (from.len() * size).div_ceil(len).min(from.len());
+                *to = from.split_off(from.len() - take);
+            }
+        }
+        split
+    }
+
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        let mut lanes = self.0.lock().recover();
+        lanes
+            .lanes
+            .iter_mut()
+            .for_each(|lane| lane.retain(|v| f(v)))
+    }
+
+    fn retain_meta<F: FnMut(&Meta, &T) -> bool>(&self, mut f: F) {
+        let mut lanes = self.0.lock().recover();
+        for (lane, values) in Lane::ALL.iter().zip(lanes.lanes.iter_mut()) {
+            let meta = lane.meta();
+            values.retain(|v| f(&meta, v))
+        }
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    /// Puts `v` in the lane of `meta`.
+    #[inline(always)]
+    fn push_meta(&self, meta: Meta, v: T) {
+        self.push_in(meta.lane, v)
+    }
+
+    #[inline(always)]
+    fn try_push_meta(&self, meta: Meta, v: T) -> Result<(), T> {
+        self.push_in(meta.lane, v);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn push_batch_meta<I: IntoIterator<Item = T>>(&self, meta: Meta, iter: I) {
+        self.0.lock().recover().lanes[meta.lane.index()].extend(iter)
+    }
+
+    /// Returns the lane of the element in the metadata.
+    #[inline(always)]
+    fn pop_meta(&self) -> Option<(Meta, T)> {
+        self.0
+            .lock()
+            .recover()
+            .pop()
+            .map(|(lane, v)| (lane.meta(), v))
+    }
+
+    /// Ignores `order`, the lanes decide the order.
+    #[inline(always)]
+    fn pop_meta_ordered(&self, _order: Order) -> Option<(Meta, T)> {
+        self.pop_meta()
+    }
+
+    fn pop_batch_meta(&self, n: usize, _order: Option<Order>) -> Vec<(Meta, T)> {
+        let mut lanes = self.0.lock().recover();
+        (0..n)
+            .map_while(|_| lanes.pop())
+            .map(|(lane, v)| (lane.meta(), v))
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+//! Limit of the elements with the same key in flight
+
+use crate::sync::{AtomicUsize, Mutex, Ordering};
+use crate::{Meta, RecoverPoison as _};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher as _, Hash};
+
+/// The elements in flight and the deferred elements of one key
+struct Slots<T> {
+    in_flight: usize,
+    deferred: VecDeque<(Meta, T)>,
+}
+
+/// Limits the number of elements with the same key, which are processed at
+/// the same time by all parallel iterators of a `DynQueue`
+pub(crate) struct KeyLimit<'a, T> {
+    key: Box<dyn Fn(&T) -> u64 + Send + Sync + 'a>,
+    limit: usize,
+    slots: Mutex<HashMap<u64, Slots<T>>>,
+    /// number of deferred elements, to check without the lock
+    deferred: AtomicUsize,
+}
+
+impl<'a, T> KeyLimit<'a, T> {
+    pub(crate) fn new<K, F>(key_fn: F, limit: usize) -> Self
+    where
+        K: Hash + 'a,
+        F: Fn(&T) -> K + Send + Sync + 'a,
+    {
+        let hasher = RandomState::new();
+        KeyLimit {
+            key: Box::new(move |v| hasher.hash_one(key_fn(v))),
+            limit: limit.max(1),
+            slots: Mutex::new(HashMap::new()),
+            deferred: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take a slot for `v` and return its key, or defer `v`, until a slot
+    /// of its key is released.
+    pub(crate) fn acquire(&self, meta: Meta, v: T) -> Option<(u64, Meta, T)> {
+        let key = (self.key)(&v);
+        let mut slots = self.slots.lock().recover();
+        let slot = slots.entry(key).or_insert_with(|| Slots {
+            in_flight: 0,
+            deferred: VecDeque::new(),
+        });
+        if slot.in_flight >= self.limit {
+            slot.deferred.push_back((meta, v));
+            self.deferred.fetch_add(1, Ordering::AcqRel);
+            return None;
+        }
+        slot.in_flight += 1;
+        Some((key, meta, v))
+    }
+
+    /// Release a slot of `key` and return the next deferred element of the
+    /// key, which can take the slot now.
+    pub(crate) fn release(&self, key: u64) -> Option<(Meta, T)> {
+        let mut slots = self.slots.lock().recover();
+        let slot = slots.get_mut(&key)?;
+        slot.in_flight -= 1;
+        let next = slot.deferred.pop_front();
+        if next.is_some() {
+            self.deferred.fetch_sub(1, Ordering::AcqRel);
+        } else if slot.in_flight == 0 {
+            slots.remove(&key);
+        }
+        next
+    }
+
+    /// Number of deferred elements
+    #[inline(always)]
+    pub(crate) fn deferred(&self) -> usize {
+        self.deferred.load(Ordering::Acquire)
+    }
+
+    /// Call `f` for every deferred element
+    pub(crate) fn for_each<F: FnMut(&T)>(&self, mut f: F) {
+        self.slots
+            .lock()
+            .recover()
+            .values()
+            .flat_map(|slot| slot.deferred.iter())
+            .for_each(|(_, v)| f(v));
+    }
+
+    /// Take all deferred elements
+    pub(crate) fn drain(&self) -> Vec<(Meta, T)> {
+        let mut slots = self.slots.lock().recover();
+        let drained = slots
+            .values_mut()
+            .flat_map(|slot| slot.deferred.drain(..))
+            .collect::<Vec<_>>();
+        self.deferred.fetch_sub(drained.len(), Ordering::AcqRel);
+        drained
+    }
+}
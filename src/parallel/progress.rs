@@ -0,0 +1,58 @@
+//! Number of processed elements for the progress callback and `max_items`
+
+use crate::sync::{AtomicUsize, Ordering};
+
+type Callback<'a> = Box<dyn Fn(usize, usize) + Send + Sync + 'a>;
+
+/// Number of processed elements of all parallel iterators of a `DynQueue`
+///
+/// Only counted, if the progress is reported or the elements are limited
+/// to `max_items`.
+pub(crate) struct Progress<'a> {
+    /// called with the number of processed and pending elements after every
+    /// `every` processed elements
+    pub(crate) on_progress: Option<(usize, Callback<'a>)>,
+    pub(crate) max_items: Option<usize>,
+    processed: AtomicUsize,
+}
+
+impl<'a> Progress<'a> {
+    pub(crate) fn new() -> Self {
+        Progress {
+            on_progress: None,
+            max_items: None,
+            processed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Count a popped element.
+    ///
+    /// Returns the number of processed elements including it, or `None`,
+    /// if it exceeds `max_items` and must not be processed.
+    #[inline(always)]
+    pub(crate) fn count(&self) -> Option<usize> {
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.max_items {
+            Some(max) if processed > max => None,
+            _ => Some(processed),
+        }
+    }
+
+    /// `true`, if `max_items` elements were processed
+    #[inline(always)]
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.max_items
+            .is_some_and(|max| self.processed.load(Ordering::Relaxed) >= max)
+    }
+
+    /// Report the progress after `processed` elements, if it is due, with
+    /// the number of elements returned by `pending`.
+    #[inline(always)]
+    pub(crate) fn report(&self, processed: usize, pending: impl FnOnce() -> usize) {
+        if let Some((every, on_progress)) = &self.on_progress {
+            if processed.is_multiple_of(*every) {
+                on_progress(processed, pending());
+            }
+        }
+    }
+}
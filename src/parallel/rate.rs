@@ -0,0 +1,59 @@
+//! Token bucket to limit the rate of enqueued elements
+
+use crate::sync::Mutex;
+use crate::RecoverPoison as _;
+use std::time::{Duration, Instant};
+
+/// Token bucket shared by all parallel iterators of a `DynQueue`
+///
+/// Every enqueued element takes a token. The bucket holds up to `burst`
+/// tokens and is refilled with `per_second` tokens per second.
+pub(crate) struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    /// available tokens, negative for tokens reserved by waiting threads,
+    /// and the time of the last refill
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimit {
+    pub(crate) fn new(per_second: f64, burst: usize) -> Self {
+        assert!(per_second > 0.0, "rate limit must be positive");
+        let burst = burst.max(1) as f64;
+        RateLimit {
+            per_second,
+            burst,
+            bucket: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Take `n` tokens, if available, or reserve them and return the time
+    /// to wait for them, if `reserve` is set.
+    fn take(&self, n: usize, reserve: bool) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().recover();
+        let now = Instant::now();
+        let (tokens, last) = &mut *bucket;
+        *tokens =
+            (*tokens + now.duration_since(*last).as_secs_f64() * self.per_second).min(self.burst);
+        *last = now;
+
+        let left = *tokens - n as f64;
+        if left < 0.0 && !reserve {
+            return None;
+        }
+        *tokens = left;
+        Some(Duration::from_secs_f64((-left).max(0.0) / self.per_second))
+    }
+
+    /// Block, until `n` tokens are available, and take them.
+    pub(crate) fn acquire(&self, n: usize) {
+        if let Some(wait) = self.take(n, true).filter(|wait| !wait.is_zero()) {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Take a token, if one is available.
+    pub(crate) fn try_acquire(&self) -> bool {
+        self.take(1, false).is_some()
+    }
+}
@@ -0,0 +1,62 @@
+//! Elements received from a channel, while the `DynQueue` is iterated
+
+use crate::sync::{AtomicBool, Mutex, Ordering};
+use crate::RecoverPoison as _;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// The receiver of [`DynQueue::with_receiver`](crate::DynQueue::with_receiver)
+pub(crate) struct Receiving<T> {
+    receiver: Mutex<Receiver<T>>,
+    /// `true`, if the receiver is connected and might receive elements
+    connected: AtomicBool,
+}
+
+impl<T> Receiving<T> {
+    pub(crate) fn new(receiver: Receiver<T>) -> Self {
+        Receiving {
+            receiver: Mutex::new(receiver),
+            connected: AtomicBool::new(true),
+        }
+    }
+
+    /// `true`, if the receiver is connected and might receive elements
+    #[inline(always)]
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    /// Receive the next element, which `admit` accepts, waiting up to
+    /// `timeout` for it.
+    ///
+    /// Without a `timeout`, another thread receiving at the same time is
+    /// not waited for either.
+    pub(crate) fn receive(&self, timeout: Duration, admit: impl Fn(&T) -> bool) -> Option<T> {
+        if !self.is_connected() {
+            return None;
+        }
+        let receiver = if timeout.is_zero() {
+            self.receiver.try_lock().ok()?
+        } else {
+            self.receiver.lock().recover()
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(v) if admit(&v) => return Some(v),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return None,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.connected.store(false, Ordering::Release);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Take all elements waiting in the receiver and stop receiving.
+    pub(crate) fn close(&self) -> Vec<T> {
+        self.connected.store(false, Ordering::Release);
+        self.receiver.lock().recover().try_iter().collect()
+    }
+}
@@ -0,0 +1,65 @@
+//! Initial elements taken lazily from an iterator
+
+use crate::sync::{AtomicBool, Mutex, Ordering};
+use crate::RecoverPoison as _;
+
+type Seeds<'a, T> = Box<dyn Iterator<Item = T> + Send + 'a>;
+
+/// Default number of elements taken from the seeds at once
+const SEED_CHUNK_SIZE: usize = 64;
+
+/// The seeds of [`DynQueue::with_seeds`](crate::DynQueue::with_seeds)
+pub(crate) struct Seeding<'a, T> {
+    seeds: Option<Mutex<Seeds<'a, T>>>,
+    /// `true`, if the seeds might have more elements
+    seeding: AtomicBool,
+    pub(crate) chunk_size: usize,
+}
+
+impl<'a, T> Seeding<'a, T> {
+    /// Without seeds until [`Seeding::set`] is called
+    pub(crate) fn new() -> Self {
+        Seeding {
+            seeds: None,
+            seeding: AtomicBool::new(false),
+            chunk_size: SEED_CHUNK_SIZE,
+        }
+    }
+
+    pub(crate) fn set<I>(&mut self, seeds: I)
+    where
+        I: Iterator<Item = T> + Send + 'a,
+    {
+        self.seeds = Some(Mutex::new(Box::new(seeds)));
+        self.seeding = AtomicBool::new(true);
+    }
+
+    /// `true`, if the seeds might have more elements
+    #[inline(always)]
+    pub(crate) fn is_seeding(&self) -> bool {
+        self.seeding.load(Ordering::Acquire)
+    }
+
+    /// Call `push` with the next elements of the seeds, until it took
+    /// `chunk_size` of them or the seeds are exhausted.
+    ///
+    /// Returns the number of elements `push` took.
+    pub(crate) fn take(&self, mut push: impl FnMut(T) -> bool) -> usize {
+        let seeds = match &self.seeds {
+            Some(seeds) if self.is_seeding() => seeds,
+            _ => return 0,
+        };
+        let mut seeds = seeds.lock().recover();
+        let mut taken = 0;
+        while taken < self.chunk_size {
+            match seeds.next() {
+                Some(v) => taken += usize::from(push(v)),
+                None => {
+                    self.seeding.store(false, Ordering::Release);
+                    break;
+                }
+            }
+        }
+        taken
+    }
+}
@@ -0,0 +1,220 @@
+//! Queue, which splits a large set of initial elements without copying them
+
+use crate::{DynQueue, IntoDynQueue, Order, Queue, RecoverPoison as _};
+use std::iter::FromIterator;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+/// A queue, which hands out index ranges of a shared store of the initial
+/// elements, when it is split.
+///
+/// Splitting a `RwLock<Vec<T>>` allocates a new `Vec` and moves half of the
+/// elements. A `SharedVec<T>` only splits the range of initial elements it
+/// owns, which pays off for millions of initial elements. Enqueued
+/// elements are stored in a private `Vec` per queue.
+///
+/// Otherwise it behaves like a `RwLock<Vec<T>>`: elements are popped from
+/// the back, first the enqueued, then the initial ones.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, SharedVec};
+///
+/// let sum = SharedVec::from((0..1_000_000u64).collect::<Vec<_>>())
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 0 {
+///             handle.enqueue(1_000_000).unwrap();
+///         }
+///         value
+///     })
+///     .sum::<u64>();
+///
+/// assert_eq!(sum, 500_000_500_000);
+/// ```
+pub struct SharedVec<T> {
+    store: Arc<[Mutex<Option<T>>]>,
+    inner: Mutex<Owned<T>>,
+}
+
+/// The part of the store and the enqueued elements owned by one queue
+struct Owned<T> {
+    range: Range<usize>,
+    local: Vec<T>,
+}
+
+impl<T> Owned<T> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.range.len() + self.local.len()
+    }
+}
+
+impl<T> Default for SharedVec<T> {
+    fn default() -> Self {
+        Vec::new().into()
+    }
+}
+
+impl<T> From<Vec<T>> for SharedVec<T> {
+    fn from(v: Vec<T>) -> Self {
+        let store = v
+            .into_iter()
+            .map(|v| Mutex::new(Some(v)))
+            .collect::<Arc<[_]>>();
+        SharedVec {
+            inner: Mutex::new(Owned {
+                range: 0..store.len(),
+                local: Vec::new(),
+            }),
+            store,
+        }
+    }
+}
+
+impl<T> FromIterator<T> for SharedVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl<T> SharedVec<T> {
+    /// Take the initial element at `index`, which is owned by the caller
+    #[inline(always)]
+    fn take(&self, index: usize) -> Option<T> {
+        self.store[index].lock().recover().take()
+    }
+
+    /// Take all remaining elements in the order of a `Vec`
+    pub fn into_vec(self) -> Vec<T> {
+        let store = self.store;
+        let owned = self.inner.into_inner().recover();
+        let mut v = owned
+            .range
+            .filter_map(|i| store[i].lock().recover().take())
+            .collect::<Vec<_>>();
+        v.extend(owned.local);
+        v
+    }
+}
+
+impl<T> IntoDynQueue<T, SharedVec<T>> for SharedVec<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, SharedVec<T>> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T> Queue<T> for SharedVec<T> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.inner.lock().recover().local.push(v)
+    }
+
+    #[inline(always)]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        self.inner.lock().recover().local.extend(iter)
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<T> {
+        let mut owned = self.inner.lock().recover();
+        if let Some(v) = owned.local.pop() {
+            return Some(v);
+        }
+        let index = owned.range.next_back()?;
+        drop(owned);
+        self.take(index)
+    }
+
+    /// `Order::Fifo` has to shift all enqueued elements on every pop of
+    /// an enqueued element, see [`Queue::has_cheap_fifo`].
+    #[inline]
+    fn pop_ordered(&self, order: Order) -> Option<T> {
+        match order {
+            Order::Lifo => self.pop(),
+            Order::Fifo => {
+                let mut owned = self.inner.lock().recover();
+                if let Some(index) = owned.range.next() {
+                    drop(owned);
+                    return self.take(index);
+                }
+                if owned.local.is_empty() {
+                    None
+                } else {
+                    Some(owned.local.remove(0))
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.inner.lock().recover().len()
+    }
+
+    #[inline(always)]
+    fn reserve(&self, additional: usize) {
+        self.inner.lock().recover().local.reserve(additional)
+    }
+
+    /// Initial elements are only moved out of the shared store, if one of
+    /// them is removed.
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        let mut owned = self.inner.lock().recover();
+        let keep = owned
+            .range
+            .clone()
+            .map(|i| self.store[i].lock().recover().as_ref().is_some_and(&mut f))
+            .collect::<Vec<_>>();
+        owned.local.retain(f);
+
+        if keep.iter().all(|keep| *keep) {
+            return;
+        }
+        let mut v = std::mem::replace(&mut owned.range, 0..0)
+            .zip(keep)
+            .filter_map(|(i, keep)| self.take(i).filter(|_| keep))
+            .collect::<Vec<_>>();
+        v.append(&mut owned.local);
+        owned.local = v;
+    }
+
+    #[inline]
+    fn split_off(&self, size: usize) -> Self {
+        let mut owned = self.inner.lock().recover();
+        let at = size.min(owned.len());
+        let other = if at <= owned.range.len() {
+            let start = owned.range.start + at;
+            let range = start..owned.range.end;
+            owned.range.end = start;
+            Owned {
+                range,
+                local: std::mem::take(&mut owned.local),
+            }
+        } else {
+            let at = at - owned.range.len();
+            Owned {
+                range: 0..0,
+                local: owned.local.split_off(at),
+            }
+        };
+        SharedVec {
+            store: self.store.clone(),
+            inner: Mutex::new(other),
+        }
+    }
+
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn has_cheap_fifo(&self) -> bool {
+        false
+    }
+}
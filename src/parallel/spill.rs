@@ -0,0 +1,286 @@
+//! Queue, which spills to disk (with `feature = "spill"`)
+
+use crate::{DynQueue, IntoDynQueue, Order, Queue, RecoverPoison as _};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write as _};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// A FIFO queue, which keeps at most about `window` elements in memory and
+/// spills the others to a temporary file
+///
+/// Elements are serialized with `bincode` in blocks of a quarter of the
+/// window and read back in blocks, as the in-memory part drains. This
+/// keeps the memory bounded for frontiers of hundreds of millions of
+/// elements, e.g. in a breadth first search of a model checker.
+///
+/// All parallel iterators share the same queue, like a [`Channel`]. The
+/// temporary file is created with the first spilled element and removed,
+/// when the queue is dropped.
+///
+/// # Panics
+///
+/// Pushing and popping panics, if the temporary file cannot be written or
+/// read, e.g. because the disk is full.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, SpillQueue};
+///
+/// let mut queue = SpillQueue::new(1_000);
+/// queue.extend(0..100_000u64);
+/// assert!(queue.spilled() > 0);
+///
+/// let sum = queue
+///     .into_dyn_queue()
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value == 0 {
+///             handle.enqueue(100_000).unwrap();
+///         }
+///         value
+///     })
+///     .sum::<u64>();
+///
+/// assert_eq!(sum, 5_000_050_000);
+/// ```
+///
+/// [`Channel`]: crate::Channel
+pub struct SpillQueue<T>(Arc<Mutex<Spill<T>>>);
+
+impl<T> Clone for SpillQueue<T> {
+    fn clone(&self) -> Self {
+        SpillQueue(self.0.clone())
+    }
+}
+
+/// The elements of a `SpillQueue` in FIFO order: `memory`, then the
+/// elements on disk, then `tail`
+struct Spill<T> {
+    window: usize,
+    block: usize,
+    dir: Option<PathBuf>,
+    memory: VecDeque<T>,
+    /// file with the spilled elements, created on demand
+    file: Option<File>,
+    /// offset of the next element to read from `file`
+    read_pos: u64,
+    /// number of elements in `file` after `read_pos`
+    on_disk: usize,
+    /// elements, which are written to `file` as one block
+    tail: Vec<T>,
+}
+
+impl<T> Spill<T> {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.memory.len() + self.on_disk + self.tail.len()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Spill<T> {
+    fn push(&mut self, v: T) {
+        if self.on_disk == 0 && self.tail.is_empty() && self.memory.len() < self.window {
+            self.memory.push_back(v);
+            return;
+        }
+        self.tail.push(v);
+        if self.tail.len() >= self.block {
+            self.write_tail()
+                .expect("SpillQueue: cannot write the temporary file");
+        }
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        if self.memory.is_empty() {
+            if self.on_disk > 0 {
+                self.read_block()
+                    .expect("SpillQueue: cannot read the temporary file");
+            } else {
+                self.memory.extend(self.tail.drain(..));
+            }
+        }
+        self.memory.pop_front()
+    }
+
+    fn tempfile(&self) -> io::Result<File> {
+        match &self.dir {
+            Some(dir) => tempfile::tempfile_in(dir),
+            None => tempfile::tempfile(),
+        }
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        if self.file.is_none() {
+            self.file = Some(self.tempfile()?);
+        }
+        Ok(self.file.as_mut().unwrap())
+    }
+
+    /// Append all elements of `tail` to the file
+    fn write_tail(&mut self) -> bincode::Result<()> {
+        let tail = std::mem::take(&mut self.tail);
+        let file = self.file()?;
+        file.seek(SeekFrom::End(0))?;
+        {
+            let mut writer = BufWriter::new(file);
+            for v in &tail {
+                bincode::serialize_into(&mut writer, v)?;
+            }
+            writer.flush()?;
+        }
+        self.on_disk += tail.len();
+        Ok(())
+    }
+
+    /// Move the next block of elements from the file to `memory`
+    fn read_block(&mut self) -> bincode::Result<()> {
+        let count = self.block.min(self.on_disk);
+        self.file()?;
+        let file = self.file.as_mut().unwrap();
+        file.seek(SeekFrom::Start(self.read_pos))?;
+        let mut reader = BufReader::new(file);
+        for _ in 0..count {
+            self.memory
+                .push_back(bincode::deserialize_from(&mut reader)?);
+        }
+        self.read_pos = reader.stream_position()?;
+        self.on_disk -= count;
+        if self.on_disk == 0 {
+            // start over to reuse the space of the file
+            self.file()?.set_len(0)?;
+            self.read_pos = 0;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the file with the elements, for which `f` returns `true`
+    fn retain_disk<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> bincode::Result<()> {
+        if self.on_disk == 0 {
+            return Ok(());
+        }
+        let mut retained = self.tempfile()?;
+        let mut kept = 0;
+        {
+            let (read_pos, on_disk) = (self.read_pos, self.on_disk);
+            let file = self.file()?;
+            file.seek(SeekFrom::Start(read_pos))?;
+            let mut reader = BufReader::new(file);
+            let mut writer = BufWriter::new(&mut retained);
+            for _ in 0..on_disk {
+                let v: T = bincode::deserialize_from(&mut reader)?;
+                if f(&v) {
+                    bincode::serialize_into(&mut writer, &v)?;
+                    kept += 1;
+                }
+            }
+            writer.flush()?;
+        }
+        self.file = Some(retained);
+        self.read_pos = 0;
+        self.on_disk = kept;
+        Ok(())
+    }
+}
+
+impl<T> SpillQueue<T> {
+    /// Create an empty queue, which keeps about `window` elements in memory
+    /// and spills the others to a temporary file in
+    /// [`std::env::temp_dir`].
+    pub fn new(window: usize) -> Self {
+        Self::build(window, None)
+    }
+
+    /// Create an empty queue, which spills to a temporary file in `dir`
+    pub fn new_in<P: Into<PathBuf>>(window: usize, dir: P) -> Self {
+        Self::build(window, Some(dir.into()))
+    }
+
+    fn build(window: usize, dir: Option<PathBuf>) -> Self {
+        let window = window.max(1);
+        SpillQueue(Arc::new(Mutex::new(Spill {
+            window,
+            block: (window / 4).max(1),
+            dir,
+            memory: VecDeque::new(),
+            file: None,
+            read_pos: 0,
+            on_disk: 0,
+            tail: Vec::new(),
+        })))
+    }
+
+    /// Number of elements, which are currently spilled to disk
+    pub fn spilled(&self) -> usize {
+        self.0.lock().recover().on_disk
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Extend<T> for SpillQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.push_batch(iter)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> IntoDynQueue<T, SpillQueue<T>> for SpillQueue<T> {
+    #[inline(always)]
+    fn into_dyn_queue<'a>(self) -> DynQueue<'a, T, Self> {
+        DynQueue::new(self)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Queue<T> for SpillQueue<T> {
+    #[inline(always)]
+    fn push(&self, v: T) {
+        self.0.lock().recover().push(v)
+    }
+
+    #[inline]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        let mut spill = self.0.lock().recover();
+        iter.into_iter().for_each(|v| spill.push(v))
+    }
+
+    #[inline(always)]
+    fn pop(&self) -> Option<T> {
+        self.0.lock().recover().pop()
+    }
+
+    #[inline]
+    fn pop_batch(&self, n: usize, _order: Option<Order>) -> Vec<T> {
+        let mut spill = self.0.lock().recover();
+        (0..n).map_while(|_| spill.pop()).collect()
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.0.lock().recover().len()
+    }
+
+    /// All parallel iterators share the same queue.
+    #[inline(always)]
+    fn split_off(&self, _size: usize) -> Self {
+        self.clone()
+    }
+
+    /// Streams the spilled elements through a new temporary file, so they
+    /// are never all in memory.
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        let mut spill = self.0.lock().recover();
+        spill.memory.retain(|v| f(v));
+        spill
+            .retain_disk(&mut f)
+            .expect("SpillQueue: cannot rewrite the temporary file");
+        spill.tail.retain(|v| f(v));
+    }
+
+    #[inline(always)]
+    fn shares_queue(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
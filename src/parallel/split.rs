@@ -0,0 +1,212 @@
+//! Custom decisions, if and how a queue is split
+
+use crate::Queue;
+
+/// Read access to the elements of a queue for a [`SplitPolicy`]
+pub trait QueueView<T> {
+    /// number of elements in the queue
+    fn len(&self) -> usize;
+
+    /// `true`, if the queue holds no element
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// call `f` for every element in the queue
+    fn for_each(&self, f: &mut dyn FnMut(&T));
+}
+
+impl<T, U: Queue<T>> QueueView<T> for U {
+    #[inline(always)]
+    fn len(&self) -> usize {
+        Queue::len(self)
+    }
+
+    #[inline(always)]
+    fn for_each(&self, f: &mut dyn FnMut(&T)) {
+        self.retain(|v| {
+            f(v);
+            true
+        })
+    }
+}
+
+/// Decides, if and how a queue is split for another parallel iterator
+///
+/// Without a policy, a queue is split in half, if it holds at least
+/// `split_threshold` elements and both halves hold at least `min_shard_size`
+/// elements.
+///
+/// Every `Fn(&dyn QueueView<T>) -> Option<usize>` is a `SplitPolicy`.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, QueueView};
+///
+/// // split only, if there is enough work for both halves
+/// let mut result = vec![1u64, 2, 3]
+///     .into_dyn_queue()
+///     .with_split_policy(|queue: &dyn QueueView<u64>| {
+///         let mut work = 0;
+///         queue.for_each(&mut |v| work += *v);
+///         if work >= 100 {
+///             Some(queue.len() / 2)
+///         } else {
+///             None
+///         }
+///     })
+///     .into_par_iter()
+///     .map(|(handle, value)| {
+///         if value < 50 {
+///             handle.enqueue(value * 10).unwrap();
+///         }
+///         value
+///     })
+///     .collect::<Vec<_>>();
+/// result.sort();
+///
+/// assert_eq!(result, vec![1, 2, 3, 10, 20, 30, 100, 200, 300]);
+/// ```
+pub trait SplitPolicy<T>: Send + Sync {
+    /// The `size` argument of [`Queue::split_off`] to split `queue` for
+    /// another parallel iterator, or `None` to keep the queue in one piece.
+    fn split_size(&self, queue: &dyn QueueView<T>) -> Option<usize>;
+}
+
+impl<T, F> SplitPolicy<T> for F
+where
+    F: Fn(&dyn QueueView<T>) -> Option<usize> + Send + Sync,
+{
+    #[inline(always)]
+    fn split_size(&self, queue: &dyn QueueView<T>) -> Option<usize> {
+        self(queue)
+    }
+}
+
+/// How the queues of all parallel iterators of a `DynQueue` are split
+pub(crate) struct Splitting<'a, T> {
+    pub(crate) threshold: usize,
+    pub(crate) min_shard_size: usize,
+    /// split again during the iteration, if a queue grew to this length
+    pub(crate) resplit_threshold: Option<usize>,
+    /// `None` to decide by the number of rayon threads
+    pub(crate) single_thread: Option<bool>,
+    pub(crate) policy: Option<Box<dyn SplitPolicy<T> + 'a>>,
+}
+
+impl<'a, T> Splitting<'a, T> {
+    pub(crate) fn new() -> Self {
+        Splitting {
+            threshold: 2,
+            min_shard_size: 1,
+            resplit_threshold: None,
+            single_thread: None,
+            policy: None,
+        }
+    }
+
+    /// `true`, if splitting is pure overhead, because there is only one
+    /// thread to run the parallel iterators
+    #[inline(always)]
+    pub(crate) fn is_single_threaded(&self) -> bool {
+        self.single_thread
+            .unwrap_or_else(|| rayon::current_num_threads() == 1)
+    }
+
+    /// `true`, if a queue with `len` elements is too short to be split
+    #[inline(always)]
+    pub(crate) fn is_below_threshold(&self, len: usize) -> bool {
+        len < self.threshold.max(2)
+    }
+
+    /// The `size` to split off `queue`, if it shall be split
+    #[inline]
+    pub(crate) fn split_size(&self, queue: &dyn QueueView<T>) -> Option<usize> {
+        match &self.policy {
+            Some(policy) => policy.split_size(queue),
+            None => {
+                let len = queue.len();
+                let split = !self.is_below_threshold(len) && len / 2 >= self.min_shard_size;
+                split.then_some(len / 2)
+            }
+        }
+    }
+}
+
+/// Estimated cost to process an element, e.g. for the [`WeightedSplit`]
+pub trait Weight {
+    /// the estimated cost to process `self`
+    fn weight(&self) -> u64;
+}
+
+/// A [`SplitPolicy`], which splits a queue in two parts with the same
+/// total [`Weight`] instead of the same number of elements
+///
+/// The weights are balanced for queues, which are split at a position, like
+/// `Vec`, `VecDeque` or `SegQueue`.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::{IntoDynQueue as _, Weight, WeightedSplit};
+///
+/// struct Job(u64);
+///
+/// impl Weight for Job {
+///     fn weight(&self) -> u64 {
+///         self.0
+///     }
+/// }
+///
+/// let total = vec![Job(1000), Job(1), Job(1), Job(1)]
+///     .into_dyn_queue()
+///     .with_split_policy(WeightedSplit::new().min_weight(2))
+///     .into_par_iter()
+///     .map(|(_, job)| job.0)
+///     .sum::<u64>();
+///
+/// assert_eq!(total, 1003);
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WeightedSplit {
+    min_weight: u64,
+}
+
+impl WeightedSplit {
+    /// Create a policy, which splits every queue with two or more elements
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only split, if both parts have a total weight of at least `weight`.
+    pub fn min_weight(mut self, weight: u64) -> Self {
+        self.min_weight = weight;
+        self
+    }
+}
+
+impl<T: Weight> SplitPolicy<T> for WeightedSplit {
+    fn split_size(&self, queue: &dyn QueueView<T>) -> Option<usize> {
+        let mut weights = Vec::with_capacity(queue.len());
+        queue.for_each(&mut |v| weights.push(v.weight()));
+        if weights.len() < 2 {
+            return None;
+        }
+
+        let total = weights.iter().sum::<u64>();
+        let mut best = (0, 0u64);
+        let mut sum = 0;
+        for (i, w) in weights[..weights.len() - 1].iter().enumerate() {
+            sum += w;
+            if best.0 == 0 || sum.abs_diff(total - sum) < best.1.abs_diff(total - best.1) {
+                best = (i + 1, sum);
+            }
+        }
+
+        let (size, weight) = best;
+        (weight >= self.min_weight && total - weight >= self.min_weight).then_some(size)
+    }
+}
@@ -0,0 +1,357 @@
+//! Run statistics (with `feature = "stats"`)
+
+use crate::sync::{AtomicUsize, Ordering, RwLock};
+use crate::RecoverPoison as _;
+use std::ops::RangeInclusive;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Number of buckets of a [`Histogram`]: one for `0` and one for every
+/// power of two
+const BUCKETS: usize = usize::BITS as usize + 1;
+
+/// The bucket of `value`, `0` for `0` and `i` for `2^(i-1)..=2^i - 1`
+#[inline(always)]
+fn bucket(value: usize) -> usize {
+    (usize::BITS - value.leading_zeros()) as usize
+}
+
+/// The values in the bucket `i`
+fn bucket_range(i: usize) -> RangeInclusive<usize> {
+    match i {
+        0 => 0..=0,
+        i => 1 << (i - 1)..=usize::MAX >> (BUCKETS - 1 - i),
+    }
+}
+
+/// Histogram of values with power of two buckets, updated concurrently
+#[derive(Debug)]
+struct Buckets {
+    counts: [AtomicUsize; BUCKETS],
+    sum: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Buckets {
+            counts: std::array::from_fn(|_| AtomicUsize::new(0)),
+            sum: AtomicUsize::new(0),
+            max: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Buckets {
+    #[inline]
+    fn record(&self, value: usize) {
+        self.counts[bucket(value)].fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Histogram {
+        Histogram {
+            counts: self
+                .counts
+                .iter()
+                .map(|count| count.load(Ordering::Relaxed))
+                .collect(),
+            sum: self.sum.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counters of one rayon worker thread
+#[derive(Debug, Default)]
+struct Worker {
+    processed: AtomicUsize,
+    busy_nanos: AtomicU64,
+    enqueued: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    popped: AtomicUsize,
+    enqueued: AtomicUsize,
+    splits: AtomicUsize,
+    pending: AtomicUsize,
+    peak_pending: AtomicUsize,
+    /// length of the queues at split time
+    split_sizes: Buckets,
+    /// elements taken by every steal
+    steal_counts: Buckets,
+    /// indexed by `rayon::current_thread_index()`
+    workers: RwLock<Vec<Worker>>,
+}
+
+impl Counters {
+    /// Call `f` with the counters of the current rayon worker thread, if
+    /// called from one
+    #[inline]
+    fn worker<F: FnOnce(&Worker)>(&self, f: F) {
+        let index = match rayon::current_thread_index() {
+            Some(index) => index,
+            None => return,
+        };
+        if let Some(worker) = self.workers.read().recover().get(index) {
+            return f(worker);
+        }
+        let mut workers = self.workers.write().recover();
+        if workers.len() <= index {
+            workers.resize_with(index + 1, Worker::default);
+        }
+        f(&workers[index])
+    }
+
+    #[inline]
+    pub(crate) fn start(&self, pending: usize) {
+        self.pending.store(pending, Ordering::Relaxed);
+        self.peak_pending.fetch_max(pending, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn pop(&self) {
+        self.popped.fetch_add(1, Ordering::Relaxed);
+        self.pending.fetch_sub(1, Ordering::Relaxed);
+        self.worker(|worker| {
+            worker.processed.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Account for the time the current worker thread spent processing an
+    /// element
+    #[inline]
+    pub(crate) fn busy(&self, time: Duration) {
+        self.worker(|worker| {
+            worker
+                .busy_nanos
+                .fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+        });
+    }
+
+    #[inline]
+    pub(crate) fn enqueue(&self) {
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+        let pending = self.pending.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak_pending.fetch_max(pending, Ordering::Relaxed);
+        self.worker(|worker| {
+            worker.enqueued.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    #[inline]
+    pub(crate) fn split(&self, len: usize) {
+        self.splits.fetch_add(1, Ordering::Relaxed);
+        self.split_sizes.record(len);
+    }
+
+    #[inline]
+    pub(crate) fn steal(&self, count: usize) {
+        self.steal_counts.record(count);
+    }
+}
+
+/// Handle to the statistics of a `DynQueue` run
+///
+/// The counters are updated while the `DynQueue` is iterated and can be
+/// read at any time, e.g. after the run.
+///
+/// ```
+/// use rayon::iter::IntoParallelIterator as _;
+/// use rayon::iter::ParallelIterator as _;
+///
+/// use dynqueue::IntoDynQueue as _;
+///
+/// let queue = vec![1, 2, 3].into_dyn_queue();
+/// let stats = queue.stats_handle();
+///
+/// queue
+///     .into_par_iter()
+///     .for_each(|(handle, value)| {
+///         if value == 2 {
+///             handle.enqueue(4).unwrap()
+///         };
+///     });
+///
+/// assert_eq!(stats.popped(), 4);
+/// assert_eq!(stats.enqueued(), 1);
+/// assert!(stats.peak_pending() >= 3);
+/// ```
+#[derive(Clone, Debug)]
+pub struct StatsHandle(pub(crate) Arc<Counters>);
+
+impl StatsHandle {
+    /// Number of elements popped from the queue
+    pub fn popped(&self) -> usize {
+        self.0.popped.load(Ordering::Relaxed)
+    }
+
+    /// Number of elements enqueued while iterating
+    pub fn enqueued(&self) -> usize {
+        self.0.enqueued.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the queue was split for another parallel iterator
+    pub fn splits(&self) -> usize {
+        self.0.splits.load(Ordering::Relaxed)
+    }
+
+    /// Number of times a parallel iterator stole elements from the queue
+    /// of another one
+    pub fn steals(&self) -> usize {
+        self.0.steal_counts.snapshot().count()
+    }
+
+    /// Histogram of the number of elements in a queue, when it was split
+    ///
+    /// Use it to tune [`DynQueueBuilder::split_threshold`]: many splits of
+    /// small queues are overhead, few splits of large queues leave threads
+    /// idle.
+    ///
+    /// [`DynQueueBuilder::split_threshold`]: crate::DynQueueBuilder::split_threshold
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let queue = (0..1000).collect::<Vec<_>>().into_dyn_queue();
+    /// let stats = queue.stats_handle();
+    ///
+    /// queue.into_par_iter().for_each(|_| {});
+    ///
+    /// let sizes = stats.split_sizes();
+    /// assert_eq!(sizes.count(), stats.splits());
+    /// for (range, count) in sizes.buckets() {
+    ///     println!("{:?}: {}", range, count);
+    /// }
+    /// ```
+    pub fn split_sizes(&self) -> Histogram {
+        self.0.split_sizes.snapshot()
+    }
+
+    /// Histogram of the number of elements taken by every steal
+    pub fn steal_counts(&self) -> Histogram {
+        self.0.steal_counts.snapshot()
+    }
+
+    /// Highest number of elements pending in all queues at the same time
+    pub fn peak_pending(&self) -> usize {
+        self.0.peak_pending.load(Ordering::Relaxed)
+    }
+
+    /// Statistics of every rayon worker thread, indexed by
+    /// `rayon::current_thread_index()`
+    ///
+    /// A thread, which did not process or enqueue anything, has zero
+    /// counters or is missing at the end. Elements processed or enqueued
+    /// outside of a rayon thread pool, e.g. by the serial iterator, a
+    /// `DynQueueSender` or the thread starting the parallel iterator without
+    /// `ThreadPool::install`, are only counted in the totals.
+    ///
+    /// ```
+    /// use rayon::iter::IntoParallelIterator as _;
+    /// use rayon::iter::ParallelIterator as _;
+    ///
+    /// use dynqueue::IntoDynQueue as _;
+    ///
+    /// let pool = rayon::ThreadPoolBuilder::new()
+    ///     .num_threads(2)
+    ///     .build()
+    ///     .unwrap();
+    /// let queue = vec![1, 2, 3].into_dyn_queue();
+    /// let stats = queue.stats_handle();
+    ///
+    /// pool.install(|| {
+    ///     queue.into_par_iter().for_each(|(handle, value)| {
+    ///         if value == 2 {
+    ///             handle.enqueue(4).unwrap()
+    ///         };
+    ///     })
+    /// });
+    ///
+    /// let workers = stats.workers();
+    /// assert_eq!(workers.iter().map(|w| w.processed).sum::<usize>(), 4);
+    /// assert_eq!(workers.iter().map(|w| w.enqueued).sum::<usize>(), 1);
+    /// ```
+    pub fn workers(&self) -> Vec<WorkerStats> {
+        self.0
+            .workers
+            .read()
+            .recover()
+            .iter()
+            .map(|worker| WorkerStats {
+                processed: worker.processed.load(Ordering::Relaxed),
+                busy: Duration::from_nanos(worker.busy_nanos.load(Ordering::Relaxed)),
+                enqueued: worker.enqueued.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// Statistics of one rayon worker thread
+///
+/// Returned by [`StatsHandle::workers`]. Comparing the threads tells a
+/// scheduling imbalance, where some threads process few elements, from a
+/// cost imbalance, where some elements take much longer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// Number of elements popped by the thread
+    pub processed: usize,
+    /// Time the thread spent processing the elements
+    pub busy: Duration,
+    /// Number of elements enqueued by the thread
+    pub enqueued: usize,
+}
+
+/// Histogram with power of two buckets
+///
+/// Returned by [`StatsHandle::split_sizes`] and
+/// [`StatsHandle::steal_counts`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    counts: Vec<usize>,
+    sum: usize,
+    max: usize,
+}
+
+impl Histogram {
+    /// Number of recorded values
+    pub fn count(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    /// Sum of all recorded values
+    pub fn sum(&self) -> usize {
+        self.sum
+    }
+
+    /// Highest recorded value, `0` if nothing was recorded
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// Average of the recorded values, `None` if nothing was recorded
+    pub fn mean(&self) -> Option<f64> {
+        match self.count() {
+            0 => None,
+            count => Some(self.sum as f64 / count as f64),
+        }
+    }
+
+    /// The values of the non-empty buckets and the number of recorded
+    /// values in them, smallest values first
+    ///
+    /// The buckets are `0`, `1`, `2..=3`, `4..=7` and so on.
+    pub fn buckets(&self) -> impl Iterator<Item = (RangeInclusive<usize>, usize)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, count)| (bucket_range(i), *count))
+    }
+}
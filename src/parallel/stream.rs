@@ -0,0 +1,205 @@
+//! Async `Stream` based variant of the `DynQueue` (with `feature = "stream"`)
+//!
+//! A `DynStream` processes its elements with an async closure and yields the
+//! results as they complete. With the `DynStreamHandle` passed to the closure,
+//! new elements can be inserted in the `DynStream`, which is currently polled.
+//!
+//! ```
+//! use dynqueue::stream::DynStream;
+//! use futures::StreamExt as _;
+//!
+//! let mut result = futures::executor::block_on(
+//!     DynStream::new(vec![1, 2, 3], 4, |handle, value| async move {
+//!         if value == 2 {
+//!             handle.enqueue(4).unwrap();
+//!         }
+//!         value
+//!     })
+//!     .collect::<Vec<_>>(),
+//! );
+//! result.sort();
+//!
+//! assert_eq!(result, vec![1, 2, 3, 4]);
+//! ```
+
+use crate::sync::Mutex;
+use crate::{EnqueueError, RecoverPoison as _};
+use futures::stream::{FuturesUnordered, Stream, StreamExt as _};
+use futures::task::AtomicWaker;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+struct StreamState<T> {
+    elements: VecDeque<T>,
+    /// `true`, after the `DynStream` ended or was dropped
+    closed: bool,
+}
+
+struct DynStreamInner<T> {
+    queue: Mutex<StreamState<T>>,
+    waker: AtomicWaker,
+}
+
+impl<T> DynStreamInner<T> {
+    #[inline]
+    fn pop(&self) -> Option<T> {
+        self.queue.lock().recover().elements.pop_front()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.queue.lock().recover().elements.is_empty()
+    }
+
+    /// Close the queue, if it is empty, so later enqueues are refused.
+    ///
+    /// Returns `false`, if elements are still queued.
+    #[inline]
+    fn close_if_empty(&self) -> bool {
+        let mut queue = self.queue.lock().recover();
+        queue.closed = queue.elements.is_empty();
+        queue.closed
+    }
+
+    /// Close the queue and drop the queued elements
+    fn close(&self) {
+        let mut queue = self.queue.lock().recover();
+        queue.closed = true;
+        queue.elements.clear();
+    }
+}
+
+/// The `DynStreamHandle` passed to the async closure in addition to `T`
+pub struct DynStreamHandle<T>(Arc<DynStreamInner<T>>);
+
+impl<T> Clone for DynStreamHandle<T> {
+    fn clone(&self) -> Self {
+        DynStreamHandle(self.0.clone())
+    }
+}
+
+impl<T> DynStreamHandle<T> {
+    /// Enqueue `T` in the `DynStream<T>`, which is currently polled.
+    ///
+    /// Returns the element with [`EnqueueError::Closed`], if the stream
+    /// already ended or was dropped.
+    #[inline]
+    pub fn enqueue(&self, job: T) -> Result<(), EnqueueError<T>> {
+        {
+            let mut queue = self.0.queue.lock().recover();
+            if queue.closed {
+                return Err(EnqueueError::Closed(job));
+            }
+            queue.elements.push_back(job);
+        }
+        self.0.waker.wake();
+        Ok(())
+    }
+
+    /// Enqueue all `jobs` at once in the `DynStream<T>`, which is currently polled.
+    ///
+    /// Returns the untouched `jobs` with [`EnqueueError::Closed`], if the
+    /// stream already ended or was dropped.
+    #[inline]
+    pub fn enqueue_many<I: IntoIterator<Item = T>>(&self, jobs: I) -> Result<(), EnqueueError<I>> {
+        {
+            let mut queue = self.0.queue.lock().recover();
+            if queue.closed {
+                return Err(EnqueueError::Closed(jobs));
+            }
+            queue.elements.extend(jobs);
+        }
+        self.0.waker.wake();
+        Ok(())
+    }
+}
+
+/// A dynamically extendable `Stream`
+///
+/// Up to `limit` futures returned by the closure are polled concurrently.
+/// The stream ends, when no future is running anymore and the queue is empty.
+pub struct DynStream<T, F, Fut> {
+    inner: Arc<DynStreamInner<T>>,
+    running: FuturesUnordered<Fut>,
+    limit: usize,
+    f: F,
+}
+
+// `Unpin` for every `F` and `Fut`, which is sound, because no field is ever
+// structurally pinned. `inner` and `limit` are `Unpin`, `f` is only called
+// through `&mut` and the futures returned by it are pinned in their own
+// allocation by `FuturesUnordered`, which is `Unpin` for every `Fut`. So
+// moving a `DynStream` never moves a pinned value, even if `F` or `Fut` are
+// `!Unpin`.
+impl<T, F, Fut> Unpin for DynStream<T, F, Fut> {}
+
+impl<T, F, Fut> Drop for DynStream<T, F, Fut> {
+    fn drop(&mut self) {
+        self.inner.close();
+    }
+}
+
+impl<T, F, Fut> DynStream<T, F, Fut>
+where
+    F: FnMut(DynStreamHandle<T>, T) -> Fut,
+    Fut: Future,
+{
+    /// Create a new `DynStream` from the `initial` elements, processing up to
+    /// `limit` elements concurrently with `f`.
+    pub fn new<I: IntoIterator<Item = T>>(initial: I, limit: usize, f: F) -> Self {
+        DynStream {
+            inner: Arc::new(DynStreamInner {
+                queue: Mutex::new(StreamState {
+                    elements: initial.into_iter().collect(),
+                    closed: false,
+                }),
+                waker: AtomicWaker::new(),
+            }),
+            running: FuturesUnordered::new(),
+            limit: limit.max(1),
+            f,
+        }
+    }
+
+    /// A handle to enqueue elements from outside of the processing closure
+    pub fn handle(&self) -> DynStreamHandle<T> {
+        DynStreamHandle(self.inner.clone())
+    }
+}
+
+impl<T, F, Fut> Stream for DynStream<T, F, Fut>
+where
+    F: FnMut(DynStreamHandle<T>, T) -> Fut,
+    Fut: Future,
+{
+    type Item = Fut::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.inner.waker.register(cx.waker());
+
+        loop {
+            while this.running.len() < this.limit {
+                match this.inner.pop() {
+                    Some(v) => this
+                        .running
+                        .push((this.f)(DynStreamHandle(this.inner.clone()), v)),
+                    None => break,
+                }
+            }
+
+            match this.running.poll_next_unpin(cx) {
+                Poll::Ready(Some(ret)) => return Poll::Ready(Some(ret)),
+                Poll::Ready(None) if this.inner.close_if_empty() => return Poll::Ready(None),
+                Poll::Pending if this.running.len() >= this.limit || this.inner.is_empty() => {
+                    return Poll::Pending
+                }
+                // elements were enqueued while polling the running futures
+                _ => continue,
+            }
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Export through the `metrics` facade (with `feature = "metrics"`)
+//!
+//! Every `DynQueue` reports to the recorder installed with
+//! `metrics::set_global_recorder`, e.g. a Prometheus exporter, at the time
+//! the `DynQueue` was created:
+//!
+//! | name                        | type    | description                            |
+//! |-----------------------------|---------|----------------------------------------|
+//! | `dynqueue_pending`          | gauge   | elements pending in all queues         |
+//! | `dynqueue_processed_total`  | counter | elements popped to be processed        |
+//! | `dynqueue_enqueued_total`   | counter | elements enqueued while iterating      |
+//! | `dynqueue_splits_total`     | counter | splits of a queue for another thread   |
+//! | `dynqueue_steals_total`     | counter | elements stolen from another queue     |
+
+use crate::sync::{AtomicUsize, Ordering};
+use metrics::{counter, describe_counter, describe_gauge, gauge, Counter, Gauge};
+use std::sync::Once;
+
+const PENDING: &str = "dynqueue_pending";
+const PROCESSED: &str = "dynqueue_processed_total";
+const ENQUEUED: &str = "dynqueue_enqueued_total";
+const SPLITS: &str = "dynqueue_splits_total";
+const STEALS: &str = "dynqueue_steals_total";
+
+fn describe() {
+    static DESCRIBE: Once = Once::new();
+    DESCRIBE.call_once(|| {
+        describe_gauge!(PENDING, "Elements pending in the queues of all DynQueues");
+        describe_counter!(PROCESSED, "Elements popped to be processed");
+        describe_counter!(ENQUEUED, "Elements enqueued while iterating");
+        describe_counter!(SPLITS, "Splits of a queue for another thread");
+        describe_counter!(STEALS, "Elements stolen from the queue of another thread");
+    });
+}
+
+/// The registered metrics of a `DynQueue`
+pub(crate) struct Metrics {
+    pending_gauge: Gauge,
+    processed: Counter,
+    enqueued: Counter,
+    splits: Counter,
+    steals: Counter,
+    /// contribution of this `DynQueue` to the shared pending gauge
+    pending: AtomicUsize,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        describe();
+        Metrics {
+            pending_gauge: gauge!(PENDING),
+            processed: counter!(PROCESSED),
+            enqueued: counter!(ENQUEUED),
+            splits: counter!(SPLITS),
+            steals: counter!(STEALS),
+            pending: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    #[inline]
+    pub(crate) fn start(&self, pending: usize) {
+        let before = self.pending.swap(pending, Ordering::Relaxed);
+        self.pending_gauge.increment(pending as f64 - before as f64);
+    }
+
+    #[inline]
+    pub(crate) fn pop(&self) {
+        self.processed.increment(1);
+    }
+
+    #[inline]
+    pub(crate) fn drop(&self) {
+        if self
+            .pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |p| p.checked_sub(1))
+            .is_ok()
+        {
+            self.pending_gauge.decrement(1.0);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn enqueue(&self) {
+        self.enqueued.increment(1);
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        self.pending_gauge.increment(1.0);
+    }
+
+    #[inline]
+    pub(crate) fn split(&self) {
+        self.splits.increment(1);
+    }
+
+    #[inline]
+    pub(crate) fn steal(&self, count: usize) {
+        self.steals.increment(count as u64);
+    }
+}
+
+impl Drop for Metrics {
+    /// Remove the elements, which were never popped, e.g. after an early
+    /// stop, from the pending gauge
+    fn drop(&mut self) {
+        let pending = self.pending.load(Ordering::Relaxed);
+        if pending > 0 {
+            self.pending_gauge.decrement(pending as f64);
+        }
+    }
+}
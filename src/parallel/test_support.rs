@@ -0,0 +1,402 @@
+//! Conformance checks for custom `Queue` implementations (with `feature = "test-support"`)
+//!
+//! [`Conformance`] runs seeded random sequences of operations on a
+//! [`Queue<u64>`](Queue) and checks the properties the `DynQueue` relies
+//! on: no element is lost or duplicated across interleaved pushes, pops,
+//! splits and retains, and the length is consistent. A violation panics
+//! with the seed to reproduce it, so the checks fit in a `#[test]`.
+//!
+//! ```
+//! use dynqueue::test_support::Conformance;
+//! use std::sync::RwLock;
+//!
+//! Conformance::new(RwLock::new).with_steps(1000).run();
+//! ```
+
+use crate::{DynQueue, Meta, Order, Queue};
+use rayon::iter::IntoParallelIterator as _;
+use rayon::iter::ParallelIterator as _;
+use std::collections::HashSet;
+
+/// Small deterministic random number generator (xorshift64*)
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// A queue under test with the expected number of elements
+struct Model<U> {
+    queue: U,
+    len: usize,
+}
+
+/// Conformance suite for a `Queue<u64>` created by a constructor
+///
+/// `make` creates a queue holding the given elements, e.g.
+/// `|v| RwLock::new(v)` or `|v| v.into_iter().collect::<MyQueue<_>>()`.
+#[derive(Clone, Debug)]
+pub struct Conformance<F> {
+    make: F,
+    seed: u64,
+    steps: usize,
+    threads: usize,
+}
+
+impl<F, U> Conformance<F>
+where
+    F: Fn(Vec<u64>) -> U + Sync,
+    U: Queue<u64> + Send + Sync,
+{
+    /// Check the queues created by `make` with 10000 steps on 4 threads.
+    pub fn new(make: F) -> Self {
+        Conformance {
+            make,
+            seed: 0x5eed,
+            steps: 10_000,
+            threads: 4,
+        }
+    }
+
+    /// Seed of the random operations, e.g. to reproduce a failure
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Number of random operations per check and thread
+    pub fn with_steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// Number of threads of the concurrent checks
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Run all checks.
+    ///
+    /// Panics, if the queue violates a property.
+    pub fn run(&self) {
+        self.check_sequential();
+        self.check_concurrent();
+        self.check_dyn_queue();
+    }
+
+    fn fail(&self, check: &str, step: usize, msg: &str) -> ! {
+        panic!(
+            "{} check failed at step {} with seed {:#x}: {}",
+            check, step, self.seed, msg
+        )
+    }
+
+    /// Check a single thread of random operations against a model.
+    ///
+    /// Every popped element must have been pushed and not popped before,
+    /// `pop` must not return `None` and `len` must be exact, while the
+    /// queue holds elements. Splits must not lose or duplicate elements.
+    /// Queues, which [share](Queue::shares_queue) their storage after a
+    /// split, are only checked for lost and duplicated elements.
+    pub fn check_sequential(&self) {
+        const CHECK: &str = "sequential";
+        let mut rng = Rng::new(self.seed);
+        let mut live = HashSet::new();
+        let mut next_id = 0u64;
+        let mut shared = false;
+
+        let initial = (0..16).collect::<Vec<_>>();
+        next_id += initial.len() as u64;
+        live.extend(initial.iter().copied());
+        let mut queues = vec![Model {
+            len: initial.len(),
+            queue: (self.make)(initial),
+        }];
+
+        let popped = |live: &mut HashSet<u64>, step: usize, v: u64| {
+            if !live.remove(&v) {
+                self.fail(
+                    CHECK,
+                    step,
+                    &format!("popped {} twice or never pushed it", v),
+                );
+            }
+        };
+
+        for step in 0..self.steps {
+            let q = rng.below(queues.len());
+            match rng.below(100) {
+                0..=24 => {
+                    queues[q].queue.push(next_id);
+                    live.insert(next_id);
+                    queues[q].len += 1;
+                    next_id += 1;
+                }
+                25..=29 => {
+                    queues[q].queue.push_meta(Meta::default(), next_id);
+                    live.insert(next_id);
+                    queues[q].len += 1;
+                    next_id += 1;
+                }
+                30..=39 => {
+                    let n = rng.below(8) as u64;
+                    queues[q].queue.push_batch(next_id..next_id + n);
+                    live.extend(next_id..next_id + n);
+                    queues[q].len += n as usize;
+                    next_id += n;
+                }
+                40..=44 => match queues[q].queue.try_push(next_id) {
+                    Ok(()) => {
+                        live.insert(next_id);
+                        queues[q].len += 1;
+                        next_id += 1;
+                    }
+                    Err(v) if v != next_id => self.fail(
+                        CHECK,
+                        step,
+                        &format!("try_push returned {} for {}", v, next_id),
+                    ),
+                    Err(_) => {}
+                },
+                45..=69 => {
+                    let v = match rng.below(4) {
+                        0 => queues[q].queue.pop_ordered(Order::Fifo),
+                        1 => queues[q].queue.pop_ordered(Order::Lifo),
+                        2 => queues[q].queue.pop_meta().map(|(_, v)| v),
+                        _ => queues[q].queue.pop(),
+                    };
+                    match v {
+                        Some(v) => {
+                            popped(&mut live, step, v);
+                            queues[q].len = queues[q].len.saturating_sub(1);
+                        }
+                        None if !shared && queues[q].len > 0 => self.fail(
+                            CHECK,
+                            step,
+                            &format!("pop returned None with {} elements", queues[q].len),
+                        ),
+                        None => {}
+                    }
+                }
+                70..=79 => {
+                    let n = rng.below(8);
+                    let batch = queues[q].queue.pop_batch(n, None);
+                    if batch.len() > n {
+                        self.fail(
+                            CHECK,
+                            step,
+                            &format!("pop_batch({}) returned {} elements", n, batch.len()),
+                        );
+                    }
+                    for v in batch.iter() {
+                        popped(&mut live, step, *v);
+                    }
+                    queues[q].len = queues[q].len.saturating_sub(batch.len());
+                }
+                80..=84 => {
+                    let modulo = rng.below(3) as u64 + 2;
+                    let mut removed = Vec::new();
+                    queues[q].queue.retain(|v| {
+                        let keep = v % modulo != 0;
+                        if !keep {
+                            removed.push(*v);
+                        }
+                        keep
+                    });
+                    for v in removed.iter() {
+                        popped(&mut live, step, *v);
+                    }
+                    queues[q].len = queues[q].len.saturating_sub(removed.len());
+                }
+                _ if queues.len() < 8 => {
+                    let size = queues[q].queue.len() / 2;
+                    let other = queues[q].queue.split_off(size);
+                    if queues[q].queue.shares_queue(&other) {
+                        shared = true;
+                    }
+                    let len = other.len();
+                    if !shared && len > queues[q].len {
+                        self.fail(
+                            CHECK,
+                            step,
+                            &format!("split_off of {} elements returned {}", queues[q].len, len),
+                        );
+                    }
+                    queues[q].len = queues[q].len.saturating_sub(len);
+                    queues.push(Model { queue: other, len });
+                }
+                _ => {
+                    // merge the last queue back
+                    let last = queues.pop().unwrap();
+                    let q = rng.below(queues.len());
+                    while let Some(v) = last.queue.pop() {
+                        queues[q].queue.push(v);
+                        queues[q].len += 1;
+                    }
+                }
+            }
+
+            if shared {
+                continue;
+            }
+            for model in queues.iter() {
+                let len = model.queue.len();
+                if len != model.len {
+                    self.fail(
+                        CHECK,
+                        step,
+                        &format!("len is {}, expected {}", len, model.len),
+                    );
+                }
+            }
+        }
+
+        for model in queues.iter() {
+            while let Some(v) = model.queue.pop() {
+                popped(&mut live, self.steps, v);
+            }
+            if model.queue.len() != 0 {
+                self.fail(CHECK, self.steps, "len of a drained queue is not 0");
+            }
+        }
+        if !live.is_empty() {
+            self.fail(
+                CHECK,
+                self.steps,
+                &format!("{} elements were lost", live.len()),
+            );
+        }
+    }
+
+    /// Check, that concurrent pushes, pops and splits on several threads
+    /// neither lose nor duplicate elements.
+    pub fn check_concurrent(&self) {
+        const CHECK: &str = "concurrent";
+        let queue = (self.make)(Vec::new());
+
+        let results = std::thread::scope(|scope| {
+            let threads = (0..self.threads as u64)
+                .map(|t| {
+                    let queue = &queue;
+                    scope.spawn(move || {
+                        let mut rng = Rng::new(self.seed ^ (t + 1));
+                        let mut next_id = t << 40;
+                        let mut pushed = Vec::new();
+                        let mut popped = Vec::new();
+                        for _ in 0..self.steps {
+                            match rng.below(10) {
+                                0..=3 => {
+                                    queue.push(next_id);
+                                    pushed.push(next_id);
+                                    next_id += 1;
+                                }
+                                4 => {
+                                    queue.push_batch(next_id..next_id + 4);
+                                    pushed.extend(next_id..next_id + 4);
+                                    next_id += 4;
+                                }
+                                5..=7 => popped.extend(queue.pop()),
+                                8 => popped.extend(queue.pop_batch(4, None)),
+                                _ => {
+                                    let other = queue.split_off(queue.len() / 2);
+                                    other.push(next_id);
+                                    pushed.push(next_id);
+                                    next_id += 1;
+                                    if other.shares_queue(queue) {
+                                        popped.extend(other.pop());
+                                    } else {
+                                        while let Some(v) = other.pop() {
+                                            popped.push(v);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        (pushed, popped)
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        let mut pushed = Vec::new();
+        let mut popped = Vec::new();
+        for (p, q) in results {
+            pushed.extend(p);
+            popped.extend(q);
+        }
+        while let Some(v) = queue.pop() {
+            popped.push(v);
+        }
+        pushed.sort_unstable();
+        popped.sort_unstable();
+        if let Some(v) = popped.windows(2).find(|w| w[0] == w[1]) {
+            self.fail(CHECK, self.steps, &format!("{} was popped twice", v[0]));
+        }
+        if pushed != popped {
+            self.fail(
+                CHECK,
+                self.steps,
+                &format!(
+                    "{} elements were pushed, but {} popped",
+                    pushed.len(),
+                    popped.len()
+                ),
+            );
+        }
+    }
+
+    /// Check, that a `DynQueue` with the queue processes every element of
+    /// a tree of enqueued elements exactly once.
+    pub fn check_dyn_queue(&self) {
+        const CHECK: &str = "dyn_queue";
+        let len = self.steps as u64;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .unwrap();
+
+        let mut processed = pool.install(|| {
+            DynQueue::new((self.make)(vec![0]))
+                .into_par_iter()
+                .map(|(handle, v)| {
+                    for child in [2 * v + 1, 2 * v + 2].iter() {
+                        if *child < len {
+                            handle.enqueue(*child).unwrap();
+                        }
+                    }
+                    v
+                })
+                .collect::<Vec<_>>()
+        });
+        processed.sort_unstable();
+
+        if let Some(v) = processed.windows(2).find(|w| w[0] == w[1]) {
+            self.fail(CHECK, self.steps, &format!("{} was processed twice", v[0]));
+        }
+        if processed.len() as u64 != len {
+            self.fail(
+                CHECK,
+                self.steps,
+                &format!("{} of {} elements were processed", processed.len(), len),
+            );
+        }
+    }
+}
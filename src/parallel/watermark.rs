@@ -0,0 +1,59 @@
+//! Callbacks for the number of pending elements crossing watermarks
+
+use crate::sync::{AtomicBool, Ordering};
+
+type Callback<'a> = Box<dyn Fn(usize) + Send + Sync + 'a>;
+
+/// High and low watermark of the number of pending elements of all parallel
+/// iterators of a `DynQueue`
+///
+/// `on_high` is called, when the pending elements rise to `high`, and
+/// `on_low`, when they fall to `low` afterwards, so the callbacks alternate.
+pub(crate) struct Watermarks<'a> {
+    high: usize,
+    low: usize,
+    on_high: Callback<'a>,
+    on_low: Callback<'a>,
+    /// `true` between crossing the high and the low watermark
+    above: AtomicBool,
+}
+
+impl<'a> Watermarks<'a> {
+    pub(crate) fn new<H, L>(high: usize, low: usize, on_high: H, on_low: L) -> Self
+    where
+        H: Fn(usize) + Send + Sync + 'a,
+        L: Fn(usize) + Send + Sync + 'a,
+    {
+        assert!(low < high, "low watermark must be below the high watermark");
+        Watermarks {
+            high,
+            low,
+            on_high: Box::new(on_high),
+            on_low: Box::new(on_low),
+            above: AtomicBool::new(false),
+        }
+    }
+
+    /// Call the callback of a watermark, which `pending` crossed.
+    ///
+    /// Only the thread, which flips the state, calls the callback.
+    #[inline]
+    pub(crate) fn check(&self, pending: usize) {
+        if pending >= self.high {
+            if self
+                .above
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                (self.on_high)(pending)
+            }
+        } else if pending <= self.low
+            && self
+                .above
+                .compare_exchange(true, false, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            (self.on_low)(pending)
+        }
+    }
+}
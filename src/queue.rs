@@ -0,0 +1,390 @@
+//! The `Queue` abstraction, which only needs `alloc`
+//!
+//! Everything in this module builds without `std`, so the queues can be
+//! shared with `no_std` code, e.g. with the `spin` lock backends.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+
+/// Everything implementing `Queue` can be handled by DynQueue
+#[allow(clippy::len_without_is_empty)]
+pub trait Queue<T>
+where
+    Self: Sized,
+{
+    /// push an element in the queue
+    fn push(&self, v: T);
+
+    /// pop an element from the queue
+    fn pop(&self) -> Option<T>;
+
+    /// number of elements in the queue
+    fn len(&self) -> usize;
+
+    /// split off `size` elements
+    ///
+    /// Other threads may pop elements concurrently, so `size` can exceed
+    /// the current length of the queue.
+    fn split_off(&self, size: usize) -> Self;
+
+    /// try to push an element in the queue
+    ///
+    /// Returns the element, if the queue refuses it.
+    /// The default implementation always succeeds.
+    #[inline(always)]
+    fn try_push(&self, v: T) -> Result<(), T> {
+        self.push(v);
+        Ok(())
+    }
+
+    /// push all elements of `iter` in the queue
+    ///
+    /// The default implementation pushes the elements one by one.
+    #[inline]
+    fn push_batch<I: IntoIterator<Item = T>>(&self, iter: I) {
+        iter.into_iter().for_each(|v| self.push(v))
+    }
+
+    /// reserve capacity for at least `additional` more elements
+    ///
+    /// The default implementation does nothing.
+    #[inline(always)]
+    fn reserve(&self, _additional: usize) {}
+
+    /// retain only the elements, for which `f` returns `true`
+    ///
+    /// The default implementation pops all elements and pushes the retained
+    /// ones back, which preserves the order of FIFO queues.
+    fn retain<F: FnMut(&T) -> bool>(&self, mut f: F) {
+        let mut all = Vec::with_capacity(self.len());
+        while let Some(v) = self.pop() {
+            all.push(v);
+        }
+        self.push_batch(all.into_iter().filter(|v| f(v)))
+    }
+
+    /// retain only the elements, for which `f` returns `true`, with access
+    /// to their metadata
+    ///
+    /// The default implementation passes the default metadata.
+    #[inline(always)]
+    fn retain_meta<F: FnMut(&Meta, &T) -> bool>(&self, mut f: F) {
+        let meta = Meta::default();
+        self.retain(|v| f(&meta, v))
+    }
+
+    /// `true`, if `self` and `other` share the same storage, e.g. because
+    /// `split_off` returns a reference to the same queue.
+    ///
+    /// The default implementation returns `false`.
+    #[inline(always)]
+    fn shares_queue(&self, _other: &Self) -> bool {
+        false
+    }
+
+    /// `true`, if all elements are pushed and popped with the methods of
+    /// this queue, so the `DynQueue` can count them itself and check the
+    /// length without a lock.
+    ///
+    /// A queue, which is fed from the outside (e.g. a channel) or shares
+    /// its elements with other queues, must return `false`.
+    /// The default implementation returns `false`.
+    #[inline(always)]
+    fn is_exclusive(&self) -> bool {
+        false
+    }
+
+    /// `true`, if `pop_ordered(Order::Fifo)` is about as cheap as `pop`.
+    ///
+    /// For an [exclusive](Queue::is_exclusive) queue returning `false`,
+    /// e.g. a `Vec`, which has to shift all remaining elements, the
+    /// `DynQueue` takes the oldest elements in batches and pops them in
+    /// FIFO order itself.
+    /// The default implementation returns `true`.
+    #[inline(always)]
+    fn has_cheap_fifo(&self) -> bool {
+        true
+    }
+
+    /// `true`, if a panic while holding the lock of the queue poisoned it
+    ///
+    /// The `DynQueue` handles a poisoned queue with its `PoisonPolicy`.
+    /// The default implementation returns `false`.
+    #[inline(always)]
+    fn is_poisoned(&self) -> bool {
+        false
+    }
+
+    /// Clear the poison of the lock of the queue
+    ///
+    /// The default implementation does nothing.
+    #[inline(always)]
+    fn clear_poison(&self) {}
+
+    /// push an element with its metadata in the queue
+    ///
+    /// The default implementation drops the metadata.
+    #[inline(always)]
+    fn push_meta(&self, _meta: Meta, v: T) {
+        self.push(v)
+    }
+
+    /// try to push an element with its metadata in the queue
+    ///
+    /// The default implementation drops the metadata.
+    #[inline(always)]
+    fn try_push_meta(&self, _meta: Meta, v: T) -> Result<(), T> {
+        self.try_push(v)
+    }
+
+    /// push all elements of `iter` with the same metadata in the queue
+    ///
+    /// The default implementation drops the metadata.
+    #[inline(always)]
+    fn push_batch_meta<I: IntoIterator<Item = T>>(&self, _meta: Meta, iter: I) {
+        self.push_batch(iter)
+    }
+
+    /// pop an element with its metadata from the queue
+    ///
+    /// The default implementation returns the default metadata.
+    #[inline(always)]
+    fn pop_meta(&self) -> Option<(Meta, T)> {
+        self.pop().map(|v| (Meta::default(), v))
+    }
+
+    /// pop an element from the queue in the given `order`
+    ///
+    /// The default implementation ignores `order` and uses `pop`.
+    #[inline(always)]
+    fn pop_ordered(&self, _order: Order) -> Option<T> {
+        self.pop()
+    }
+
+    /// pop an element with its metadata from the queue in the given `order`
+    ///
+    /// The default implementation returns the default metadata.
+    #[inline(always)]
+    fn pop_meta_ordered(&self, order: Order) -> Option<(Meta, T)> {
+        self.pop_ordered(order).map(|v| (Meta::default(), v))
+    }
+
+    /// pop up to `n` elements from the queue in the given `order` or the
+    /// natural order of the queue
+    ///
+    /// The elements are returned in the order they were popped.
+    /// The default implementation pops the elements one by one.
+    fn pop_batch(&self, n: usize, order: Option<Order>) -> Vec<T> {
+        (0..n)
+            .map_while(|_| match order {
+                Some(order) => self.pop_ordered(order),
+                None => self.pop(),
+            })
+            .collect()
+    }
+
+    /// pop up to `n` elements with their metadata from the queue
+    ///
+    /// The default implementation returns the default metadata.
+    #[inline(always)]
+    fn pop_batch_meta(&self, n: usize, order: Option<Order>) -> Vec<(Meta, T)> {
+        self.pop_batch(n, order)
+            .into_iter()
+            .map(|v| (Meta::default(), v))
+            .collect()
+    }
+}
+
+/// A `Queue`, which can push elements on both ends
+pub trait DoubleEndedQueue<T>: Queue<T> {
+    /// push an element in the front of the queue
+    fn push_front(&self, v: T);
+
+    /// push an element with its metadata in the front of the queue
+    ///
+    /// The default implementation drops the metadata.
+    #[inline(always)]
+    fn push_front_meta(&self, _meta: Meta, v: T) {
+        self.push_front(v)
+    }
+}
+
+/// Order in which the elements are popped from the queue
+///
+/// Only the `Vec` and `VecDeque` based queues honor the order, all other
+/// queues pop their elements in their natural order.
+/// Without an explicit order, a `Vec` is popped LIFO and a `VecDeque` FIFO.
+/// A `Vec` popped FIFO hands out its oldest elements in batches, so every
+/// pop is amortized `O(1)`.
+///
+/// ```
+/// use dynqueue::{IntoDynQueue as _, Order};
+///
+/// let result = vec![1, 2, 3]
+///     .into_dyn_queue()
+///     .with_order(Order::Fifo)
+///     .into_iter()
+///     .map(|(handle, value)| {
+///         if value == 2 {
+///             handle.enqueue(4).unwrap()
+///         };
+///         value
+///     })
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(result, vec![1, 2, 3, 4]);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// first in, first out: the oldest element first (breadth-first)
+    Fifo,
+    /// last in, first out: the newest element first (depth-first)
+    Lifo,
+}
+
+/// Metadata of an element in a `Tracked` queue
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Meta {
+    pub(crate) depth: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) index: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) attempt: usize,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) id: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) parent: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) lane: Lane,
+}
+
+impl Meta {
+    /// Number of ancestors of the element.
+    ///
+    /// The initial elements have a depth of `0`.
+    #[inline(always)]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Position of the element in the order, in which all elements were
+    /// enqueued.
+    ///
+    /// Only counted for [`DynQueue::collect_ordered`](crate::DynQueue::collect_ordered), `0` otherwise.
+    #[inline(always)]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Number of times the element was requeued with
+    /// [`DynQueueHandle::requeue`](crate::DynQueueHandle::requeue).
+    ///
+    /// Counted for all queues, not only for `Tracked` queues.
+    #[inline(always)]
+    pub fn attempt(&self) -> usize {
+        self.attempt
+    }
+
+    /// Unique id of the element.
+    ///
+    /// Only assigned while a [`Lineage`](crate::Lineage) handle exists, `None` otherwise.
+    #[inline(always)]
+    pub fn id(&self) -> Option<usize> {
+        self.id
+    }
+
+    /// Id of the element, whose handle enqueued this element.
+    ///
+    /// `None` for the initial elements and without a [`Lineage`](crate::Lineage) handle.
+    #[inline(always)]
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    /// Lane of the element in a [`LaneQueue`](crate::LaneQueue).
+    ///
+    /// [`Lane::Normal`] for all other queues.
+    #[inline(always)]
+    pub fn lane(&self) -> Lane {
+        self.lane
+    }
+
+    #[inline(always)]
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn child(&self) -> Self {
+        Meta {
+            depth: self.depth + 1,
+            index: 0,
+            attempt: 0,
+            id: None,
+            parent: self.id,
+            lane: Lane::Normal,
+        }
+    }
+}
+
+/// Priority lane of an element in a [`LaneQueue`](crate::LaneQueue)
+///
+/// Enqueue in a lane with
+/// [`DynQueueHandle::enqueue_in`](crate::DynQueueHandle::enqueue_in).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Lane {
+    /// popped first
+    High,
+    /// the lane of all elements not enqueued in a specific lane
+    #[default]
+    Normal,
+    /// popped last
+    Low,
+}
+
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+impl Lane {
+    pub(crate) const ALL: [Lane; 3] = [Lane::High, Lane::Normal, Lane::Low];
+
+    #[inline(always)]
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The default metadata of an element in the lane
+    #[inline(always)]
+    pub(crate) fn meta(self) -> Meta {
+        Meta {
+            lane: self,
+            ..Meta::default()
+        }
+    }
+}
+
+/// Split off a heap keeping `size` elements in `heap`.
+///
+/// Deal the elements out alternately in the order of the heap array, which
+/// is about the priority order level by level, so both heaps keep a fair
+/// share of the highest priority elements without sorting them. The
+/// greatest element stays in `heap` and the second greatest goes to the
+/// split off heap.
+#[cfg_attr(not(any(feature = "std", feature = "lock_api")), allow(dead_code))]
+pub(crate) fn split_heap<T: Ord>(heap: &mut BinaryHeap<T>, size: usize) -> BinaryHeap<T> {
+    let len = heap.len();
+    let size = size.min(len);
+    let mut keep = Vec::with_capacity(size);
+    let mut other = Vec::with_capacity(len - size);
+
+    let mut all = core::mem::take(heap).into_vec();
+    if len > 2 && all[2] > all[1] {
+        all.swap(1, 2);
+    }
+    for (i, ele) in all.into_iter().enumerate() {
+        if keep.len() < size && (i % 2 == 0 || other.len() >= len - size) {
+            keep.push(ele);
+        } else {
+            other.push(ele);
+        }
+    }
+
+    *heap = BinaryHeap::from(keep);
+    BinaryHeap::from(other)
+}
@@ -0,0 +1,175 @@
+//! Synchronization primitives of the shared state
+//!
+//! Building with `RUSTFLAGS="--cfg loom"` replaces the atomics, the internal
+//! locks and the `Arc` and `Weak` of the queue and its handles with the
+//! `loom` versions, so the protocol can be model checked:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" LOOM_MAX_PREEMPTIONS=2 cargo test --release loom_
+//! ```
+//!
+//! The `Arc`s handed out to the user, e.g. of the shared context, and the
+//! locks of the `Queue` implementations are part of the public API and are
+//! always the `std` versions.
+
+#[cfg(loom)]
+pub(crate) use self::arc::{Arc, Weak};
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicBool, AtomicUsize};
+#[cfg(loom)]
+pub(crate) use loom::sync::{Condvar, Mutex, RwLock};
+#[cfg(loom)]
+pub(crate) use loom::thread::yield_now;
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicBool, AtomicUsize};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Arc, Condvar, Mutex, RwLock, Weak};
+#[cfg(not(loom))]
+pub(crate) use std::thread::yield_now;
+
+pub(crate) use std::sync::atomic::Ordering;
+
+#[cfg(loom)]
+mod arc {
+    //! `Arc` and `Weak` with the strong count in a `loom` atomic
+    //!
+    //! `loom` has no `Weak`, but the upgrade of a stale handle racing with
+    //! the end of the iteration is exactly what needs to be explored. The
+    //! memory itself is kept alive by a `std` `Arc` shared with the `Weak`s.
+
+    use loom::sync::atomic::{fence, AtomicUsize, Ordering};
+    use std::cell::UnsafeCell;
+    use std::fmt;
+    use std::mem::ManuallyDrop;
+    use std::ops::Deref;
+
+    struct Inner<T> {
+        strong: AtomicUsize,
+        value: UnsafeCell<ManuallyDrop<T>>,
+    }
+
+    pub(crate) struct Arc<T>(std::sync::Arc<Inner<T>>);
+
+    pub(crate) struct Weak<T>(std::sync::Arc<Inner<T>>);
+
+    // SAFETY: like `std::sync::Arc`, the value is only shared by reference
+    unsafe impl<T: Send + Sync> Send for Arc<T> {}
+    unsafe impl<T: Send + Sync> Sync for Arc<T> {}
+    unsafe impl<T: Send + Sync> Send for Weak<T> {}
+    unsafe impl<T: Send + Sync> Sync for Weak<T> {}
+
+    impl<T> Arc<T> {
+        pub(crate) fn new(value: T) -> Self {
+            Arc(std::sync::Arc::new(Inner {
+                strong: AtomicUsize::new(1),
+                value: UnsafeCell::new(ManuallyDrop::new(value)),
+            }))
+        }
+
+        pub(crate) fn downgrade(this: &Self) -> Weak<T> {
+            Weak(this.0.clone())
+        }
+
+        pub(crate) fn as_ptr(this: &Self) -> *const T {
+            &**this
+        }
+
+        pub(crate) fn ptr_eq(this: &Self, other: &Self) -> bool {
+            std::sync::Arc::ptr_eq(&this.0, &other.0)
+        }
+
+        pub(crate) fn get_mut(this: &mut Self) -> Option<&mut T> {
+            std::sync::Arc::get_mut(&mut this.0).map(|inner| &mut **inner.value.get_mut())
+        }
+
+        pub(crate) fn try_unwrap(this: Self) -> Result<T, Self> {
+            if this
+                .0
+                .strong
+                .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                return Err(this);
+            }
+            let this = ManuallyDrop::new(this);
+            // SAFETY: the strong count is zero, so neither an `Arc` nor an
+            // upgraded `Weak` can access the value anymore
+            unsafe {
+                let value = ManuallyDrop::take(&mut *this.0.value.get());
+                drop(std::ptr::read(&this.0));
+                Ok(value)
+            }
+        }
+    }
+
+    impl<T: Default> Default for Arc<T> {
+        fn default() -> Self {
+            Arc::new(T::default())
+        }
+    }
+
+    impl<T> Clone for Arc<T> {
+        fn clone(&self) -> Self {
+            self.0.strong.fetch_add(1, Ordering::Relaxed);
+            Arc(self.0.clone())
+        }
+    }
+
+    impl<T> Drop for Arc<T> {
+        fn drop(&mut self) {
+            if self.0.strong.fetch_sub(1, Ordering::Release) == 1 {
+                fence(Ordering::Acquire);
+                // SAFETY: this was the last `Arc`, see `try_unwrap`
+                unsafe { ManuallyDrop::drop(&mut *self.0.value.get()) }
+            }
+        }
+    }
+
+    impl<T> Deref for Arc<T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            // SAFETY: the value lives, as long as this `Arc` does
+            unsafe { &*self.0.value.get() }
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for Arc<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fmt::Debug::fmt(&**self, f)
+        }
+    }
+
+    impl<T> Weak<T> {
+        pub(crate) fn upgrade(&self) -> Option<Arc<T>> {
+            let mut strong = self.0.strong.load(Ordering::Relaxed);
+            while strong > 0 {
+                match self.0.strong.compare_exchange(
+                    strong,
+                    strong + 1,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(Arc(self.0.clone())),
+                    Err(current) => strong = current,
+                }
+            }
+            None
+        }
+
+        pub(crate) fn strong_count(&self) -> usize {
+            self.0.strong.load(Ordering::Acquire)
+        }
+
+        pub(crate) fn as_ptr(&self) -> *const T {
+            self.0.value.get().cast::<T>()
+        }
+    }
+
+    impl<T> Clone for Weak<T> {
+        fn clone(&self) -> Self {
+            Weak(self.0.clone())
+        }
+    }
+}
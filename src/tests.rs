@@ -1,4 +1,4 @@
-use crate::{DynQueueHandle, IntoDynQueue, Queue};
+use crate::{DynQueueHandle, EnqueueError, IntoDynQueue, Queue};
 use std::collections::VecDeque;
 
 const SLEEP_MS: u64 = 10;
@@ -8,17 +8,17 @@ fn handle_queue<U: Queue<u64>>(t: (DynQueueHandle<u64, U>, u64)) -> u64 {
     let (h, v) = t;
 
     if v % 2 == 0 {
-        h.enqueue(11);
+        h.enqueue(11).unwrap();
     }
     if v % 3 == 0 {
-        h.enqueue(11);
+        h.enqueue(11).unwrap();
     }
     if v % 4 == 0 {
-        h.enqueue(11);
+        h.enqueue(11).unwrap();
     }
     if v == 11 {
-        h.enqueue(5);
-        h.enqueue(17);
+        h.enqueue(5).unwrap();
+        h.enqueue(17).unwrap();
     }
     v
 }
@@ -47,7 +47,7 @@ fn dynqueue_iter_test_const_sleep() {
     use std::time::Duration;
     let expected = get_expected();
 
-    let med = expected.iter().sum::<u64>() / expected.iter().count() as u64;
+    let med = expected.iter().sum::<u64>() / expected.len() as u64;
 
     let jq = get_input().into_dyn_queue();
     let now = std::time::Instant::now();
@@ -65,7 +65,7 @@ fn dynqueue_iter_test_const_sleep() {
     assert_eq!(res, expected);
     eprintln!(
         "instead of = {}ms",
-        res.iter().count() * med as usize * SLEEP_MS as usize
+        res.len() * med as usize * SLEEP_MS as usize
     );
 }
 
@@ -78,7 +78,7 @@ fn dynqueue_iter_test_const_sleep_segqueue() {
     use std::time::Duration;
     let expected = get_expected();
 
-    let med = expected.iter().sum::<u64>() / expected.iter().count() as u64;
+    let med = expected.iter().sum::<u64>() / expected.len() as u64;
     let jq = SegQueue::new();
     get_input().drain(..).for_each(|ele| jq.push(ele));
 
@@ -98,7 +98,7 @@ fn dynqueue_iter_test_const_sleep_segqueue() {
     assert_eq!(res, expected);
     eprintln!(
         "instead of = {}ms",
-        res.iter().count() * med as usize * SLEEP_MS as usize
+        res.len() * med as usize * SLEEP_MS as usize
     );
 }
 
@@ -109,7 +109,7 @@ fn dynqueue_iter_test_const_sleep_vecdeque() {
     use std::time::Duration;
     let expected = get_expected();
 
-    let med = expected.iter().sum::<u64>() / expected.iter().count() as u64;
+    let med = expected.iter().sum::<u64>() / expected.len() as u64;
 
     let jq = VecDeque::from(get_input());
     let now = std::time::Instant::now();
@@ -128,7 +128,7 @@ fn dynqueue_iter_test_const_sleep_vecdeque() {
     assert_eq!(res, expected);
     eprintln!(
         "instead of = {}ms",
-        res.iter().count() * med as usize * SLEEP_MS as usize
+        res.len() * med as usize * SLEEP_MS as usize
     );
 }
 
@@ -181,7 +181,7 @@ fn dynqueue_iter_test_sleep_inv_v() {
     assert_eq!(res, get_expected());
     eprintln!(
         "instead of = {}ms",
-        (res.iter().count() as u64 * 22 - res.iter().sum::<u64>()) * SLEEP_MS
+        (res.len() as u64 * 22 - res.iter().sum::<u64>()) * SLEEP_MS
     );
 }
 
@@ -196,10 +196,4468 @@ fn par_iter_test() {
     let res = get_expected()
         .into_par_iter()
         .map(|v| {
-            std::thread::sleep(Duration::from_millis(SLEEP_MS * v as u64));
+            std::thread::sleep(Duration::from_millis(SLEEP_MS * v));
             v
         })
         .collect::<Vec<_>>();
     eprintln!("elapsed = {:#?}", now.elapsed());
     eprintln!("instead of = {}ms", res.iter().sum::<u64>() * SLEEP_MS);
 }
+
+#[test]
+fn dynqueue_iter_test_binaryheap() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::BinaryHeap;
+
+    let mut res = BinaryHeap::from(get_input())
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn binaryheap_split_off() {
+    use std::collections::BinaryHeap;
+    use std::sync::RwLock;
+
+    let heap = RwLock::new(BinaryHeap::from(get_input()));
+    let other = heap.split_off(10);
+
+    assert_eq!(heap.len(), 10);
+    assert_eq!(other.len(), 11);
+    assert_eq!(heap.pop(), Some(21));
+    assert_eq!(other.pop(), Some(20));
+}
+
+#[cfg(feature = "crossbeam-queue")]
+#[test]
+fn dynqueue_iter_test_arrayqueue() {
+    use crossbeam_queue::ArrayQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let jq = ArrayQueue::new(128);
+    get_input().drain(..).for_each(|ele| jq.push(ele).unwrap());
+
+    let mut res = jq
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[cfg(feature = "crossbeam-queue")]
+#[test]
+fn dynqueue_try_enqueue_arrayqueue() {
+    use crossbeam_queue::ArrayQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let jq = ArrayQueue::new(2);
+    jq.push(1u64).unwrap();
+
+    let rejected = jq
+        .into_dyn_queue()
+        .into_par_iter()
+        .filter_map(|(h, v)| {
+            if v == 1 {
+                h.try_enqueue(2).unwrap();
+                h.try_enqueue(3).unwrap();
+                h.try_enqueue(4).err()
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rejected, vec![EnqueueError::Full(4)]);
+}
+
+#[cfg(feature = "crossbeam-queue")]
+#[test]
+#[should_panic(expected = "ArrayQueue with a capacity of 2 is full")]
+fn dynqueue_enqueue_full_arrayqueue() {
+    use crossbeam_queue::ArrayQueue;
+
+    let jq = ArrayQueue::new(2);
+    jq.push(1u64).unwrap();
+
+    jq.into_dyn_queue().into_iter().for_each(|(h, v)| {
+        if v == 1 {
+            h.enqueue(2).unwrap();
+            h.enqueue(3).unwrap();
+            h.enqueue(4).unwrap();
+        }
+    });
+}
+
+#[cfg(feature = "crossbeam-queue")]
+#[test]
+fn dynqueue_split_arrayqueue() {
+    use crossbeam_queue::ArrayQueue;
+
+    let jq = ArrayQueue::new(4);
+    (1u64..=4).for_each(|v| jq.push(v).unwrap());
+    let queue = std::sync::Arc::new(jq);
+
+    let other = queue.split_off(2);
+    assert!(queue.shares_queue(&other));
+    assert_eq!(Queue::len(&queue), 4);
+    assert_eq!(Queue::len(&other), 4);
+}
+
+#[test]
+fn dynqueue_enqueue_many() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = vec![3u64]
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(|(h, v)| {
+            if v > 0 {
+                h.enqueue_many((0..v).map(|_| v - 1)).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 2, 2, 2, 3]);
+}
+
+#[test]
+fn dynqueue_with_dedup() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = vec![0u64, 0, 1]
+        .into_dyn_queue()
+        .with_dedup(|v| *v)
+        .into_par_iter()
+        .map(|(h, v)| {
+            h.enqueue((v + 1) % 100).unwrap();
+            h.enqueue_many(vec![(v * 2) % 100, (v * 3) % 100]).unwrap();
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn dynqueue_serial_iter() {
+    let res = get_input()
+        .into_dyn_queue()
+        .into_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+
+    let mut sorted = res.clone();
+    sorted.sort();
+    assert_eq!(sorted, get_expected());
+
+    // the serial iterator is deterministic
+    let again = get_input()
+        .into_dyn_queue()
+        .into_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    assert_eq!(res, again);
+}
+
+#[cfg(feature = "stream")]
+#[test]
+fn dynstream_test() {
+    use crate::stream::DynStream;
+    use crate::EnqueueError;
+    use futures::StreamExt as _;
+
+    let mut res = futures::executor::block_on(
+        DynStream::new(get_input(), 4, |h, v| async move {
+            futures::future::ready(()).await;
+            if v % 2 == 0 {
+                h.enqueue(11).unwrap();
+            }
+            if v % 3 == 0 {
+                h.enqueue(11).unwrap();
+            }
+            if v % 4 == 0 {
+                h.enqueue(11).unwrap();
+            }
+            if v == 11 {
+                h.enqueue_many(vec![5, 17]).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>(),
+    );
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // a handle used after the stream ended gets its element back
+    let stream = DynStream::new(vec![1], 1, |_, v| async move { v });
+    let handle = stream.handle();
+    assert_eq!(futures::executor::block_on(stream.collect::<Vec<_>>()), [1]);
+    assert_eq!(handle.enqueue(2), Err(EnqueueError::Closed(2)));
+    assert_eq!(
+        handle.enqueue_many(vec![3]),
+        Err(EnqueueError::Closed(vec![3]))
+    );
+}
+
+#[test]
+fn dynqueue_cancel() {
+    use crate::CancelToken;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let token = CancelToken::new();
+    let canceller = token.clone();
+
+    // without cancellation this would never end
+    let res = vec![0u64; 4]
+        .into_dyn_queue()
+        .with_cancel(token)
+        .into_par_iter()
+        .map(|(h, v)| {
+            if v == 1000 {
+                canceller.cancel();
+            }
+            h.enqueue(v + 1).unwrap();
+            v
+        })
+        .collect::<Vec<_>>();
+    assert!(res.contains(&1000));
+    assert!(canceller.is_cancelled());
+}
+
+#[test]
+fn dynqueue_tracked_max_depth() {
+    use crate::Tracked;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::VecDeque;
+
+    // binary tree, nodes are numbered in heap order
+    let res = Tracked::from(VecDeque::from(vec![1u64]))
+        .into_dyn_queue()
+        .with_max_depth(4)
+        .into_par_iter()
+        .map(|(h, v)| {
+            assert_eq!(h.depth() as u32, 63 - v.leading_zeros());
+            h.enqueue_many(vec![2 * v, 2 * v + 1]).unwrap();
+            h.depth()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(res.len(), 31);
+    assert_eq!(res.iter().filter(|d| **d == 4).count(), 16);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn dynqueue_stats() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let jq = get_input().into_dyn_queue();
+    let stats = jq.stats_handle();
+
+    let res = jq.into_par_iter().map(handle_queue).collect::<Vec<_>>();
+    assert_eq!(stats.popped(), res.len());
+    assert_eq!(stats.enqueued(), res.len() - get_input().len());
+    assert!(stats.peak_pending() >= get_input().len());
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn dynqueue_stats_workers() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let jq = get_input().into_dyn_queue();
+    let stats = jq.stats_handle();
+
+    let res = pool.install(|| {
+        jq.into_par_iter()
+            .map(|(h, v)| {
+                std::thread::sleep(Duration::from_millis(1));
+                handle_queue((h, v))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let workers = stats.workers();
+    assert!(!workers.is_empty() && workers.len() <= 4);
+    assert_eq!(
+        workers.iter().map(|w| w.processed).sum::<usize>(),
+        res.len()
+    );
+    assert_eq!(
+        workers.iter().map(|w| w.enqueued).sum::<usize>(),
+        stats.enqueued()
+    );
+    let busy = workers.iter().map(|w| w.busy).sum::<Duration>();
+    assert!(busy >= Duration::from_millis(res.len() as u64));
+    assert!(workers
+        .iter()
+        .all(|w| w.processed > 0 || w.busy == Duration::ZERO));
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn dynqueue_stats_histograms() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let jq = (0..1000u64).collect::<Vec<_>>().into_dyn_queue();
+    let stats = jq.stats_handle();
+
+    pool.install(|| {
+        jq.into_par_iter().for_each(|(h, v)| {
+            if v < 10 {
+                h.enqueue_many(vec![v + 1; 2]).unwrap();
+            }
+        })
+    });
+
+    let sizes = stats.split_sizes();
+    assert!(stats.splits() > 0);
+    assert_eq!(sizes.count(), stats.splits());
+    assert!(sizes.max() <= 1000 + 2046);
+    assert!(sizes.mean().is_some_and(|mean| mean >= 1.0));
+    let mut last = None;
+    for (range, count) in sizes.buckets() {
+        assert!(count > 0);
+        assert!(range.start().is_power_of_two());
+        assert_eq!(*range.end(), range.start() * 2 - 1);
+        assert!(last.is_none_or(|last| last < *range.start()));
+        last = Some(*range.end());
+    }
+    assert!(sizes.max() <= last.unwrap());
+
+    let steals = stats.steal_counts();
+    assert_eq!(steals.count(), stats.steals());
+    assert_eq!(
+        steals.buckets().map(|(_, count)| count).sum::<usize>(),
+        stats.steals()
+    );
+}
+
+#[test]
+fn dynqueue_work_stealing() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // only the element `0` enqueues more work, all other shards run dry
+    let threads = pool.install(|| {
+        vec![0u64, 1, 2, 3]
+            .into_dyn_queue()
+            .into_par_iter()
+            .filter_map(|(h, v)| {
+                if v == 0 {
+                    h.enqueue_many(100..200).unwrap();
+                }
+                if v >= 100 {
+                    std::thread::sleep(Duration::from_millis(1));
+                    rayon::current_thread_index()
+                } else {
+                    None
+                }
+            })
+            .collect::<HashSet<_>>()
+    });
+    assert!(threads.len() > 1);
+}
+
+#[test]
+fn dynqueue_builder() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::VecDeque;
+
+    let mut res = DynQueue::builder()
+        .split_threshold(8)
+        .min_shard_size(4)
+        .build(VecDeque::from(get_input()))
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let rejected = DynQueue::builder()
+        .capacity_limit(2)
+        .build(vec![0u64])
+        .into_iter()
+        .flat_map(|(h, v)| {
+            if v == 0 {
+                (1..5).filter_map(|i| h.try_enqueue(i).err()).collect()
+            } else {
+                vec![]
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rejected, vec![EnqueueError::Full(3), EnqueueError::Full(4)]);
+
+    // the `with_*` methods add to the configuration of the builder and only
+    // run the new filters over the initial elements
+    let mut res = DynQueue::builder()
+        .dedup(|v: &u64| *v)
+        .pop_batch_size(2)
+        .build(vec![1u64, 1, 2, 3])
+        .with_filter(|v| *v != 3)
+        .with_order(crate::Order::Fifo)
+        .into_iter()
+        .map(|(h, v)| {
+            h.enqueue(v).unwrap();
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 2]);
+}
+
+#[test]
+fn dynqueue_order() {
+    use crate::{DynQueue, Order, Tracked};
+    use std::collections::VecDeque;
+
+    fn expand<U: Queue<u64>>((h, v): (DynQueueHandle<u64, U>, u64)) -> u64 {
+        if v < 3 {
+            h.enqueue_many(vec![v * 10 + 10, v * 10 + 20]).unwrap();
+        }
+        v
+    }
+
+    let lifo = vec![1, 2]
+        .into_dyn_queue()
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(lifo, vec![2, 40, 30, 1, 30, 20]);
+
+    let fifo = vec![1, 2]
+        .into_dyn_queue()
+        .with_order(Order::Fifo)
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(fifo, vec![1, 2, 20, 30, 30, 40]);
+
+    let lifo = DynQueue::builder()
+        .order(Order::Lifo)
+        .build(VecDeque::from(vec![1, 2]))
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(lifo, vec![2, 40, 30, 1, 30, 20]);
+
+    let fifo = Tracked::from(vec![1, 2])
+        .into_dyn_queue()
+        .with_order(Order::Fifo)
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(fifo, vec![1, 2, 20, 30, 30, 40]);
+}
+
+#[cfg(feature = "parking_lot")]
+#[test]
+fn dynqueue_iter_test_parking_lot() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::{BinaryHeap, VecDeque};
+
+    let mut res = parking_lot::Mutex::new(get_input())
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = parking_lot::RwLock::new(VecDeque::from(get_input()))
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = parking_lot::RwLock::new(BinaryHeap::from(get_input()))
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[cfg(feature = "spin")]
+#[test]
+fn dynqueue_iter_test_spin() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::{BinaryHeap, VecDeque};
+
+    // push, pop and split of the generic `lock_api` queues
+    let queue = spin::lock_api::Mutex::new(vec![1u64, 2, 3, 4]);
+    queue.push(5);
+    assert_eq!(queue.pop(), Some(5));
+    let other = queue.split_off(2);
+    assert_eq!(other.into_inner(), vec![3, 4]);
+    assert_eq!(queue.into_inner(), vec![1, 2]);
+
+    let queue = spin::lock_api::RwLock::new(VecDeque::from(vec![1u64, 2, 3, 4]));
+    queue.push(5);
+    assert_eq!(queue.pop(), Some(1));
+    let other = queue.split_off(2);
+    assert_eq!(Queue::len(&queue) + Queue::len(&other), 4);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = pool.install(|| {
+        spin::lock_api::Mutex::new(get_input())
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = pool.install(|| {
+        spin::lock_api::RwLock::new(VecDeque::from(get_input()))
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = pool.install(|| {
+        spin::lock_api::RwLock::new(BinaryHeap::from(get_input()))
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[cfg(feature = "crossbeam-deque")]
+#[test]
+fn dynqueue_iter_test_deque() {
+    use crate::Deque;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = pool.install(|| {
+        Deque::from(get_input())
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let deque = Deque::new_fifo();
+    deque.push_batch(0..10);
+    let other = deque.split_off(4);
+    assert_eq!(deque.len(), 6);
+    assert_eq!(other.len(), 4);
+    assert_eq!(deque.pop(), Some(4));
+    deque.retain(|v| *v > 8);
+    assert_eq!(deque.len(), 1);
+    // an empty deque steals from its peers
+    assert_eq!(deque.pop(), Some(9));
+    assert_eq!(deque.pop(), Some(0));
+}
+
+#[test]
+fn dynqueue_stale_handle() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let handles = get_input()
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(|(h, _)| h)
+        .collect::<Vec<_>>();
+    assert_eq!(handles.len(), get_input().len());
+
+    for h in handles {
+        assert!(h.is_closed());
+        assert_eq!(h.enqueue(1), Err(EnqueueError::Closed(1)));
+        assert_eq!(h.try_enqueue(2), Err(EnqueueError::Closed(2)));
+        assert_eq!(h.enqueue_many(vec![3]), Err(EnqueueError::Closed(vec![3])));
+    }
+
+    let mut iter = vec![1u64].into_dyn_queue().into_iter();
+    let (h, _) = iter.next().unwrap();
+    assert!(!h.is_closed());
+    h.enqueue(2).unwrap();
+    assert_eq!(iter.next().map(|(_, v)| v), Some(2));
+    assert!(iter.next().is_none());
+    assert_eq!(h.enqueue(3), Err(EnqueueError::Closed(3)));
+}
+
+#[test]
+fn dynqueue_poison_recovery() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::RwLock;
+
+    let queue = RwLock::new(vec![1u64]);
+    let _ = catch_unwind(AssertUnwindSafe(|| {
+        queue.push_batch((2..4).map(|v| if v == 3 { panic!("work item") } else { v }))
+    }));
+    assert!(queue.is_poisoned());
+
+    let mut res = queue
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(h, v)| {
+            if v == 2 {
+                // the panic leaves the queue poisoned again
+                let _ = catch_unwind(AssertUnwindSafe(|| {
+                    h.enqueue_many((4..6).map(|v| if v == 5 { panic!("work item") } else { v }))
+                }));
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 2, 4]);
+}
+
+#[test]
+fn dynqueue_poison_policy() {
+    use crate::{DynQueue, PoisonPolicy, QueuePoisoned};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::RwLock;
+
+    let run = |policy| {
+        let queue = RwLock::new(vec![1u64]);
+        let _ = catch_unwind(AssertUnwindSafe(|| {
+            queue.push_batch((2..4).map(|v| if v == 3 { panic!("work item") } else { v }))
+        }));
+        let mut res = DynQueue::builder()
+            .poison_policy(policy)
+            .build(queue)
+            .into_iter()
+            .map(|(h, v)| {
+                if v == 1 {
+                    h.enqueue(5).unwrap();
+                }
+                v
+            })
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    };
+
+    assert_eq!(run(PoisonPolicy::Ignore), vec![1, 2, 5]);
+    // only the element popped from the poisoned queue is dropped
+    assert_eq!(run(PoisonPolicy::Skip), vec![1, 5]);
+    let err = catch_unwind(AssertUnwindSafe(|| run(PoisonPolicy::Propagate))).unwrap_err();
+    assert!(err.is::<QueuePoisoned>());
+}
+
+#[test]
+fn dynqueue_pop_batch() {
+    use crate::{DynQueue, Order};
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::RwLock;
+
+    let queue = RwLock::new(vec![1, 2, 3, 4, 5]);
+    assert_eq!(queue.pop_batch(2, None), vec![5, 4]);
+    assert_eq!(queue.pop_batch(2, Some(Order::Fifo)), vec![1, 2]);
+    assert_eq!(queue.pop_batch(2, None), vec![3]);
+
+    let queue = RwLock::new(VecDeque::from(vec![1, 2, 3, 4, 5]));
+    assert_eq!(queue.pop_batch(2, None), vec![1, 2]);
+    assert_eq!(queue.pop_batch(2, Some(Order::Lifo)), vec![5, 4]);
+    assert_eq!(queue.pop_batch(2, None), vec![3]);
+
+    let mut res = DynQueue::builder()
+        .pop_batch_size(4)
+        .build(get_input())
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let found = get_input()
+        .into_dyn_queue()
+        .with_pop_batch_size(8)
+        .into_par_iter()
+        .map(handle_queue)
+        .find_any(|v| *v == 17);
+    assert_eq!(found, Some(17));
+}
+
+#[test]
+fn dynqueue_enqueue_buffer() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = DynQueue::builder()
+        .enqueue_buffer_size(2)
+        .build(get_input())
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut iter = vec![0u64]
+        .into_dyn_queue()
+        .with_enqueue_buffer_size(3)
+        .into_iter();
+    let (h, _) = iter.next().unwrap();
+    h.enqueue(1).unwrap();
+    h.enqueue(2).unwrap();
+    // still buffered
+    assert_eq!(h.inner.upgrade().unwrap().queue.len(), 0);
+    h.enqueue(3).unwrap();
+    assert_eq!(h.inner.upgrade().unwrap().queue.len(), 3);
+    h.enqueue(4).unwrap();
+    h.flush();
+    assert_eq!(h.inner.upgrade().unwrap().queue.len(), 4);
+    h.enqueue(5).unwrap();
+    drop(h);
+
+    let mut rest = iter.map(|(_, v)| v).collect::<Vec<_>>();
+    rest.sort();
+    assert_eq!(rest, vec![1, 2, 3, 4, 5]);
+
+    // handles outliving their element do not hold back the buffered elements
+    let mut res = vec![0u64]
+        .into_dyn_queue()
+        .with_enqueue_buffer_size(4)
+        .into_par_iter()
+        .map(|(h, v)| {
+            if v < 5 {
+                h.enqueue(v + 1).unwrap();
+            }
+            (h, v)
+        })
+        .collect::<Vec<_>>();
+    res.sort_by_key(|(_, v)| *v);
+    assert_eq!(
+        res.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+        vec![0, 1, 2, 3, 4, 5]
+    );
+    assert_eq!(res[0].0.enqueue(6), Err(crate::EnqueueError::Closed(6)));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn dynqueue_checkpoint() {
+    use crate::{Checkpoint, DynQueue, Tracked};
+    use std::collections::VecDeque;
+    use std::sync::RwLock;
+
+    fn expand<U: Queue<u64>>(h: &DynQueueHandle<u64, U>, v: u64) -> u64 {
+        if v < 100 {
+            h.enqueue_many(vec![v * 10 + 1, v * 10 + 2]).unwrap();
+        }
+        v
+    }
+
+    let mut full = vec![1u64]
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(h, v)| expand(&h, v))
+        .collect::<Vec<_>>();
+    full.sort();
+
+    let mut json = None;
+    let mut res = vec![1u64]
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(h, v)| {
+            expand(&h, v);
+            if v == 12 {
+                json = Some(serde_json::to_string(&h.checkpoint().unwrap()).unwrap());
+                h.cancel();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+
+    let checkpoint: Checkpoint<u64> = serde_json::from_str(&json.unwrap()).unwrap();
+    assert!(!checkpoint.is_empty());
+    let queue: DynQueue<_, RwLock<VecDeque<_>>> = checkpoint.into_dyn_queue();
+    res.extend(queue.into_iter().map(|(h, v)| expand(&h, v)));
+    res.sort();
+    assert_eq!(res, full);
+
+    // the metadata survives a checkpoint
+    let mut iter = Tracked::from(vec![1u64]).into_dyn_queue().into_iter();
+    let (h, _) = iter.next().unwrap();
+    h.enqueue(2).unwrap();
+    let checkpoint = h.checkpoint().unwrap();
+    drop(h);
+    drop(iter);
+    assert_eq!(checkpoint.len(), 1);
+    let queue: DynQueue<_, Tracked<RwLock<Vec<_>>>> = checkpoint.into_dyn_queue();
+    let depths = queue
+        .into_iter()
+        .map(|(h, v)| (v, h.depth()))
+        .collect::<Vec<_>>();
+    assert_eq!(depths, vec![(2, 1)]);
+}
+
+#[test]
+fn dynqueue_progress() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::Mutex;
+
+    let reports = Mutex::new(Vec::new());
+    let res = DynQueue::builder()
+        .on_progress(5, |processed, pending| {
+            reports.lock().unwrap().push((processed, pending))
+        })
+        .build(get_input())
+        .into_par_iter()
+        .map(handle_queue)
+        .count();
+
+    let mut reports = reports.into_inner().unwrap();
+    reports.sort();
+    assert_eq!(reports.len(), res / 5);
+    for (i, (processed, pending)) in reports.into_iter().enumerate() {
+        assert_eq!(processed, (i + 1) * 5);
+        assert!(pending <= res - processed);
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn dynqueue_tracing() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    #[derive(Default)]
+    struct CountEvents(Arc<AtomicUsize>);
+
+    impl Subscriber for CountEvents {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let subscriber = CountEvents::default();
+    let events = subscriber.0.clone();
+
+    let res = tracing::subscriber::with_default(subscriber, || {
+        get_input()
+            .into_dyn_queue()
+            .into_iter()
+            .map(handle_queue)
+            .count()
+    });
+
+    // every pop, every enqueue and the exhausted queue
+    let enqueued = res - get_input().len();
+    assert_eq!(events.load(Ordering::Relaxed), res + enqueued + 1);
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn dynqueue_log() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::Mutex;
+
+    struct Records(Mutex<Vec<String>>);
+
+    impl log::Log for Records {
+        fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+            metadata.target() == "dynqueue"
+        }
+        fn log(&self, record: &log::Record<'_>) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+        fn flush(&self) {}
+    }
+
+    static RECORDS: Records = Records(Mutex::new(Vec::new()));
+    log::set_logger(&RECORDS).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+    let res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .count()
+    });
+    assert_eq!(res, get_expected().len());
+
+    let records = RECORDS.0.lock().unwrap();
+    let split = records.iter().find(|r| r.starts_with("split:")).unwrap();
+    assert!(split.contains(" shard=0x"));
+    assert!(split.contains(" size="));
+    assert!(records.iter().any(|r| r.starts_with("shard exhausted:")));
+    // trace records are filtered out
+    assert!(!records.iter().any(|r| r.starts_with("pop:")));
+}
+
+#[test]
+fn dynqueue_try_process() {
+    use crate::CancelToken;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every element enqueues more elements, only the error ends the run
+    let token = CancelToken::new();
+    let res = pool.install(|| {
+        vec![0u64, 0, 0, 0]
+            .into_dyn_queue()
+            .with_cancel(token.clone())
+            .try_process(|h, v| {
+                if v == 50 {
+                    return Err(v);
+                }
+                h.enqueue_many(vec![v + 1, v + 1]).unwrap();
+                Ok(())
+            })
+    });
+    assert_eq!(res, Err(50));
+    assert!(token.is_cancelled());
+
+    let ok: Result<(), ()> = get_input().into_dyn_queue().try_process(|h, v| {
+        handle_queue((h, v));
+        Ok(())
+    });
+    assert_eq!(ok, Ok(()));
+}
+
+#[test]
+fn dynqueue_deadline() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::{Duration, Instant};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every element enqueues two more elements, only the deadline ends the run
+    let queue = DynQueue::builder()
+        .pop_batch_size(4)
+        .timeout(Duration::from_millis(50))
+        .build(vec![0u64]);
+    let leftovers = queue.leftovers();
+    let start = Instant::now();
+
+    let processed = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|(h, v)| h.enqueue_many(vec![v + 1, v + 1]).unwrap())
+            .count()
+    });
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(leftovers.len(), processed + 1);
+
+    // without interest in the leftovers, nothing is collected
+    let queue = vec![0u64].into_dyn_queue().with_deadline(Instant::now());
+    let leftovers = queue.leftovers();
+    drop(leftovers);
+    assert_eq!(queue.into_par_iter().count(), 0);
+}
+
+#[test]
+fn dynqueue_max_items() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every element enqueues two more elements, only the limit ends the run
+    let queue = DynQueue::builder()
+        .max_items(100)
+        .pop_batch_size(3)
+        .build(vec![0u64]);
+    let leftovers = queue.leftovers();
+
+    let processed = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|(h, v)| h.enqueue_many(vec![v + 1, v + 1]).unwrap())
+            .count()
+    });
+    assert_eq!(processed, 100);
+    assert_eq!(leftovers.len(), 101);
+
+    let queue = vec![0u64].into_dyn_queue().with_max_items(10);
+    let leftovers = queue.leftovers();
+    let res = queue
+        .into_iter()
+        .map(|(h, v)| {
+            h.enqueue(v + 1).unwrap();
+            v
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(res, (0..10).collect::<Vec<_>>());
+    assert_eq!(leftovers.take(), vec![10]);
+}
+
+#[test]
+fn dynqueue_enqueue_front() {
+    use crate::{DynQueue, Order, Tracked};
+
+    fn expand<U: crate::DoubleEndedQueue<u64>>((h, v): (DynQueueHandle<u64, U>, u64)) -> u64 {
+        if v < 3 {
+            h.enqueue(v * 10 + 10).unwrap();
+            h.enqueue_front(v * 10 + 20).unwrap();
+        }
+        v
+    }
+
+    let res = VecDeque::from(vec![1, 2])
+        .into_dyn_queue()
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(res, vec![1, 30, 2, 40, 20, 30]);
+
+    let res = DynQueue::builder()
+        .order(Order::Lifo)
+        .build(VecDeque::from(vec![1, 2]))
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(res, vec![2, 40, 30, 1, 30, 20]);
+
+    let res = Tracked::from(VecDeque::from(vec![1, 2]))
+        .into_dyn_queue()
+        .into_iter()
+        .map(expand)
+        .collect::<Vec<_>>();
+    assert_eq!(res, vec![1, 30, 2, 40, 20, 30]);
+}
+
+#[test]
+fn dynqueue_lanes() {
+    use crate::{Lane, LaneQueue};
+
+    fn drain(queue: LaneQueue<u64>) -> Vec<u64> {
+        queue.push_batch(vec![50]);
+        (100..108).for_each(|v| queue.push_in(Lane::High, v));
+        queue.push_in(Lane::Low, 0);
+        std::iter::from_fn(|| Queue::pop(&queue)).collect()
+    }
+
+    // a waiting lower lane is popped after 4 elements of higher lanes
+    assert_eq!(
+        drain(LaneQueue::new()),
+        vec![100, 101, 102, 103, 0, 50, 104, 105, 106, 107]
+    );
+    let strict = LaneQueue::new()
+        .with_ratio(Lane::Normal, usize::MAX)
+        .with_ratio(Lane::Low, usize::MAX);
+    assert_eq!(
+        drain(strict),
+        vec![100, 101, 102, 103, 104, 105, 106, 107, 50, 0]
+    );
+
+    let res = LaneQueue::from(vec![0u64])
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(h, v)| {
+            if v == 0 {
+                h.enqueue_in(Lane::Low, 1).unwrap();
+                h.enqueue(2).unwrap();
+                h.enqueue_in(Lane::High, 3).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(res, vec![0, 3, 2, 1]);
+
+    let queue = LaneQueue::new();
+    (0..4).for_each(|v| queue.push_in(Lane::High, v));
+    (4..8).for_each(|v| queue.push_in(Lane::Low, v));
+    let other = Queue::split_off(&queue, 4);
+    assert_eq!(other.lane_len(Lane::High), 2);
+    assert_eq!(other.lane_len(Lane::Low), 2);
+    assert_eq!(Queue::len(&queue), 4);
+}
+
+#[test]
+fn dynqueue_fair_queue() {
+    use crate::FairQueue;
+
+    let queue = FairQueue::new()
+        .with_weight("interactive", 3)
+        .with_weight("bulk", 1);
+    Queue::push_batch(&queue, (0..100).map(|v| ("bulk", v)));
+    Queue::push_batch(&queue, (0..30).map(|v| ("interactive", v)));
+
+    // interactive elements are interleaved 3:1, not popped strictly first
+    let first = (0..40)
+        .map(|_| Queue::pop(&queue).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        first.iter().filter(|(c, _)| *c == "interactive").count(),
+        30
+    );
+    assert!(first[..4].iter().any(|(c, _)| *c == "bulk"));
+    // FIFO within a class
+    let bulk = first
+        .iter()
+        .filter(|(c, _)| *c == "bulk")
+        .map(|(_, v)| *v)
+        .collect::<Vec<_>>();
+    assert_eq!(bulk, (0..10).collect::<Vec<_>>());
+
+    // no credit saved up by an idle class
+    Queue::push(&queue, ("interactive", 30));
+    Queue::push(&queue, ("interactive", 31));
+    assert_eq!(Queue::pop(&queue), Some(("interactive", 30)));
+    assert_eq!(Queue::pop(&queue), Some(("interactive", 31)));
+    assert_eq!(Queue::pop(&queue), Some(("bulk", 10)));
+
+    let other = Queue::split_off(&queue, 45);
+    assert_eq!(other.class_len(&"bulk"), 45);
+    assert_eq!(queue.class_len(&"bulk"), 44);
+    queue.retain(|(_, v)| v % 2 == 0);
+    assert_eq!(Queue::len(&queue), 22);
+}
+
+#[test]
+fn dynqueue_deadline_queue() {
+    use crate::DeadlineQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::{Duration, Instant};
+
+    let now = Instant::now();
+    let at = |secs: u64| now + Duration::from_secs(secs);
+
+    let queue = DeadlineQueue::from(vec![(at(3), 'c'), (at(1), 'a'), (at(2), 'b'), (at(1), 'd')]);
+    assert_eq!(queue.next_deadline(), Some(at(1)));
+    let order = std::iter::from_fn(|| Queue::pop(&queue))
+        .map(|(_, v)| v)
+        .collect::<String>();
+    assert_eq!(order, "adbc");
+
+    // expired elements are dropped and counted, also by split off queues
+    let past = now - Duration::from_millis(1);
+    let queue =
+        DeadlineQueue::from(vec![(past, 0), (at(1), 1), (past, 2), (at(2), 3)]).with_drop_expired();
+    assert!(!queue.is_exclusive());
+    let other = Queue::split_off(&queue, 2);
+    assert_eq!(Queue::len(&queue) + Queue::len(&other), 4);
+    let mut left = std::iter::from_fn(|| Queue::pop(&queue))
+        .chain(std::iter::from_fn(|| Queue::pop(&other)))
+        .map(|(_, v)| v)
+        .collect::<Vec<_>>();
+    left.sort();
+    assert_eq!(left, vec![1, 3]);
+    assert_eq!(queue.expired(), 2);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+    let mut res = pool.install(|| {
+        get_input()
+            .into_iter()
+            .map(|v| (at(v), v))
+            .collect::<DeadlineQueue<_>>()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(|(h, (deadline, v))| {
+                if v > 0 {
+                    h.enqueue((deadline, v - 1)).unwrap();
+                }
+                v
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    let mut expected = get_input()
+        .into_iter()
+        .flat_map(|v| 0..=v)
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(res, expected);
+}
+
+#[test]
+fn dynqueue_aging_queue() {
+    use crate::AgingQueue;
+    use std::time::Duration;
+
+    let drain = |queue: &AgingQueue<u8, char>| {
+        std::iter::from_fn(|| Queue::pop(queue))
+            .map(|(_, v)| v)
+            .collect::<String>()
+    };
+
+    // without aging, highest priority first and FIFO within a priority
+    let queue = AgingQueue::from(vec![(1, 'a'), (3, 'b'), (1, 'c'), (2, 'd')]);
+    assert_eq!(drain(&queue), "bdac");
+
+    // a low priority element overtakes after waiting long enough
+    let queue = AgingQueue::new().with_aging(Duration::from_millis(1));
+    Queue::push(&queue, (0, 'l'));
+    std::thread::sleep(Duration::from_millis(30));
+    Queue::push_batch(&queue, vec![(5, 'h'), (100, 'u')]);
+    assert_eq!(drain(&queue), "ulh");
+
+    // the elements already in the queue age from the call to `with_aging`
+    let queue = AgingQueue::from(vec![(0, 'l')]).with_aging(Duration::from_secs(3600));
+    std::thread::sleep(Duration::from_millis(5));
+    Queue::push(&queue, (1, 'h'));
+    let other = Queue::split_off(&queue, 1);
+    assert_eq!(Queue::len(&queue) + Queue::len(&other), 2);
+    Queue::push_batch(&queue, std::iter::from_fn(|| Queue::pop(&other)));
+    assert_eq!(drain(&queue), "hl");
+
+    let queue = (0..10u8).map(|v| (v, 'x')).collect::<AgingQueue<_, _>>();
+    queue.retain(|(p, _)| p % 2 == 0);
+    assert_eq!(Queue::len(&queue), 5);
+    assert_eq!(Queue::pop(&queue), Some((8, 'x')));
+}
+
+#[test]
+fn dynqueue_key_limit() {
+    use crate::{CancelToken, DynQueue};
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let in_flight = [AtomicUsize::new(0), AtomicUsize::new(0)];
+    let max = [AtomicUsize::new(0), AtomicUsize::new(0)];
+    let mut res = pool.install(|| {
+        DynQueue::builder()
+            .key_limit(|v: &u64| v % 2, 2)
+            .build(get_input())
+            .into_par_iter()
+            .map(|(h, v)| {
+                let key = (v % 2) as usize;
+                let now = in_flight[key].fetch_add(1, Ordering::SeqCst) + 1;
+                max[key].fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(1));
+                in_flight[key].fetch_sub(1, Ordering::SeqCst);
+                handle_queue((h, v))
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+    assert!(max.iter().all(|max| max.load(Ordering::SeqCst) <= 2));
+
+    // deferred elements are left over after a stop
+    let token = CancelToken::new();
+    let cancel = token.clone();
+    let queue = vec![1u64, 3, 5, 7]
+        .into_dyn_queue()
+        .with_key_limit(|_| (), 1)
+        .with_cancel(token);
+    let leftovers = queue.leftovers();
+    let count = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|_| {
+                std::thread::sleep(Duration::from_millis(20));
+                cancel.cancel();
+            })
+            .count()
+    });
+    assert_eq!(count, 1);
+    assert_eq!(leftovers.take().len(), 3);
+}
+
+#[test]
+fn dynqueue_shutdown() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let queue = vec![0u64; 4].into_dyn_queue();
+    let control = queue.control();
+    let leftovers = queue.leftovers();
+    let enqueued = AtomicUsize::new(4);
+    let refused = AtomicUsize::new(0);
+    let frontier = Mutex::new(Vec::new());
+
+    // without the shutdown this would never end
+    let processed = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|(h, v)| {
+                if v == 100 && !control.is_shut_down() {
+                    frontier.lock().unwrap().extend(control.shutdown());
+                }
+                for _ in 0..2 {
+                    match h.enqueue(v + 1) {
+                        Ok(()) => enqueued.fetch_add(1, Ordering::SeqCst),
+                        Err(EnqueueError::Closed(_)) => refused.fetch_add(1, Ordering::SeqCst),
+                        Err(e) => panic!("{}", e),
+                    };
+                }
+            })
+            .count()
+    });
+
+    let frontier = frontier.into_inner().unwrap();
+    assert!(control.is_shut_down());
+    assert!(!frontier.is_empty());
+    assert!(refused.load(Ordering::SeqCst) >= 2);
+    // no element was lost
+    assert_eq!(
+        processed + frontier.len() + leftovers.take().len(),
+        enqueued.load(Ordering::SeqCst)
+    );
+}
+
+#[test]
+fn dynqueue_control_configure() {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    // the handles can be created before the configuration of `collect_map`
+    let queue = vec![(1u64, 0u64)].into_dyn_queue();
+    let control = queue.control();
+    #[cfg(feature = "stats")]
+    let stats = queue.stats_handle();
+    let frontier = Mutex::new(Vec::new());
+
+    let map = pool.install(|| {
+        queue.collect_map(|handle, k, _| {
+            for next in [(2, 0), (2, 1), (3, 0)] {
+                handle.enqueue(next).unwrap();
+            }
+            assert_eq!(control.pending(), 2);
+            frontier.lock().unwrap().extend(control.shutdown());
+            *k
+        })
+    });
+
+    let mut frontier = frontier.into_inner().unwrap();
+    frontier.sort();
+    assert_eq!(frontier, vec![(2, 0), (3, 0)]);
+    assert_eq!(map, HashMap::from([(1, 1)]));
+    assert!(control.is_shut_down());
+    #[cfg(feature = "stats")]
+    assert_eq!(stats.popped(), 1);
+}
+
+#[test]
+fn dynqueue_watermarks() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::Mutex;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let events = Mutex::new(Vec::new());
+    let count = pool.install(|| {
+        DynQueue::builder()
+            .watermarks(
+                50,
+                5,
+                |pending| events.lock().unwrap().push((true, pending)),
+                |pending| events.lock().unwrap().push((false, pending)),
+            )
+            .build(VecDeque::from(vec![0u64]))
+            .into_par_iter()
+            .map(|(h, v)| {
+                if v < 10 {
+                    h.enqueue_many(vec![v + 1; 2]).unwrap();
+                }
+            })
+            .count()
+    });
+    assert_eq!(count, 2047);
+
+    let events = events.into_inner().unwrap();
+    assert!(!events.is_empty());
+    for (i, (high, pending)) in events.iter().enumerate() {
+        // the callbacks alternate, starting with the high watermark
+        assert_eq!(*high, i % 2 == 0);
+        if *high {
+            assert!(*pending >= 50);
+        } else {
+            assert!(*pending <= 5);
+        }
+    }
+    // the queue was drained at the end
+    assert!(events.last().is_some_and(|(high, _)| !high));
+}
+
+#[test]
+fn dynqueue_collect_map() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // a graph with cycles, every node links to its double and its half
+    let calls = AtomicUsize::new(0);
+    let closure = pool.install(|| {
+        vec![(1u64, 0usize), (1, 1)]
+            .into_dyn_queue()
+            .collect_map(|h, node, depth| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                for next in [node * 2, node / 2] {
+                    if (1..1000).contains(&next) {
+                        h.enqueue((next, depth + 1)).unwrap();
+                    }
+                }
+                node * 10
+            })
+    });
+
+    assert_eq!(calls.load(Ordering::SeqCst), closure.len());
+    assert_eq!(closure.len(), 10);
+    for (node, result) in closure {
+        assert!(node.is_power_of_two());
+        assert_eq!(result, node * 10);
+    }
+}
+
+#[test]
+fn dynqueue_receiver() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let producer = std::thread::spawn(move || {
+        for burst in 0..5u64 {
+            for v in get_input() {
+                sender.send(v + burst * 100).unwrap();
+            }
+            // longer than the parallel iterators wait for the next element
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    });
+
+    let mut res = pool.install(|| {
+        DynQueue::builder()
+            .receiver(receiver)
+            .resplit_threshold(4)
+            .build(vec![1000u64])
+            .into_par_iter()
+            .map(|(h, v)| {
+                if v % 10 == 0 {
+                    h.enqueue(v + 1).unwrap();
+                }
+                v
+            })
+            .collect::<Vec<_>>()
+    });
+    producer.join().unwrap();
+    res.sort();
+
+    let mut expected = vec![1000, 1001];
+    for burst in 0..5u64 {
+        for v in get_input() {
+            let v = v + burst * 100;
+            expected.push(v);
+            if v % 10 == 0 {
+                expected.push(v + 1);
+            }
+        }
+    }
+    expected.sort();
+    assert_eq!(res, expected);
+
+    // the elements left in the channel after a stop are not lost
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let queue = DynQueue::from_receiver(receiver);
+    let leftovers = queue.leftovers();
+    let control = queue.control();
+    sender.send(1u64).unwrap();
+    let frontiers = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|_| {
+                sender.send(2).unwrap();
+                sender.send(3).unwrap();
+                control.shutdown()
+            })
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(frontiers, vec![vec![2, 3]]);
+    assert!(leftovers.take().is_empty());
+}
+
+#[test]
+fn dynqueue_pending() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut iter = vec![1u64, 2, 3].into_dyn_queue().into_iter();
+    let (h, _) = iter.next().unwrap();
+    assert_eq!(h.pending(), 2);
+    assert_eq!(h.pending_total(), 2);
+    h.enqueue_many(vec![4, 5]).unwrap();
+    assert_eq!(h.pending(), 4);
+    assert!(!h.is_empty());
+    assert_eq!(iter.by_ref().count(), 4);
+    assert!(h.is_empty());
+    assert_eq!(h.pending_total(), 0);
+
+    // throttle the enqueueing with the global estimate
+    let res = vec![0u64]
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(|(h, v)| {
+            assert!(h.pending() <= h.pending_total());
+            if v < 10 && h.pending_total() < 4 {
+                h.enqueue_many(vec![v + 1, v + 1]).unwrap();
+            }
+        })
+        .count();
+    assert!(res > 1 && res < 2047);
+}
+
+#[test]
+fn dynqueue_context() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    struct Limit(u64);
+
+    let mut res = DynQueue::builder()
+        .context(Limit(5))
+        .build(vec![0u64])
+        .into_par_iter()
+        .map(|(h, v)| {
+            assert!(h.context::<u64>().is_none());
+            if v < h.context::<Limit>().unwrap().0 {
+                h.enqueue(v + 1).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![0, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn dynqueue_from_iter() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::BinaryHeap;
+    use std::sync::RwLock;
+
+    let queue: DynQueue<_, RwLock<BinaryHeap<_>>> = get_input().into_iter().collect();
+    let mut res = queue.into_par_iter().map(handle_queue).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let queue: DynQueue<_, RwLock<Vec<_>>> = get_input().into_par_iter().collect();
+    let mut res = queue.into_par_iter().map(handle_queue).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_extend() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelExtend as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::RwLock;
+
+    let input = get_input();
+    let (first, second) = input.split_at(input.len() / 2);
+
+    let mut queue: DynQueue<_, RwLock<Vec<_>>> = first.to_vec().into_dyn_queue();
+    queue.extend(second.iter().copied());
+    let mut res = queue.into_par_iter().map(handle_queue).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut queue: DynQueue<_, RwLock<Vec<_>>> = first.to_vec().into_dyn_queue();
+    queue.par_extend(second.to_vec());
+    let mut res = queue.into_par_iter().map(handle_queue).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut queue = vec![1u64, 2].into_dyn_queue().with_dedup(|v: &u64| *v);
+    queue.extend(vec![2, 3]);
+    queue.par_extend(vec![3, 4]);
+    let mut res = queue.into_par_iter().map(|(_, v)| v).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn dynqueue_with_capacity() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::VecDeque;
+    use std::sync::RwLock;
+
+    let queue = RwLock::new(Vec::<u64>::new());
+    queue.reserve(100);
+    assert!(queue.read().unwrap().capacity() >= 100);
+
+    let mut queue = DynQueue::<_, RwLock<VecDeque<_>>>::with_capacity(100);
+    queue.extend(get_input());
+    let mut res = queue.into_par_iter().map(handle_queue).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_into_inner() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::VecDeque;
+    use std::sync::RwLock;
+
+    let queue: DynQueue<_, RwLock<VecDeque<_>>> = VecDeque::from(get_input()).into_dyn_queue();
+    assert_eq!(
+        Vec::from(queue.into_inner().into_inner().unwrap()),
+        get_input()
+    );
+
+    let queue = vec![0u64].into_dyn_queue();
+    let leftovers = queue.leftovers();
+    let found = queue
+        .into_par_iter()
+        .map(|(handle, value)| {
+            if value < 10 {
+                handle.enqueue_many(vec![value + 1, value + 2]).unwrap();
+            }
+            value
+        })
+        .find_any(|value| *value == 8);
+    assert_eq!(found, Some(8));
+
+    let mut rest = leftovers.take();
+    rest.sort();
+    rest.dedup();
+    assert!(!rest.is_empty());
+    assert!(rest.iter().all(|v| *v >= 1 && *v <= 11));
+}
+
+#[test]
+fn dynqueue_resume_leftovers() {
+    use crate::{DynQueue, Tracked};
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::{Mutex, RwLock};
+
+    let seen = Mutex::new(Vec::new());
+    let expand = |(handle, value): (DynQueueHandle<u64, _>, u64)| {
+        seen.lock().unwrap().push(value);
+        if handle.depth() < 4 {
+            handle.enqueue_many(vec![value * 2, value * 2 + 1]).unwrap();
+        }
+        value
+    };
+
+    let queue = Tracked::from(vec![1u64]).into_dyn_queue();
+    let leftovers = queue.leftovers();
+    let found = queue.into_par_iter().map(expand).find_any(|v| *v >= 8);
+    assert!(found.is_some());
+    assert!(!leftovers.is_empty());
+
+    let queue: DynQueue<_, Tracked<RwLock<Vec<_>>>> = leftovers.clone().into_dyn_queue();
+    assert!(leftovers.is_empty());
+    queue.into_par_iter().map(expand).for_each(drop);
+
+    let mut seen = seen.into_inner().unwrap();
+    seen.sort();
+    assert_eq!(seen, (1..32).collect::<Vec<_>>());
+}
+
+#[test]
+fn dynqueue_for_each_streamed() {
+    let mut res = get_input()
+        .into_dyn_queue()
+        .for_each_streamed(|handle, value| handle_queue((handle, value)))
+        .iter()
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // dropping the receiver stops the endless iteration
+    let results = vec![0u64]
+        .into_dyn_queue()
+        .for_each_streamed(|handle, value| {
+            handle.enqueue(value + 1).ok();
+            value
+        });
+    assert_eq!(results.iter().take(10).count(), 10);
+    drop(results);
+}
+
+#[test]
+fn dynqueue_scope() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = crate::scope(get_input(), |queue| {
+        queue.into_par_iter().map(handle_queue).collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_run_on() {
+    use crate::CancelToken;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = get_input().into_dyn_queue().run_on(&pool, |queue| {
+        queue
+            .into_par_iter()
+            .map(|item| {
+                assert!(pool.current_thread_index().is_some());
+                handle_queue(item)
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let token = CancelToken::new();
+    let count = vec![0u64]
+        .into_dyn_queue()
+        .with_cancel(token.clone())
+        .run_on(&pool, |queue| {
+            queue
+                .into_par_iter()
+                .map(|(handle, value)| {
+                    if value == 10 {
+                        handle.cancel();
+                    }
+                    handle.enqueue(value + 1).ok();
+                })
+                .count()
+        });
+    assert!(token.is_cancelled());
+    assert!(count >= 11);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn dynqueue_single_thread() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let single = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let multi = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let run = |pool: &rayon::ThreadPool, single_thread: Option<bool>| {
+        let mut jq = get_input().into_dyn_queue();
+        if let Some(single_thread) = single_thread {
+            jq = jq.with_single_thread(single_thread);
+        }
+        let stats = jq.stats_handle();
+        let mut res = jq.run_on(pool, |jq| {
+            jq.into_par_iter().map(handle_queue).collect::<Vec<_>>()
+        });
+        res.sort();
+        assert_eq!(res, get_expected());
+        stats.splits()
+    };
+
+    assert_eq!(run(&single, None), 0);
+    assert_eq!(run(&multi, Some(true)), 0);
+    assert!(run(&multi, None) > 0);
+    assert!(run(&single, Some(false)) > 0);
+}
+
+#[cfg(loom)]
+#[test]
+fn loom_max_items() {
+    use std::sync::Arc;
+
+    loom::model(|| {
+        let jq = Arc::new(Vec::<u64>::new().into_dyn_queue().with_max_items(1));
+        let other = jq.clone();
+        let thread = loom::thread::spawn(move || other.0.on_pop());
+        let mine = jq.0.on_pop();
+        let theirs = thread.join().unwrap();
+        assert!(mine ^ theirs);
+        assert!(jq.0.shared.is_stopped());
+    });
+}
+
+/// Collects the elements of a parallel iterator folded in a `loom` model
+#[cfg(loom)]
+struct Collect(Vec<u64>);
+
+#[cfg(loom)]
+impl<H> rayon::iter::plumbing::Folder<(H, u64)> for Collect {
+    type Result = Vec<u64>;
+
+    fn consume(mut self, (_, v): (H, u64)) -> Self {
+        self.0.push(v);
+        self
+    }
+
+    fn complete(self) -> Vec<u64> {
+        self.0
+    }
+
+    fn full(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(loom)]
+#[test]
+fn loom_split() {
+    use rayon::iter::plumbing::UnindexedProducer as _;
+
+    loom::model(|| {
+        let (left, right) = vec![1u64, 2]
+            .into_dyn_queue()
+            .with_single_thread(false)
+            .split();
+        let right = right.unwrap();
+        // both halves steal from each other, until both are exhausted
+        let thread = loom::thread::spawn(move || right.fold_with(Collect(Vec::new())).0);
+        let mut res = left.fold_with(Collect(Vec::new())).0;
+        res.extend(thread.join().unwrap());
+        res.sort();
+        assert_eq!(res, vec![1, 2]);
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn loom_enqueue_stale_handle() {
+    use crate::sync::Arc;
+    use crate::Meta;
+
+    loom::model(|| {
+        let jq = vec![0u64].into_dyn_queue();
+        let handle = DynQueueHandle {
+            inner: Arc::downgrade(&jq.0),
+            meta: Meta::default(),
+            sequence: None,
+        };
+        let thread = loom::thread::spawn(move || handle.enqueue(1).is_ok());
+
+        // close the queue like an exhausted parallel iterator
+        let mut inner = jq.0;
+        let inner = loop {
+            match Arc::try_unwrap(inner) {
+                Ok(inner) => break inner,
+                Err(arc) => {
+                    inner = arc;
+                    loom::thread::yield_now();
+                }
+            }
+        };
+        let enqueued = thread.join().unwrap();
+
+        // an element is either refused or left for the closed queue
+        assert_eq!(inner.has_queued(), true);
+        let queued = inner.queue.len() + inner.buffer.lock().unwrap().len();
+        assert_eq!(queued, if enqueued { 2 } else { 1 });
+    });
+}
+
+#[cfg(loom)]
+#[test]
+fn loom_termination() {
+    use crate::sync::{Arc, Ordering};
+    use crate::Meta;
+    use rayon::iter::plumbing::UnindexedProducer as _;
+
+    loom::model(|| {
+        let jq = Vec::<u64>::new().into_dyn_queue();
+        let handle = DynQueueHandle {
+            inner: Arc::downgrade(&jq.0),
+            meta: Meta::default(),
+            sequence: None,
+        };
+        // an element of another parallel iterator is in flight
+        let shared = jq.0.shared.clone();
+        shared.in_flight.fetch_add(1, Ordering::AcqRel);
+        let thread = loom::thread::spawn(move || {
+            handle.enqueue(1).unwrap();
+            // like dropping the `InFlight` of the element
+            shared.in_flight.fetch_sub(1, Ordering::AcqRel);
+            shared.idle.wake(true);
+        });
+
+        // the exhausted parallel iterator waits for the enqueued element
+        let res = jq.fold_with(Collect(Vec::new())).0;
+        thread.join().unwrap();
+        assert_eq!(res, vec![1]);
+    });
+}
+
+#[test]
+fn dynqueue_resplit() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // a single initial element cannot be split by rayon
+    let threads = |resplit: Option<usize>| {
+        pool.install(|| {
+            let mut jq = vec![0u64].into_dyn_queue();
+            if let Some(threshold) = resplit {
+                jq = jq.with_resplit_threshold(threshold);
+            }
+            jq.into_par_iter()
+                .filter_map(|(h, v)| {
+                    if v == 0 {
+                        h.enqueue_many(100..200).unwrap();
+                        return None;
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                    rayon::current_thread_index()
+                })
+                .collect::<HashSet<_>>()
+        })
+    };
+    assert_eq!(threads(None).len(), 1);
+    assert!(threads(Some(4)).len() > 1);
+
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_resplit_threshold(2)
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_split_policy() {
+    use crate::QueueView;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let calls = AtomicUsize::new(0);
+    let (mut res, threads) = pool.install(|| {
+        let res = get_input()
+            .into_dyn_queue()
+            .with_split_policy(|_: &dyn QueueView<u64>| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                None
+            })
+            .into_par_iter()
+            .map(|item| (handle_queue(item), rayon::current_thread_index()))
+            .collect::<Vec<_>>();
+        let threads = res.iter().map(|(_, t)| *t).collect::<HashSet<_>>();
+        (res.into_iter().map(|(v, _)| v).collect::<Vec<_>>(), threads)
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+    assert!(calls.load(Ordering::Relaxed) > 0);
+    assert_eq!(threads.len(), 1);
+
+    // split off the elements, which sum up to half of the total weight
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_split_policy(|queue: &dyn QueueView<u64>| {
+                let mut weights = Vec::new();
+                queue.for_each(&mut |v| weights.push(*v));
+                let half = weights.iter().sum::<u64>() / 2;
+                let mut sum = 0;
+                let at = weights.iter().take_while(|w| {
+                    sum += **w;
+                    sum <= half
+                });
+                Some(at.count()).filter(|at| *at > 0 && *at < weights.len())
+            })
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_weighted_split() {
+    use crate::{QueueView, SplitPolicy, Weight, WeightedSplit};
+    use std::sync::RwLock;
+
+    #[derive(Debug, PartialEq)]
+    struct Job(u64);
+
+    impl Weight for Job {
+        fn weight(&self) -> u64 {
+            self.0
+        }
+    }
+
+    let split_size = |policy: WeightedSplit, jobs: Vec<u64>| {
+        let queue = RwLock::new(jobs.into_iter().map(Job).collect::<Vec<_>>());
+        policy.split_size(&queue as &dyn QueueView<Job>)
+    };
+
+    assert_eq!(
+        split_size(WeightedSplit::new(), vec![1000, 1, 1, 1]),
+        Some(1)
+    );
+    assert_eq!(
+        split_size(WeightedSplit::new(), vec![1, 1, 1, 1000]),
+        Some(3)
+    );
+    assert_eq!(split_size(WeightedSplit::new(), vec![1, 1, 1, 1]), Some(2));
+    assert_eq!(split_size(WeightedSplit::new(), vec![1]), None);
+    assert_eq!(
+        split_size(WeightedSplit::new().min_weight(4), vec![1000, 1, 1, 1]),
+        None
+    );
+
+    // the queue keeps the heavy job, the other three are split off
+    let queue = RwLock::new(vec![Job(1000), Job(1), Job(1), Job(1)]);
+    let size = WeightedSplit::new().split_size(&queue).unwrap();
+    let other = Queue::split_off(&queue, size);
+    assert_eq!(queue.into_inner().unwrap(), vec![Job(1000)]);
+    assert_eq!(other.into_inner().unwrap(), vec![Job(1), Job(1), Job(1)]);
+}
+
+#[test]
+fn dynqueue_dedup_hasher() {
+    use crate::{DynQueue, Tracked};
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::BuildHasher;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct CountingHasher(Arc<AtomicUsize>);
+
+    impl BuildHasher for CountingHasher {
+        type Hasher = DefaultHasher;
+
+        fn build_hasher(&self) -> DefaultHasher {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            DefaultHasher::new()
+        }
+    }
+
+    let hasher = CountingHasher::default();
+    let mut res = Tracked::from(vec![1u64, 2, 3])
+        .into_dyn_queue()
+        .with_dedup_hasher(|v: &u64| *v % 10, hasher.clone())
+        .into_par_iter()
+        .map(|(handle, value)| {
+            handle.enqueue_many(vec![value + 10, value + 1]).unwrap();
+            value
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, (1..=10).collect::<Vec<_>>());
+    assert!(hasher.0.load(Ordering::Relaxed) >= 3 + 2 * 10);
+
+    let hasher = CountingHasher::default();
+    let mut res = DynQueue::builder()
+        .dedup_with_hasher(|v: &u64| *v, hasher.clone())
+        .build(vec![1u64, 1, 2])
+        .into_par_iter()
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 2]);
+    assert_eq!(hasher.0.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn btreemap_queue() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::BTreeMap;
+    use std::sync::RwLock;
+
+    let queue = RwLock::new(BTreeMap::<u64, Vec<u64>>::new());
+    queue.push_batch(get_input().into_iter().map(|v| (v % 4, v)));
+    assert_eq!(queue.len(), 21);
+    assert_eq!(queue.pop(), Some((0, 20)));
+
+    let other = queue.split_off(10);
+    assert_eq!(queue.len(), 10);
+    assert_eq!(other.len(), 10);
+    assert_eq!(queue.pop(), Some((0, 16)));
+    assert_eq!(other.pop(), Some((2, 18)));
+
+    queue.retain(|(k, _)| *k != 0);
+    assert_eq!(queue.len(), 6);
+    assert_eq!(queue.pop(), Some((1, 21)));
+
+    // every element enqueues its children with a higher key
+    let mut res = BTreeMap::from([(0u32, vec![1u64])])
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(|(handle, (k, v))| {
+            if k < 4 {
+                handle
+                    .enqueue_many(vec![(k + 1, 2 * v), (k + 1, 2 * v + 1)])
+                    .unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, (1..32).collect::<Vec<_>>());
+}
+
+#[cfg(feature = "crossbeam-queue")]
+#[test]
+fn dynqueue_lockfree() {
+    use crate::IntoLockFreeDynQueue as _;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = get_input()
+        .into_dyn_queue_lockfree()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = (1..=21u64)
+        .into_dyn_queue_lockfree()
+        .into_par_iter()
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[cfg(feature = "concurrent-queue")]
+#[test]
+fn dynqueue_iter_test_concurrent_queue() {
+    use concurrent_queue::ConcurrentQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::Arc;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    for queue in [ConcurrentQueue::unbounded(), ConcurrentQueue::bounded(128)] {
+        get_input()
+            .into_iter()
+            .for_each(|ele| queue.push(ele).unwrap());
+        let mut res = pool.install(|| {
+            queue
+                .into_dyn_queue()
+                .into_par_iter()
+                .map(handle_queue)
+                .collect::<Vec<_>>()
+        });
+        res.sort();
+        assert_eq!(res, get_expected());
+    }
+
+    let queue = Arc::new(ConcurrentQueue::unbounded());
+    queue.push_batch(0..10);
+    let other = Queue::split_off(&queue, 4);
+    assert!(!queue.shares_queue(&other));
+    assert_eq!((queue.len(), other.len()), (6, 4));
+
+    let queue = Arc::new(ConcurrentQueue::bounded(1));
+    let other = Queue::split_off(&queue, 4);
+    assert!(queue.shares_queue(&other));
+    assert_eq!(Queue::try_push(&queue, 1), Ok(()));
+    assert_eq!(Queue::try_push(&other, 2), Err(2));
+}
+
+#[cfg(feature = "crossbeam-channel")]
+#[test]
+fn dynqueue_iter_test_channel() {
+    use crate::Channel;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let channel = Channel::unbounded();
+    let sender = channel.sender();
+    let mut res = std::thread::scope(|s| {
+        s.spawn(move || {
+            get_input()
+                .into_iter()
+                .for_each(|v| sender.send(v).unwrap())
+        })
+        .join()
+        .unwrap();
+        pool.install(|| {
+            channel
+                .into_dyn_queue()
+                .into_par_iter()
+                .map(handle_queue)
+                .collect::<Vec<_>>()
+        })
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let channel = Channel::bounded(1);
+    let other = Queue::split_off(&channel, 1);
+    assert!(channel.shares_queue(&other));
+    assert_eq!(Queue::try_push(&channel, 1), Ok(()));
+    assert_eq!(Queue::try_push(&other, 2), Err(2));
+    assert_eq!(Queue::pop(&other), Some(1));
+}
+
+#[cfg(feature = "spill")]
+#[test]
+fn dynqueue_iter_test_spill() {
+    use crate::SpillQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // a window of 4 spills most of the elements
+    let mut queue = SpillQueue::new(4);
+    queue.extend(get_input());
+    assert!(queue.spilled() > 0);
+    let mut res = pool.install(|| {
+        queue
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // FIFO across memory, disk and the unwritten tail
+    let queue = SpillQueue::new(8);
+    Queue::push_batch(&queue, 0..103u32);
+    assert_eq!(Queue::len(&queue), 103);
+    assert_eq!(queue.spilled(), 94);
+    let other = Queue::split_off(&queue, 10);
+    assert!(queue.shares_queue(&other));
+    assert_eq!(
+        Queue::pop_batch(&other, 10, None),
+        (0..10).collect::<Vec<_>>()
+    );
+    queue.retain(|v| v % 2 == 0);
+    assert_eq!(Queue::len(&queue), 47);
+    let mut all = Vec::new();
+    while let Some(v) = Queue::pop(&queue) {
+        all.push(v);
+    }
+    assert_eq!(all, (10..103).filter(|v| v % 2 == 0).collect::<Vec<_>>());
+    assert_eq!(queue.spilled(), 0);
+}
+
+#[cfg(feature = "compress")]
+#[test]
+fn dynqueue_iter_test_compressed() {
+    use crate::Compressed;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::RwLock;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut queue = Compressed::new(RwLock::new(Vec::new())).with_block_size(2);
+    queue.extend(get_input());
+    assert_eq!(Queue::len(&queue), get_input().len());
+    let mut res = pool.install(|| {
+        queue
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // similar elements compress well
+    let queue = Compressed::new(RwLock::new(VecDeque::new()));
+    Queue::push_batch(&queue, (0..1024u64).map(|i| [i % 4; 32]));
+    let blocks = queue.inner().read().unwrap();
+    assert_eq!(blocks.len(), 4);
+    assert!(blocks.iter().all(|block| block.len() == 256));
+    assert!(blocks.iter().all(|block| block.compressed_len() < 256 * 32));
+    drop(blocks);
+
+    let other = Queue::split_off(&queue, 512);
+    assert_eq!(Queue::len(&other), 512);
+    assert_eq!(Queue::len(&queue), 512);
+    queue.retain(|v| v[0] != 0);
+    assert_eq!(Queue::len(&queue), 384);
+    let mut count = 0;
+    while let Some(v) = Queue::pop(&queue) {
+        assert_ne!(v[0], 0);
+        count += 1;
+    }
+    assert_eq!(count, 384);
+}
+
+#[test]
+fn dynqueue_sender() {
+    use crate::CancelToken;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let queue = Vec::new().into_dyn_queue().with_resplit_threshold(4);
+    let sender = queue.sender();
+    let input = get_input();
+    let (left, right) = input.split_at(input.len() / 2);
+    let feeders = vec![left.to_vec(), right.to_vec()]
+        .into_iter()
+        .map(|values| {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                for v in values {
+                    sender.send(v).unwrap();
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(sender);
+
+    let mut res = pool.install(|| queue.into_par_iter().map(handle_queue).collect::<Vec<_>>());
+    res.sort();
+    assert_eq!(res, get_expected());
+    feeders.into_iter().for_each(|f| f.join().unwrap());
+
+    let queue = vec![1u64].into_dyn_queue();
+    let sender = queue.sender();
+    let feeder = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(SLEEP_MS));
+        sender.send(2).unwrap();
+    });
+    let res = queue.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+    feeder.join().unwrap();
+    assert_eq!(res, vec![1, 2]);
+
+    // a cancelled iteration ends, although a sender is still alive
+    let token = CancelToken::new();
+    let queue = vec![1u64].into_dyn_queue().with_cancel(token.clone());
+    let sender = queue.sender();
+    let count = queue.into_iter().inspect(|_| token.cancel()).count();
+    assert_eq!(count, 1);
+    assert_eq!(sender.send(2), Err(EnqueueError::Closed(2)));
+    assert!(sender.is_closed());
+
+    // a sender created before the configuration sends to the configured queue
+    let queue = vec![1u64].into_dyn_queue();
+    let sender = queue.sender();
+    let queue = queue.with_filter(|v| v % 2 == 1).with_dedup(|v| *v);
+    sender.send(2).unwrap();
+    sender.send(3).unwrap();
+    sender.send(3).unwrap();
+    drop(sender);
+    let mut res = queue.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 3]);
+}
+
+#[cfg(feature = "dashmap")]
+#[test]
+fn dashmap_queue() {
+    use dashmap::DashMap;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::Arc;
+
+    let queue = Arc::new(DashMap::<u64, u64>::new());
+    queue.push_batch(get_input().into_iter().map(|v| (v % 4, v)));
+    assert_eq!(queue.len(), 4);
+    assert_eq!(queue.get(&1).map(|v| *v), Some(21));
+
+    let other = queue.split_off(2);
+    assert!(other.shares_queue(&queue));
+    queue.retain(|(k, _)| *k != 0);
+    assert_eq!(other.len(), 3);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every element marks the same node dirty, which is pending only once
+    let res = pool.install(|| {
+        (0..100u64)
+            .map(|k| (k, k))
+            .collect::<DashMap<_, _>>()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(|(handle, (k, v))| {
+                if k < 100 {
+                    handle.enqueue((1000, v)).unwrap();
+                }
+                k
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut nodes = res.iter().copied().filter(|k| *k < 100).collect::<Vec<_>>();
+    nodes.sort();
+    assert_eq!(nodes, (0..100).collect::<Vec<_>>());
+
+    let dirty = res.iter().filter(|k| **k == 1000).count();
+    assert!((1..=100).contains(&dirty));
+}
+
+#[test]
+fn dynqueue_clone_debug() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let queue = vec![1u64, 2].into_dyn_queue();
+    let debug = format!("{:?}", queue);
+    assert!(debug.starts_with("DynQueue { queue: "));
+    assert!(debug.contains("RwLock<alloc::vec::Vec<u64>>"));
+    assert!(debug.ends_with("pending: 2 }"));
+
+    fn enqueue_child<U: Queue<u64>>(handle: DynQueueHandle<u64, U>, v: u64) {
+        if v < 3 {
+            handle.enqueue(v + 10).unwrap();
+        }
+    }
+
+    let mut res = queue
+        .into_par_iter()
+        .map(|(handle, v)| {
+            assert!(format!("{:?}", handle).contains("closed: false"));
+            enqueue_child(handle.clone(), v);
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 2, 11, 12]);
+}
+
+#[test]
+fn dynqueue_downgrade() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = get_input()
+        .into_dyn_queue()
+        .into_par_iter()
+        .map(|(handle, v)| {
+            let weak = handle.downgrade();
+            handle_queue((handle, v));
+            if v == 1 {
+                assert!(!weak.is_closed());
+                weak.enqueue(23).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+
+    let mut expected = get_expected();
+    expected.push(23);
+    expected.sort();
+    assert_eq!(res, expected);
+
+    let weak = vec![1u64]
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(handle, _)| handle.downgrade())
+        .next()
+        .unwrap();
+    assert!(weak.is_closed());
+    assert_eq!(weak.enqueue(2), Err(EnqueueError::Closed(2)));
+}
+
+#[test]
+fn dynqueue_nested() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    // the handles of the outer and the inner queue on the same thread
+    let mut res = pool.install(|| {
+        vec![1u64, 2]
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(|(outer, v)| {
+                let inner = vec![v].into_dyn_queue().into_par_iter().map(|(inner, w)| {
+                    if w < 10 {
+                        inner.enqueue(w * 10).unwrap();
+                        outer.enqueue(w * 100).unwrap();
+                    }
+                    w
+                });
+                let sum = inner.sum::<u64>();
+                if v < 10 {
+                    // the outer handle is lent again after the inner iteration
+                    outer.enqueue(sum + 1000).unwrap();
+                }
+                v
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, vec![1, 2, 100, 200, 1011, 1022]);
+}
+
+#[test]
+fn dynqueue_collect_isolated() {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let (mut res, panics) = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .collect_isolated(|handle, value| {
+                let value = handle_queue((handle, *value));
+                assert_ne!(value, 17, "unlucky");
+                value
+            })
+    });
+    res.sort();
+
+    let mut expected = get_expected();
+    expected.retain(|v| *v != 17);
+    assert_eq!(res, expected);
+
+    assert_eq!(panics.len(), get_expected().len() - expected.len());
+    assert!(panics.iter().all(|p| p.item == 17));
+    assert!(panics[0].message().unwrap().contains("unlucky"));
+}
+
+#[test]
+fn dynqueue_max_pending() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_max_pending(4)
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // exceeding the limit does not deadlock
+    let count = pool.install(|| {
+        vec![0u64]
+            .into_dyn_queue()
+            .with_max_pending(8)
+            .into_par_iter()
+            .map(|(handle, value)| {
+                if value < 12 {
+                    handle.enqueue_many(vec![value + 1; 2]).unwrap();
+                }
+            })
+            .count()
+    });
+    assert_eq!(count, 8191);
+
+    // the serial iterator is never blocked, but `try_enqueue` refuses
+    let accepted = crate::DynQueue::builder()
+        .max_pending(4)
+        .build(vec![0u64])
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                (1..10)
+                    .take_while(|v| handle.try_enqueue(*v).is_ok())
+                    .count()
+            } else {
+                0
+            }
+        })
+        .sum::<usize>();
+    assert_eq!(accepted, 4);
+}
+
+#[test]
+fn dynqueue_overflow_policy() {
+    use crate::{DynQueue, Order, OverflowPolicy};
+    use std::collections::VecDeque;
+
+    let run = |policy| {
+        let mut rejected = Vec::new();
+        let result = DynQueue::builder()
+            .capacity_limit(2)
+            .overflow_policy(policy)
+            .build(VecDeque::from(vec![0u64]))
+            .into_iter()
+            .map(|(handle, value)| {
+                if value == 0 {
+                    for v in 1..=4 {
+                        if let Err(EnqueueError::Full(v)) = handle.enqueue(v) {
+                            rejected.push(v);
+                        }
+                    }
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+        (result, rejected)
+    };
+
+    assert_eq!(run(OverflowPolicy::DropNewest), (vec![0, 1, 2], vec![]));
+    assert_eq!(run(OverflowPolicy::DropOldest), (vec![0, 3, 4], vec![]));
+    assert_eq!(run(OverflowPolicy::Reject), (vec![0, 1, 2], vec![3, 4]));
+
+    // the order of the queue does not change the oldest element
+    let result = DynQueue::builder()
+        .capacity_limit(2)
+        .overflow_policy(OverflowPolicy::DropOldest)
+        .order(Order::Lifo)
+        .build(vec![0u64])
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                (1..=3).for_each(|v| handle.enqueue(v).unwrap());
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(result, vec![0, 3, 2]);
+}
+
+#[test]
+fn dynqueue_overflow_policy_all_enqueues() {
+    use crate::{DynQueue, Order, OverflowPolicy};
+    use std::collections::VecDeque;
+
+    let mut rejected = Vec::new();
+    let result = DynQueue::builder()
+        .capacity_limit(2)
+        .overflow_policy(OverflowPolicy::Reject)
+        .order(Order::Fifo)
+        .build(VecDeque::from(vec![0u64]))
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                if let Err(EnqueueError::Full(v)) = handle.enqueue_front(1) {
+                    rejected.push(v);
+                }
+                if let Err(EnqueueError::Full(v)) = handle.downgrade().enqueue(2) {
+                    rejected.push(v);
+                }
+                for v in 3..=4 {
+                    if let Err(EnqueueError::Full(v)) = handle.enqueue_front(v) {
+                        rejected.push(v);
+                    }
+                }
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(result, vec![0, 1, 2]);
+    assert_eq!(rejected, vec![3, 4]);
+
+    // `enqueue_many` gives back the refused element and all after it
+    let result = DynQueue::builder()
+        .capacity_limit(2)
+        .overflow_policy(OverflowPolicy::Reject)
+        .order(Order::Fifo)
+        .build(VecDeque::from(vec![0u64]))
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                assert_eq!(
+                    handle.enqueue_many(1..=4),
+                    Err(EnqueueError::Full(vec![3, 4]))
+                );
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(result, vec![0, 1, 2]);
+}
+
+#[test]
+fn dynqueue_overflow_policy_dedup() {
+    use crate::{DynQueue, Order, OverflowPolicy};
+    use std::collections::VecDeque;
+
+    // a rejected element does not mark its key as seen, so it can be
+    // enqueued again later
+    for approximate in [false, true] {
+        let builder = DynQueue::builder()
+            .capacity_limit(2)
+            .overflow_policy(OverflowPolicy::Reject)
+            .order(Order::Fifo);
+        let builder = if approximate {
+            builder.approximate_dedup(|v: &u64| *v, 100, 0.001)
+        } else {
+            builder.dedup(|v: &u64| *v)
+        };
+        let result = builder
+            .build(VecDeque::from(vec![0u64]))
+            .into_iter()
+            .map(|(handle, value)| {
+                match value {
+                    0 => {
+                        assert_eq!(handle.enqueue(1), Ok(()));
+                        assert_eq!(handle.enqueue(2), Ok(()));
+                        assert_eq!(handle.enqueue(3), Err(EnqueueError::Full(3)));
+                        assert_eq!(handle.enqueue(1), Ok(()));
+                    }
+                    1 => assert_eq!(handle.enqueue(3), Ok(())),
+                    _ => {}
+                }
+                value
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(result, vec![0, 1, 2, 3]);
+    }
+}
+
+#[cfg(feature = "crossbeam-queue")]
+#[test]
+fn dynqueue_overflow_policy_bounded() {
+    use crate::{DynQueue, OverflowPolicy};
+    use crossbeam_queue::ArrayQueue;
+
+    let queue = ArrayQueue::new(2);
+    queue.push(0u64).unwrap();
+
+    let result = DynQueue::builder()
+        .overflow_policy(OverflowPolicy::DropNewest)
+        .build(queue)
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                (1..=4).for_each(|v| handle.enqueue(v).unwrap());
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(result, vec![0, 1, 2]);
+}
+
+#[test]
+fn dynqueue_rate_limit() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::{Duration, Instant};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_rate_limit(1000.0, 5)
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // the limit is shared by all parallel iterators
+    let enqueued = get_expected().len() - get_input().len();
+    assert!(start.elapsed() >= Duration::from_millis((enqueued - 5) as u64 * 9 / 10));
+
+    let accepted = crate::DynQueue::builder()
+        .rate_limit(1.0, 2)
+        .build(vec![0u64])
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                (1..10)
+                    .take_while(|v| handle.try_enqueue(*v).is_ok())
+                    .count()
+            } else {
+                0
+            }
+        })
+        .sum::<usize>();
+    assert_eq!(accepted, 2);
+
+    // a duplicate does not use up a token
+    let result = crate::DynQueue::builder()
+        .rate_limit(1.0, 2)
+        .dedup(|v: &u64| *v)
+        .build(vec![0u64])
+        .into_iter()
+        .map(|(handle, value)| {
+            if value == 0 {
+                assert_eq!(handle.try_enqueue(1), Ok(()));
+                assert_eq!(handle.try_enqueue(1), Ok(()));
+                assert_eq!(handle.try_enqueue(2), Ok(()));
+                assert_eq!(handle.try_enqueue(3), Err(EnqueueError::Full(3)));
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(result.len(), 3);
+}
+
+#[test]
+fn dynqueue_sequence() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut trace = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_sequence()
+            .into_par_iter()
+            .map(|(handle, value)| (handle.sequence().unwrap(), handle_queue((handle, value))))
+            .collect::<Vec<_>>()
+    });
+    trace.sort();
+    let (seqs, mut res): (Vec<_>, Vec<_>) = trace.into_iter().unzip();
+    assert_eq!(seqs, (0..get_expected().len()).collect::<Vec<_>>());
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let seqs = crate::DynQueue::builder()
+        .sequence()
+        .build(get_input())
+        .into_iter()
+        .map(|(handle, _)| handle.sequence().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(seqs, (0..get_input().len()).collect::<Vec<_>>());
+
+    let (handle, _) = get_input().into_dyn_queue().into_iter().next().unwrap();
+    assert_eq!(handle.sequence(), None);
+}
+
+#[test]
+fn dynqueue_collect_ordered() {
+    use crate::Tracked;
+    use std::collections::VecDeque;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // a binary tree, where every node enqueues its two children
+    let res = pool.install(|| {
+        Tracked::from(vec![0u64, 1])
+            .into_dyn_queue()
+            .with_enqueue_buffer_size(4)
+            .collect_ordered(|handle, value| {
+                if value > 1 && value < 64 {
+                    handle.enqueue_many(vec![2 * value, 2 * value + 1]).unwrap();
+                } else if value == 1 {
+                    handle.enqueue(2).unwrap();
+                    handle.enqueue(3).unwrap();
+                }
+                value
+            })
+    });
+
+    let mut sorted = res.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..128).collect::<Vec<_>>());
+    assert_eq!(&res[..2], &[0, 1]);
+
+    let position = |v: u64| res.iter().position(|r| *r == v).unwrap();
+    for v in 2..128 {
+        assert!(position(v / 2) < position(v));
+    }
+    for v in (2..128).step_by(2) {
+        assert!(position(v) < position(v + 1));
+    }
+
+    // the initial elements keep the order of the queue
+    let res = Tracked::from(VecDeque::from(vec![3u64, 2, 1]))
+        .into_dyn_queue()
+        .collect_ordered(|handle, value| {
+            if value < 10 {
+                handle.enqueue(value * 10).unwrap();
+            }
+            value
+        });
+    assert_eq!(res[..3], [3, 2, 1]);
+    assert_eq!(res.len(), 6);
+}
+
+#[test]
+fn dynqueue_for_each() {
+    use std::sync::Mutex;
+
+    let res = Mutex::new(Vec::new());
+    crate::for_each(get_input(), |handle, value| {
+        let value = handle_queue((handle, value));
+        res.lock().unwrap().push(value);
+    });
+
+    let mut res = res.into_inner().unwrap();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_process() {
+    let mut res = crate::process(get_input(), |value| {
+        let mut children = Vec::new();
+        if value % 2 == 0 {
+            children.push(11);
+        }
+        if value % 3 == 0 {
+            children.push(11);
+        }
+        if value % 4 == 0 {
+            children.push(11);
+        }
+        if value == 11 {
+            children.extend(vec![5, 17]);
+        }
+        (value, children)
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_par_dyn_map() {
+    use crate::DynMapExt as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = get_input()
+        .par_dyn_map(|handle, value| handle_queue((handle, value)))
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = VecDeque::from(get_input())
+        .par_dyn_map(|handle, value| handle_queue((handle, value)))
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let input = get_input();
+    let mut res = input[..]
+        .par_dyn_map(|handle, value| handle_queue((handle, value)))
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_from_array_range() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let mut res = [1u64, 2, 3, 4, 5, 6, 7, 8, 9, 10]
+        .into_dyn_queue()
+        .into_par_iter()
+        .chain((11..=15).into_dyn_queue().into_par_iter())
+        .chain((16..22).into_dyn_queue().into_par_iter())
+        .map(handle_queue)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut res = get_input()
+        .into_iter()
+        .filter(|v| v % 2 == 0)
+        .collect::<crate::DynQueue<_, std::sync::RwLock<Vec<_>>>>()
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, (2..=20).step_by(2).collect::<Vec<_>>());
+}
+
+#[cfg(feature = "arena")]
+#[test]
+fn dynqueue_arena() {
+    use crate::Arena;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let arena = Arena::new();
+    let mut res = pool.install(|| {
+        arena
+            .dyn_queue(get_input())
+            .into_par_iter()
+            .map(|(handle, value)| {
+                let v = *value;
+                if v % 2 == 0 {
+                    handle.enqueue(arena.alloc(11)).unwrap();
+                }
+                if v % 3 == 0 {
+                    handle.enqueue(arena.alloc(11)).unwrap();
+                }
+                if v % 4 == 0 {
+                    handle.enqueue(arena.alloc(11)).unwrap();
+                }
+                if v == 11 {
+                    handle.enqueue(arena.alloc(5)).unwrap();
+                    handle.enqueue(arena.alloc(17)).unwrap();
+                }
+                value as *const u64 as usize
+            })
+            .collect::<Vec<_>>()
+    });
+
+    // every element was processed by reference exactly once
+    res.sort();
+    res.dedup();
+    assert_eq!(res.len(), arena.len());
+
+    let mut values = arena.into_vec();
+    values.sort();
+    assert_eq!(values, get_expected());
+}
+
+#[test]
+fn dynqueue_iterative_deepening() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let calls = AtomicUsize::new(0);
+    let search = |target: u64, max_depth: usize| {
+        calls.store(0, Ordering::Relaxed);
+        pool.install(|| {
+            crate::iterative_deepening(vec![1u64], max_depth, |handle, value| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                assert!(handle.depth() <= max_depth);
+                if value == target {
+                    return Some((handle.depth(), value));
+                }
+                handle.enqueue_many(vec![value * 2, value * 2 + 1]).unwrap();
+                None
+            })
+        })
+    };
+
+    assert_eq!(search(1, 5), Some((0, 1)));
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+    assert_eq!(search(13, 5), Some((3, 13)));
+
+    // passes with the limits 0, 1 and 2 visit 1 + 3 + 7 nodes
+    assert_eq!(search(1000, 2), None);
+    assert_eq!(calls.load(Ordering::Relaxed), 11);
+}
+
+#[test]
+fn dynqueue_bound() {
+    use crate::Bound;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::cmp::Reverse;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // 0/1 knapsack: (weight, value)
+    let items = [
+        (12u64, 4u64),
+        (2, 2),
+        (1, 1),
+        (1, 2),
+        (4, 10),
+        (3, 7),
+        (7, 8),
+        (5, 5),
+    ];
+    let capacity = 15;
+
+    let mut best = 0;
+    for set in 0..1u32 << items.len() {
+        let (weight, value) = items
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| set & (1 << i) != 0)
+            .fold((0, 0), |(w, v), (_, (iw, iv))| (w + iw, v + iv));
+        if weight <= capacity {
+            best = best.max(value);
+        }
+    }
+
+    // (next item, weight, value)
+    let search = |prune: bool| {
+        let bound = Bound::new();
+        let processed = AtomicUsize::new(0);
+        let queue = crate::DynQueue::builder();
+        let queue = if prune {
+            queue.bound_pruning(bound.clone(), |(next, _, value): &(usize, u64, u64)| {
+                value + items[*next..].iter().map(|(_, v)| v).sum::<u64>()
+            })
+        } else {
+            queue.bound(bound.clone())
+        };
+        pool.install(|| {
+            queue
+                .build(vec![(0usize, 0u64, 0u64)])
+                .into_par_iter()
+                .for_each(|(handle, (next, weight, value))| {
+                    processed.fetch_add(1, Ordering::Relaxed);
+                    if handle.try_improve_bound(value) {
+                        assert!(handle.bound::<u64>().unwrap() >= value);
+                    }
+                    if let Some((w, v)) = items.get(next) {
+                        if weight + w <= capacity {
+                            handle.enqueue((next + 1, weight + w, value + v)).unwrap();
+                        }
+                        handle.enqueue((next + 1, weight, value)).unwrap();
+                    }
+                })
+        });
+        (bound.get(), processed.into_inner())
+    };
+
+    let (unpruned, all) = search(false);
+    assert_eq!(unpruned, Some(best));
+    let (pruned, some) = search(true);
+    assert_eq!(pruned, Some(best));
+    assert!(some < all);
+
+    // wrong type and minimizing
+    let bound = Bound::with_initial(Reverse(10u64));
+    vec![5u64, 20, 7]
+        .into_dyn_queue()
+        .with_bound(bound.clone())
+        .into_iter()
+        .for_each(|(handle, v)| {
+            assert!(!handle.try_improve_bound(v));
+            assert_eq!(handle.bound::<u64>(), None);
+            handle.try_improve_bound(Reverse(v));
+        });
+    assert_eq!(bound.get(), Some(Reverse(5)));
+    assert!(bound.is_dominated(&Reverse(6)));
+    assert_eq!(format!("{:?}", bound), "Bound(Some(Reverse(5)))");
+}
+
+#[test]
+fn dynqueue_filter() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // only the numbers below 1000 without the digit 7 pass
+    let passes = |v: &u64| *v < 1000 && !v.to_string().contains('7');
+
+    let mut res = pool.install(|| {
+        DynQueue::builder()
+            .filter(passes)
+            .dedup(|v: &u64| *v)
+            .build(vec![0u64, 7, 1000])
+            .into_par_iter()
+            .map(|(handle, value)| {
+                handle
+                    .enqueue_many((0..10).map(|digit| value * 10 + digit))
+                    .unwrap();
+                value
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, (0..1000).filter(passes).collect::<Vec<_>>());
+
+    let mut res = vec![1u64, 2, 3]
+        .into_dyn_queue()
+        .with_filter(|v| v % 2 == 1)
+        .with_filter(|v| *v < 20)
+        .into_iter()
+        .map(|(handle, value)| {
+            handle.enqueue(value + 1).unwrap();
+            handle.enqueue(value + 2).unwrap();
+            value
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(
+        res,
+        vec![1, 3, 3, 5, 5, 7, 7, 9, 9, 11, 11, 13, 13, 15, 15, 17, 17, 19, 19]
+    );
+}
+
+#[test]
+fn dynqueue_shared_state() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let queue = DynQueue::builder()
+        .shared(AtomicU64::new(100))
+        .shared(AtomicU64::new(0))
+        .shared(AtomicUsize::new(0))
+        .build(get_input());
+    let sum = queue.shared::<AtomicU64>().unwrap();
+    let count = queue.shared::<AtomicUsize>().unwrap();
+    assert!(queue.shared::<String>().is_none());
+
+    let mut res = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|(handle, value)| {
+                let sum = handle.shared::<AtomicU64>().unwrap();
+                sum.fetch_add(value, Ordering::Relaxed);
+                handle
+                    .shared::<AtomicUsize>()
+                    .unwrap()
+                    .fetch_add(1, Ordering::Relaxed);
+                handle_queue((handle, value))
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    assert_eq!(
+        sum.load(Ordering::Relaxed),
+        get_expected().iter().sum::<u64>()
+    );
+    assert_eq!(count.load(Ordering::Relaxed), get_expected().len());
+
+    // the state is only kept alive by the readers after the iteration
+    assert_eq!(Arc::strong_count(&sum), 1);
+}
+
+#[test]
+fn dynqueue_in_flight() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // The parallel iterator of `0` is exhausted, while `1` is still in
+    // flight, and helps with the children of `1` afterwards.
+    let threads = pool.install(|| {
+        vec![0u64, 1]
+            .into_dyn_queue()
+            .into_par_iter()
+            .filter_map(|(handle, value)| match value {
+                0 => None,
+                1 => {
+                    std::thread::sleep(Duration::from_millis(2));
+                    handle.enqueue_many(100..120).unwrap();
+                    None
+                }
+                _ => {
+                    std::thread::sleep(Duration::from_millis(1));
+                    rayon::current_thread_index()
+                }
+            })
+            .collect::<HashSet<_>>()
+    });
+    assert!(threads.len() > 1);
+
+    // nested parallelism does not wait for the elements in flight below
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(|(handle, value)| {
+                let sum = (0..value).into_par_iter().sum::<u64>();
+                assert_eq!(sum, value * value.saturating_sub(1) / 2);
+                handle_queue((handle, value))
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    // a panic does not leave the element in flight
+    let res = pool.install(|| {
+        std::panic::catch_unwind(|| {
+            vec![0u64, 1]
+                .into_dyn_queue()
+                .into_par_iter()
+                .for_each(|(_, value)| {
+                    if value == 1 {
+                        panic!("in flight");
+                    }
+                })
+        })
+    });
+    assert!(res.is_err());
+}
+
+#[test]
+fn dynqueue_idle_wakeup() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // The exhausted parallel iterators are parked, until `1` enqueues its
+    // children in small steps.
+    let threads = pool.install(|| {
+        vec![0u64, 1]
+            .into_dyn_queue()
+            .into_par_iter()
+            .filter_map(|(handle, value)| match value {
+                0 => None,
+                1 => {
+                    for child in 100..120 {
+                        std::thread::sleep(Duration::from_millis(1));
+                        handle.enqueue(child).unwrap();
+                    }
+                    None
+                }
+                _ => {
+                    std::thread::sleep(Duration::from_millis(2));
+                    rayon::current_thread_index()
+                }
+            })
+            .collect::<HashSet<_>>()
+    });
+    assert!(threads.len() > 1);
+}
+
+#[test]
+fn dynqueue_shared_vec() {
+    use crate::{Order, SharedVec};
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let queue = SharedVec::from(vec![1, 2, 3, 4]);
+    queue.push(5);
+    assert_eq!(queue.len(), 5);
+
+    // split within the initial elements, the enqueued ones go along
+    let other = queue.split_off(1);
+    assert_eq!(queue.len(), 1);
+    assert_eq!(other.pop(), Some(5));
+    assert_eq!(other.pop_ordered(Order::Fifo), Some(2));
+    assert_eq!(other.pop(), Some(4));
+    other.push(6);
+    other.push(7);
+
+    // split within the enqueued elements
+    let third = other.split_off(2);
+    assert_eq!(third.into_vec(), vec![7]);
+    assert_eq!(other.into_vec(), vec![3, 6]);
+
+    queue.push(8);
+    queue.retain(|v| *v != 8);
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), None);
+
+    let queue = SharedVec::from(vec![1, 2, 3, 4]);
+    queue.push(5);
+    queue.retain(|v| v % 2 == 1);
+    assert_eq!(queue.pop_ordered(Order::Fifo), Some(1));
+    assert_eq!(queue.into_vec(), vec![3, 5]);
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = pool.install(|| {
+        get_input()
+            .into_iter()
+            .collect::<SharedVec<_>>()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let sum = pool.install(|| {
+        SharedVec::from((0..100_000u64).collect::<Vec<_>>())
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(|(_, v)| v)
+            .sum::<u64>()
+    });
+    assert_eq!(sum, 99_999 * 100_000 / 2);
+}
+
+#[test]
+fn dynqueue_counted_fifo() {
+    use crate::parallel::counted::Counted;
+    use crate::Order;
+    use std::sync::RwLock;
+
+    // a `Vec` popped FIFO shifts every element only about twice
+    const N: u64 = 200_000;
+    let queue = Counted::new(RwLock::new((0..N).collect::<Vec<_>>()));
+    for v in 0..N / 2 {
+        assert_eq!(queue.pop_ordered(Order::Fifo), Some(v));
+        queue.push(N + v);
+    }
+    assert_eq!(queue.len(), N as usize);
+
+    // the newest element is still popped first in LIFO order
+    assert_eq!(queue.pop_ordered(Order::Lifo), Some(N + N / 2 - 1));
+    assert_eq!(
+        queue.pop_batch(3, Some(Order::Fifo)),
+        vec![N / 2, N / 2 + 1, N / 2 + 2]
+    );
+    for v in N / 2 + 3..N + N / 2 - 1 {
+        assert_eq!(queue.pop_ordered(Order::Fifo), Some(v));
+    }
+    assert_eq!(queue.pop_ordered(Order::Fifo), None);
+    assert_eq!(queue.len(), 0);
+}
+
+#[test]
+fn dynqueue_counted_len() {
+    use crate::parallel::counted::Counted;
+    use crate::Order;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::RwLock;
+
+    let queue = Counted::new(RwLock::new(vec![1, 2, 3]));
+    queue.push(4);
+    queue.push_batch(vec![5, 6]);
+    assert_eq!(queue.len(), 6);
+    assert_eq!(queue.pop(), Some(6));
+    assert_eq!(queue.pop_ordered(Order::Fifo), Some(1));
+    assert_eq!(queue.len(), 4);
+
+    // the FIFO pop took the oldest half [2, 3] aside, only [4, 5] are split
+    let other = queue.split_off(1);
+    assert_eq!(queue.len(), 3);
+    assert_eq!(other.len(), 1);
+
+    queue.retain(|v| v % 2 == 0);
+    assert_eq!(queue.len(), 2);
+    assert_eq!(other.pop_batch(5, None), vec![5]);
+    assert_eq!(other.len(), 0);
+    assert_eq!(other.pop(), None);
+    assert_eq!(queue.into_inner().into_inner().unwrap(), vec![2, 4]);
+
+    // queues fed from the outside are not counted
+    #[cfg(feature = "crossbeam-queue")]
+    {
+        use crossbeam_queue::ArrayQueue;
+        use std::sync::Arc;
+
+        let outside = Arc::new(ArrayQueue::new(4));
+        let queue = Counted::new(outside.clone());
+        ArrayQueue::push(&outside, 1).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pop(), Some(1));
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+}
+
+#[test]
+fn dynqueue_map_scoped() {
+    use crate::DynQueue;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_enqueue_buffer_size(4)
+            .map_scoped(|handle, v| {
+                assert_eq!(handle.sequence(), None);
+                handle_queue((handle.to_handle(), v))
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let sum = pool.install(|| {
+        DynQueue::new(std::sync::RwLock::new(vec![0u64]))
+            .with_resplit_threshold(64)
+            .map_scoped(|handle, v| {
+                if v < 10_000 {
+                    handle.enqueue(v + 1).unwrap();
+                }
+                v
+            })
+            .sum::<u64>()
+    });
+    assert_eq!(sum, 10_000 * 10_001 / 2);
+
+    let depths = pool.install(|| {
+        crate::Tracked::new(std::sync::RwLock::new(vec![(crate::Meta::default(), 0u64)]))
+            .into_dyn_queue()
+            .map_scoped(|handle, v| {
+                if v < 3 {
+                    handle.enqueue_many(vec![v + 1, v + 1]).unwrap();
+                }
+                (v, handle.depth())
+            })
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(depths.len(), 15);
+    assert!(depths.iter().all(|(v, depth)| *v as usize == *depth));
+}
+
+#[cfg(feature = "bench")]
+#[test]
+fn dynqueue_bench_workload() {
+    use crate::bench::{Backend, VecBackend, VecDequeBackend, Workload};
+
+    assert_eq!(Workload::new(3).len(), 3);
+    assert_eq!(Workload::new(3).with_depth(2).len(), 3);
+    let workload = Workload::new(3)
+        .with_fan_out(3)
+        .with_depth(4)
+        .with_item_cost(10);
+    assert_eq!(workload.len(), 3 * (1 + 3 + 9 + 27 + 81));
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    pool.install(|| {
+        assert_eq!(VecBackend::run(&workload), workload.len());
+        assert_eq!(VecDequeBackend::run(&workload), workload.len());
+        #[cfg(feature = "crossbeam-queue")]
+        assert_eq!(
+            crate::bench::SegQueueBackend::run(&workload),
+            workload.len()
+        );
+    });
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn dynqueue_test_support() {
+    use crate::test_support::Conformance;
+    use crate::SharedVec;
+    use std::collections::BinaryHeap;
+    use std::sync::RwLock;
+
+    Conformance::new(RwLock::new).with_steps(2000).run();
+    Conformance::new(|v: Vec<u64>| RwLock::new(VecDeque::from(v)))
+        .with_steps(2000)
+        .run();
+    Conformance::new(|v: Vec<u64>| RwLock::new(BinaryHeap::from(v)))
+        .with_steps(2000)
+        .run();
+    Conformance::new(SharedVec::from).with_steps(2000).run();
+    #[cfg(feature = "compress")]
+    Conformance::new(|v: Vec<u64>| {
+        let mut queue = crate::Compressed::new(RwLock::new(Vec::new())).with_block_size(4);
+        queue.extend(v);
+        queue
+    })
+    .with_steps(2000)
+    .run();
+    #[cfg(feature = "crossbeam-queue")]
+    Conformance::new(|v: Vec<u64>| {
+        let queue = crossbeam_queue::SegQueue::new();
+        v.into_iter().for_each(|v| queue.push(v));
+        queue
+    })
+    .with_steps(2000)
+    .run();
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+#[should_panic(expected = "sequential check failed")]
+fn dynqueue_test_support_lost() {
+    use crate::test_support::Conformance;
+    use std::sync::RwLock;
+
+    /// Drops every 100th element
+    struct Lossy(RwLock<Vec<u64>>);
+
+    impl Queue<u64> for Lossy {
+        fn push(&self, v: u64) {
+            if v % 100 != 99 {
+                self.0.push(v)
+            }
+        }
+
+        fn pop(&self) -> Option<u64> {
+            self.0.pop()
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn split_off(&self, size: usize) -> Self {
+            Lossy(self.0.split_off(size))
+        }
+    }
+
+    Conformance::new(|v| Lossy(RwLock::new(v))).run();
+}
+
+#[test]
+fn dynqueue_requeue() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every element fails twice, before it succeeds
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .with_pop_batch_size(4)
+            .into_par_iter()
+            .filter_map(|(handle, v)| {
+                if handle.attempt() < 2 {
+                    handle.requeue(v).unwrap();
+                    return None;
+                }
+                Some(handle_queue((handle, v)))
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, get_expected());
+
+    let mut exhausted = pool.install(|| {
+        DynQueue::builder()
+            .max_retries(3)
+            .build(vec![1u64, 2, 3])
+            .into_par_iter()
+            .filter_map(|(handle, v)| match handle.requeue(v) {
+                Ok(()) => None,
+                Err(EnqueueError::Exhausted(v)) => Some((handle.attempt(), v)),
+                Err(e) => panic!("{}", e),
+            })
+            .collect::<Vec<_>>()
+    });
+    exhausted.sort();
+    assert_eq!(exhausted, vec![(3, 1), (3, 2), (3, 3)]);
+
+    // the serial iterator and the depth of a tracked queue
+    let res = crate::Tracked::from(vec![0u64])
+        .into_dyn_queue()
+        .with_max_retries(1)
+        .into_iter()
+        .map(|(handle, v)| {
+            if v < 2 && handle.attempt() == 0 {
+                handle.enqueue(v + 1).unwrap();
+            }
+            let requeued = handle.requeue(v).is_ok();
+            (v, handle.depth(), handle.attempt(), requeued)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        res,
+        vec![
+            (0, 0, 0, true),
+            (1, 1, 0, true),
+            (2, 2, 0, true),
+            (0, 0, 1, false),
+            (1, 1, 1, false),
+            (2, 2, 1, false),
+        ]
+    );
+}
+
+#[test]
+fn dynqueue_dead_letters() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // odd elements are rejected, multiples of 4 are retried until exhausted
+    let queue = get_input().into_dyn_queue().with_max_retries(2);
+    let dead_letters = queue.dead_letters();
+    let mut res = pool.install(|| {
+        queue
+            .into_par_iter()
+            .filter_map(|(handle, v)| {
+                if v % 2 == 1 {
+                    handle.reject(v, format!("odd {}", v)).unwrap();
+                    None
+                } else if v % 4 == 0 {
+                    handle.requeue(v).unwrap();
+                    None
+                } else {
+                    Some(v)
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res, vec![2, 6, 10, 14, 18]);
+
+    assert_eq!(dead_letters.len(), 16);
+    let mut failed = dead_letters
+        .take()
+        .into_iter()
+        .map(|dead| {
+            let error = dead.error.map(|e| e.to_string());
+            (dead.item, dead.attempts, error)
+        })
+        .collect::<Vec<_>>();
+    failed.sort();
+    assert_eq!(failed[0], (1, 0, Some("odd 1".to_string())));
+    assert_eq!(failed[2], (4, 2, None));
+    assert!(dead_letters.is_empty());
+
+    // without a `DeadLetters` handle, exhausted elements are given back
+    // and rejected elements are dropped
+    let exhausted = pool.install(|| {
+        vec![1u64, 2]
+            .into_dyn_queue()
+            .with_max_retries(0)
+            .map_scoped(|handle, v| {
+                if v == 1 {
+                    handle.reject(v, "rejected");
+                    return None;
+                }
+                handle.requeue(v).err().map(EnqueueError::into_inner)
+            })
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(exhausted, vec![None, Some(2)]);
+}
+
+#[test]
+fn dynqueue_enqueue_after() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every input element enqueues a delayed child, which must not be
+    // processed before it is due
+    let delay = Duration::from_millis(30);
+    let enqueued = Mutex::new(Vec::new());
+    let mut res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(|(handle, v)| {
+                if v < 100 {
+                    enqueued.lock().unwrap().push((v + 100, Instant::now()));
+                    handle.enqueue_after(v + 100, delay).unwrap();
+                } else {
+                    let enqueued = enqueued.lock().unwrap();
+                    let (_, start) = enqueued.iter().find(|(e, _)| *e == v).unwrap();
+                    assert!(start.elapsed() >= delay);
+                }
+                v
+            })
+            .collect::<Vec<_>>()
+    });
+    res.sort();
+    assert_eq!(res.len(), 42);
+    assert_eq!(res[21], 101);
+
+    // the serial iterator waits for delayed elements, too
+    let start = Instant::now();
+    let res = vec![1u64]
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(handle, v)| {
+            if v == 1 {
+                handle.enqueue_after(2, delay).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(res, vec![1, 2]);
+    assert!(start.elapsed() >= delay);
+
+    // delayed elements count against the capacity of the overflow policy
+    let mut rejected = Vec::new();
+    let mut res = crate::DynQueue::builder()
+        .capacity_limit(2)
+        .overflow_policy(crate::OverflowPolicy::Reject)
+        .build(vec![0u64])
+        .into_iter()
+        .map(|(handle, v)| {
+            if v == 0 {
+                for v in 1..=3 {
+                    if let Err(EnqueueError::Full(v)) = handle.enqueue_after(v, delay) {
+                        rejected.push(v);
+                    }
+                }
+                if let Err(EnqueueError::Full(v)) = handle.enqueue(4) {
+                    rejected.push(v);
+                }
+            }
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![0, 1, 2]);
+    assert_eq!(rejected, vec![3, 4]);
+}
+
+#[test]
+fn dynqueue_lineage() {
+    use crate::Tracked;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // binary tree: the children of `v` are `2v` and `2v + 1`
+    let queue = Tracked::from(vec![1u64]).into_dyn_queue();
+    let lineage = queue.lineage();
+    let res = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|(handle, v)| {
+                if v < 64 {
+                    handle.enqueue_many(vec![2 * v, 2 * v + 1]).unwrap();
+                }
+                let id = handle.current_id().unwrap();
+                (v, id, handle.parent_of(id))
+            })
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(res.len(), 127);
+    assert_eq!(lineage.len(), 127);
+
+    let id_of = |v: u64| res.iter().find(|(e, _, _)| *e == v).unwrap().1;
+    for (v, id, parent) in res.iter() {
+        assert_eq!(*parent, lineage.parent_of(*id));
+        if *v == 1 {
+            assert_eq!(*parent, None);
+        } else {
+            assert_eq!(*parent, Some(id_of(v / 2)));
+        }
+    }
+    assert_eq!(
+        lineage.ancestors(id_of(77)),
+        vec![id_of(38), id_of(19), id_of(9), id_of(4), id_of(2), id_of(1)]
+    );
+
+    // without a `Lineage` handle no ids are assigned
+    let ids = Tracked::from(vec![1u64, 2])
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(handle, _)| handle.current_id())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![None, None]);
+}
+
+#[test]
+fn dynqueue_fork_join() {
+    use crate::Tracked;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // size of the subtrees of a ternary tree, children beyond `max_depth`
+    // are dropped and not waited for
+    let res = pool.install(|| {
+        Tracked::from(vec![0u64, 1])
+            .into_dyn_queue()
+            .with_max_depth(5)
+            .fork_join(
+                |handle, v| {
+                    for i in 0..3 {
+                        handle.enqueue(v * 3 + i).unwrap();
+                    }
+                    1usize
+                },
+                |size, child| size + child,
+            )
+    });
+    assert_eq!(res, vec![364, 364]);
+
+    // negamax on a game tree: a node with value `v` has the children
+    // `v * 2` and `v * 2 + 1`, leaves score `v % 7`
+    fn negamax(v: i64) -> i64 {
+        if v >= 64 {
+            return v % 7;
+        }
+        (-negamax(v * 2)).max(-negamax(v * 2 + 1))
+    }
+    let res = pool.install(|| {
+        Tracked::from(vec![1i64]).into_dyn_queue().fork_join(
+            |handle, v| {
+                if v >= 64 {
+                    return v % 7;
+                }
+                handle.enqueue_many(vec![v * 2, v * 2 + 1]).unwrap();
+                i64::MIN
+            },
+            |best, child| best.max(-child),
+        )
+    });
+    assert_eq!(res, vec![negamax(1)]);
+}
+
+#[test]
+fn dynqueue_tickets() {
+    use crate::{Ticket, Tracked};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // every element below 100 enqueues its square and keeps the ticket;
+    // odd squares are filtered out and get no ticket
+    let mut results = pool.install(|| {
+        Tracked::from(get_input())
+            .into_dyn_queue()
+            .with_filter(|v: &u64| *v < 100 || v.is_multiple_of(2))
+            .collect_tickets(|handle, v| {
+                let ticket: Option<Ticket> = if v < 100 {
+                    handle.enqueue_ticket(v * v + 100).unwrap()
+                } else {
+                    None
+                };
+                (v, ticket)
+            })
+    });
+    assert_eq!(results.len(), 21 + 10);
+
+    for i in 0..21u64 {
+        let (v, ticket) = results.take_initial(i as usize).unwrap();
+        assert_eq!(v, i + 1);
+        match ticket {
+            Some(ticket) => {
+                assert_eq!(v % 2, 0);
+                assert_eq!(results.get(ticket), Some(&(v * v + 100, None)));
+                assert_eq!(results.take(ticket), Some((v * v + 100, None)));
+            }
+            None => assert_eq!(v % 2, 1),
+        }
+    }
+    assert!(results.is_empty());
+
+    // without tracking the lineage, no tickets are handed out
+    let tickets = Tracked::from(vec![1u64])
+        .into_dyn_queue()
+        .into_iter()
+        .map(|(handle, v)| {
+            if v > 1 {
+                return None;
+            }
+            handle.enqueue_ticket(v + 1).unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(tickets, vec![None, None]);
+}
+
+#[test]
+fn dynqueue_pause() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // a paused queue pops nothing, until it is resumed
+    let queue = get_input().into_dyn_queue();
+    let control = queue.control();
+    control.pause();
+    let processed = AtomicUsize::new(0);
+    let count = std::thread::scope(|scope| {
+        let running = scope.spawn(|| {
+            pool.install(|| {
+                queue
+                    .into_par_iter()
+                    .map(handle_queue)
+                    .inspect(|_| {
+                        processed.fetch_add(1, Ordering::Relaxed);
+                    })
+                    .count()
+            })
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(processed.load(Ordering::Relaxed), 0);
+        assert!(!running.is_finished());
+        control.resume();
+        running.join().unwrap()
+    });
+    assert_eq!(count, get_expected().len());
+
+    // pause from within the serial iterator
+    let queue = get_input().into_dyn_queue();
+    let control = queue.control();
+    let count = std::thread::scope(|scope| {
+        let control = &control;
+        let running = scope.spawn(move || {
+            queue
+                .into_iter()
+                .inspect(|(_, v)| {
+                    if *v == 1 {
+                        control.pause();
+                    }
+                })
+                .map(handle_queue)
+                .count()
+        });
+        while !control.is_paused() {
+            std::thread::yield_now();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!running.is_finished());
+        control.resume();
+        running.join().unwrap()
+    });
+    assert_eq!(count, get_expected().len());
+}
+
+#[test]
+fn dynqueue_snapshot() {
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::time::Duration;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let queue = get_input().into_dyn_queue().with_max_retries(1);
+    let control = queue.control();
+    let mut snapshot = control.snapshot();
+    snapshot.sort();
+    assert_eq!(snapshot, get_input());
+    assert_eq!(control.pending(), 21);
+
+    // the delayed and requeued elements are part of the snapshot, while
+    // the shards were split across the threads
+    let count = pool.install(|| {
+        queue
+            .into_par_iter()
+            .map(|(handle, v)| {
+                if v == 1 {
+                    handle
+                        .enqueue_after(100, Duration::from_millis(20))
+                        .unwrap();
+                    handle.requeue(101).unwrap();
+                    let snapshot = control.snapshot();
+                    assert!(snapshot.contains(&100));
+                    assert!(snapshot.contains(&101));
+                    assert!(!snapshot.contains(&1));
+                    assert!(control.pending() >= 2);
+                }
+            })
+            .count()
+    });
+    assert_eq!(count, 23);
+    assert!(control.snapshot().is_empty());
+    assert_eq!(control.pending(), 0);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn dynqueue_metrics() {
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, SharedString, Unit};
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Recorder keeping the value of every metric by name
+    #[derive(Default)]
+    struct Values(Mutex<HashMap<String, Arc<AtomicU64>>>);
+
+    impl Values {
+        fn get(&self, name: &str) -> Arc<AtomicU64> {
+            self.0
+                .lock()
+                .unwrap()
+                .entry(name.to_string())
+                .or_default()
+                .clone()
+        }
+    }
+
+    impl metrics::Recorder for &'static Values {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.get(key.name()))
+        }
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.get(key.name()))
+        }
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    let values: &'static Values = Box::leak(Box::default());
+    metrics::set_global_recorder(values).unwrap();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    let res = pool.install(|| {
+        get_input()
+            .into_dyn_queue()
+            .into_par_iter()
+            .map(handle_queue)
+            .collect::<Vec<_>>()
+    });
+
+    // other tests may report to the same recorder concurrently
+    let counter = |name: &str| values.get(name).load(Ordering::Relaxed);
+    assert!(counter("dynqueue_processed_total") >= res.len() as u64);
+    assert!(counter("dynqueue_enqueued_total") >= (res.len() - get_input().len()) as u64);
+    assert!(counter("dynqueue_splits_total") > 0);
+    let pending = f64::from_bits(counter("dynqueue_pending"));
+    assert!(pending >= 0.0);
+}
+
+#[test]
+fn dynqueue_approximate_dedup() {
+    use crate::DynQueueBuilder;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::collections::HashSet;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    // a graph of the numbers below 10000 with the edges `v + 1` and `2v`
+    const N: u64 = 10_000;
+    let res = pool.install(|| {
+        vec![0u64, 0, 1]
+            .into_dyn_queue()
+            .with_approximate_dedup(|v| *v, N as usize, 0.01)
+            .into_par_iter()
+            .map(|(handle, v)| {
+                let next = [v + 1, v * 2];
+                handle
+                    .enqueue_many(next.iter().copied().filter(|n| *n < N))
+                    .unwrap();
+                v
+            })
+            .collect::<Vec<_>>()
+    });
+    let distinct = res.iter().collect::<HashSet<_>>().len();
+    // false positives drop a few unseen elements, whose successors are
+    // mostly reached on another path
+    assert!(distinct as u64 > N * 95 / 100, "{}", distinct);
+    assert!(res.len() - distinct < 10, "{}", res.len() - distinct);
+
+    let mut res = DynQueueBuilder::new()
+        .approximate_dedup(|v: &u64| *v, 100, 0.001)
+        .build(vec![1u64, 1, 2])
+        .into_iter()
+        .map(|(handle, v)| {
+            handle.enqueue(1).unwrap();
+            v
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    assert_eq!(res, vec![1, 2]);
+}
+
+// not a `loom` model, but selected by the `loom_` filter of the model runs
+#[cfg(not(loom))]
+#[test]
+fn dynqueue_bloom_false_positive_rate() {
+    use crate::parallel::bloom::BloomFilter;
+
+    const ITEMS: u64 = 10_000;
+    const PROBES: u64 = 2_000;
+
+    let filter = BloomFilter::new(ITEMS as usize, 0.01);
+    for key in 0..ITEMS {
+        filter.insert(&key);
+    }
+    // every probe inserts an unseen key, so the rate grows slightly
+    // beyond the configured rate towards the end
+    let false_positives = (ITEMS..ITEMS + PROBES)
+        .filter(|key| !filter.insert(key))
+        .count();
+    let rate = false_positives as f64 / PROBES as f64;
+    assert!(rate > 0.005 && rate < 0.03, "{}", rate);
+}
+
+#[test]
+fn dynqueue_seeds() {
+    use crate::DynQueue;
+    use rayon::iter::IntoParallelIterator as _;
+    use rayon::iter::ParallelIterator as _;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(4)
+        .build()
+        .unwrap();
+
+    const SEEDS: usize = 10_000;
+    let generated = AtomicUsize::new(0);
+    let generated_at_first = AtomicUsize::new(usize::MAX);
+
+    let mut res = pool.install(|| {
+        DynQueue::from_fn(|| {
+            let v = generated.fetch_add(1, Ordering::SeqCst);
+            (v < SEEDS).then_some(v)
+        })
+        .into_par_iter()
+        .map(|(h, v)| {
+            if v == 0 {
+                generated_at_first.store(generated.load(Ordering::SeqCst), Ordering::SeqCst);
+            }
+            if v < SEEDS && v % 100 == 0 {
+                h.enqueue(v + SEEDS).unwrap();
+            }
+            v
+        })
+        .collect::<Vec<_>>()
+    });
+    res.sort();
+
+    // the seeds are generated in chunks, not all before the first pop
+    assert!(generated_at_first.load(Ordering::SeqCst) < SEEDS);
+    let mut expected = (0..SEEDS).collect::<Vec<_>>();
+    expected.extend((0..SEEDS).step_by(100).map(|v| v + SEEDS));
+    assert_eq!(res, expected);
+
+    // the seeds pass the filters and follow the initial elements
+    let res = DynQueue::builder()
+        .dedup(|v| *v)
+        .seeds([3u64, 1, 3, 2, 1])
+        .seed_chunk_size(2)
+        .build(vec![1u64])
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect::<Vec<_>>();
+    assert_eq!(res, [1, 2, 3]);
+}